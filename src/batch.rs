@@ -0,0 +1,168 @@
+// Copyright 2026 Stairwell, Inc.
+// Author: mrdomino@stairwell.com
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! `--batch`: runs several syncs described by a JSON document on stdin, for orchestration systems
+//! that want more than flags can express.
+//!
+//! The document lists the jobs to run, in order:
+//!
+//! ```json
+//! {"jobs": [
+//!   {"host": "devbox-us"},
+//!   {"host": "devbox-eu", "keyring": "session", "ssh_args": ["-J", "bastion"], "force": true}
+//! ]}
+//! ```
+//!
+//! Only `host` is required. `remote`, `credential_helper`, `keyring`, `key_name`, and
+//! `ssh_args` override whatever the command line and config file (including the host's table
+//! there) give, short of dedicated ssh flags such as `-J`, and `force` forces both
+//! the login and the push, as `--force` does. The outcome of each job is reported as in CI mode,
+//! as a single JSON object `{"jobs": [...]}` on stdout. The exit code is 1 if any job failed.
+//! `--timeout` applies to each job on its own.
+
+use std::{io::Read, process::ExitCode};
+
+use anyhow::{Context, Result};
+use aspect_reauth::{
+    ReauthFlow,
+    keyctl::{self, KeyringTarget},
+    probe::Probed,
+    ssh_mux,
+};
+use clap::ArgMatches;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    Args, EXIT_SYNCED, Interrupted,
+    ci::{Report, Status},
+    config, connect, disconnect, exit_code, interruptible,
+};
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Batch {
+    jobs: Vec<Job>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Job {
+    host: String,
+    remote: Option<String>,
+    credential_helper: Option<String>,
+    #[serde(default, with = "aspect_reauth::keyctl::keyrings")]
+    keyring: Vec<KeyringTarget>,
+    key_name: Option<String>,
+    #[serde(default)]
+    ssh_args: Vec<String>,
+    #[serde(default)]
+    force: bool,
+}
+
+#[derive(Serialize)]
+struct BatchReport {
+    jobs: Vec<Report>,
+}
+
+pub async fn run(args: &Args, matches: &ArgMatches) -> Result<ExitCode> {
+    let batch = read_batch().await?;
+    let config = config::Config::load()?;
+    let mut reports = Vec::with_capacity(batch.jobs.len());
+    let mut interrupted = None;
+    for job in batch.jobs {
+        let args = job.apply(args, &config, matches)?;
+        let result = interruptible(&args, sync(&args)).await;
+        // An interrupted batch stops there, reporting on the jobs it got to.
+        interrupted = result
+            .as_ref()
+            .err()
+            .filter(|e| e.downcast_ref::<Interrupted>().is_some())
+            .map(exit_code);
+        reports.push(Report::new(&args, result));
+        if interrupted.is_some() {
+            break;
+        }
+    }
+    let any = |status: fn(&Status) -> bool| reports.iter().any(|r| status(&r.status));
+    let code = if let Some(code) = interrupted {
+        code
+    } else if any(|s| matches!(s, Status::Error)) {
+        ExitCode::FAILURE
+    } else if args.report_action_in_exit_code && any(|s| matches!(s, Status::Synced)) {
+        ExitCode::from(EXIT_SYNCED)
+    } else {
+        ExitCode::SUCCESS
+    };
+    println!("{}", serde_json::to_string(&BatchReport { jobs: reports })?);
+    Ok(code)
+}
+
+impl Job {
+    /// Returns a copy of `args` for this job, with `config` applied for its host.
+    fn apply(self, args: &Args, config: &config::Config, matches: &ArgMatches) -> Result<Args> {
+        let mut args = args.clone();
+        args.host = Some(self.host);
+        // Set before the config fills in the rest, so that the job's ssh_args replace the ones
+        // from there but not the ones the command line's ssh flags stand for.
+        if !self.ssh_args.is_empty() {
+            args.ssh_args = self.ssh_args;
+        }
+        args.prepare(config.clone(), matches, false)?;
+        args.remote = self.remote.unwrap_or(args.remote);
+        args.credential_helper = self.credential_helper.unwrap_or(args.credential_helper);
+        if !self.keyring.is_empty() {
+            args.keyring = self.keyring;
+        }
+        args.key_name = self.key_name.or(args.key_name);
+        if self.force {
+            args.force_local = true;
+            args.force_remote = true;
+        }
+        Ok(args)
+    }
+}
+
+async fn sync(args: &Args) -> Result<(Status, Option<i64>)> {
+    let mut args = args.clone();
+    args.ssh_args = ssh_mux::split_ssh_args(&args.ssh_args).context("invalid ssh_args")?;
+    let template = args.key_name.as_deref().unwrap_or(keyctl::DEFAULT_KEY_NAME);
+    let key_name =
+        keyctl::expand_key_name(template, &args.remote, args.host()).context("invalid key_name")?;
+    let options = args.sync_options();
+    let sink = Probed::new(args.sink_named(&options, key_name), &options);
+    let ssh = connect(&args).await?;
+    let source = args.source(&options);
+    let outcome = ReauthFlow::new(&source, &ssh, &sink, &options)
+        .force_local(args.force_local)
+        .force_remote(args.force_remote)
+        .run()
+        .await;
+    let outcome = disconnect(ssh, outcome).await?;
+    Ok(if outcome.synced {
+        (Status::Synced, outcome.key)
+    } else {
+        (Status::Valid, None)
+    })
+}
+
+async fn read_batch() -> Result<Batch> {
+    let input = smol::unblock(|| {
+        let mut buf = String::new();
+        std::io::stdin().read_to_string(&mut buf).map(|_| buf)
+    })
+    .await
+    .context("failed to read batch from stdin")?;
+    serde_json::from_str(&input).context("failed to parse batch")
+}
@@ -0,0 +1,301 @@
+// Copyright 2026 Stairwell, Inc.
+// Author: mrdomino@stairwell.com
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A stand-in for every external program aspect-reauth runs, for end-to-end tests.
+//!
+//! The program to imitate is chosen by the name this is invoked as, so tests symlink it into a
+//! scratch `PATH` as `ssh`, `keyctl`, and the credential helper. All state lives in files under
+//! `$MOCK_DIR`:
+//!
+//! - `local-state`, `remote-state`: the helper on that side has a valid credential iff this
+//!   contains `valid`.
+//! - `local-expires`, `remote-expires`: if present, the expiry time the helper on that side reports
+//!   along with a valid credential.
+//! - `login-fail`, `keyctl-fail`, `ssh-fail`: if present, that operation fails.
+//! - `login-hang`: if present, login waits for a minute, as if the browser flow was abandoned.
+//! - `keyring-unavailable`: if present, `keyctl rdescribe` fails, as in a container that hides
+//!   the kernel keyrings.
+//! - `remote-key`: the payload most recently written with `keyctl padd` or `pupdate`; while it
+//!   exists, `keyctl search` finds the key.
+//! - `ssh-config`: what `ssh -G` prints, if present.
+//! - `master-stale`: if present, `ssh -O check` without `-S`, on the user's own control master,
+//!   fails as if its socket, this file, were left behind by a master that died.
+//! - `master-absent`: if present, `ssh -O check` without `-S` fails as if the user's control
+//!   master had no socket, in a French locale.
+//! - `log`: one line per invocation of `ssh`, prefixed with `ssh`, and of the helper and
+//!   `keyctl`, prefixed with the side it ran on.
+//!
+//! A control master is modelled by a plain file at its socket path, created by `ssh -xMTS` and
+//! removed by `ssh -O exit`; `ssh -O check -S` succeeds while the file is there. To simulate a
+//! host without keyctl, remove the `keyctl` shim from `$MOCK_REMOTE_PATH`.
+//!
+//! The `ssh` shim runs remote commands locally through `sh -c`, with `$MOCK_REMOTE_PATH` as `PATH`
+//! and the side set to `remote`, and so do the `tsh` shim for `tsh ssh`, the `kubectl` shim for
+//! `kubectl exec ... -- sh -c`, and the `docker` and `podman` shims for `exec ... sh -c`.
+
+use std::{
+    env, fs,
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+    process::{Command, ExitCode},
+};
+
+fn main() -> ExitCode {
+    let mut args = env::args();
+    let argv0 = args.next().unwrap_or_default();
+    let name = Path::new(&argv0)
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let args: Vec<String> = args.collect();
+    let dir = PathBuf::from(env::var_os("MOCK_DIR").expect("MOCK_DIR is set"));
+    match name.as_str() {
+        "ssh" => ssh(&dir, &args),
+        "tsh" => tsh(&dir, &args),
+        "kubectl" => kubectl(&dir, &args),
+        "docker" | "podman" => container(&dir, &name, &args),
+        "keyctl" => keyctl(&dir, &args),
+        _ => helper(&dir, &name, &args),
+    }
+}
+
+fn side() -> String {
+    env::var("MOCK_SIDE").unwrap_or_else(|_| "local".into())
+}
+
+fn log(dir: &Path, line: &str) {
+    let mut f = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(dir.join("log"))
+        .expect("open log");
+    writeln!(f, "{line}").expect("write log");
+}
+
+fn ssh(dir: &Path, args: &[String]) -> ExitCode {
+    log(dir, &format!("ssh {}", args.join(" ")));
+    if args.iter().any(|a| a == "-G") {
+        match fs::read_to_string(dir.join("ssh-config")) {
+            Ok(config) => print!("{config}"),
+            Err(_) => println!("hostname mock\ncontrolmaster no"),
+        }
+        return ExitCode::SUCCESS;
+    }
+    // A plain file stands in for the control socket, there from when a master starts until it is
+    // told to exit.
+    let socket = args
+        .iter()
+        .position(|a| a == "-S" || a == "-xMTS")
+        .map(|i| Path::new(&args[i + 1]));
+    if let Some(op) = args.iter().find_map(|a| a.strip_prefix("-O")) {
+        return match (op, socket) {
+            ("check", Some(socket)) if !socket.exists() => {
+                eprintln!(
+                    "Control socket connect({}): No such file or directory",
+                    socket.display()
+                );
+                ExitCode::from(255)
+            }
+            ("check", None) if dir.join("master-stale").exists() => {
+                eprintln!(
+                    "Control socket connect({}): Connection refused",
+                    dir.join("master-stale").display()
+                );
+                ExitCode::from(255)
+            }
+            ("check", None) if dir.join("master-absent").exists() => {
+                eprintln!(
+                    "Control socket connect({}): Aucun fichier ou dossier de ce type",
+                    dir.join("no-master").display()
+                );
+                ExitCode::from(255)
+            }
+            ("exit", Some(socket)) => {
+                let _ = fs::remove_file(socket);
+                ExitCode::SUCCESS
+            }
+            _ => ExitCode::SUCCESS,
+        };
+    }
+    if dir.join("ssh-fail").exists() {
+        eprintln!("ssh: connect to host mock port 22: Connection refused");
+        return ExitCode::from(255);
+    }
+    if let Some(socket) = socket.filter(|_| args.iter().any(|a| a == "-xMTS")) {
+        fs::write(socket, "").expect("create socket");
+    }
+    let Some(sep) = args.iter().position(|a| a == "--") else {
+        eprintln!("mock ssh: missing --");
+        return ExitCode::from(255);
+    };
+    // Like real ssh, join the remote command into one string for the remote shell.
+    run_remote(&args[sep + 2..].join(" "))
+}
+
+fn tsh(dir: &Path, args: &[String]) -> ExitCode {
+    log(dir, &format!("tsh {}", args.join(" ")));
+    if args.first().is_none_or(|a| a != "ssh") {
+        eprintln!("mock tsh: only ssh is supported");
+        return ExitCode::FAILURE;
+    }
+    if dir.join("ssh-fail").exists() {
+        eprintln!("ERROR: your Teleport session has expired; run tsh login");
+        return ExitCode::FAILURE;
+    }
+    let Some(host) = args[1..].iter().position(|a| !a.starts_with('-')) else {
+        eprintln!("mock tsh: missing host");
+        return ExitCode::FAILURE;
+    };
+    run_remote(&args[host + 2..].join(" "))
+}
+
+fn kubectl(dir: &Path, args: &[String]) -> ExitCode {
+    log(dir, &format!("kubectl {}", args.join(" ")));
+    if args.first().is_none_or(|a| a != "exec") {
+        eprintln!("mock kubectl: only exec is supported");
+        return ExitCode::FAILURE;
+    }
+    if dir.join("ssh-fail").exists() {
+        eprintln!("Error from server (NotFound): pods \"mock\" not found");
+        return ExitCode::FAILURE;
+    }
+    match args.iter().position(|a| a == "--") {
+        Some(sep) if args[sep + 1..].starts_with(&["sh".into(), "-c".into()]) => {
+            run_remote(&args[sep + 3..].join(" "))
+        }
+        _ => {
+            eprintln!("mock kubectl: expected -- sh -c");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn container(dir: &Path, name: &str, args: &[String]) -> ExitCode {
+    log(dir, &format!("{name} {}", args.join(" ")));
+    if args.first().is_none_or(|a| a != "exec") {
+        eprintln!("mock {name}: only exec is supported");
+        return ExitCode::FAILURE;
+    }
+    if dir.join("ssh-fail").exists() {
+        eprintln!("Error response from daemon: container mock is not running");
+        return ExitCode::FAILURE;
+    }
+    // The first word that is not an option is the container, followed by the command.
+    match args[1..].iter().position(|a| !a.starts_with('-')) {
+        Some(i) if args[i + 2..].starts_with(&["sh".into(), "-c".into()]) => {
+            run_remote(&args[i + 4..].join(" "))
+        }
+        _ => {
+            eprintln!("mock {name}: expected a container and sh -c");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Runs `command` through `sh -c` as if on the remote.
+fn run_remote(command: &str) -> ExitCode {
+    let status = Command::new("sh")
+        .args(["-c", command])
+        .env("PATH", env::var_os("MOCK_REMOTE_PATH").unwrap_or_default())
+        .env("MOCK_SIDE", "remote")
+        .status()
+        .expect("spawn sh");
+    ExitCode::from(status.code().unwrap_or(255) as u8)
+}
+
+fn keyctl(dir: &Path, args: &[String]) -> ExitCode {
+    log(dir, &format!("{} keyctl {}", side(), args.join(" ")));
+    if args.first().is_some_and(|a| a == "search") {
+        if !dir.join("remote-key").exists() {
+            eprintln!("keyctl_search: Required key not available");
+            return ExitCode::FAILURE;
+        }
+        println!("123456");
+        return ExitCode::SUCCESS;
+    }
+    if args.first().is_some_and(|a| a == "rdescribe") {
+        if dir.join("keyring-unavailable").exists() {
+            eprintln!("keyctl_describe_alloc: Operation not supported");
+            return ExitCode::FAILURE;
+        }
+        println!("keyring;1000;1000;3f030000;_uid.1000");
+        return ExitCode::SUCCESS;
+    }
+    if args
+        .first()
+        .is_some_and(|a| ["timeout", "setperm", "link"].contains(&a.as_str()))
+    {
+        return ExitCode::SUCCESS;
+    }
+    if args.first().is_some_and(|a| a == "invalidate") {
+        let _ = fs::remove_file(dir.join("remote-key"));
+        let _ = fs::write(dir.join("remote-state"), "expired");
+        return ExitCode::SUCCESS;
+    }
+    let mut payload = String::new();
+    io::stdin()
+        .read_to_string(&mut payload)
+        .expect("read stdin");
+    if dir.join("keyctl-fail").exists() {
+        eprintln!("keyctl_add_key: Permission denied");
+        return ExitCode::FAILURE;
+    }
+    fs::write(dir.join("remote-key"), payload).expect("write key");
+    fs::write(dir.join("remote-state"), "valid").expect("write state");
+    if args.first().is_some_and(|a| a == "padd") {
+        println!("123456");
+    }
+    ExitCode::SUCCESS
+}
+
+fn helper(dir: &Path, name: &str, args: &[String]) -> ExitCode {
+    let side = side();
+    log(dir, &format!("{side} {name} {}", args.join(" ")));
+    let state = dir.join(format!("{side}-state"));
+    match args.first().map(String::as_str) {
+        Some("get") => {
+            let mut request = String::new();
+            io::stdin()
+                .read_to_string(&mut request)
+                .expect("read stdin");
+            if fs::read_to_string(&state).is_ok_and(|s| s == "valid") {
+                let expires = match fs::read_to_string(dir.join(format!("{side}-expires"))) {
+                    Ok(expires) => format!(r#","expires":"{}""#, expires.trim()),
+                    Err(_) => String::new(),
+                };
+                println!(r#"{{"headers":{{"Authorization":["Bearer mock"]}}{expires}}}"#);
+                ExitCode::SUCCESS
+            } else {
+                eprintln!("Error: credentials expired. Please run `{name} login mock` first.");
+                ExitCode::FAILURE
+            }
+        }
+        Some("login") => {
+            if dir.join("login-fail").exists() {
+                eprintln!("login cancelled");
+                return ExitCode::FAILURE;
+            }
+            if dir.join("login-hang").exists() {
+                std::thread::sleep(std::time::Duration::from_secs(60));
+            }
+            fs::write(&state, "valid").expect("write state");
+            ExitCode::SUCCESS
+        }
+        _ => {
+            eprintln!("usage: {name} get|login");
+            ExitCode::from(2)
+        }
+    }
+}
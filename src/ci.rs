@@ -0,0 +1,178 @@
+// Copyright 2026 Stairwell, Inc.
+// Author: mrdomino@stairwell.com
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Non-interactive mode for provisioning ephemeral build runners.
+//!
+//! In this mode the credential is supplied by the pipeline rather than the local keychain, the
+//! interactive login is never attempted, and the outcome is reported as a single JSON object on
+//! stdout along with a distinct exit code:
+//!
+//! | code | status    | meaning                                            |
+//! |------|-----------|----------------------------------------------------|
+//! | 0    | `synced`  | the credential was written and the host accepts it |
+//! | 4    | `synced`  | the same, with `--report-action-in-exit-code`      |
+//! | 0    | `valid`   | the host already had a valid credential            |
+//! | 1    | `error`   | something went wrong; see `error`                  |
+//! | 6    | `error`   | ssh could not connect to the host                  |
+//! | 8    | `error`   | keyctl or the sink failed on the host              |
+//! | 9    | `error`   | the sync did not finish within `--timeout`         |
+//! | 130  | `error`   | the sync was interrupted by SIGINT (143: SIGTERM)  |
+//! | 3    | `invalid` | with `--check`, the host's credential is invalid   |
+
+use std::{env, io::Read, process::ExitCode};
+
+use anyhow::{Context, Result};
+use aspect_reauth::{
+    ReauthFlow,
+    probe::Probed,
+    sink::RemoteSink,
+    source::{CredentialSource, External, Fixed},
+    state,
+    transport::RemoteTransport,
+};
+use secrecy::SecretString;
+use serde::Serialize;
+use zeroize::Zeroizing;
+
+use crate::{
+    Args, EXIT_INVALID, EXIT_SYNCED, connect, disconnect, exit_code, interruptible, note_fallback,
+    record,
+};
+
+const CREDENTIAL_VAR: &str = "ASPECT_REAUTH_CREDENTIAL";
+
+#[derive(Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Synced,
+    Valid,
+    Invalid,
+    Error,
+}
+
+/// The outcome of a sync to one host, as reported in CI and batch mode.
+#[derive(Serialize)]
+pub struct Report {
+    pub host: String,
+    pub remote: String,
+    pub status: Status,
+    /// The serial number of the key the credential was stored as on the host, if it was synced.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl Report {
+    /// Reports on a sync to the host in `args` that finished with `result`, the status and the
+    /// serial number of any key stored, recording the outcome for `prompt-status` too.
+    pub fn new(args: &Args, result: Result<(Status, Option<i64>)>) -> Self {
+        let (status, key, error) = match result {
+            Ok((status, key)) => (status, key, None),
+            Err(e) => (Status::Error, None, Some(format!("{e:#}"))),
+        };
+        record(
+            args,
+            match status {
+                Status::Synced | Status::Valid => state::Status::Valid,
+                Status::Invalid => state::Status::Invalid,
+                Status::Error => state::Status::Error,
+            },
+        );
+        Report {
+            host: args.host().into(),
+            remote: args.remote.clone(),
+            status,
+            key,
+            error,
+        }
+    }
+}
+
+pub async fn run(args: &Args) -> ExitCode {
+    let result = interruptible(args, sync(args)).await;
+    let failed = result.as_ref().err().map(exit_code);
+    let report = Report::new(args, result);
+    match serde_json::to_string(&report) {
+        Ok(json) => println!("{json}"),
+        Err(e) => eprintln!("failed to serialize report: {e}"),
+    }
+    match report.status {
+        Status::Synced if args.report_action_in_exit_code => ExitCode::from(EXIT_SYNCED),
+        Status::Synced | Status::Valid => ExitCode::SUCCESS,
+        Status::Error => failed.unwrap_or(ExitCode::FAILURE),
+        Status::Invalid => ExitCode::from(EXIT_INVALID),
+    }
+}
+
+async fn sync(args: &Args) -> Result<(Status, Option<i64>)> {
+    let ssh = connect(args).await?;
+    let result = sync_over(args, &ssh).await;
+    disconnect(ssh, result).await
+}
+
+async fn sync_over(args: &Args, ssh: &impl RemoteTransport) -> Result<(Status, Option<i64>)> {
+    let options = args.sync_options();
+    let sink = Probed::new(args.sink(&options)?, &options);
+    if args.check {
+        // The check a sync would make first, so that a sink the helper does not read from, such
+        // as keyctl's fallback file, is looked at too.
+        let status = if sink.check(ssh, &options).await?.needs_refresh {
+            Status::Invalid
+        } else {
+            Status::Valid
+        };
+        return Ok((status, None));
+    }
+    let source = Fixed(match args.external_source() {
+        Some(kind) => External::new(kind.clone()).credential().await?,
+        None => read_credential().await?,
+    });
+    let (synced, key) = ReauthFlow::new(&source, ssh, &sink, &options)
+        .force_remote(args.force_remote)
+        .sync_remote_with_key()
+        .await?;
+    if synced {
+        note_fallback(args, sink.sink());
+    }
+    Ok(if synced {
+        (Status::Synced, key)
+    } else {
+        (Status::Valid, None)
+    })
+}
+
+/// Reads the credential from the environment, falling back to stdin.
+async fn read_credential() -> Result<SecretString> {
+    let credential = match env::var(CREDENTIAL_VAR) {
+        Ok(val) => Zeroizing::new(val),
+        Err(env::VarError::NotPresent) => {
+            smol::unblock(|| {
+                let mut buf = Zeroizing::new(String::new());
+                std::io::stdin()
+                    .read_to_string(&mut buf)
+                    .map(|_| buf)
+                    .context("failed to read credential from stdin")
+            })
+            .await?
+        }
+        Err(e) => return Err(e).context(format!("failed to read ${CREDENTIAL_VAR}")),
+    };
+    let credential = credential.trim_end_matches(['\r', '\n']);
+    if credential.is_empty() {
+        anyhow::bail!("no credential provided in ${CREDENTIAL_VAR} or on stdin");
+    }
+    Ok(credential.into())
+}
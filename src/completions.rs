@@ -0,0 +1,93 @@
+// Copyright 2026 Stairwell, Inc.
+// Author: mrdomino@stairwell.com
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! `aspect-reauth completions SHELL`: shell completion, including hosts from `~/.ssh/config` and
+//! the config file.
+//!
+//! The script this prints only registers a function that calls back into `aspect-reauth` (with
+//! `$ASPECT_REAUTH_COMPLETE` set) for each completion, so the hosts offered are always those
+//! configured at the time, and the completions keep up with upgrades when the script is sourced
+//! at shell startup.
+
+use std::{
+    env,
+    io::{self, Write},
+    path::{self, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use aspect_reauth::ssh_mux::config::user_host_aliases;
+use clap::{Args, CommandFactory};
+use clap_complete::{CompleteEnv, CompletionCandidate, env::Shells};
+
+use crate::config;
+
+/// The variable that makes a run answer a completion request instead.
+const VAR: &str = "ASPECT_REAUTH_COMPLETE";
+
+const NAME: &str = "aspect-reauth";
+
+#[derive(Args, Clone)]
+pub struct CompletionsArgs {
+    /// Shell to print the completion script for
+    #[arg(value_parser = ["bash", "elvish", "fish", "powershell", "zsh"])]
+    shell: String,
+}
+
+/// Answers a completion request and exits, if this run is one.
+pub fn complete() {
+    CompleteEnv::with_factory(crate::Args::command)
+        .var(VAR)
+        .complete();
+}
+
+pub fn run(args: &CompletionsArgs) -> Result<()> {
+    let shells = Shells::builtins();
+    let shell = shells.completer(&args.shell).context("unsupported shell")?;
+    let mut script = Vec::new();
+    shell.write_registration(VAR, NAME, NAME, &completer()?, &mut script)?;
+    io::stdout()
+        .write_all(&script)
+        .context("failed to write the completion script")
+}
+
+/// How the completion script should run this program: as it was run now, made absolute if it was
+/// run by a relative path.
+fn completer() -> Result<String> {
+    let argv0 = PathBuf::from(env::args_os().next().unwrap_or_else(|| NAME.into()));
+    let path = if argv0.components().count() > 1 {
+        path::absolute(argv0).context("failed to get the current directory")?
+    } else {
+        argv0
+    };
+    Ok(path.to_string_lossy().into_owned())
+}
+
+/// The hosts to offer: the aliases in `~/.ssh/config`, then any in the config file not among them.
+pub fn hosts() -> Vec<CompletionCandidate> {
+    let mut hosts = user_host_aliases();
+    if let Ok(config) = config::Config::load() {
+        for host in config
+            .default_host
+            .into_iter()
+            .chain(config.hosts.into_keys())
+        {
+            if !hosts.contains(&host) {
+                hosts.push(host);
+            }
+        }
+    }
+    hosts.into_iter().map(CompletionCandidate::new).collect()
+}
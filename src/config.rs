@@ -0,0 +1,257 @@
+// Copyright 2026 Stairwell, Inc.
+// Author: mrdomino@stairwell.com
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The user's configuration file, `aspect-reauth/config.toml` under the platform config
+//! directory (`~/.config` on Linux), or wherever `$ASPECT_REAUTH_CONFIG` points.
+//!
+//! Everything here is a default: environment variables and flags take precedence.
+
+use std::{collections::BTreeMap, env, fs, io, path::PathBuf};
+
+use anyhow::{Context, Result};
+use aspect_reauth::{
+    keyctl::KeyringTarget, sink::SinkKind, source::SourceKind, ssh_mux::SocketMode,
+};
+
+use crate::session::Transport;
+use serde::{Deserialize, Serialize};
+
+const CONFIG_VAR: &str = "ASPECT_REAUTH_CONFIG";
+
+#[derive(Clone, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Host to sync to when none is given on the command line or in `$ASPECT_REAUTH_HOST`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default_host: Option<String>,
+
+    /// Aspect remote DNS name, overriding the built-in default.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub remote: Option<String>,
+
+    /// Aspect credential helper executable name, overriding the built-in default.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub credential_helper: Option<String>,
+
+    /// Where to read the credential from locally; see `--source`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<SourceKind>,
+
+    /// Where to read the credential from with no keychain to reach; see `--keychain-fallback`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keychain_fallback: Option<SourceKind>,
+
+    /// Keychain service the credential helper stores its credential under; see
+    /// `--keyring-service`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keyring_service: Option<String>,
+
+    /// Keychain account the credential helper stores its credential under; see
+    /// `--keyring-account`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keyring_account: Option<String>,
+
+    /// Extra arguments for the credential helper's `login`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub helper_args: Vec<String>,
+
+    /// Environment variables to set for the credential helper's `login`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub login_env: BTreeMap<String, String>,
+
+    /// Extra arguments for ssh; see `--ssh-arg`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ssh_args: Vec<String>,
+
+    /// Template for the description of the key on the remote; see `--key-name`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key_name: Option<String>,
+
+    /// Keyrings on the remote to store the credential in; see `--keyring`. A single keyring may be
+    /// given as a string.
+    #[serde(
+        default,
+        skip_serializing_if = "Vec::is_empty",
+        with = "aspect_reauth::keyctl::keyrings"
+    )]
+    pub keyring: Vec<KeyringTarget>,
+
+    /// Directory to create temporary control sockets in; see `--socket-dir`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub socket_dir: Option<PathBuf>,
+
+    /// Whether control masters outlive the run; see `--socket-mode`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub socket_mode: Option<SocketMode>,
+
+    /// SSH client to use; see `--ssh-binary`.
+    #[serde(alias = "ssh_path", skip_serializing_if = "Option::is_none")]
+    pub ssh_binary: Option<PathBuf>,
+
+    /// How to reach the host; see `--transport`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub transport: Option<Transport>,
+
+    /// Command to reach the host with instead of ssh, taking precedence over `transport`; see
+    /// `--transport-cmd`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub transport_cmd: Option<String>,
+
+    /// Kubernetes namespace of the pod, with the kubectl transport; see `--namespace`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+
+    /// Pod to reach, with the kubectl transport; see `--pod`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pod: Option<String>,
+
+    /// Where to store the credential on the remote; see `--sink`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sink: Option<SinkKind>,
+
+    /// Permissions to give a key added on the remote, as a number (such as `0x3f010000`); see
+    /// `--key-perm`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key_perm: Option<u32>,
+
+    /// Store the credential in one remote shell script; see `--single-round-trip`.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub single_round_trip: bool,
+
+    /// Store the credential in a file on remotes without keyctl; see `--file-fallback`.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub file_fallback: bool,
+
+    /// Add the key from a new session keyring if keyctl is denied permission; see
+    /// `--join-session-keyring`.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub join_session_keyring: bool,
+
+    /// Check once a day whether a newer release is available.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub check_for_updates: bool,
+
+    /// The hosts this user syncs to, offered in a picker when no host is specified.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub hosts: BTreeMap<String, HostConfig>,
+}
+
+/// Settings for one host, from its `[hosts.<name>]` table. Each takes the place of the global
+/// setting of the same name when syncing to that host.
+#[derive(Clone, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct HostConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub remote: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub credential_helper: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ssh_args: Vec<String>,
+
+    #[serde(
+        default,
+        skip_serializing_if = "Vec::is_empty",
+        with = "aspect_reauth::keyctl::keyrings"
+    )]
+    pub keyring: Vec<KeyringTarget>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key_name: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub transport: Option<Transport>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub transport_cmd: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pod: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sink: Option<SinkKind>,
+}
+
+impl Config {
+    pub fn path() -> Option<PathBuf> {
+        match env::var_os(CONFIG_VAR) {
+            Some(path) => Some(PathBuf::from(path)),
+            None => dirs::config_dir().map(|dir| dir.join("aspect-reauth").join("config.toml")),
+        }
+    }
+
+    /// Returns whether there is a configuration file, even an empty one.
+    pub fn exists() -> bool {
+        Self::path().is_some_and(|path| path.exists())
+    }
+
+    /// Loads the configuration, treating a missing file as empty.
+    pub fn load() -> Result<Self> {
+        let Some(path) = Self::path() else {
+            return Ok(Self::default());
+        };
+        match fs::read_to_string(&path) {
+            Ok(text) => {
+                toml::from_str(&text).with_context(|| format!("failed to parse {}", path.display()))
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e).with_context(|| format!("failed to read {}", path.display())),
+        }
+    }
+
+    /// Returns the configuration for syncing to `host`: the global settings, with those in its
+    /// `[hosts.<name>]` table in their place.
+    pub fn for_host(mut self, host: &str) -> Self {
+        let Some(profile) = self.hosts.get(host).cloned() else {
+            return self;
+        };
+        self.remote = profile.remote.or(self.remote);
+        self.credential_helper = profile.credential_helper.or(self.credential_helper);
+        if !profile.ssh_args.is_empty() {
+            self.ssh_args = profile.ssh_args;
+        }
+        if !profile.keyring.is_empty() {
+            self.keyring = profile.keyring;
+        }
+        self.key_name = profile.key_name.or(self.key_name);
+        // The two are one setting: a host that says how to reach it says so completely.
+        if profile.transport.is_some() || profile.transport_cmd.is_some() {
+            self.transport = profile.transport;
+            self.transport_cmd = profile.transport_cmd;
+        }
+        if profile.namespace.is_some() || profile.pod.is_some() {
+            self.namespace = profile.namespace;
+            self.pod = profile.pod;
+        }
+        self.sink = profile.sink.or(self.sink);
+        self
+    }
+
+    /// Writes the configuration file, returning where it went.
+    pub fn save(&self) -> Result<PathBuf> {
+        let path = Self::path().context("cannot find the configuration directory")?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("failed to create {}", dir.display()))?;
+        }
+        let text = toml::to_string_pretty(self).context("failed to serialize configuration")?;
+        fs::write(&path, text).with_context(|| format!("failed to write {}", path.display()))?;
+        Ok(path)
+    }
+}
@@ -0,0 +1,305 @@
+// Copyright 2026 Stairwell, Inc.
+// Author: mrdomino@stairwell.com
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The control socket of `aspect-reauth watch`, through which a plain sync to the same host asks
+//! the running watch to sync now rather than opening a second connection and racing it.
+//!
+//! A request is one JSON object, sent before the client shuts down its side of the socket; the
+//! reply is another, sent once the pass it asked for is done.
+
+use std::{
+    collections::BTreeMap,
+    time::{Duration, UNIX_EPOCH},
+};
+
+use anyhow::Result;
+use aspect_reauth::flow::Outcome;
+use serde::{Deserialize, Serialize};
+
+/// How long the watch waits for a client to finish sending its request.
+#[cfg(unix)]
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A request to sync now.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Request {
+    pub force_local: bool,
+    pub force_remote: bool,
+    /// The settings the client would have synced with, which the watch's must match.
+    pub settings: Settings,
+}
+
+/// The settings that decide where and how a sync goes, by the flag that sets each, so that a watch
+/// only syncs for a client that would have synced the same way.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Settings(BTreeMap<String, String>);
+
+impl<const N: usize> From<[(&str, String); N]> for Settings {
+    fn from(settings: [(&str, String); N]) -> Self {
+        Settings(
+            settings
+                .into_iter()
+                .map(|(flag, value)| (flag.to_owned(), value))
+                .collect(),
+        )
+    }
+}
+
+impl Settings {
+    /// The flags whose settings differ between `self` and `other`.
+    pub fn differences<'a>(&'a self, other: &'a Settings) -> Vec<&'a str> {
+        let mut flags: Vec<&str> = self
+            .0
+            .iter()
+            .filter(|(flag, value)| other.0.get(*flag) != Some(value))
+            .chain(
+                other
+                    .0
+                    .iter()
+                    .filter(|(flag, _)| !self.0.contains_key(*flag)),
+            )
+            .map(|(flag, _)| flag.as_str())
+            .collect();
+        flags.sort_unstable();
+        flags
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Reply {
+    Done {
+        logged_in: bool,
+        synced: bool,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        key: Option<i64>,
+        /// When the credential expires, in seconds since the Unix epoch.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        expires: Option<u64>,
+    },
+    Failed {
+        error: String,
+    },
+    /// The request was not acted on, because the client's settings differ from the watch's.
+    Refused {
+        reason: String,
+    },
+}
+
+impl From<&Result<Outcome>> for Reply {
+    fn from(result: &Result<Outcome>) -> Self {
+        match result {
+            Ok(outcome) => Reply::Done {
+                logged_in: outcome.logged_in,
+                synced: outcome.synced,
+                key: outcome.key,
+                expires: outcome.expires.map(|expires| {
+                    expires
+                        .duration_since(UNIX_EPOCH)
+                        .unwrap_or_default()
+                        .as_secs()
+                }),
+            },
+            Err(e) => Reply::Failed {
+                error: format!("{e:#}"),
+            },
+        }
+    }
+}
+
+impl From<Reply> for Result<Outcome> {
+    fn from(reply: Reply) -> Self {
+        match reply {
+            Reply::Done {
+                logged_in,
+                synced,
+                key,
+                expires,
+            } => Ok(Outcome {
+                logged_in,
+                synced,
+                key,
+                expires: expires.map(|secs| UNIX_EPOCH + Duration::from_secs(secs)),
+            }),
+            Reply::Failed { error } => Err(anyhow::anyhow!("the running watch failed: {error}")),
+            Reply::Refused { reason } => Err(anyhow::anyhow!("{reason}")),
+        }
+    }
+}
+
+#[cfg(unix)]
+pub use unix::{Listener, delegate};
+
+#[cfg(unix)]
+mod unix {
+    use std::{fs, path::PathBuf};
+
+    use anyhow::{Context, Result, bail};
+    use aspect_reauth::{flow::Outcome, ssh_mux::MAX_SOCKET_PATH, state::State};
+    use smol::{
+        Timer, future,
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::unix::{UnixListener, UnixStream},
+    };
+
+    use super::{REQUEST_TIMEOUT, Reply, Request};
+
+    /// The socket a watch listens on, which is removed when it stops.
+    pub struct Listener {
+        socket: UnixListener,
+        path: PathBuf,
+    }
+
+    /// A request accepted by a `Listener`, to be answered once the pass it asked for is done.
+    pub struct Pending {
+        stream: UnixStream,
+        pub request: Request,
+    }
+
+    impl Listener {
+        /// Listens for requests to sync `host`, or returns `None` if there is nowhere to put the
+        /// socket. Fails if another watch is already listening for the same host.
+        pub fn bind(host: &str) -> Result<Option<Self>> {
+            let Some(path) = State::watch_socket(host) else {
+                return Ok(None);
+            };
+            let len = path.as_os_str().len();
+            if len > MAX_SOCKET_PATH {
+                bail!(
+                    "the watch's control socket {} would have a {len}-character path, over the \
+                     limit of {MAX_SOCKET_PATH}; set $XDG_STATE_HOME to a shorter directory",
+                    path.display()
+                );
+            }
+            let dir = path.parent().expect("socket path has a parent");
+            fs::create_dir_all(dir)
+                .with_context(|| format!("failed to create {}", dir.display()))?;
+            if path.exists() {
+                if std::os::unix::net::UnixStream::connect(&path).is_ok() {
+                    bail!("another watch is already keeping {host} in sync");
+                }
+                // Left behind by a watch that did not get to clean up after itself.
+                fs::remove_file(&path)
+                    .with_context(|| format!("failed to remove {}", path.display()))?;
+            }
+            let socket = UnixListener::bind(&path)
+                .with_context(|| format!("failed to listen on {}", path.display()))?;
+            Ok(Some(Listener { socket, path }))
+        }
+
+        /// Waits for the next well-formed request. Clients that fail to send one are dropped.
+        pub async fn accept(&self) -> Pending {
+            loop {
+                let Ok((mut stream, _)) = self.socket.accept().await else {
+                    continue;
+                };
+                let read = async {
+                    let mut bytes = Vec::new();
+                    stream.read_to_end(&mut bytes).await.ok()?;
+                    serde_json::from_slice(&bytes).ok()
+                };
+                let timeout = async {
+                    Timer::after(REQUEST_TIMEOUT).await;
+                    None
+                };
+                if let Some(request) = future::or(read, timeout).await {
+                    return Pending { stream, request };
+                }
+            }
+        }
+    }
+
+    impl Drop for Listener {
+        fn drop(&mut self) {
+            _ = fs::remove_file(&self.path);
+        }
+    }
+
+    impl Pending {
+        /// Answers the request with the result of the pass it asked for. A client that has gone
+        /// away in the meantime is not an error.
+        pub async fn reply(self, result: &Result<Outcome>) {
+            self.send(&Reply::from(result)).await;
+        }
+
+        /// Answers the request without acting on it, for `reason`.
+        pub async fn refuse(self, reason: String) {
+            self.send(&Reply::Refused { reason }).await;
+        }
+
+        async fn send(mut self, reply: &Reply) {
+            let reply = serde_json::to_vec(reply).expect("reply serializes");
+            _ = self.stream.write_all(&reply).await;
+        }
+    }
+
+    /// Asks the watch running for `host`, if there is one, to sync now with `request`, and returns
+    /// what came of it. Returns `None` if no watch is listening.
+    pub async fn delegate(host: &str, request: Request) -> Option<Result<Outcome>> {
+        let path = State::watch_socket(host)?;
+        let mut stream = UnixStream::connect(&path).await.ok()?;
+        let exchange = async {
+            let bytes = serde_json::to_vec(&request)?;
+            stream.write_all(&bytes).await?;
+            stream.shutdown(std::net::Shutdown::Write)?;
+            let mut reply = Vec::new();
+            stream.read_to_end(&mut reply).await?;
+            Ok::<_, anyhow::Error>(serde_json::from_slice::<Reply>(&reply)?)
+        };
+        Some(match exchange.await {
+            Ok(reply) => reply.into(),
+            Err(e) => Err(e.context(format!("failed to talk to the watch at {}", path.display()))),
+        })
+    }
+}
+
+/// Without Unix sockets there is no control socket: a watch only keeps to its schedule, and a
+/// plain sync always runs on its own.
+#[cfg(not(unix))]
+pub use fallback::{Listener, delegate};
+
+#[cfg(not(unix))]
+mod fallback {
+    use anyhow::Result;
+    use aspect_reauth::flow::Outcome;
+
+    use super::Request;
+
+    pub enum Listener {}
+
+    pub struct Pending {
+        pub request: Request,
+    }
+
+    impl Listener {
+        pub fn bind(_host: &str) -> Result<Option<Self>> {
+            Ok(None)
+        }
+
+        pub async fn accept(&self) -> Pending {
+            match *self {}
+        }
+    }
+
+    impl Pending {
+        pub async fn reply(self, _result: &Result<Outcome>) {}
+
+        pub async fn refuse(self, _reason: String) {}
+    }
+
+    pub async fn delegate(_host: &str, _request: Request) -> Option<Result<Outcome>> {
+        None
+    }
+}
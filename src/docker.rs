@@ -0,0 +1,201 @@
+// Copyright 2026 Stairwell, Inc.
+// Author: mrdomino@stairwell.com
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{collections::HashMap, env, path::PathBuf};
+
+use anyhow::{Context, Result};
+use aspect_reauth::transport::{Local, RemoteTransport};
+use base64::{Engine, prelude::BASE64_STANDARD};
+use secrecy::{ExposeSecret, SecretString};
+use serde::Deserialize;
+use zeroize::{Zeroize, Zeroizing};
+
+/// The subset of `~/.docker/config.json` we need to locate a registry's credentials.
+#[derive(Default, Deserialize)]
+struct DockerConfig {
+    #[serde(default)]
+    auths: HashMap<String, AuthEntry>,
+    #[serde(default, rename = "credsStore")]
+    creds_store: Option<String>,
+    #[serde(default, rename = "credHelpers")]
+    cred_helpers: HashMap<String, String>,
+}
+
+#[derive(Deserialize)]
+struct AuthEntry {
+    auth: Option<String>,
+}
+
+impl Drop for AuthEntry {
+    fn drop(&mut self) {
+        self.auth.zeroize();
+    }
+}
+
+/// The response of `docker-credential-<helper> get`.
+#[derive(Deserialize)]
+struct HelperCredential {
+    #[serde(rename = "Username")]
+    username: String,
+    #[serde(rename = "Secret")]
+    secret: String,
+}
+
+impl Drop for HelperCredential {
+    fn drop(&mut self) {
+        self.secret.zeroize();
+    }
+}
+
+struct RegistryCredential {
+    username: String,
+    secret: SecretString,
+}
+
+/// Copies the local credentials for each of `registries` into the remote's docker credential
+/// store by way of `docker login --password-stdin`, so that the remote's own configured
+/// credential store (if any) is the one that ends up holding them.
+pub async fn sync_registries<T: RemoteTransport>(
+    transport: &T,
+    registries: &[String],
+) -> Result<()> {
+    let config = read_config().await?;
+    for registry in registries {
+        let cred = config
+            .credential(registry)
+            .await
+            .with_context(|| format!("failed to get local credentials for {registry}"))?;
+        let output = transport
+            .run(
+                "docker",
+                &[
+                    "login",
+                    "--username",
+                    &cred.username,
+                    "--password-stdin",
+                    registry,
+                ],
+                cred.secret.expose_secret().as_bytes(),
+            )
+            .await?;
+        if !output.status.success() {
+            anyhow::bail!(
+                "{}docker login {}: {}\n\n{}",
+                transport.prefix(),
+                registry,
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim(),
+            );
+        }
+    }
+    Ok(())
+}
+
+fn config_path() -> Option<PathBuf> {
+    match env::var_os("DOCKER_CONFIG") {
+        Some(dir) => Some(PathBuf::from(dir)),
+        None => env::home_dir().map(|home| home.join(".docker")),
+    }
+    .map(|dir| dir.join("config.json"))
+}
+
+async fn read_config() -> Result<DockerConfig> {
+    let Some(path) = config_path() else {
+        return Ok(DockerConfig::default());
+    };
+    match smol::fs::read(&path).await.map(Zeroizing::new) {
+        Ok(bytes) => serde_json::from_slice(&bytes)
+            .with_context(|| format!("failed to parse {}", path.display())),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(DockerConfig::default()),
+        Err(e) => Err(e).with_context(|| format!("failed to read {}", path.display())),
+    }
+}
+
+/// Reduces a registry reference to its bare hostname, so that e.g. `ghcr.io` matches an `auths`
+/// entry keyed by `https://ghcr.io/v2/`. Docker Hub is special-cased to its legacy index URL.
+fn normalize(registry: &str) -> &str {
+    let host = registry
+        .trim_start_matches("https://")
+        .trim_start_matches("http://");
+    let host = host.split('/').next().unwrap_or(host);
+    match host {
+        "docker.io" | "registry-1.docker.io" => "index.docker.io",
+        _ => host,
+    }
+}
+
+/// The server address docker keys a registry's credentials by, in `auths` and in what it asks a
+/// credential helper for: the legacy index URL for Docker Hub, and the bare hostname otherwise.
+fn server_address(registry: &str) -> &str {
+    match normalize(registry) {
+        "index.docker.io" => "https://index.docker.io/v1/",
+        host => host,
+    }
+}
+
+impl DockerConfig {
+    async fn credential(&self, registry: &str) -> Result<RegistryCredential> {
+        let host = normalize(registry);
+        let helper = self
+            .cred_helpers
+            .iter()
+            .find(|(k, _)| normalize(k) == host)
+            .map(|(_, v)| v)
+            .or(self.creds_store.as_ref());
+        if let Some(helper) = helper {
+            return helper_get(helper, server_address(registry)).await;
+        }
+        let auth = self
+            .auths
+            .iter()
+            .find(|(k, _)| normalize(k) == host)
+            .and_then(|(_, v)| v.auth.as_deref())
+            .with_context(|| format!("no credentials for {registry} in docker config"))?;
+        let decoded = Zeroizing::new(
+            BASE64_STANDARD
+                .decode(auth)
+                .context("failed to decode docker auth entry")?,
+        );
+        let decoded = std::str::from_utf8(&decoded).context("docker auth entry is not UTF-8")?;
+        let (username, secret) = decoded
+            .split_once(':')
+            .context("malformed docker auth entry")?;
+        Ok(RegistryCredential {
+            username: username.into(),
+            secret: secret.into(),
+        })
+    }
+}
+
+/// Asks `docker-credential-<helper>` for the credential it keeps for `server`.
+async fn helper_get(helper: &str, server: &str) -> Result<RegistryCredential> {
+    let program = format!("docker-credential-{helper}");
+    let output = Local.run(&program, &["get"], server.as_bytes()).await?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "{} get: {}\n\n{}",
+            program,
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim(),
+        );
+    }
+    let stdout = Zeroizing::new(output.stdout);
+    let cred: HelperCredential = serde_json::from_slice(&stdout)
+        .with_context(|| format!("failed to parse {program} output"))?;
+    Ok(RegistryCredential {
+        username: cred.username.clone(),
+        secret: cred.secret.as_str().into(),
+    })
+}
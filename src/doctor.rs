@@ -0,0 +1,244 @@
+// Copyright 2026 Stairwell, Inc.
+// Author: mrdomino@stairwell.com
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! `aspect-reauth doctor`: explains how a sync to a host would go, and checks that everything it
+//! needs is in place, with a hint at what to do about anything that is not.
+
+use std::{
+    env,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+use anstream::println;
+use anyhow::Result;
+use aspect_reauth::{
+    human,
+    sink::SinkKind,
+    ssh_mux::config::{self, Connection, ControlPersist, SshConfig},
+    transport::RemoteTransport,
+};
+use clap::Args;
+use clap_complete::ArgValueCandidates;
+
+use crate::{connect, disconnect, status::Report};
+
+/// The exit status of a shell that could not find the command.
+const NOT_FOUND: i32 = 127;
+
+#[derive(Args, Clone)]
+pub struct DoctorArgs {
+    /// SSH hostname to diagnose [default: from the config file, or devbox]
+    #[arg(env = "ASPECT_REAUTH_HOST", add = ArgValueCandidates::new(crate::completions::hosts))]
+    pub host: Option<String>,
+}
+
+/// Diagnoses the host in `args`, which has been set up as for a sync to it. Exits with 1 if any
+/// check fails.
+pub async fn run(args: &crate::Args) -> Result<ExitCode> {
+    let host = args.host();
+    // ssh's own configuration only matters when it is ssh that reaches the host.
+    let mux = match &args.transport_cmd {
+        Some(template) => {
+            println!("{host} is reached with: {template}\n");
+            None
+        }
+        None => {
+            let resolved = config::resolve(args.ssh(), host, &args.ssh_args).await;
+            let mux = resolved.as_deref().map(SshConfig::parse);
+            if let (Some(resolved), Some(mux)) = (&resolved, &mux) {
+                print_connection(host, &Connection::parse(resolved), mux);
+                println!();
+            }
+            mux
+        }
+    };
+
+    println!("Checks:");
+    let mut report = Report::default();
+    let (name, client, hint) = match &args.transport_cmd {
+        Some(template) => (
+            "transport",
+            Path::new(template.program()),
+            "install it, or change --transport or --transport-cmd",
+        ),
+        None => (
+            "ssh",
+            args.ssh(),
+            "install OpenSSH or point --ssh-binary at it",
+        ),
+    };
+    match find_program(client) {
+        Some(path) => report.pass(name, path.display()),
+        None => {
+            report.fail(name, format!("{} not found; {hint}", client.display()));
+            return Ok(ExitCode::FAILURE);
+        }
+    }
+    let helper = &args.credential_helper;
+    match find_program(Path::new(helper)) {
+        Some(path) => report.pass("local helper", path.display()),
+        None => report.fail(
+            "local helper",
+            format!("{helper} not found on PATH; install it, or set credential_helper"),
+        ),
+    }
+    match &mux {
+        _ if args.transport_cmd.is_some() => {}
+        None => report.fail(
+            "ssh config",
+            format!("ssh -G {host} failed; check ~/.ssh/config"),
+        ),
+        Some(mux) if !mux.reuses_master() => report.note(
+            "multiplexing",
+            "every run opens a new connection; see `aspect-reauth config suggest-ssh`",
+        ),
+        Some(_) => report.pass("multiplexing", "connections are reused"),
+    }
+
+    let ssh = match connect(args).await {
+        Ok(ssh) => ssh,
+        Err(e) => {
+            report.fail("connection", format!("{e:#}"));
+            return Ok(ExitCode::FAILURE);
+        }
+    };
+    report.pass("connection", format!("connected to {host}"));
+    let elsewhere = match args.sink.unwrap_or_default() {
+        SinkKind::Keyctl => None,
+        SinkKind::File => Some("not used; the credential goes in a file"),
+        SinkKind::SecretTool => Some("not used; the credential goes in the Secret Service"),
+        SinkKind::SystemdCreds => Some("not used; the credential is encrypted with systemd-creds"),
+        SinkKind::Security => Some("not used; the credential goes in the login keychain"),
+        SinkKind::CredentialManager => {
+            Some("not used; the credential goes in the Windows Credential Manager")
+        }
+    };
+    if let Some(note) = elsewhere {
+        report.note("keyrings", note);
+    } else {
+        for target in args.sync_options().keyrings {
+            let name = format!("{target} keyring");
+            let keyring = match target.resolve(&ssh).await {
+                Ok(keyring) => keyring,
+                Err(e) => {
+                    report.fail(&name, format!("{e:#}"));
+                    continue;
+                }
+            };
+            match ssh.run("keyctl", &["rdescribe", &keyring], b"").await {
+                Ok(output) if output.status.success() => report.pass(&name, "available"),
+                Ok(output) if output.status.code() == Some(NOT_FOUND) => report.fail(
+                    &name,
+                    format!("keyctl not found on {host}; install keyutils there"),
+                ),
+                Ok(output) => report.fail(
+                    &name,
+                    format!(
+                        "not available on {host}; is the kernel built with keyrings, and is this a \
+                         container that hides them?\n{}",
+                        String::from_utf8_lossy(&output.stderr).trim()
+                    ),
+                ),
+                Err(e) => report.fail(&name, format!("{e:#}")),
+            }
+        }
+    }
+
+    let request = format!(r#"{{"uri":"https://{}"}}"#, args.remote);
+    match ssh.run(helper, &["get"], request.as_bytes()).await {
+        Ok(output) if output.status.code() == Some(NOT_FOUND) => report.fail(
+            "remote helper",
+            format!("{helper} not found on {host}; install it there"),
+        ),
+        Ok(_) => report.pass("remote helper", "installed"),
+        Err(e) => report.fail("remote helper", format!("{e:#}")),
+    }
+
+    let code = if report.failed {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    };
+    disconnect(ssh, Ok(code)).await
+}
+
+/// Finds `program` as the shell would: as given if it is a path, else on `$PATH`.
+fn find_program(program: &Path) -> Option<PathBuf> {
+    if program.components().count() > 1 {
+        return program.is_file().then(|| program.into());
+    }
+    env::split_paths(&env::var_os("PATH")?)
+        .map(|dir| dir.join(program))
+        .find(|path| path.is_file())
+}
+
+/// Prints how ssh will reach `host`, so that it is clear at a glance why a host is slow or
+/// unreachable.
+fn print_connection(host: &str, conn: &Connection, mux: &SshConfig) {
+    println!("Connection to {host}:");
+    let row = |name: &str, value: &str| println!("  {name:<16}{value}");
+    row("hostname", conn.hostname.as_deref().unwrap_or(host));
+    row("user", conn.user.as_deref().unwrap_or("(default)"));
+    row(
+        "port",
+        &conn.port.map_or("(default)".into(), |p| p.to_string()),
+    );
+    if !conn.proxy_jump.is_empty() {
+        row("proxy jump", &conn.proxy_jump.join(" -> "));
+    }
+    if let Some(command) = &conn.proxy_command {
+        row("proxy command", command);
+    }
+    for (i, file) in conn.identity_files.iter().enumerate() {
+        let note = if expand_home(file).exists() {
+            ""
+        } else {
+            " (missing)"
+        };
+        row(
+            if i == 0 { "identity files" } else { "" },
+            &format!("{file}{note}"),
+        );
+    }
+    let persist = match mux.control_persist {
+        ControlPersist::No => "no".into(),
+        ControlPersist::Yes => "yes".into(),
+        ControlPersist::Seconds(secs) => human::duration(std::time::Duration::from_secs(secs)),
+    };
+    row(
+        "multiplexing",
+        &format!(
+            "ControlMaster {}, ControlPersist {persist}, ControlPath {}",
+            format!("{:?}", mux.control_master).to_lowercase(),
+            mux.control_path.as_deref().unwrap_or("none"),
+        ),
+    );
+    row(
+        "aspect-reauth",
+        if mux.reuses_master() {
+            "will share your control master"
+        } else {
+            "will start its own control master for the run"
+        },
+    );
+}
+
+fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), env::home_dir()) {
+        (Some(rest), Some(home)) => home.join(rest),
+        _ => PathBuf::from(path),
+    }
+}
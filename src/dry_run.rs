@@ -0,0 +1,681 @@
+// Copyright 2026 Stairwell, Inc.
+// Author: mrdomino@stairwell.com
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! `--dry-run`: prints what a sync would do without changing anything.
+//!
+//! The sync's own flow is run, with the sink `--sink` chooses, over stand-ins for this machine and
+//! the host that record each command. Those that only look, such as the helper's `get`, are run
+//! for real until the first that would change something; that one and everything after it are
+//! only supposed to have worked. So what is printed is what a sync would run, given what is there
+//! now.
+
+use std::{
+    cell::{Cell, OnceCell, RefCell},
+    ffi::{OsStr, OsString},
+    process::Output,
+};
+
+use anstream::println;
+use anyhow::Result;
+use aspect_reauth::{
+    ReauthFlow, SyncOptions, helper,
+    helper::Check,
+    human, keyctl,
+    probe::{self, Probed},
+    sink::{File, RemoteSink, SinkKind, SystemdCreds},
+    source::CredentialSource,
+    ssh_mux::{
+        PERSISTENT_IDLE, SocketMode, SocketStrategy, TEMP_SOCKET_NAME, TEMP_SOCKET_PREFIX,
+        check_args, command_args, exec_args, exit_args, master_args, persistent_socket_dir,
+        remote_command,
+    },
+    transport::{Local, RemoteTransport, exit_status},
+};
+use secrecy::{ExposeSecret, SecretString};
+use zeroize::Zeroizing;
+
+use crate::{Args, connect, disconnect, style};
+
+/// Stands in for the temporary control socket path, which is only chosen when it is created.
+const SOCKET: &str = "<socket>";
+
+/// Stands in for the credential in what is printed: the one read, or one a login would fetch.
+const CREDENTIAL: &str = "<credential>";
+
+/// The serial number keyctl is supposed to give the key it adds, which is shown as `KEY_ID`.
+const KEY: i64 = 536_870_911;
+
+/// What `keyctl get_persistent` is supposed to print, in place of the persistent keyring's ID.
+const PERSISTENT: &str = "PERSISTENT_KEYRING";
+
+pub async fn print(args: &Args) -> Result<()> {
+    let client = match &args.transport_cmd {
+        Some(template) => template.program().into(),
+        None => args.ssh().to_string_lossy(),
+    };
+    let socket_mode = args.socket_mode.unwrap_or_default();
+    let socket_in_dir = match socket_mode {
+        SocketMode::Temporary => args.socket_dir.as_ref().map(|dir| {
+            dir.join(format!("{TEMP_SOCKET_PREFIX}XXXXXX"))
+                .join(TEMP_SOCKET_NAME)
+        }),
+        SocketMode::Persistent => args
+            .socket_dir
+            .clone()
+            .or_else(dirs::cache_dir)
+            .map(|dir| persistent_socket_dir(&dir).join("HASH")),
+    };
+    let socket = match args.create_socket {
+        _ if args.transport_cmd.is_some() => None,
+        SocketStrategy::Never => None,
+        _ => Some(
+            socket_in_dir
+                .as_deref()
+                .map_or(OsStr::new(SOCKET), |path| path.as_os_str()),
+        ),
+    };
+    let ssh = |program: &str, rest: &[&str]| -> Result<Vec<OsString>> {
+        let command = remote_command(program, rest)?;
+        if let Some(template) = &args.transport_cmd {
+            return Ok(os_strings(template.args(args.host(), &command)));
+        }
+        let mut argv = command_args(args.host(), &args.ssh_args, socket, &command);
+        // ssh joins its trailing arguments with spaces anyway, so show plain commands word by
+        // word.
+        let words: Vec<&str> = [program].into_iter().chain(rest.iter().copied()).collect();
+        if command == words.join(" ") {
+            argv.pop();
+            argv.extend(words.into_iter().map(OsString::from));
+        }
+        Ok(argv)
+    };
+    println!("Dry run: nothing will be changed. A sync would:\n");
+    if let Some(template) = &args.transport_cmd {
+        step("Check that the transport command reaches the host");
+        run(
+            &client,
+            &os_strings(template.args(args.host(), "true")),
+            None,
+        );
+    } else if let SocketStrategy::Infer | SocketStrategy::ReuseIfPersist = args.create_socket {
+        if args.create_socket == SocketStrategy::Infer {
+            step("Check for ControlMaster auto, and if unset use a temporary control master");
+        } else {
+            step(
+                "Check for ControlMaster auto and ControlPersist, and if either is unset use a \
+                 temporary control master",
+            );
+        }
+        note("read from the ssh config files, or from ssh -G if they need ssh to evaluate");
+        step("Otherwise check that the master is usable, and if not use a temporary one");
+        run(&client, &user_check_args(args), None);
+    } else if args.create_socket == SocketStrategy::ReuseIfMasterRunning {
+        step("Check for a running control master, and if there is none use a temporary one");
+        run(&client, &user_check_args(args), None);
+    }
+    if let (SocketMode::Persistent, Some(socket)) = (socket_mode, socket) {
+        step("Check for a control master left up by an earlier run");
+        run(
+            &client,
+            &check_args(args.host(), &args.ssh_args, socket),
+            None,
+        );
+        step("If there is none, open the ssh session, starting one");
+    } else if args.transport_cmd.is_none() {
+        step("Open the ssh session");
+    }
+    if args.transport_cmd.is_none() {
+        run(
+            &client,
+            &master_args(args.host(), &args.ssh_args, socket, socket_mode),
+            None,
+        );
+    }
+
+    let log = Log::default();
+    // A persistent master would outlive the dry run, so the checks go over a temporary one; the
+    // persistent master is only planned above.
+    let mut temporary = args.clone();
+    temporary.socket_mode = Some(SocketMode::Temporary);
+    let host = connect(&temporary).await?;
+    let planned = plan(args, &log, &host).await;
+    let changes = disconnect(host, planned).await?;
+    let mut last = None;
+    for Entry { step: what, line } in log.entries.take() {
+        if last.as_ref() != Some(&what) {
+            step(&what);
+            last = Some(what);
+        }
+        match line {
+            Line::Note(what) => note(&what),
+            Line::Run {
+                remote,
+                program,
+                args: words,
+                input,
+            } => {
+                let words: Vec<&str> = words.iter().map(String::as_str).collect();
+                let input = Some(input.as_str()).filter(|input| !input.is_empty());
+                if remote {
+                    run(&client, &ssh(&program, &words)?, input);
+                } else {
+                    run(
+                        &program,
+                        &words.into_iter().map(OsString::from).collect::<Vec<_>>(),
+                        input,
+                    );
+                }
+            }
+        }
+    }
+    if !changes {
+        step("Stop there, since neither credential needs a refresh");
+    }
+
+    for registry in &args.docker_registries {
+        step(&format!(
+            "Sync container registry credentials for {registry}"
+        ));
+        note(&format!("look up {registry} in the local docker config"));
+        run(
+            &client,
+            &ssh(
+                "docker",
+                &[
+                    "login",
+                    "--username",
+                    "USERNAME",
+                    "--password-stdin",
+                    registry,
+                ],
+            )?,
+            Some("<secret>"),
+        );
+    }
+    for git_host in &args.git_hosts {
+        step(&format!("Sync git credentials for {git_host}"));
+        run(
+            "git",
+            &["credential", "fill"].map(OsString::from),
+            Some(&format!("protocol=https host={git_host}")),
+        );
+        run(
+            &client,
+            &ssh("git", &["credential", "approve"])?,
+            Some("<credential>"),
+        );
+    }
+
+    if let (SocketMode::Persistent, Some(_)) = (socket_mode, socket) {
+        step(&format!(
+            "Leave the control master up for later runs, until it has gone unused for {PERSISTENT_IDLE}"
+        ));
+    } else if let Some(socket) = socket {
+        step("Stop the control master");
+        run(
+            &client,
+            &exit_args(args.host(), &args.ssh_args, socket),
+            None,
+        );
+    }
+    if !args.exec.is_empty() {
+        step("Run the command");
+        if args.forward_agent {
+            note("with your ssh agent forwarded to the host");
+        }
+        let argv = match &args.transport_cmd {
+            Some(template) => os_strings(template.args(args.host(), &args.exec.join(" "))),
+            None => exec_args(args.host(), &args.ssh_args, &args.exec, args.forward_agent),
+        };
+        run(&client, &argv, None);
+    }
+    Ok(())
+}
+
+/// The arguments to `ssh -O check` the master at the user's `ControlPath`.
+fn user_check_args(args: &Args) -> Vec<OsString> {
+    let mut argv: Vec<OsString> = args.ssh_args.iter().map(OsString::from).collect();
+    argv.extend(["-Ocheck", "--", args.host()].map(OsString::from));
+    argv
+}
+
+fn os_strings(words: Vec<String>) -> Vec<OsString> {
+    words.into_iter().map(OsString::from).collect()
+}
+
+fn step(what: &str) {
+    println!(
+        "{}# {what}{}",
+        style::COMMENT,
+        style::COMMENT.render_reset()
+    );
+}
+
+fn note(what: &str) {
+    println!(
+        "{}  ({what}){}",
+        style::COMMENT,
+        style::COMMENT.render_reset()
+    );
+}
+
+fn run(program: &str, args: &[OsString], input: Option<&str>) {
+    let mut line = quote(OsStr::new(program));
+    for arg in args {
+        line.push(' ');
+        line.push_str(&quote(arg));
+    }
+    if let Some(input) = input {
+        line.push_str(" <<< ");
+        line.push_str(&quote(OsStr::new(input)));
+    }
+    println!("  {line}");
+}
+
+/// Quotes `arg` for a POSIX shell if it needs it, so that the printed commands can be pasted.
+fn quote(arg: &OsStr) -> String {
+    let arg = arg.to_string_lossy();
+    let safe = |c: char| c.is_ascii_alphanumeric() || "-_=+./:@%,".contains(c);
+    if !arg.is_empty() && arg.chars().all(safe) {
+        arg.into_owned()
+    } else {
+        format!("'{}'", arg.replace('\'', r"'\''"))
+    }
+}
+
+/// What makes a credential stale, as the condition of a step.
+fn stale(args: &Args) -> String {
+    if args.min_validity.is_zero() {
+        "is stale".into()
+    } else {
+        format!(
+            "is stale or expires within {}",
+            human::duration(args.min_validity)
+        )
+    }
+}
+
+/// What a dry run would do, in order.
+#[derive(Default)]
+struct Log {
+    entries: RefCell<Vec<Entry>>,
+    /// The credential a sync would push, once it has been read, to be left out of what is printed.
+    credential: OnceCell<SecretString>,
+}
+
+/// Something a dry run would do, as part of `step`.
+struct Entry {
+    step: String,
+    line: Line,
+}
+
+enum Line {
+    /// Runs `program` with `args` and `input`, on the host if `remote` and otherwise here.
+    Run {
+        remote: bool,
+        program: String,
+        args: Vec<String>,
+        input: String,
+    },
+    /// Something worth knowing about the step that is not a command.
+    Note(String),
+}
+
+/// Runs the sync's flow over stand-ins for this machine and `host`, recording in `log` what it
+/// would do on each. Returns whether it would log in or push anything.
+async fn plan<T: RemoteTransport>(args: &Args, log: &Log, host: &T) -> Result<bool> {
+    let options = args.sync_options();
+    let kind = args.sink.unwrap_or_default();
+    let local = Plan::new(log, &Local, &options, kind);
+    let remote = Plan::new(log, host, &options, kind);
+    let push = if args.force_remote {
+        "Sync the credential to the remote (forced)".into()
+    } else {
+        format!(
+            "Sync the credential to the remote, since the remote credential {}",
+            stale(args)
+        )
+    };
+    let source = Source {
+        args,
+        options: &options,
+        local: &local,
+        push: &push,
+        logged_in: Cell::new(false),
+    };
+    let sink = Sink {
+        sink: Probed::new(args.sink(&options)?, &options),
+        plan: &remote,
+        push: &push,
+    };
+    let flow = ReauthFlow::new(&source, &remote, &sink, &options)
+        .force_local(args.force_local)
+        .force_remote(args.force_remote);
+    if args.ci {
+        return flow.sync_remote().await;
+    }
+    let outcome = flow.run().await?;
+    Ok(outcome.logged_in || outcome.synced)
+}
+
+/// Stands in for this machine or the host in a dry run. Each command run on it is recorded in the
+/// log, as part of the step the flow has reached. One that only looks is run on `real`, unless
+/// something has been supposed already; any other is only supposed to work, and answered as a host
+/// whose credential is stale and that has what `--sink` needs would answer it, or once something
+/// has been stored, as one that has the credential would.
+struct Plan<'a, T> {
+    log: &'a Log,
+    real: &'a T,
+    helper: &'a str,
+    kind: SinkKind,
+    step: RefCell<String>,
+    /// The input of the last command supposed to work, which reading the credential back then
+    /// gives. Once there is one, nothing more is run for real, since the host is no longer as the
+    /// rest of a sync would find it. It may hold the credential, so it is zeroed when dropped.
+    stored: RefCell<Option<Zeroizing<Vec<u8>>>>,
+}
+
+impl<'a, T: RemoteTransport> Plan<'a, T> {
+    fn new(log: &'a Log, real: &'a T, options: &'a SyncOptions, kind: SinkKind) -> Self {
+        Plan {
+            log,
+            real,
+            helper: &options.credential_helper,
+            kind,
+            step: RefCell::default(),
+            stored: RefCell::default(),
+        }
+    }
+
+    /// Records what follows as part of `step`.
+    fn begin(&self, step: String) {
+        self.step.replace(step);
+    }
+
+    fn note(&self, what: String) {
+        self.push(Line::Note(what));
+    }
+
+    fn push(&self, line: Line) {
+        let step = self.step.borrow().clone();
+        self.log.entries.borrow_mut().push(Entry { step, line });
+    }
+
+    /// Answers `program` with `args` as the host would if its credential were stale and it had
+    /// what `--sink` needs, or had the credential once something has been stored.
+    fn suppose(&self, program: &str, args: &[&str]) -> Output {
+        let stored = self.stored.borrow();
+        match (program, args) {
+            (_, ["get"]) if program == self.helper => match *stored {
+                Some(_) => ok(b"{}"),
+                None => failed(br#"{"error":"unauthenticated"}"#, b""),
+            },
+            ("sh", ["-c", script]) if *script == probe::SCRIPT => match self.kind {
+                SinkKind::Security => ok(b"Darwin\n"),
+                SinkKind::CredentialManager => failed(
+                    b"",
+                    b"'sh' is not recognized as an internal or external command",
+                ),
+                _ => {
+                    ok(b"Linux\nhas keyctl\nhas secret-tool\nhas systemd-creds\nhas runtime-dir\n")
+                }
+            },
+            ("sh", ["-c", ..]) if looks(self.helper, program, args) => match &*stored {
+                Some(stored) => ok(stored),
+                None => failed(b"", b""),
+            },
+            // What the single-round-trip script prints; other scripts' output is not looked at.
+            ("sh", _) => ok(format!("key {KEY}\nrun\n{{}}").as_bytes()),
+            ("keyctl", ["search", ..]) => failed(b"", b"keyctl_search: Required key not available"),
+            ("keyctl", ["get_persistent", ..]) => ok(PERSISTENT.as_bytes()),
+            ("keyctl", ["padd" | "session", ..]) => ok(KEY.to_string().as_bytes()),
+            ("uname", _) => ok(b"Linux\n"),
+            _ => ok(b""),
+        }
+    }
+}
+
+impl<T: RemoteTransport> RemoteTransport for Plan<'_, T> {
+    fn host(&self) -> Option<&str> {
+        self.real.host()
+    }
+
+    async fn run(&self, program: &str, args: &[&str], input: &[u8]) -> Result<Output> {
+        let key = KEY.to_string();
+        let shown = Zeroizing::new(String::from_utf8_lossy(input).into_owned());
+        let shown = match self.log.credential.get() {
+            Some(credential) if !credential.expose_secret().is_empty() => {
+                shown.replace(credential.expose_secret(), CREDENTIAL)
+            }
+            _ => shown.to_string(),
+        };
+        self.push(Line::Run {
+            remote: self.real.host().is_some(),
+            program: program.into(),
+            args: args
+                .iter()
+                .map(|&arg| if arg == key { "KEY_ID" } else { arg }.into())
+                .collect(),
+            input: shown.trim_end_matches('\n').into(),
+        });
+        let looks = looks(self.helper, program, args);
+        if looks && self.stored.borrow().is_none() {
+            return self.real.run(program, args, input).await;
+        }
+        if args == ["-c", probe::SCRIPT] {
+            self.note(format!(
+                "supposing the host has what --sink={} needs, as the sync finds out here",
+                self.kind
+            ));
+        }
+        let output = self.suppose(program, args);
+        if !looks {
+            self.stored.replace(Some(Zeroizing::new(input.to_vec())));
+        }
+        Ok(output)
+    }
+}
+
+/// Whether `program` with `args` only looks at what is there, rather than changing anything.
+fn looks(helper: &str, program: &str, args: &[&str]) -> bool {
+    match (program, args) {
+        (_, ["get"]) => program == helper,
+        ("keyctl", [command, ..]) => matches!(*command, "search" | "rdescribe"),
+        ("sh", ["-c", script, ..]) => {
+            *script == probe::SCRIPT
+                || *script == File::read_script()
+                || *script == SystemdCreds::decrypt_script()
+        }
+        ("uname", _) => true,
+        _ => false,
+    }
+}
+
+fn ok(stdout: &[u8]) -> Output {
+    Output {
+        status: exit_status(0),
+        stdout: stdout.to_vec(),
+        stderr: Vec::new(),
+    }
+}
+
+fn failed(stdout: &[u8], stderr: &[u8]) -> Output {
+    Output {
+        status: exit_status(1),
+        stdout: stdout.to_vec(),
+        stderr: stderr.to_vec(),
+    }
+}
+
+/// The source of a dry run: it checks the local credential as a sync would, on `local`, but a
+/// login is only recorded there. The credential is read from the keychain as a sync would read
+/// it, unless a login would fetch a new one first or it is supplied up front, when [`CREDENTIAL`]
+/// stands in for it.
+struct Source<'a> {
+    args: &'a Args,
+    options: &'a SyncOptions,
+    local: &'a Plan<'a, Local>,
+    /// The step the credential is read in, to be pushed to the host.
+    push: &'a str,
+    logged_in: Cell<bool>,
+}
+
+impl Source<'_> {
+    /// Whether the credential is the helper's, in the keychain, rather than one supplied up front.
+    fn helper(&self) -> bool {
+        !self.args.ci && self.args.external_source().is_none()
+    }
+
+    fn account(&self) -> &str {
+        self.args
+            .keyring_account
+            .as_deref()
+            .unwrap_or(&self.args.remote)
+    }
+}
+
+impl CredentialSource for Source<'_> {
+    async fn needs_login(&self) -> Result<bool> {
+        if !self.helper() {
+            return Ok(false);
+        }
+        self.local.begin("Check the local credential".into());
+        helper::needs_refresh(self.local, self.options).await
+    }
+
+    async fn login(&self) -> Result<()> {
+        if !self.helper() {
+            return Ok(());
+        }
+        let args = self.args;
+        self.local.begin(if args.force_local {
+            "Log in locally (forced)".into()
+        } else {
+            format!("Log in locally, since the local credential {}", stale(args))
+        });
+        self.logged_in.set(true);
+        let mut login = vec!["login", args.remote.as_str()];
+        login.extend(args.helper_args.iter().map(String::as_str));
+        if args.login_env.is_empty() {
+            self.local.run(&args.credential_helper, &login, b"").await?;
+        } else {
+            let env: Vec<String> = args
+                .login_env
+                .iter()
+                .map(|(k, v)| format!("{k}={v}"))
+                .collect();
+            let mut argv: Vec<&str> = env.iter().map(String::as_str).collect();
+            argv.push(&args.credential_helper);
+            argv.extend(login);
+            self.local.run("env", &argv, b"").await?;
+        }
+        if let Some(timeout) = args.login_timeout {
+            self.local.note(format!(
+                "give up if it has not finished after {}",
+                human::duration(timeout)
+            ));
+        }
+        let service = args.keyring_service.as_deref().unwrap_or(keyctl::SERVICE);
+        let account = self.account();
+        self.local.note(format!(
+            "read keychain entry {service}/{account} and copy it to aspect-reauth/{account}"
+        ));
+        Ok(())
+    }
+
+    async fn credential(&self) -> Result<SecretString> {
+        self.local.begin(self.push.into());
+        self.local.note(match self.args.external_source() {
+            Some(source) => format!("read the credential from --source={source}"),
+            None if self.args.ci => {
+                "read the credential from $ASPECT_REAUTH_CREDENTIAL or stdin".into()
+            }
+            None => format!("read keychain entry aspect-reauth/{}", self.account()),
+        });
+        if !self.helper() || self.logged_in.get() {
+            return Ok(CREDENTIAL.into());
+        }
+        // Only looked up: reading it through `credential` copies the helper's into our entry.
+        match self.args.keyring(self.options).lookup().await {
+            Ok(found) => {
+                if found.copy {
+                    let service = self
+                        .args
+                        .keyring_service
+                        .as_deref()
+                        .unwrap_or(keyctl::SERVICE);
+                    self.local.note(format!(
+                        "which is missing, so read {service}/{} and would copy the helper's \
+                         credential into the keychain",
+                        self.account()
+                    ));
+                }
+                _ = self.local.log.credential.set(found.credential.clone());
+                Ok(found.credential)
+            }
+            Err(e) => {
+                self.local.note(format!(
+                    "which fails, so {CREDENTIAL} stands in for it: {e:#}"
+                ));
+                Ok(CREDENTIAL.into())
+            }
+        }
+    }
+}
+
+/// The sink of a dry run: `sink`, beginning the steps of the flow it takes part in on `plan`.
+struct Sink<'a, T> {
+    sink: Probed,
+    plan: &'a Plan<'a, T>,
+    /// The step the credential is pushed to the host in.
+    push: &'a str,
+}
+
+impl<U: RemoteTransport> RemoteSink for Sink<'_, U> {
+    async fn preflight<T: RemoteTransport>(&self, transport: &T) -> Result<()> {
+        self.plan.begin(self.push.into());
+        self.sink.preflight(transport).await
+    }
+
+    async fn store<T: RemoteTransport>(
+        &self,
+        transport: &T,
+        credential: &str,
+    ) -> Result<Option<i64>> {
+        self.plan.begin(self.push.into());
+        self.sink.store(transport, credential).await
+    }
+
+    async fn check<T: RemoteTransport>(
+        &self,
+        transport: &T,
+        options: &SyncOptions,
+    ) -> Result<Check> {
+        self.plan.begin("Check the remote credential".into());
+        self.sink.check(transport, options).await
+    }
+
+    async fn store_and_check<T: RemoteTransport>(
+        &self,
+        transport: &T,
+        credential: &str,
+        options: &SyncOptions,
+    ) -> Result<(Option<i64>, Check)> {
+        self.plan.begin(self.push.into());
+        self.sink
+            .store_and_check(transport, credential, options)
+            .await
+    }
+}
@@ -0,0 +1,98 @@
+// Copyright 2026 Stairwell, Inc.
+// Author: mrdomino@stairwell.com
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The kinds of failure a caller may want to act on, rather than only report.
+//!
+//! The library's functions return `anyhow::Result`, and a `ReauthError` turns up somewhere in the
+//! chain of the error, under whatever context was added on the way out, so look for it with
+//! `downcast_ref::<ReauthError>()` and match on that. Its own source, if any, stays in the chain
+//! after it.
+
+use std::{io, time::Duration};
+
+use crate::human;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ReauthError {
+    /// ssh could not be started, or could not connect to the host, or the connection dropped.
+    #[error("{message}")]
+    Ssh {
+        message: String,
+        #[source]
+        source: Option<io::Error>,
+    },
+    /// The credential helper could not be started to log in.
+    #[error("failed to spawn {helper}")]
+    HelperSpawn {
+        helper: String,
+        #[source]
+        source: io::Error,
+    },
+    /// The credential helper's login failed.
+    #[error("{0}")]
+    Login(String),
+    /// The helper's login did not finish within the timeout, and was killed.
+    #[error("login did not finish within {}", human::duration(*.0))]
+    LoginTimedOut(Duration),
+    /// The local keychain could not be read or written.
+    #[error("{message}")]
+    Keyring {
+        message: String,
+        #[source]
+        source: keyring::Error,
+    },
+    /// `keyctl` failed on the remote, for the reason `kind` gives if it is one to act on.
+    #[error("{message}")]
+    Keyctl {
+        message: String,
+        kind: KeyctlErrorKind,
+    },
+    /// Another sink failed to store the credential on the remote.
+    #[error("{0}")]
+    Store(String),
+    /// The run did not finish within the time it was given.
+    #[error("gave up after {}", human::duration(*.0))]
+    TimedOut(Duration),
+}
+
+/// Why `keyctl` failed on the remote, where a caller may do something about it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeyctlErrorKind {
+    /// There is no keyctl on the remote to run.
+    NotInstalled,
+    /// keyctl was denied permission to the keyring, as when the ssh session has no session
+    /// keyring of its own.
+    PermissionDenied,
+    /// Any other failure.
+    Other,
+}
+
+impl ReauthError {
+    /// An ssh failure with no underlying error, such as ssh exiting with an error.
+    pub fn ssh(message: impl Into<String>) -> Self {
+        ReauthError::Ssh {
+            message: message.into(),
+            source: None,
+        }
+    }
+
+    /// A keyctl failure of no kind in particular.
+    pub fn keyctl(message: impl Into<String>) -> Self {
+        ReauthError::Keyctl {
+            message: message.into(),
+            kind: KeyctlErrorKind::Other,
+        }
+    }
+}
@@ -0,0 +1,67 @@
+// Copyright 2026 Stairwell, Inc.
+// Author: mrdomino@stairwell.com
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Running a command on the host once its credential is fresh, so that e.g. `aspect-reauth devbox
+//! -- bazel build //...` never starts a build with a stale credential.
+
+use std::process::{ExitCode, ExitStatus};
+
+use anstream::eprintln;
+use anyhow::{Context, Result};
+use aspect_reauth::ssh_mux::exec_args;
+use smol::process::Command;
+
+use crate::{Args, style};
+
+/// Runs `args.exec` on the host with the terminal attached, returning its exit code.
+pub async fn run(args: &Args) -> Result<ExitCode> {
+    if let Some(template) = &args.transport_cmd {
+        if args.forward_agent {
+            anyhow::bail!("--forward-agent needs ssh, not --transport-cmd");
+        }
+        // The command is joined with spaces for the remote shell, as ssh would.
+        let status = Command::new(template.program())
+            .args(template.args(args.host(), &args.exec.join(" ")))
+            .status()
+            .await
+            .with_context(|| format!("failed to run {}", template.program()))?;
+        return Ok(exit_code(status));
+    }
+    if args.forward_agent {
+        eprintln!(
+            "{}warning:{} forwarding your ssh agent to {}; while the command runs, anyone with \
+             root there can use your keys",
+            style::WARNING,
+            style::WARNING.render_reset(),
+            args.host()
+        );
+    }
+    let status = Command::new(args.ssh())
+        .args(exec_args(
+            args.host(),
+            &args.ssh_args,
+            &args.exec,
+            args.forward_agent,
+        ))
+        .status()
+        .await
+        .context("failed to run ssh")?;
+    Ok(exit_code(status))
+}
+
+/// Like ssh itself, exits with the remote command's status, or 255 if it did not get that far.
+fn exit_code(status: ExitStatus) -> ExitCode {
+    ExitCode::from(status.code().map_or(255, |code| code as u8))
+}
@@ -0,0 +1,67 @@
+// Copyright 2026 Stairwell, Inc.
+// Author: mrdomino@stairwell.com
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! `aspect-reauth export-token`: prints the credential for another tool to read.
+//!
+//! This exists so that nobody has to script against the keychain entries themselves. Since it
+//! puts the credential in the clear, it has to be asked for explicitly, only ever writes it to a
+//! pipe or file, and never mentions it anywhere else.
+
+use std::io::{IsTerminal, Write};
+
+use anyhow::{Context, Result};
+use aspect_reauth::{
+    SyncOptions,
+    source::{CredentialSource, Keyring},
+};
+use clap::Args;
+use secrecy::ExposeSecret;
+
+#[derive(Args, Clone)]
+pub struct ExportTokenArgs {
+    /// Acknowledge that the credential is printed in the clear, and that anything that can read
+    /// it can act as you
+    #[arg(long)]
+    i_understand_the_risk: bool,
+}
+
+pub async fn run(args: &ExportTokenArgs, options: &SyncOptions, source: &Keyring) -> Result<()> {
+    if !args.i_understand_the_risk {
+        anyhow::bail!(
+            "export-token prints your Aspect credential in the clear; pass \
+             --i-understand-the-risk if that is what you want"
+        );
+    }
+    let mut stdout = std::io::stdout().lock();
+    if stdout.is_terminal() {
+        anyhow::bail!(
+            "refusing to print the credential to a terminal; pipe it into the command that needs \
+             it, e.g. `aspect-reauth export-token --i-understand-the-risk | docker login \
+             --password-stdin`"
+        );
+    }
+    // Logging in here would have the helper write to the same stdout as the credential.
+    if source.needs_login().await? {
+        anyhow::bail!(
+            "the local credential needs a refresh; run `{} login {}` (or a sync) first",
+            options.credential_helper,
+            options.remote
+        );
+    }
+    let credential = source.credential().await?;
+    writeln!(stdout, "{}", credential.expose_secret())
+        .and_then(|()| stdout.flush())
+        .context("failed to write the credential to stdout")
+}
@@ -0,0 +1,203 @@
+// Copyright 2026 Stairwell, Inc.
+// Author: mrdomino@stairwell.com
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::time::{Duration, SystemTime};
+
+use anyhow::Result;
+use secrecy::ExposeSecret;
+use smol::future;
+
+use crate::{
+    SyncOptions, helper, sink::RemoteSink, source::CredentialSource, token,
+    transport::RemoteTransport,
+};
+
+/// The decision logic of a sync, independent of where the credential comes from, how the remote
+/// is reached, and where the credential ends up there.
+///
+/// The local credential is refreshed if it is stale (or `force_local` is set), and then pushed to
+/// the remote if the remote's is stale (or `force_remote` is set). After a push, the remote is
+/// checked again to make sure the push took.
+///
+/// The two sides are independent until the push, so `run` asks both whether they are stale at
+/// once, and readies the sink for the push while the source logs in.
+pub struct ReauthFlow<'a, S, T, K> {
+    source: &'a S,
+    transport: &'a T,
+    sink: &'a K,
+    options: &'a SyncOptions,
+    force_local: bool,
+    force_remote: bool,
+}
+
+/// What a `ReauthFlow` did.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Outcome {
+    /// Whether the source was logged in again.
+    pub logged_in: bool,
+    /// Whether the credential was pushed to the remote.
+    pub synced: bool,
+    /// The serial number of the key the credential was stored as, if it was pushed to a sink that
+    /// reports one.
+    pub key: Option<i64>,
+    /// When the remote's credential expires, if the remote helper says or the credential pushed
+    /// is a JWT that does.
+    pub expires: Option<SystemTime>,
+}
+
+impl<'a, S, T, K> ReauthFlow<'a, S, T, K>
+where
+    S: CredentialSource,
+    T: RemoteTransport,
+    K: RemoteSink,
+{
+    pub fn new(source: &'a S, transport: &'a T, sink: &'a K, options: &'a SyncOptions) -> Self {
+        ReauthFlow {
+            source,
+            transport,
+            sink,
+            options,
+            force_local: false,
+            force_remote: false,
+        }
+    }
+
+    /// Log in again even if the local credential is still valid.
+    pub fn force_local(mut self, force: bool) -> Self {
+        self.force_local = force;
+        self
+    }
+
+    /// Push to the remote even if the remote credential is still valid.
+    pub fn force_remote(mut self, force: bool) -> Self {
+        self.force_remote = force;
+        self
+    }
+
+    pub async fn run(&self) -> Result<Outcome> {
+        let (needs_login, check) = future::zip(self.needs_login(), self.check_remote()).await;
+        let (needs_login, check) = (needs_login?, check?);
+        let needs_push = check.as_ref().is_none_or(|check| check.needs_refresh);
+        if needs_login {
+            let preflight = async {
+                if needs_push {
+                    self.sink.preflight(self.transport).await
+                } else {
+                    Ok(())
+                }
+            };
+            let (login, preflight) = future::zip(self.source.login(), preflight).await;
+            login?;
+            preflight?;
+        }
+        let (synced, key, expires) = match check {
+            Some(check) if !check.needs_refresh => (false, None, check.expires),
+            _ => self.push_with_expiry().await?,
+        };
+        Ok(Outcome {
+            logged_in: needs_login,
+            synced,
+            key,
+            expires,
+        })
+    }
+
+    async fn needs_login(&self) -> Result<bool> {
+        Ok(self.force_local || self.source.needs_login().await?)
+    }
+
+    /// Asks the remote helper whether the remote's credential is stale, unless `force_remote`
+    /// means it does not matter.
+    async fn check_remote(&self) -> Result<Option<helper::Check>> {
+        if self.force_remote {
+            return Ok(None);
+        }
+        Ok(Some(self.sink.check(self.transport, self.options).await?))
+    }
+
+    /// Logs the source in if needed, returning whether it did.
+    pub async fn refresh_local(&self) -> Result<bool> {
+        if !self.needs_login().await? {
+            return Ok(false);
+        }
+        self.source.login().await?;
+        Ok(true)
+    }
+
+    /// Pushes the credential to the remote if needed, returning whether it did.
+    ///
+    /// The credential is only fetched if a push is needed, since fetching it may involve
+    /// prompting the user for access to their keychain.
+    pub async fn sync_remote(&self) -> Result<bool> {
+        Ok(self.sync_remote_with_key().await?.0)
+    }
+
+    /// Like `sync_remote`, but also returns the serial number of the stored key, if any.
+    pub async fn sync_remote_with_key(&self) -> Result<(bool, Option<i64>)> {
+        let (synced, key, _) = self.sync_remote_with_expiry().await?;
+        Ok((synced, key))
+    }
+
+    /// Like `sync_remote_with_key`, but also returns when the remote's credential expires, if
+    /// that is known.
+    async fn sync_remote_with_expiry(&self) -> Result<(bool, Option<i64>, Option<SystemTime>)> {
+        if let Some(check) = self.check_remote().await?
+            && !check.needs_refresh
+        {
+            return Ok((false, None, check.expires));
+        }
+        self.push_with_expiry().await
+    }
+
+    /// Fetches the credential and stores it, returning what `sync_remote_with_expiry` does.
+    async fn push_with_expiry(&self) -> Result<(bool, Option<i64>, Option<SystemTime>)> {
+        let credential = self.source.credential().await?;
+        let (key, expires) = self.store(credential.expose_secret()).await?;
+        Ok((
+            true,
+            key,
+            expires.or_else(|| token::expiry(credential.expose_secret())),
+        ))
+    }
+
+    /// Stores `credential` on the remote and checks that the remote helper now accepts it,
+    /// returning the stored key's serial number, if any.
+    pub async fn push(&self, credential: &str) -> Result<Option<i64>> {
+        Ok(self.store(credential).await?.0)
+    }
+
+    /// Does what `push` does, also returning when the remote helper says the credential expires.
+    async fn store(&self, credential: &str) -> Result<(Option<i64>, Option<SystemTime>)> {
+        // A fresh credential is as good as it gets, however soon it expires.
+        let options = SyncOptions {
+            min_validity: Duration::ZERO,
+            ..self.options.clone()
+        };
+        let (key, check) = self
+            .sink
+            .store_and_check(self.transport, credential, &options)
+            .await?;
+        if check.needs_refresh {
+            anyhow::bail!(
+                concat!(
+                    "We tried syncing your credentials to {} but they are still invalid.\n",
+                    "Try rerunning with --force."
+                ),
+                self.transport.host().unwrap_or("localhost")
+            );
+        }
+        Ok((key, check.expires))
+    }
+}
@@ -0,0 +1,108 @@
+// Copyright 2026 Stairwell, Inc.
+// Author: mrdomino@stairwell.com
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use anyhow::{Context, Result};
+use aspect_reauth::transport::RemoteTransport;
+use smol::{
+    io::AsyncWriteExt,
+    process::{Command, Stdio},
+};
+use zeroize::Zeroizing;
+
+/// Copies the local git credential for each of `hosts` to the remote.
+///
+/// Both ends speak git's own credential protocol: the credential is read with `git credential
+/// fill` (so whatever helper is configured locally, e.g. osxkeychain, answers it) and written with
+/// `git credential approve` on the remote, which hands it to the remote's configured helper. Since
+/// git silently discards it if the remote has no `credential.helper` configured, that is checked
+/// first.
+pub async fn sync_hosts<T: RemoteTransport>(transport: &T, git_hosts: &[String]) -> Result<()> {
+    check_helper(transport).await?;
+    for git_host in git_hosts {
+        let credential = fill(git_host)
+            .await
+            .with_context(|| format!("failed to get local git credential for {git_host}"))?;
+        let output = transport
+            .run("git", &["credential", "approve"], &credential)
+            .await?;
+        if !output.status.success() {
+            anyhow::bail!(
+                "{}git credential approve: {}\n\n{}",
+                transport.prefix(),
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim(),
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Fails unless git on the remote has a `credential.helper` to hand credentials to.
+async fn check_helper<T: RemoteTransport>(transport: &T) -> Result<()> {
+    let output = transport
+        .run("git", &["config", "--get", "credential.helper"], b"")
+        .await?;
+    match output.status.code() {
+        Some(0) if !output.stdout.trim_ascii().is_empty() => Ok(()),
+        Some(0 | 1) => anyhow::bail!(
+            "{}git has no credential.helper configured, so it would discard the credential; set \
+             one on the host, e.g. with `git config --global credential.helper store`",
+            transport.prefix(),
+        ),
+        _ => anyhow::bail!(
+            "{}git config --get credential.helper: {}\n\n{}",
+            transport.prefix(),
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim(),
+        ),
+    }
+}
+
+/// Runs `git credential fill` for `https://<git_host>`, returning its output verbatim since that is
+/// also the input format `git credential approve` expects. It holds the password, so it is zeroed
+/// when dropped.
+async fn fill(git_host: &str) -> Result<Zeroizing<Vec<u8>>> {
+    let mut child = Command::new("git")
+        .args(["credential", "fill"])
+        // Never fall back to prompting on the terminal; we want what is stored, or nothing.
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .context("failed to spawn git")?;
+    let mut stdin = child.stdin.take().context("failed to open stdin")?;
+    stdin
+        .write_all(format!("protocol=https\nhost={git_host}\n\n").as_bytes())
+        .await?;
+    drop(stdin);
+    let output = child.output().await?;
+    let stdout = Zeroizing::new(output.stdout);
+    if !output.status.success() {
+        anyhow::bail!(
+            "git credential fill: {}\n\n{}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim(),
+        );
+    }
+    if !stdout
+        .split(|&b| b == b'\n')
+        .any(|line| line.starts_with(b"password="))
+    {
+        anyhow::bail!("no stored git credential for {git_host}");
+    }
+    Ok(stdout)
+}
@@ -0,0 +1,165 @@
+// Copyright 2026 Stairwell, Inc.
+// Author: mrdomino@stairwell.com
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{process::Output, time::SystemTime};
+
+use anyhow::{Context, Result};
+use regex::bytes::Regex;
+use serde::Deserialize;
+use zeroize::Zeroizing;
+
+use crate::{SyncOptions, transport::RemoteTransport};
+
+/// Error codes with which a helper may say that it needs a login, compared ignoring case.
+const LOGIN_CODES: [&str; 3] = ["unauthenticated", "expired", "login_required"];
+
+/// The parts of a credential helper's response to `get` that we look at. On success this is the
+/// response of the Bazel credential helper protocol; on failure, some helpers print a JSON
+/// object with an error code instead of (or as well as) a message.
+#[derive(Deserialize)]
+struct Response {
+    expires: Option<String>,
+    error: Option<ErrorField>,
+    code: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ErrorField {
+    Code(String),
+    Object { code: String },
+}
+
+/// What a credential helper said about its credential.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Check {
+    /// Whether it needs a login: because it said so, because the credential it gave has already
+    /// expired, or because it expires within the `min_validity` of the `SyncOptions`.
+    pub needs_refresh: bool,
+    /// When the credential expires, if the helper gave one and said.
+    pub expires: Option<SystemTime>,
+}
+
+/// Asks the credential helper on `transport` for a credential, returning whether it needs a
+/// login; see [`Check::needs_refresh`].
+pub async fn needs_refresh<T: RemoteTransport>(
+    transport: &T,
+    options: &SyncOptions,
+) -> Result<bool> {
+    Ok(check(transport, options).await?.needs_refresh)
+}
+
+/// Asks the credential helper on `transport` when its credential expires. Returns `None` if it
+/// needs a login, or if it does not say.
+pub async fn expires<T: RemoteTransport>(
+    transport: &T,
+    options: &SyncOptions,
+) -> Result<Option<SystemTime>> {
+    let check = check(transport, options).await?;
+    Ok(check.expires.filter(|_| !check.needs_refresh))
+}
+
+/// Asks the credential helper on `transport` for a credential, and reports what it said.
+///
+/// Helpers report when the credential expires as an RFC 3339 timestamp in UTC in the optional
+/// `expires` field of their response. A response we cannot make sense of is taken at its word
+/// that the credential is good.
+pub async fn check<T: RemoteTransport>(transport: &T, options: &SyncOptions) -> Result<Check> {
+    let output = transport
+        .run(
+            &options.credential_helper,
+            &["get"],
+            request(options).as_bytes(),
+        )
+        .await?;
+    check_output(transport, options, output)
+}
+
+/// The request the credential helper's `get` is given on stdin.
+pub fn request(options: &SyncOptions) -> String {
+    format!(concat!(r#"{{"uri":"https://{}"}}"#, "\n"), &options.remote)
+}
+
+/// Reports what the credential helper said in `output`, from a `get` run on `transport` some other
+/// way than `check` runs it.
+pub fn check_output<T: RemoteTransport>(
+    transport: &T,
+    options: &SyncOptions,
+    output: Output,
+) -> Result<Check> {
+    let Some(stdout) = get(transport, options, output)? else {
+        return Ok(Check {
+            needs_refresh: true,
+            expires: None,
+        });
+    };
+    // The response holds the credential itself, so it is zeroed when dropped.
+    let stdout = Zeroizing::new(stdout);
+    let expires = serde_json::from_slice::<Response>(&stdout)
+        .ok()
+        .and_then(|response| response.expires)
+        .and_then(|expires| humantime::parse_rfc3339_weak(&expires).ok());
+    let deadline = SystemTime::now() + options.min_validity;
+    Ok(Check {
+        needs_refresh: expires.is_some_and(|expires| expires <= deadline),
+        expires,
+    })
+}
+
+/// Makes sense of the output of the credential helper's `get`, returning its stdout, or `None` if
+/// it told us to log in again.
+fn get<T: RemoteTransport>(
+    transport: &T,
+    options: &SyncOptions,
+    output: Output,
+) -> Result<Option<Vec<u8>>> {
+    let helper = &options.credential_helper;
+    if output.status.success() {
+        return Ok(Some(output.stdout));
+    }
+    if wants_login(&output.stdout) || wants_login(&output.stderr) {
+        return Ok(None);
+    }
+    // Otherwise fall back on the wording of the message, for helpers that only explain themselves
+    // in prose.
+    let re = Regex::new(&format!(
+        r"(?mis)please\s+run.*{}\s+login",
+        regex::escape(helper)
+    ))
+    .context("failed to compile regex")?;
+    if !re.is_match(&output.stderr) {
+        anyhow::bail!(
+            "{}{} get: {}\n\n{}",
+            transport.prefix(),
+            helper,
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim(),
+        );
+    }
+    Ok(None)
+}
+
+/// Returns whether `output` is a JSON object whose `error`, `error.code`, or `code` is one of
+/// `LOGIN_CODES`.
+fn wants_login(output: &[u8]) -> bool {
+    let Ok(response) = serde_json::from_slice::<Response>(output) else {
+        return false;
+    };
+    let code = match response.error {
+        Some(ErrorField::Code(code) | ErrorField::Object { code }) => Some(code),
+        None => response.code,
+    };
+    code.is_some_and(|code| LOGIN_CODES.iter().any(|c| code.eq_ignore_ascii_case(c)))
+}
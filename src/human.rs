@@ -0,0 +1,53 @@
+// Copyright 2026 Stairwell, Inc.
+// Author: mrdomino@stairwell.com
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Formatting of durations for people: the two most significant units, e.g. `3h 12m`, so that
+//! nobody has to do arithmetic on epoch seconds. Machine-readable output keeps exact timestamps.
+
+use std::time::Duration;
+
+const UNITS: [(u64, &str); 4] = [(86400, "d"), (3600, "h"), (60, "m"), (1, "s")];
+
+/// Formats `d` as its two most significant nonzero units, e.g. `2d 4h`, `3h 12m`, or `45s`.
+pub fn duration(d: Duration) -> String {
+    let mut secs = d.as_secs();
+    let mut parts = Vec::with_capacity(2);
+    for (size, unit) in UNITS {
+        if secs >= size || (parts.is_empty() && size == 1) {
+            parts.push(format!("{}{unit}", secs / size));
+            secs %= size;
+        } else if !parts.is_empty() {
+            // Don't skip a unit: "2d 0h" would be odd, but "2d 3m" would be misleading.
+            break;
+        }
+        if parts.len() == 2 {
+            break;
+        }
+    }
+    parts.join(" ")
+}
+
+/// Formats a time `d` in the past, e.g. `2d 4h ago`.
+pub fn ago(d: Duration) -> String {
+    if d < Duration::from_secs(1) {
+        return "just now".into();
+    }
+    format!("{} ago", duration(d))
+}
+
+/// Formats a time `d` in the future, e.g. `in 3h 12m`.
+pub fn from_now(d: Duration) -> String {
+    format!("in {}", duration(d))
+}
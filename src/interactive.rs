@@ -0,0 +1,69 @@
+// Copyright 2026 Stairwell, Inc.
+// Author: mrdomino@stairwell.com
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    io::{self, BufRead, IsTerminal, Write},
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use anyhow::{Context, Result};
+use dialoguer::FuzzySelect;
+
+static ASSUME_YES: AtomicBool = AtomicBool::new(false);
+
+/// Answers yes to every `confirm` from now on, and stops treating the run as interactive, for
+/// `--yes`.
+pub fn assume_yes() {
+    ASSUME_YES.store(true, Ordering::Relaxed);
+}
+
+/// Asks a yes/no question on the terminal, defaulting to no.
+///
+/// Fails rather than guessing when stdin is not a terminal, since an unattended run should never
+/// make a change nobody agreed to, unless that was agreed to in advance with `--yes`.
+pub fn confirm(question: &str) -> Result<bool> {
+    if ASSUME_YES.load(Ordering::Relaxed) {
+        println!("{question} [y/N] yes (--yes)");
+        return Ok(true);
+    }
+    if !io::stdin().is_terminal() {
+        anyhow::bail!("cannot ask \"{question}\" without a terminal");
+    }
+    print!("{question} [y/N] ");
+    io::stdout().flush()?;
+    let mut answer = String::new();
+    io::stdin()
+        .lock()
+        .read_line(&mut answer)
+        .context("failed to read answer")?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes" | "Yes"))
+}
+
+/// Whether we can interact with the user, i.e. both stdin and stderr (where prompts go) are a
+/// terminal and `--yes` was not given.
+pub fn is_interactive() -> bool {
+    !ASSUME_YES.load(Ordering::Relaxed) && io::stdin().is_terminal() && io::stderr().is_terminal()
+}
+
+/// Asks the user to choose one of `items` with a fuzzy-searchable list, starting on `default`.
+pub fn pick<'a>(prompt: &str, items: &[&'a str], default: usize) -> Result<&'a str> {
+    let index = FuzzySelect::new()
+        .with_prompt(prompt)
+        .items(items)
+        .default(default)
+        .interact()
+        .context("no selection made")?;
+    Ok(items[index])
+}
@@ -0,0 +1,82 @@
+// Copyright 2026 Stairwell, Inc.
+// Author: mrdomino@stairwell.com
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A stand-in for the OS keychain that keeps each entry as a file in a directory, so that tests of
+//! the binary can see what it reads and writes without touching the real keychain.
+
+use std::{
+    any::Any,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use keyring::{
+    Error, Result,
+    credential::{Credential, CredentialApi, CredentialBuilderApi, CredentialPersistence},
+};
+
+/// Makes `dir` the keychain for every entry created from now on.
+pub fn install(dir: &Path) {
+    keyring::set_default_credential_builder(Box::new(Builder(dir.to_owned())));
+}
+
+/// The path of the entry for `service` and `user` under `dir`.
+pub fn entry(dir: &Path, service: &str, user: &str) -> PathBuf {
+    dir.join(format!("{service}@{user}"))
+}
+
+struct Builder(PathBuf);
+
+impl CredentialBuilderApi for Builder {
+    fn build(&self, _: Option<&str>, service: &str, user: &str) -> Result<Box<Credential>> {
+        Ok(Box::new(DirCredential(entry(&self.0, service, user))))
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn persistence(&self) -> CredentialPersistence {
+        CredentialPersistence::UntilDelete
+    }
+}
+
+#[derive(Debug)]
+struct DirCredential(PathBuf);
+
+impl CredentialApi for DirCredential {
+    fn set_secret(&self, secret: &[u8]) -> Result<()> {
+        fs::write(&self.0, secret).map_err(|e| Error::PlatformFailure(Box::new(e)))
+    }
+
+    fn get_secret(&self) -> Result<Vec<u8>> {
+        fs::read(&self.0).map_err(not_found)
+    }
+
+    fn delete_credential(&self) -> Result<()> {
+        fs::remove_file(&self.0).map_err(not_found)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+fn not_found(e: io::Error) -> Error {
+    match e.kind() {
+        io::ErrorKind::NotFound => Error::NoEntry,
+        _ => Error::PlatformFailure(Box::new(e)),
+    }
+}
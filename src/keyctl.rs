@@ -0,0 +1,613 @@
+// Copyright 2026 Stairwell, Inc.
+// Author: mrdomino@stairwell.com
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{env, fmt, process::ExitStatus, str::FromStr, time::Duration};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::{ReauthError, error::KeyctlErrorKind, transport::RemoteTransport};
+
+/// The keyring service name the credential helper stores its credential under.
+pub const SERVICE: &str = "AspectWorkflows";
+
+/// The default key description template: keyring-rs's `keyring-rs:<user>@<service>`, where the
+/// credential helper uses the remote as the keyring user.
+pub const DEFAULT_KEY_NAME: &str = "keyring-rs:{remote}@{service}";
+
+/// The status a shell exits with when the command it was asked to run is not installed.
+const NOT_FOUND: i32 = 127;
+
+/// The type of key keyring-rs stores the credential as, and the only one it looks for.
+pub const USER: &str = "user";
+
+/// The type of key for a credential too big for a `user` key, which holds up to 1 MiB.
+pub const BIG_KEY: &str = "big_key";
+
+/// The most a `user` key can hold.
+pub const USER_PAYLOAD_MAX: usize = 32767;
+
+/// Returns the type of key to store `password` as: [`USER`], unless it is too big for one, when
+/// it is [`BIG_KEY`]. Only a credential helper that looks for a `big_key` will find one.
+pub fn key_type(password: &str) -> &'static str {
+    if password.len() > USER_PAYLOAD_MAX {
+        BIG_KEY
+    } else {
+        USER
+    }
+}
+
+/// Parses a key permissions mask in hex, as `keyctl describe` prints it, with or without `0x`.
+pub fn parse_perm(perm: &str) -> Result<u32> {
+    let digits = perm
+        .strip_prefix("0x")
+        .or_else(|| perm.strip_prefix("0X"))
+        .unwrap_or(perm);
+    u32::from_str_radix(digits, 16)
+        .with_context(|| format!("invalid key permissions {perm:?}: expected a hex mask"))
+}
+
+/// Returns the description keyring-rs gives the credential helper's key for `remote`.
+pub fn key_name(remote: &str) -> String {
+    format!("keyring-rs:{remote}@{SERVICE}")
+}
+
+/// Expands a key description template, replacing `{remote}` with `remote`, `{service}` with
+/// [`SERVICE`], `{user}` with the local user name, and `{profile}` with `profile`, the host as
+/// given (which names its `[hosts.<name>]` table). `{{` and `}}` stand for literal braces.
+pub fn expand_key_name(template: &str, remote: &str, profile: &str) -> Result<String> {
+    let mut ret = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(i) = rest.find(['{', '}']) {
+        ret.push_str(&rest[..i]);
+        rest = &rest[i..];
+        if let Some(r) = rest.strip_prefix("{{").or_else(|| rest.strip_prefix("}}")) {
+            ret.push_str(&rest[..1]);
+            rest = r;
+            continue;
+        }
+        let end = rest
+            .find('}')
+            .filter(|_| rest.starts_with('{'))
+            .with_context(|| format!("unbalanced brace in key name template {template:?}"))?;
+        match &rest[1..end] {
+            "remote" => ret.push_str(remote),
+            "service" => ret.push_str(SERVICE),
+            "user" => ret.push_str(&local_user()?),
+            "profile" => ret.push_str(profile),
+            other => anyhow::bail!("unknown placeholder {{{other}}} in key name template"),
+        }
+        rest = &rest[end + 1..];
+    }
+    ret.push_str(rest);
+    Ok(ret)
+}
+
+fn local_user() -> Result<String> {
+    env::var("USER")
+        .or_else(|_| env::var("USERNAME"))
+        .context("cannot expand {user}: neither $USER nor $USERNAME is set")
+}
+
+/// The remote keyring to store the credential in.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum KeyringTarget {
+    /// The thread keyring, `@t`.
+    Thread,
+    /// The process keyring, `@p`.
+    Process,
+    /// The session keyring, `@s`, which only processes in the same session can see.
+    Session,
+    /// The user keyring, `@u`, shared by all of the user's processes.
+    #[default]
+    User,
+    /// The user session keyring, `@us`.
+    UserSession,
+    /// The user's persistent keyring, which outlives their sessions; see `keyctl get_persistent`.
+    Persistent,
+    /// The user keyring, with the key linked into the session keyring as well, so that both
+    /// long-lived daemons and the interactive session see it.
+    Both,
+    /// A keyring by its numeric ID.
+    Id(i64),
+    /// A keyring by its description, as keyctl looks it up in the keyrings the process has:
+    /// `%:NAME`, or `%keyring:NAME` in full. One shared with a build daemon, say.
+    Named(String),
+}
+
+impl KeyringTarget {
+    /// Returns the `keyctl` argument naming this keyring, or `None` for the persistent keyring,
+    /// whose ID has to be looked up on the remote.
+    pub fn arg(&self) -> Option<String> {
+        Some(match self {
+            KeyringTarget::Thread => "@t".into(),
+            KeyringTarget::Process => "@p".into(),
+            KeyringTarget::Session => "@s".into(),
+            KeyringTarget::User => "@u".into(),
+            KeyringTarget::UserSession => "@us".into(),
+            KeyringTarget::Persistent => return None,
+            KeyringTarget::Both => "@u".into(),
+            KeyringTarget::Id(id) => id.to_string(),
+            KeyringTarget::Named(name) => name.clone(),
+        })
+    }
+
+    /// Returns the `keyctl` argument naming the keyring the key is linked into once it is stored
+    /// in this one, if there is one.
+    pub fn link(&self) -> Option<&'static str> {
+        match self {
+            KeyringTarget::Both => Some("@s"),
+            _ => None,
+        }
+    }
+
+    /// Returns the `keyctl` argument naming this keyring on `transport`'s host, first looking up
+    /// (and if need be creating) the persistent keyring, which `get_persistent` also links into
+    /// the session keyring.
+    pub async fn resolve<T: RemoteTransport>(&self, transport: &T) -> Result<String> {
+        if let Some(arg) = self.arg() {
+            return Ok(arg);
+        }
+        let output = transport
+            .run("keyctl", &["get_persistent", "@s"], b"")
+            .await?;
+        if !output.status.success() {
+            return Err(failure(
+                output.status,
+                format!(
+                    "{}keyctl get_persistent: {}\n\n{}",
+                    transport.prefix(),
+                    output.status,
+                    String::from_utf8_lossy(&output.stderr).trim(),
+                ),
+            ));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).trim().into())
+    }
+}
+
+impl fmt::Display for KeyringTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeyringTarget::Thread => f.write_str("thread"),
+            KeyringTarget::Process => f.write_str("process"),
+            KeyringTarget::Session => f.write_str("session"),
+            KeyringTarget::User => f.write_str("user"),
+            KeyringTarget::UserSession => f.write_str("user-session"),
+            KeyringTarget::Persistent => f.write_str("persistent"),
+            KeyringTarget::Both => f.write_str("both"),
+            KeyringTarget::Id(id) => write!(f, "{id}"),
+            KeyringTarget::Named(name) => f.write_str(name),
+        }
+    }
+}
+
+impl FromStr for KeyringTarget {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s {
+            "thread" | "@t" => KeyringTarget::Thread,
+            "process" | "@p" => KeyringTarget::Process,
+            "session" | "@s" => KeyringTarget::Session,
+            "user" | "@u" => KeyringTarget::User,
+            "user-session" | "@us" => KeyringTarget::UserSession,
+            "persistent" => KeyringTarget::Persistent,
+            "both" => KeyringTarget::Both,
+            _ if s.starts_with('%') => match s.split_once(':') {
+                Some(("%" | "%keyring", name)) if !name.is_empty() => {
+                    // It goes into the single-round-trip script as it is.
+                    if !name
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || "-_.:@+=,/".contains(c))
+                    {
+                        anyhow::bail!(
+                            "invalid keyring {s:?}: a name may only have letters, digits, and \
+                             -_.:@+=,/ in it"
+                        );
+                    }
+                    KeyringTarget::Named(s.into())
+                }
+                _ => anyhow::bail!("invalid keyring {s:?}; expected %:NAME or %keyring:NAME"),
+            },
+            _ => KeyringTarget::Id(s.parse().map_err(|_| {
+                anyhow::anyhow!(
+                    "unknown keyring {s:?}; expected thread, process, session, user, \
+                     user-session, persistent, both, a keyring ID, or %:NAME"
+                )
+            })?),
+        })
+    }
+}
+
+impl TryFrom<String> for KeyringTarget {
+    type Error = anyhow::Error;
+
+    fn try_from(s: String) -> Result<Self> {
+        s.parse()
+    }
+}
+
+impl From<KeyringTarget> for String {
+    fn from(target: KeyringTarget) -> Self {
+        target.to_string()
+    }
+}
+
+/// (De)serializes a list of keyrings for `#[serde(with)]`, as a single keyring when there is only
+/// one, so that both `keyring = "session"` and `keyring = ["user", "session"]` are accepted.
+pub mod keyrings {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer, de::Error};
+
+    use super::KeyringTarget;
+
+    pub fn serialize<S: Serializer>(
+        keyrings: &[KeyringTarget],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match keyrings {
+            [keyring] => keyring.serialize(serializer),
+            _ => keyrings.serialize(serializer),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<KeyringTarget>, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum OneOrMany {
+            One(String),
+            Many(Vec<String>),
+        }
+        let names = match OneOrMany::deserialize(deserializer)? {
+            OneOrMany::One(name) => vec![name],
+            OneOrMany::Many(names) => names,
+        };
+        names
+            .iter()
+            .map(|name| name.parse().map_err(D::Error::custom))
+            .collect()
+    }
+}
+
+/// Adds `password` to `keyring` under `key_name` with `keyctl padd`, as a key of its
+/// [`key_type`], replacing any existing key of that type, and returns the new key's serial number.
+pub async fn padd<T: RemoteTransport>(
+    transport: &T,
+    key_name: &str,
+    keyring: &str,
+    password: &str,
+) -> Result<i64> {
+    let output = transport
+        .run(
+            "keyctl",
+            &["padd", key_type(password), key_name, keyring],
+            password.as_bytes(),
+        )
+        .await?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let (kind, hint) = if stderr.contains(PERMISSION_DENIED) {
+            (KeyctlErrorKind::PermissionDenied, SESSION_HINT)
+        } else {
+            (KeyctlErrorKind::Other, "")
+        };
+        return Err(failure_of_kind(
+            output.status,
+            kind,
+            format!(
+                "{}keyctl padd: {}\n\n{}{hint}",
+                transport.prefix(),
+                output.status,
+                stderr.trim(),
+            ),
+        ));
+    }
+    parse_serial(transport, "padd", &output.stdout)
+}
+
+/// Adds `password` to `keyring` as `padd` does, but from a new session keyring with the user
+/// keyring linked into it, with `keyctl session`, for an ssh session that has no session keyring
+/// of its own to reach the user keyring from. A key added to the session keyring itself goes with
+/// the new one, so this is only of use for the user keyring and others not tied to the session.
+pub async fn padd_in_session<T: RemoteTransport>(
+    transport: &T,
+    key_name: &str,
+    keyring: &str,
+    password: &str,
+) -> Result<i64> {
+    let output = transport
+        .run(
+            "keyctl",
+            &[
+                "session",
+                "-",
+                "sh",
+                "-c",
+                SESSION_SCRIPT,
+                "sh",
+                key_name,
+                keyring,
+                key_type(password),
+            ],
+            password.as_bytes(),
+        )
+        .await?;
+    if !output.status.success() {
+        return Err(failure(
+            output.status,
+            format!(
+                "{}keyctl session - keyctl padd: {}\n\n{}",
+                transport.prefix(),
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim(),
+            ),
+        ));
+    }
+    parse_serial(transport, "padd", &output.stdout)
+}
+
+/// The script `padd_in_session` runs in the new session keyring, with the key name, keyring, and
+/// key type as `$1`, `$2`, and `$3`. It is passed to the remote shell as a word, so it has no `'`
+/// or `\` in it.
+pub const SESSION_SCRIPT: &str =
+    "keyctl link @u @s >/dev/null && exec keyctl padd \"$3\" \"$1\" \"$2\"";
+
+/// What keyctl says when the keyring it was given is out of reach.
+pub(crate) const PERMISSION_DENIED: &str = "Permission denied";
+
+/// Follows the message for a `padd` that was denied permission.
+pub(crate) const SESSION_HINT: &str = "\n\nThe ssh session may have no session keyring of its own; rerun with \
+     --join-session-keyring to add the key from a new one.";
+
+/// Reports whether `error` is the failure of a `padd` that was denied permission, which
+/// `padd_in_session` may get around.
+pub fn permission_denied(error: &anyhow::Error) -> bool {
+    matches!(
+        error.downcast_ref(),
+        Some(ReauthError::Keyctl {
+            kind: KeyctlErrorKind::PermissionDenied,
+            ..
+        })
+    )
+}
+
+/// Finds the user key described `key_name` in `keyring` (or a keyring linked from it) with
+/// `keyctl search`, returning its serial number, or `None` if there is no such key.
+///
+/// Any failure counts as not finding it: if keyctl itself is broken, the `padd` that follows says
+/// so more clearly.
+pub async fn search<T: RemoteTransport>(
+    transport: &T,
+    key_name: &str,
+    keyring: &str,
+) -> Result<Option<i64>> {
+    search_type(transport, USER, key_name, keyring).await
+}
+
+/// Finds the key of type `key_type` described `key_name`, as `search` does a user key.
+pub async fn search_type<T: RemoteTransport>(
+    transport: &T,
+    key_type: &str,
+    key_name: &str,
+    keyring: &str,
+) -> Result<Option<i64>> {
+    let output = transport
+        .run("keyctl", &["search", keyring, key_type, key_name], b"")
+        .await?;
+    if !output.status.success() {
+        return Ok(None);
+    }
+    parse_serial(transport, "search", &output.stdout).map(Some)
+}
+
+/// Checks that `keyring` is reachable with `keyctl rdescribe`, which also shows that keyctl is
+/// installed at all.
+pub async fn rdescribe<T: RemoteTransport>(transport: &T, keyring: &str) -> Result<()> {
+    let output = transport
+        .run("keyctl", &["rdescribe", keyring], b"")
+        .await?;
+    if !output.status.success() {
+        return Err(failure(
+            output.status,
+            format!(
+                "{}keyctl rdescribe {keyring}: {}\n\n{}",
+                transport.prefix(),
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim(),
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// Invalidates the key `serial` with `keyctl invalidate`, which destroys it at once, in whichever
+/// keyrings it is linked into.
+pub async fn invalidate<T: RemoteTransport>(transport: &T, serial: i64) -> Result<()> {
+    let output = transport
+        .run("keyctl", &["invalidate", &serial.to_string()], b"")
+        .await?;
+    if !output.status.success() {
+        return Err(failure(
+            output.status,
+            format!(
+                "{}keyctl invalidate {serial}: {}\n\n{}",
+                transport.prefix(),
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim(),
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// Links the key `serial` into `keyring` with `keyctl link`, which leaves it where it was too.
+pub async fn link<T: RemoteTransport>(transport: &T, serial: i64, keyring: &str) -> Result<()> {
+    let output = transport
+        .run("keyctl", &["link", &serial.to_string(), keyring], b"")
+        .await?;
+    if !output.status.success() {
+        return Err(failure(
+            output.status,
+            format!(
+                "{}keyctl link {serial} {keyring}: {}\n\n{}",
+                transport.prefix(),
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim(),
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// Sets the permissions of the key `serial` to `perm` with `keyctl setperm`.
+pub async fn setperm<T: RemoteTransport>(transport: &T, serial: i64, perm: u32) -> Result<()> {
+    let perm = format!("{perm:#010x}");
+    let output = transport
+        .run("keyctl", &["setperm", &serial.to_string(), &perm], b"")
+        .await?;
+    if !output.status.success() {
+        return Err(failure(
+            output.status,
+            format!(
+                "{}keyctl setperm {serial} {perm}: {}\n\n{}",
+                transport.prefix(),
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim(),
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// Sets the key `serial` to expire `after` from now with `keyctl timeout`, rounded up to a whole
+/// second (since a timeout of zero would mean none at all).
+pub async fn timeout<T: RemoteTransport>(
+    transport: &T,
+    serial: i64,
+    after: Duration,
+) -> Result<()> {
+    let secs = timeout_secs(after);
+    let output = transport
+        .run(
+            "keyctl",
+            &["timeout", &serial.to_string(), &secs.to_string()],
+            b"",
+        )
+        .await?;
+    if !output.status.success() {
+        return Err(failure(
+            output.status,
+            format!(
+                "{}keyctl timeout {serial} {secs}: {}\n\n{}",
+                transport.prefix(),
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim(),
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// The whole seconds `keyctl timeout` is given for `after`: rounded up, and at least one, since a
+/// timeout of zero would mean none at all.
+pub(crate) fn timeout_secs(after: Duration) -> u64 {
+    (after.as_secs() + u64::from(after.subsec_nanos() > 0)).max(1)
+}
+
+/// Replaces the payload of the key `serial` with `password` with `keyctl pupdate`, which keeps its
+/// permissions, links, and timeout.
+pub async fn pupdate<T: RemoteTransport>(transport: &T, serial: i64, password: &str) -> Result<()> {
+    let output = transport
+        .run(
+            "keyctl",
+            &["pupdate", &serial.to_string()],
+            password.as_bytes(),
+        )
+        .await?;
+    if !output.status.success() {
+        return Err(failure(
+            output.status,
+            format!(
+                "{}keyctl pupdate {serial}: {}\n\n{}{PUPDATE_HINT}",
+                transport.prefix(),
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim(),
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// Follows the message for a failed `pupdate`.
+pub(crate) const PUPDATE_HINT: &str = "\n\nRerun with --replace-key to replace the key instead.";
+
+/// The error for a keyctl run that exited with `status`. ssh exits with 255 when the connection
+/// fails or drops, which keyctl itself never does, and the remote shell with 127 when there is no
+/// keyctl to run.
+pub(crate) fn failure(status: ExitStatus, message: String) -> anyhow::Error {
+    failure_of_kind(status, KeyctlErrorKind::Other, message)
+}
+
+/// Like `failure`, for a keyctl run whose stderr said it failed for the reason `kind`.
+pub(crate) fn failure_of_kind(
+    status: ExitStatus,
+    kind: KeyctlErrorKind,
+    message: String,
+) -> anyhow::Error {
+    let not_installed = || ReauthError::Keyctl {
+        message: format!("{message}{NOT_FOUND_HINT}"),
+        kind: KeyctlErrorKind::NotInstalled,
+    };
+    match status.code() {
+        Some(255) => ReauthError::ssh(message).into(),
+        Some(NOT_FOUND) => not_installed().into(),
+        // Windows shells exit with 1 for a command that does not exist, and say so.
+        _ if message.contains(crate::sink::NOT_RECOGNIZED) => not_installed().into(),
+        _ => ReauthError::Keyctl { message, kind }.into(),
+    }
+}
+
+/// Follows the message for a keyctl that is not installed.
+const NOT_FOUND_HINT: &str = "\n\nInstall keyutils on the host to get keyctl.";
+
+/// Reports whether `error` is the failure of a keyctl that is not installed on the remote.
+pub fn not_installed(error: &anyhow::Error) -> bool {
+    matches!(
+        error.downcast_ref(),
+        Some(ReauthError::Keyctl {
+            kind: KeyctlErrorKind::NotInstalled,
+            ..
+        })
+    )
+}
+
+/// Parses the key serial number keyctl `command` printed.
+pub(crate) fn parse_serial<T: RemoteTransport>(
+    transport: &T,
+    command: &str,
+    stdout: &[u8],
+) -> Result<i64> {
+    let stdout = String::from_utf8_lossy(stdout);
+    stdout.trim().parse().with_context(|| {
+        ReauthError::keyctl(format!(
+            "{}keyctl {command} printed {:?} rather than a key ID",
+            transport.prefix(),
+            stdout.trim()
+        ))
+    })
+}
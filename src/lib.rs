@@ -0,0 +1,141 @@
+// Copyright 2026 Stairwell, Inc.
+// Author: mrdomino@stairwell.com
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+pub mod error;
+pub mod flow;
+pub mod helper;
+pub mod human;
+#[cfg(feature = "test-support")]
+pub mod keychain_dir;
+pub mod keyctl;
+pub mod probe;
+pub mod sink;
+pub mod source;
+pub mod ssh_mux;
+pub mod state;
+pub mod token;
+pub mod transport;
+
+use std::{
+    path::Path,
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{Context, Result};
+use transport::RemoteTransport;
+
+pub use error::ReauthError;
+pub use flow::ReauthFlow;
+
+/// What to sync, and how to check it.
+#[derive(Clone)]
+pub struct SyncOptions {
+    /// Aspect remote DNS name.
+    pub remote: String,
+    /// Aspect credential helper executable name.
+    pub credential_helper: String,
+    /// The keyrings on the remote to store the credential in, in order.
+    pub keyrings: Vec<keyctl::KeyringTarget>,
+    /// How long a credential must have left to count as valid: one that expires sooner is
+    /// refreshed as if it had already expired. Zero accepts any credential still valid now.
+    pub min_validity: Duration,
+}
+
+/// What `sync` did.
+#[derive(Clone, Debug)]
+pub struct SyncReport {
+    /// The host synced to.
+    pub host: String,
+    /// The Aspect remote whose credential was synced.
+    pub remote: String,
+    /// Whether the local credential helper was logged in again.
+    pub logged_in: bool,
+    /// Whether the credential was pushed to the host.
+    pub synced: bool,
+    /// The serial number of the key the credential was stored as on the host, if it was pushed.
+    pub key: Option<i64>,
+    /// When the host's credential expires, if that is known.
+    pub expires: Option<SystemTime>,
+}
+
+/// Runs a full interactive sync to `host`, as `aspect-reauth HOST` does with default options: logs
+/// in locally with the credential helper if the local credential is stale (which may open a
+/// browser), then pushes it to `host` over ssh if the host's is stale.
+///
+/// The outcome is not recorded for `prompt-status`; use [`state::record`] for that. If the sync
+/// fails for want of ssh, the helper's login, the keychain, or keyctl, the error's chain holds a
+/// [`ReauthError`] saying which.
+///
+/// ```no_run
+/// # smol::block_on(async {
+/// use std::time::Duration;
+///
+/// use aspect_reauth::{SyncOptions, keyctl::KeyringTarget};
+///
+/// let options = SyncOptions {
+///     remote: "aw-remote.example".into(),
+///     credential_helper: "aspect-credential-helper".into(),
+///     keyrings: vec![KeyringTarget::default()],
+///     min_validity: Duration::ZERO,
+/// };
+/// let report = aspect_reauth::sync("devbox", &options).await?;
+/// if report.synced {
+///     println!("synced to {}", report.host);
+/// }
+/// # anyhow::Ok(())
+/// # });
+/// ```
+pub async fn sync(host: &str, options: &SyncOptions) -> Result<SyncReport> {
+    let ssh = ssh_mux::SshMux::new(
+        Path::new(ssh_mux::DEFAULT_SSH),
+        host,
+        &[] as &[&str],
+        ssh_mux::SocketStrategy::Infer,
+        None,
+        ssh_mux::SocketMode::Temporary,
+    )
+    .await
+    .context("failed setting up ssh session")?;
+    let report = sync_credential(&ssh, options).await;
+    let closed = ssh.close().await;
+    let report = report?;
+    closed?;
+    Ok(report)
+}
+
+/// Does what [`sync`] does over a `transport` the caller already has, such as an [`SshMux`] a
+/// long-running process keeps open between syncs: reads the credential from the local keychain,
+/// logging in again if it is stale, and stores it with `keyctl` on the host if the host's is
+/// stale. The transport is left open.
+///
+/// [`SshMux`]: ssh_mux::SshMux
+pub async fn sync_credential<T: RemoteTransport>(
+    transport: &T,
+    options: &SyncOptions,
+) -> Result<SyncReport> {
+    let source = source::Keyring::new(options);
+    let sink = sink::Keyctl::new(options);
+    let outcome = ReauthFlow::new(&source, transport, &sink, options)
+        .run()
+        .await?;
+    Ok(SyncReport {
+        host: transport.host().unwrap_or("localhost").into(),
+        remote: options.remote.clone(),
+        logged_in: outcome.logged_in,
+        synced: outcome.synced,
+        key: outcome.key,
+        expires: outcome.expires,
+    })
+}
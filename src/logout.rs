@@ -0,0 +1,116 @@
+// Copyright 2026 Stairwell, Inc.
+// Author: mrdomino@stairwell.com
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! `aspect-reauth logout`: takes the credential back off a host and out of the local keychain,
+//! for decommissioning a host or revoking a leaked token.
+
+use anstream::println;
+use anyhow::Result;
+use aspect_reauth::{keyctl, sink::AnySink, state::Status};
+use clap::Args;
+use clap_complete::ArgValueCandidates;
+
+use crate::{connect, disconnect, interactive::confirm, record};
+
+#[derive(Args, Clone)]
+pub struct LogoutArgs {
+    /// SSH hostname to remove the credential from [default: from the config file, or devbox]
+    #[arg(env = "ASPECT_REAUTH_HOST", add = ArgValueCandidates::new(crate::completions::hosts))]
+    pub host: Option<String>,
+}
+
+/// Invalidates the key in each of the host's keyrings, then deletes the local credential.
+///
+/// The host goes first, so that if it cannot be reached the local credential is still there to
+/// try again with.
+pub async fn run(args: &crate::Args) -> Result<()> {
+    let options = args.sync_options();
+    let key_name = args.key_name()?;
+    let host = args.host();
+    if !confirm(&format!(
+        "Remove the {} credential from {host} and from this machine's keychain?",
+        options.remote
+    ))? {
+        println!("Nothing removed.");
+        return Ok(());
+    }
+
+    let ssh = connect(args).await?;
+    let revoked = async {
+        match args.host_sink(&options, &ssh).await? {
+            AnySink::File(file) => {
+                if file.remove(&ssh).await? {
+                    println!("Removed the credential file on {host}.");
+                } else {
+                    println!("No credential file on {host}.");
+                }
+            }
+            AnySink::SystemdCreds(creds) => {
+                if creds.remove(&ssh).await? {
+                    println!("Removed the encrypted credential {} on {host}.", creds.name);
+                } else {
+                    println!("No encrypted credential {} on {host}.", creds.name);
+                }
+            }
+            AnySink::Security(keychain) => {
+                if keychain.delete(&ssh).await? {
+                    println!("Removed the credential from the login keychain on {host}.");
+                } else {
+                    println!("No credential in the login keychain on {host}.");
+                }
+            }
+            AnySink::CredentialManager(credential_manager) => {
+                if credential_manager.delete(&ssh).await? {
+                    println!("Removed the credential from the Credential Manager on {host}.");
+                } else {
+                    println!("No credential in the Credential Manager on {host}.");
+                }
+            }
+            AnySink::SecretTool(secret_tool) => {
+                if secret_tool.clear(&ssh).await? {
+                    println!("Removed the credential from the Secret Service on {host}.");
+                } else {
+                    println!("No credential in the Secret Service on {host}.");
+                }
+            }
+            AnySink::Keyctl(_) => {
+                for target in &options.keyrings {
+                    let keyring = target.resolve(&ssh).await?;
+                    match keyctl::search(&ssh, &key_name, &keyring).await? {
+                        Some(serial) => {
+                            keyctl::invalidate(&ssh, serial).await?;
+                            println!("Revoked key {serial} in the {target} keyring on {host}.");
+                        }
+                        None => println!("No key in the {target} keyring on {host}."),
+                    }
+                }
+            }
+        }
+        anyhow::Ok(())
+    }
+    .await;
+    disconnect(ssh, revoked).await?;
+    record(args, Status::Invalid);
+
+    if args.keyring(&options).forget().await? {
+        println!(
+            "Removed the {} credential from the local keychain.",
+            options.remote
+        );
+    } else {
+        println!("No {} credential in the local keychain.", options.remote);
+    }
+    Ok(())
+}
@@ -13,29 +13,88 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-mod ssh_mux;
+mod batch;
+mod ci;
+mod completions;
+mod config;
+mod control;
+mod docker;
+mod doctor;
+mod dry_run;
+mod exec;
+mod export_token;
+mod git;
+mod interactive;
+mod logout;
+mod output;
+mod prompt_status;
+mod prune;
+mod session;
+mod status;
+mod style;
+mod suggest_ssh;
+mod update_check;
+mod watch;
+mod wizard;
 
-use std::{str::FromStr, sync::Arc};
+use std::{
+    path::{Path, PathBuf},
+    process::ExitCode,
+    time::{Duration, Instant, SystemTime},
+};
 
+use anstream::{eprintln, println};
 use anyhow::{Context, Result};
-use clap::Parser;
-use keyring::Entry;
-use regex::bytes::Regex;
-use smol::{
-    io::AsyncWriteExt,
-    process::{Command, Stdio},
+use aspect_reauth::{
+    ReauthError, ReauthFlow, SyncOptions,
+    flow::Outcome,
+    human,
+    keyctl::{self, KeyringTarget},
+    probe::{self, Probed},
+    sink::{self, AnySink, SinkKind},
+    source,
+    ssh_mux::{self, SocketMode, SocketStrategy, SshMux},
+    state::{self, Status},
+    transport::{
+        RemoteTransport,
+        command::{CommandTemplate, CommandTransport},
+        retry::{Backoff, Retry},
+    },
 };
-use ssh_mux::{CreateSocket, SshMux};
+use async_signal::{Signal, Signals};
+use clap::{ArgMatches, CommandFactory, FromArgMatches, Parser, Subcommand, parser::ValueSource};
+use clap_complete::ArgValueCandidates;
+use session::{Session, Transport};
+use smol::{future, stream::StreamExt};
 
 const DEFAULT_REMOTE: &str = env!("ASPECT_REMOTE");
 const DEFAULT_HELPER: &str = env!("ASPECT_CREDENTIAL_HELPER");
+const DEFAULT_HOST: &str = "devbox";
+/// The exit code for a run that pushed the credential, with `--report-action-in-exit-code`.
+const EXIT_SYNCED: u8 = 4;
+/// The exit code for a `--ci --check` run that found the host's credential invalid.
+const EXIT_INVALID: u8 = 3;
+/// The exit code for a run whose login outlasted `--login-timeout`.
+const EXIT_LOGIN_TIMED_OUT: u8 = 5;
+/// The exit code for a run that could not connect to the host.
+const EXIT_SSH_FAILED: u8 = 6;
+/// The exit code for a run whose login failed.
+const EXIT_LOGIN_FAILED: u8 = 7;
+/// The exit code for a run that failed to store the credential with keyctl on the host.
+const EXIT_KEYCTL_FAILED: u8 = 8;
+/// The exit code for a run that outlasted `--timeout`.
+const EXIT_TIMED_OUT: u8 = 9;
 
-#[derive(Parser)]
-#[command(version, about)]
+#[derive(Parser, Clone)]
+#[command(version, about, args_conflicts_with_subcommands = true)]
 struct Args {
-    /// SSH hostname to which to sync credential
-    #[arg(default_value = "devbox")]
-    host: String,
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// SSH hostname to which to sync credential, optionally as user@host:port [default: from the
+    /// config file, or devbox]
+    #[arg(env = "ASPECT_REAUTH_HOST", add = ArgValueCandidates::new(crate::completions::hosts))]
+    host: Option<String>,
 
     /// Aspect remote DNS name
     #[arg(env = "ASPECT_REMOTE", default_value = DEFAULT_REMOTE, long)]
@@ -45,6 +104,36 @@ struct Args {
     #[arg(env = "ASPECT_CREDENTIAL_HELPER", default_value = DEFAULT_HELPER, long)]
     credential_helper: String,
 
+    /// Keychain service the credential helper stores its credential under locally, for a fork of
+    /// the helper that uses another [default: AspectWorkflows]
+    #[arg(long, value_name = "SERVICE")]
+    keyring_service: Option<String>,
+
+    /// Keychain account the credential helper stores its credential under locally [default: the
+    /// remote]
+    #[arg(long, value_name = "ACCOUNT")]
+    keyring_account: Option<String>,
+
+    /// Where to read the credential from locally: keyring (the keychain, logging in with the
+    /// credential helper when it is stale), file:PATH, env:VAR, stdin, cmd:COMMAND (run with sh),
+    /// age:PATH or gpg:PATH (a file decrypted with age or gpg), or pass:ENTRY (the first line of
+    /// `pass show ENTRY`), for a machine with no keychain; with
+    /// the cloud-secrets feature, also gcp:SECRET (GCP Secret Manager) or aws:SECRET (AWS Secrets
+    /// Manager); in CI mode, in place of $ASPECT_REAUTH_CREDENTIAL and stdin [default: keyring]
+    #[arg(long, env = "ASPECT_REAUTH_SOURCE", value_name = "SOURCE")]
+    source: Option<source::SourceKind>,
+
+    /// Where to read the credential from when there is no keychain to reach at all, as on a
+    /// headless host: age:PATH or gpg:PATH for an encrypted file, or any other --source but the
+    /// keychain [default: age:$XDG_DATA_HOME/aspect-reauth/credential.age]
+    #[arg(
+        long,
+        env = "ASPECT_REAUTH_KEYCHAIN_FALLBACK",
+        value_name = "SOURCE",
+        value_parser = parse_keychain_fallback
+    )]
+    keychain_fallback: Option<source::SourceKind>,
+
     /// Force re-login and sync even if the credentials are still valid
     #[arg(short, long)]
     force: bool,
@@ -57,205 +146,1220 @@ struct Args {
     #[arg(short = 'r', long, conflicts_with = "force")]
     force_remote: bool,
 
-    /// Use the session (rather than user) keyring on the VM
+    /// Pass an additional argument to the credential helper's login, e.g. --helper-arg=--no-browser
+    /// (takes multiple; replaces `helper_args` from the config file)
+    #[arg(long = "helper-arg", allow_hyphen_values = true, action = clap::ArgAction::Append)]
+    helper_args: Vec<String>,
+
+    /// Set an environment variable for the credential helper's login, e.g. --login-env
+    /// BROWSER=firefox (takes multiple; overrides the same variable in `login_env` from the config
+    /// file)
+    #[arg(long, value_name = "KEY=VALUE", value_parser = parse_env, action = clap::ArgAction::Append)]
+    login_env: Vec<(String, String)>,
+
+    /// Give up on the credential helper's login if it has not finished after this long (e.g. 5m),
+    /// exiting with 5
+    #[arg(long, value_name = "DURATION", value_parser = humantime::parse_duration)]
+    login_timeout: Option<Duration>,
+
+    /// Give up on the whole sync if it has not finished after this long (e.g. 2m), stopping the
+    /// control master and exiting with 9
+    #[arg(long, value_name = "DURATION", value_parser = humantime::parse_duration)]
+    timeout: Option<Duration>,
+
+    /// Refresh the credential if it expires within this long (e.g. 30m), even though it is still
+    /// valid, so that it does not lapse part way through a long build
+    #[arg(long, value_name = "DURATION", default_value = "0s", value_parser = humantime::parse_duration)]
+    min_validity: Duration,
+
+    /// Keyring on the VM to store the credential in: thread, process, session, user,
+    /// user-session, persistent, both (user, with the key linked into session too), a keyring ID,
+    /// or %:NAME for a keyring by name, such as one shared with a build daemon (takes multiple:
+    /// --keyring=user --keyring=session) [default: user]
+    #[arg(long, visible_alias = "keyring-id", value_name = "KEYRING")]
+    keyring: Vec<KeyringTarget>,
+
+    /// Use the session keyring on the VM; short for --keyring=session
     #[arg(short, long)]
     session_keyring: bool,
 
-    /// Create a temporary SSH control socket [values: true, false, infer]
+    /// Description of the key on the VM, with {remote}, {service} (AspectWorkflows), {user} (your
+    /// local user name) and {profile} (the host, as given) replaced
+    /// [default: keyring-rs:{remote}@{service}]
+    #[arg(long, value_name = "TEMPLATE")]
+    key_name: Option<String>,
+
+    /// Replace the key on the VM with a new one, rather than updating an existing key in place
+    /// (which keeps its permissions, links, and timeout)
+    #[arg(long)]
+    replace_key: bool,
+
+    /// Permissions to give a key added on the VM with keyctl setperm, as a hex mask [default: no
+    /// setperm, so the key keeps keyctl's default permissions]
+    #[arg(long, value_name = "MASK", value_parser = keyctl::parse_perm)]
+    key_perm: Option<u32>,
+
+    /// If keyctl on the VM is denied permission to add the key, as it is when the ssh session has
+    /// no session keyring of its own, add it again from a new one with the user keyring linked in
+    #[arg(long)]
+    join_session_keyring: bool,
+
+    /// Where to store the credential on the VM: keyctl (the kernel keyring, where the credential
+    /// helper looks), file (a file under the runtime directory, for hosts without keyutils),
+    /// secret-tool (the Secret Service of a desktop session, such as GNOME Keyring),
+    /// systemd-creds (encrypted for systemd user units to load), security (the login keychain of a
+    /// Mac), or credential-manager (that of Windows); keyctl uses the last two on a Mac and on
+    /// Windows [default: keyctl]
+    #[arg(long, env = "ASPECT_REAUTH_SINK", value_name = "SINK")]
+    sink: Option<SinkKind>,
+
+    /// If the VM has no keyctl, store the credential in a file under the runtime directory instead
+    /// (as --sink=file does), and print the variable that points the credential helper at it
+    #[arg(long)]
+    file_fallback: bool,
+
+    /// Store the credential and check that the remote accepts it in one remote shell script,
+    /// rather than a round trip to the host for each keyctl and helper command
+    #[arg(long)]
+    single_round_trip: bool,
+
+    /// When to create a temporary SSH control socket rather than share yours: always (true),
+    /// never (false), infer (if your ssh config does not set `ControlMaster auto`, or its master
+    /// is stale), running (unless your master is running now), or persist (as infer, and also
+    /// without `ControlPersist`)
     #[arg(
         short,
         long,
+        visible_alias = "socket-strategy",
+        value_name = "STRATEGY",
         conflicts_with = "no_create_socket",
         default_value = "infer",
         default_missing_value = "true",
         num_args = 0..=1,
         require_equals = true,
     )]
-    create_socket: CreateSocket,
+    create_socket: SocketStrategy,
 
     /// Do not create a temporary SSH control socket
     #[arg(short = 'C', long, conflicts_with = "create_socket")]
     no_create_socket: bool,
 
-    /// Call SSH with an additional argument (takes multiple: --ssh-arg='-p 23' --ssh-arg='-A')
-    #[arg(short = 'A', long = "ssh-arg", alias = "ssh_arg", action = clap::ArgAction::Append)]
+    /// Directory to create the temporary SSH control socket in [default: $TMPDIR]
+    #[arg(long, value_name = "DIR")]
+    socket_dir: Option<PathBuf>,
+
+    /// Whether the control master ends with the run or stays up, under a socket per host in the
+    /// cache directory (or `--socket-dir`), for later runs to reuse [values: temporary,
+    /// persistent] [default: temporary]
+    #[arg(long, env = "ASPECT_REAUTH_SOCKET_MODE", value_name = "MODE")]
+    socket_mode: Option<SocketMode>,
+
+    /// SSH client to use [default: `ssh_binary` from the config file, or ssh from PATH]
+    #[arg(
+        long,
+        visible_alias = "ssh-path",
+        env = "ASPECT_REAUTH_SSH",
+        value_name = "PATH"
+    )]
+    ssh_binary: Option<PathBuf>,
+
+    /// Reach the host through this command instead of ssh, e.g. --transport-cmd='gcloud compute
+    /// ssh {host} --', with {host} replaced and the remote command appended, or put in place of
+    /// {command}; there is no control master, and --ssh-arg does not apply
+    #[arg(long, env = "ASPECT_REAUTH_TRANSPORT_CMD", value_name = "TEMPLATE")]
+    transport_cmd: Option<CommandTemplate>,
+
+    /// How to reach the host: ssh, tsh for Teleport's `tsh ssh`, kubectl for `kubectl exec` into a
+    /// pod, or docker or podman for `exec` into a local container [default: ssh]
+    #[arg(
+        long,
+        env = "ASPECT_REAUTH_TRANSPORT",
+        conflicts_with = "transport_cmd"
+    )]
+    transport: Option<Transport>,
+
+    /// Kubernetes namespace of the pod, with --transport=kubectl [default: kubectl's current
+    /// namespace]
+    #[arg(
+        long,
+        env = "ASPECT_REAUTH_NAMESPACE",
+        conflicts_with = "transport_cmd"
+    )]
+    namespace: Option<String>,
+
+    /// Sync to this local container, through `docker exec`, or `podman exec` with
+    /// --transport=podman, in place of a host
+    #[arg(long, value_name = "NAME", conflicts_with = "transport_cmd")]
+    container: Option<String>,
+
+    /// Pod to run in, with --transport=kubectl [default: the host]
+    #[arg(long, env = "ASPECT_REAUTH_POD", conflicts_with = "transport_cmd")]
+    pod: Option<String>,
+
+    /// Read this ssh config file instead of ~/.ssh/config, as with ssh -F
+    #[arg(short = 'F', long, value_name = "PATH")]
+    ssh_config: Option<String>,
+
+    /// Authenticate with this identity file, as with ssh -i (takes multiple)
+    #[arg(short = 'i', long, value_name = "PATH", action = clap::ArgAction::Append)]
+    identity: Vec<String>,
+
+    /// Set an ssh option, as with ssh -o, e.g. --ssh-option StrictHostKeyChecking=accept-new
+    /// (takes multiple)
+    #[arg(
+        short = 'o',
+        long,
+        value_name = "KEY=VALUE",
+        value_parser = parse_ssh_option,
+        action = clap::ArgAction::Append,
+    )]
+    ssh_option: Vec<(String, String)>,
+
+    /// Give up on connecting to the host after this long (e.g. 10s), as with ssh's ConnectTimeout
+    #[arg(long, value_name = "DURATION", value_parser = humantime::parse_duration)]
+    connect_timeout: Option<Duration>,
+
+    /// Retry this many times when the connection to the host cannot be made or drops
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    retries: u32,
+
+    /// Wait this long before the first retry, doubling the wait for each one after
+    #[arg(long, value_name = "DURATION", default_value = "1s", value_parser = humantime::parse_duration)]
+    retry_delay: Duration,
+
+    /// Connect through this jump host, as with ssh -J (takes multiple, connected through in order)
+    #[arg(short = 'J', long, value_name = "HOST", action = clap::ArgAction::Append)]
+    jump_host: Vec<String>,
+
+    /// Port to connect to on the host, as with ssh -p
+    #[arg(long)]
+    port: Option<u16>,
+
+    /// User to log in to the host as, as with ssh -l
+    #[arg(long, value_name = "USER")]
+    login_user: Option<String>,
+
+    /// Call SSH with an additional argument (takes multiple: --ssh-arg='-p 23' --ssh-arg='-A'); an
+    /// option and its value may be given together or as two --ssh-args
+    #[arg(
+        short = 'A',
+        long = "ssh-arg",
+        alias = "ssh_arg",
+        allow_hyphen_values = true,
+        action = clap::ArgAction::Append,
+    )]
     ssh_args: Vec<String>,
+
+    /// Also sync local credentials for this container registry to the remote's docker credential
+    /// store (takes multiple)
+    #[arg(long = "docker-registry", value_name = "REGISTRY", action = clap::ArgAction::Append)]
+    docker_registries: Vec<String>,
+
+    /// Also sync the local git credential for this HTTPS host (e.g. github.com) to the remote's
+    /// git credential helper (takes multiple)
+    #[arg(long = "git-host", value_name = "HOST", action = clap::ArgAction::Append)]
+    git_hosts: Vec<String>,
+
+    /// Non-interactive mode for CI: read the credential from $ASPECT_REAUTH_CREDENTIAL or stdin,
+    /// never log in, and report the result as JSON
+    #[arg(long)]
+    ci: bool,
+
+    /// Run the sync jobs described by a JSON document on stdin, reporting their outcomes as JSON
+    #[arg(long, conflicts_with_all = ["ci", "dry_run", "exec"])]
+    batch: bool,
+
+    /// In CI mode, only check whether the host's credential is valid, without syncing
+    #[arg(long, requires = "ci")]
+    check: bool,
+
+    /// Print what would be run, on which hosts, without running anything
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Forward your ssh agent to the host for the command (not for the sync itself); anyone with
+    /// root on the host can use your keys while it runs
+    #[arg(long, requires = "exec")]
+    forward_agent: bool,
+
+    /// How to report the outcome: text, or json for a single JSON object on stdout
+    #[arg(long, value_name = "FORMAT", default_value = "text", conflicts_with_all = ["ci", "batch", "dry_run", "exec"])]
+    output: output::Format,
+
+    /// Exit with 4 rather than 0 if the credential was pushed to the host, e.g. to decide whether
+    /// to restart a remote bazel server
+    #[arg(long, conflicts_with = "exec")]
+    report_action_in_exit_code: bool,
+
+    /// Command to run on the host once its credential is fresh, e.g. aspect-reauth devbox -- bazel
+    /// build //...
+    #[arg(last = true, value_name = "COMMAND", conflicts_with = "ci")]
+    exec: Vec<String>,
+
+    /// Neither reuse nor remember --ssh-arg, --keyring and --key-name for this host, forgetting
+    /// any remembered before
+    #[arg(long)]
+    no_remember: bool,
+
+    /// What to remember for the host if the sync succeeds.
+    #[arg(skip)]
+    remember: state::HostOptions,
+
+    /// Set from the config file.
+    #[arg(skip)]
+    check_for_updates: bool,
+
+    /// Answer yes to any confirmation, and never stop to ask anything else (such as the first-run
+    /// setup or which host to sync to)
+    #[arg(short = 'y', long, global = true)]
+    yes: bool,
+
+    /// When to color output; auto colors terminals unless NO_COLOR is set
+    #[arg(long, global = true, value_name = "WHEN", default_value = "auto")]
+    color: clap::ColorChoice,
+
+    /// Inject faults into remote commands (test builds only)
+    #[cfg(feature = "test-support")]
+    #[arg(long, hide = true)]
+    chaos: Option<aspect_reauth::transport::chaos::ChaosSpec>,
+
+    /// Record remote commands and their responses to a fixture file (test builds only)
+    #[cfg(feature = "test-support")]
+    #[arg(long, hide = true, value_name = "FILE")]
+    record: Option<std::path::PathBuf>,
+
+    /// Keep keychain entries as files in this directory instead (test builds only)
+    #[cfg(feature = "test-support")]
+    #[arg(long, global = true, hide = true, value_name = "DIR")]
+    keychain_dir: Option<std::path::PathBuf>,
 }
 
-fn main() -> Result<()> {
-    smol::block_on(async_main())
+#[derive(Subcommand, Clone)]
+enum Command {
+    /// Print a one-character credential freshness indicator for shell prompts, using only the
+    /// state recorded by previous runs
+    PromptStatus(prompt_status::PromptStatusArgs),
+
+    /// Report whether a host's credential is in place and accepted, without logging in or syncing
+    Status(status::StatusArgs),
+
+    /// Remove the credential from a host and from the local keychain
+    Logout(logout::LogoutArgs),
+
+    /// Explain how a sync to a host would go
+    Doctor(doctor::DoctorArgs),
+
+    /// Stay running and keep a host's credential fresh, logging in again shortly before it
+    /// expires
+    Watch(watch::WatchArgs),
+
+    /// Remove control sockets left behind by interrupted runs, and forget hosts not synced to in
+    /// a long time
+    Prune(prune::PruneArgs),
+
+    /// Print the local credential to stdout, for piping into another tool
+    ExportToken(export_token::ExportTokenArgs),
+
+    /// Print a script that sets up completion for a shell, e.g. `source <(aspect-reauth
+    /// completions bash)`
+    Completions(completions::CompletionsArgs),
+
+    /// Help with configuration
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+}
+
+#[derive(Subcommand, Clone)]
+enum ConfigCommand {
+    /// Suggest ssh settings that make connecting to a host faster, and optionally add them to
+    /// ~/.ssh/config
+    SuggestSsh(suggest_ssh::SuggestSshArgs),
+}
+
+fn main() -> ExitCode {
+    completions::complete();
+    match smol::block_on(run()) {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!(
+                "{}Error:{} {e:?}",
+                style::ERROR,
+                style::ERROR.render_reset()
+            );
+            exit_code(&e)
+        }
+    }
 }
 
-async fn async_main() -> Result<()> {
-    let mut args = Args::parse();
-    if args.no_create_socket {
-        args.create_socket = CreateSocket::Specify(false);
+/// The exit code for a run that failed with `e`: one for the kind of failure if it is known, else
+/// 1.
+fn exit_code(e: &anyhow::Error) -> ExitCode {
+    if let Some(interrupted) = e.downcast_ref::<Interrupted>() {
+        // As a shell reports a command killed by the signal.
+        return ExitCode::from(128 + interrupted.number);
     }
-    if args.force {
-        args.force_remote = true;
-        args.force_local = true;
+    match e.downcast_ref::<ReauthError>() {
+        Some(ReauthError::Ssh { .. }) => ExitCode::from(EXIT_SSH_FAILED),
+        Some(ReauthError::HelperSpawn { .. } | ReauthError::Login(_)) => {
+            ExitCode::from(EXIT_LOGIN_FAILED)
+        }
+        Some(ReauthError::LoginTimedOut(_)) => ExitCode::from(EXIT_LOGIN_TIMED_OUT),
+        Some(ReauthError::Keyctl { .. } | ReauthError::Store(_)) => {
+            ExitCode::from(EXIT_KEYCTL_FAILED)
+        }
+        Some(ReauthError::TimedOut(_)) => ExitCode::from(EXIT_TIMED_OUT),
+        Some(ReauthError::Keyring { .. }) | None => ExitCode::FAILURE,
     }
-    let args = Arc::new(args);
+}
 
-    let ssh = SshMux::new(&args.host, &args.ssh_args, args.create_socket)
-        .await
-        .context("failed setting up ssh session")?;
+async fn run() -> Result<ExitCode> {
+    let matches = Args::command().get_matches();
+    let args = Args::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    style::init(args.color);
+    #[cfg(feature = "test-support")]
+    if let Some(dir) = &args.keychain_dir {
+        aspect_reauth::keychain_dir::install(dir);
+    }
+    if args.yes {
+        interactive::assume_yes();
+    }
+    match &args.command {
+        Some(Command::PromptStatus(cmd)) => {
+            prompt_status::run(cmd);
+            Ok(ExitCode::SUCCESS)
+        }
+        Some(Command::Status(cmd)) => {
+            let args = host_args(&args, cmd.host.clone(), &matches)?;
+            status::run(&args).await
+        }
+        Some(Command::Logout(cmd)) => {
+            let args = host_args(&args, cmd.host.clone(), &matches)?;
+            logout::run(&args).await?;
+            Ok(ExitCode::SUCCESS)
+        }
+        Some(Command::Doctor(cmd)) => {
+            let args = host_args(&args, cmd.host.clone(), &matches)?;
+            doctor::run(&args).await
+        }
+        Some(Command::Watch(cmd)) => {
+            let args = host_args(&args, cmd.host.clone(), &matches)?;
+            watch::run(cmd, &args).await?;
+            Ok(ExitCode::SUCCESS)
+        }
+        Some(Command::Prune(cmd)) => {
+            prune::run(cmd, config::Config::load()?.socket_dir.as_deref())?;
+            Ok(ExitCode::SUCCESS)
+        }
+        Some(Command::ExportToken(cmd)) => {
+            let args = local_args(&args, &matches)?;
+            let options = args.sync_options();
+            export_token::run(cmd, &options, &args.keyring(&options)).await?;
+            Ok(ExitCode::SUCCESS)
+        }
+        Some(Command::Completions(cmd)) => {
+            completions::run(cmd)?;
+            Ok(ExitCode::SUCCESS)
+        }
+        Some(Command::Config {
+            command: ConfigCommand::SuggestSsh(cmd),
+        }) => {
+            let args = local_args(&args, &matches)?;
+            suggest_ssh::run(cmd, &configured_host()?, args.ssh()).await?;
+            Ok(ExitCode::SUCCESS)
+        }
+        None => async_main(args, &matches).await,
+    }
+}
 
-    let remote_needs_refresh = async {
-        Ok::<bool, anyhow::Error>(args.force_remote || needs_refresh(&args, Some(&ssh)).await?)
+async fn async_main(mut args: Args, matches: &ArgMatches) -> Result<ExitCode> {
+    let interactive = !args.ci && !args.batch && interactive::is_interactive();
+    let wizard = if interactive
+        && !args.dry_run
+        && args.output == output::Format::Text
+        && args.host.is_none()
+        && !config::Config::exists()
+    {
+        wizard::run(&mut args)?
+    } else {
+        None
     };
-    if args.force_local || needs_refresh(&args, None).await? {
-        let status = Command::new(&args.credential_helper)
-            .arg("login")
-            .arg(&args.remote)
-            .stdin(Stdio::null())
-            .status()
-            .await
-            .with_context(|| format!("failed to spawn {}", &args.credential_helper))?;
-        if !status.success() {
-            anyhow::bail!("{} login: {}", args.credential_helper, status);
+    if args.batch {
+        // Each job names its host, and so gets the config for it.
+        return batch::run(&args, matches).await;
+    }
+    args.prepare(config::Config::load()?, matches, interactive)?;
+    if args.dry_run {
+        interruptible(&args, dry_run::print(&args)).await?;
+        return Ok(ExitCode::SUCCESS);
+    }
+    if args.ci {
+        return Ok(ci::run(&args).await);
+    }
+
+    let update_check = args.check_for_updates.then(update_check::spawn);
+    let started = Instant::now();
+    let result = interruptible(&args, sync(&args)).await.inspect_err(|e| {
+        // The sync records its own failures, but not being cut short.
+        if matches!(e.downcast_ref(), Some(ReauthError::TimedOut(_))) {
+            record(&args, Status::Error);
         }
-        let password = get_credential("AspectWorkflows", &args)
+    });
+    if let Some(check) = update_check {
+        update_check::report(check).await;
+    }
+    if args.output == output::Format::Json {
+        return Ok(output::print(&args, &result, started.elapsed()));
+    }
+    let synced = result?.synced;
+    if let Some(config) = wizard {
+        let path = config.save()?;
+        args.say(format_args!(
+            "{}Saved these settings to {}.{}",
+            style::SUCCESS,
+            path.display(),
+            style::SUCCESS.render_reset()
+        ));
+    }
+    if !args.exec.is_empty() {
+        return exec::run(&args).await;
+    }
+    Ok(if synced && args.report_action_in_exit_code {
+        ExitCode::from(EXIT_SYNCED)
+    } else {
+        ExitCode::SUCCESS
+    })
+}
+
+/// The interactive sync: logs in locally if need be, then syncs the remote. If a `watch` is
+/// already keeping the host in sync, it is asked to do this instead.
+async fn sync(args: &Args) -> Result<Outcome> {
+    if let Some(outcome) = delegate(args).await {
+        let outcome = outcome?;
+        report(args, &outcome);
+        return Ok(outcome);
+    }
+    let ssh = connect(args)
+        .await
+        .inspect_err(|_| record(args, Status::Error))?;
+    let result = sync_over(args, &ssh).await;
+    disconnect(ssh, result).await
+}
+
+/// Does the rest of `sync` over `ssh`, once connected.
+async fn sync_over(args: &Args, ssh: &impl RemoteTransport) -> Result<Outcome> {
+    let options = args.sync_options();
+    let source = args.source(&options);
+    let sink = Probed::new(args.sink(&options)?, &options);
+    let outcome = ReauthFlow::new(&source, ssh, &sink, &options)
+        .force_local(args.force_local)
+        .force_remote(args.force_remote)
+        .run()
+        .await
+        .inspect_err(|_| record(args, Status::Error))?;
+    if outcome.synced {
+        note_fallback(args, sink.sink());
+    }
+    record(args, Status::Valid);
+    remember(args);
+    if !args.docker_registries.is_empty() {
+        docker::sync_registries(ssh, &args.docker_registries)
             .await
-            .context("failed to fetch fresh password from by aspect-credential-helper")?;
-        set_credential("aspect-reauth", &args, password)
+            .context("failed to sync container registry credentials")?;
+        args.say(format_args!(
+            "{}Registry credentials synced to {}.{}",
+            style::SUCCESS,
+            args.host(),
+            style::SUCCESS.render_reset()
+        ));
+    }
+    if !args.git_hosts.is_empty() {
+        git::sync_hosts(ssh, &args.git_hosts)
             .await
-            .context("failed to store password for aspect-reauth")?;
+            .context("failed to sync git credentials")?;
+        args.say(format_args!(
+            "{}Git credentials synced to {}.{}",
+            style::SUCCESS,
+            args.host(),
+            style::SUCCESS.render_reset()
+        ));
+    }
+    report(args, &outcome);
+    Ok(outcome)
+}
+
+/// Asks a `watch` running for the host in `args`, if there is one, to sync it now, and returns
+/// what came of it. Registries and git hosts are synced over the run's own connection, so a run
+/// that asks for them does not delegate.
+async fn delegate(args: &Args) -> Option<Result<Outcome>> {
+    if !args.docker_registries.is_empty() || !args.git_hosts.is_empty() {
+        return None;
+    }
+    let request = control::Request {
+        force_local: args.force_local,
+        force_remote: args.force_remote,
+        settings: args.watch_settings(),
+    };
+    let outcome = control::delegate(args.host(), request).await?;
+    args.say(format_args!(
+        "Asked the watch running for {} to sync it.",
+        args.host()
+    ));
+    Some(outcome)
+}
+
+/// Says how a sync to the host in `args` went.
+fn report(args: &Args, outcome: &Outcome) {
+    let left = outcome.expires.map(|expires| {
+        expires
+            .duration_since(SystemTime::now())
+            .unwrap_or_default()
+    });
+    let validity = match left {
+        Some(left) => format!(" It expires {}.", human::from_now(left)),
+        None => String::new(),
+    };
+    if outcome.synced {
+        args.say(format_args!(
+            "{}Aspect credentials synced to {}.{}{validity} Have a nice day.",
+            style::SUCCESS,
+            args.host(),
+            style::SUCCESS.render_reset()
+        ));
+    } else {
+        args.say(format_args!(
+            "Credential refresh not needed.{validity} Have a nice day."
+        ));
     }
-    if !remote_needs_refresh.await? {
-        println!("Credential refresh not needed. Have a nice day.");
-        return Ok(());
+    if let Some(left) = left.filter(|&left| left < args.min_validity) {
+        eprintln!(
+            "{}warning:{} the fresh credential only has {} left, less than --min-validity",
+            style::WARNING,
+            style::WARNING.render_reset(),
+            human::duration(left)
+        );
     }
+}
 
-    let password = match get_credential("aspect-reauth", &args).await {
-        Ok(p) => p,
-        _ => {
-            let password = get_credential("AspectWorkflows", &args)
+/// A run stopped by SIGINT or SIGTERM.
+#[derive(Debug, thiserror::Error)]
+#[error("interrupted by {name}")]
+struct Interrupted {
+    name: &'static str,
+    number: u8,
+}
+
+/// Listens for the signals that interrupt a run: SIGINT and SIGTERM, or only SIGINT (Ctrl-C) on
+/// Windows, which has no SIGTERM to listen for.
+fn interrupts() -> Result<Signals> {
+    let signals: &[Signal] = if cfg!(unix) {
+        &[Signal::Int, Signal::Term]
+    } else {
+        &[Signal::Int]
+    };
+    Signals::new(signals.iter().copied()).context("failed to listen for signals")
+}
+
+/// Runs `sync` until it finishes, `--timeout` runs out, or SIGINT or SIGTERM arrives. Giving up
+/// on it drops it, which kills whatever it was waiting on, stops the control master, and removes
+/// its socket.
+async fn interruptible<T>(args: &Args, sync: impl Future<Output = Result<T>>) -> Result<T> {
+    let mut signals = interrupts()?;
+    let interrupted = async {
+        let (name, number) = match signals.next().await {
+            Some(Ok(Signal::Term)) => ("SIGTERM", 15),
+            _ => ("SIGINT", 2),
+        };
+        Err(Interrupted { name, number }.into())
+    };
+    let timed_out = async {
+        match args.timeout {
+            Some(timeout) => {
+                smol::Timer::after(timeout).await;
+                Err(ReauthError::TimedOut(timeout).into())
+            }
+            None => future::pending().await,
+        }
+    };
+    future::or(sync, future::or(interrupted, timed_out)).await
+}
+
+/// Closes `ssh` once the run over it finished with `result`, which is returned as it is: a failure
+/// to close is only warned about.
+async fn disconnect<T>(ssh: impl RemoteTransport, result: Result<T>) -> Result<T> {
+    if let Err(e) = ssh.close().await {
+        eprintln!(
+            "{}warning:{} {e:#}",
+            style::WARNING,
+            style::WARNING.render_reset()
+        );
+    }
+    result
+}
+
+/// Sets up the session to the target host: over ssh, or through `--transport-cmd`. Failures to
+/// connect, then and for each remote command, are retried as `--retries` says.
+async fn connect(args: &Args) -> Result<impl RemoteTransport + '_> {
+    let backoff = Backoff {
+        retries: args.retries,
+        delay: args.retry_delay,
+    };
+    let mut n = 0;
+    let ssh = loop {
+        match open_session(args).await {
+            Ok(ssh) => break ssh,
+            Err(e)
+                if n < backoff.retries
+                    && matches!(e.downcast_ref(), Some(ReauthError::Ssh { .. })) =>
+            {
+                let delay = backoff.delay(n);
+                // Only the outermost context; the whole error is there if the last try fails.
+                eprintln!(
+                    "{}warning:{} {e}; retrying in {}",
+                    style::WARNING,
+                    style::WARNING.render_reset(),
+                    humantime::format_duration(delay)
+                );
+                smol::Timer::after(delay).await;
+                n += 1;
+            }
+            Err(e) => return Err(e),
+        }
+    };
+    #[cfg(feature = "test-support")]
+    let ssh = {
+        use aspect_reauth::transport::{chaos::Chaos, record::Recorder};
+        let ssh = Recorder::new(ssh, args.record.clone());
+        Chaos::new(ssh, args.chaos.clone().unwrap_or_default())
+    };
+    Ok(Retry::new(ssh, backoff))
+}
+
+async fn open_session(args: &Args) -> Result<Session<'_>> {
+    let ssh = match &args.transport_cmd {
+        Some(template) => Session::Command(
+            CommandTransport::new(template, args.host())
                 .await
-                .context("failed to fetch password from aspect-credential-helper")?;
-            if let Err(e) = set_credential("aspect-reauth", &args, password.clone()).await {
-                eprintln!("failed to sync aspect-reauth password:\n{e}");
+                .context("failed reaching the host through --transport-cmd")?,
+        ),
+        None => {
+            let ssh = SshMux::new(
+                args.ssh(),
+                args.host(),
+                &args.ssh_args,
+                args.create_socket,
+                args.socket_dir.as_deref(),
+                args.socket_mode.unwrap_or_default(),
+            )
+            .await
+            .context("failed setting up ssh session")?;
+            if let Some(why) = ssh.unusable_master() {
+                eprintln!(
+                    "{}warning:{} the control master for {} is not usable ({why}); started another",
+                    style::WARNING,
+                    style::WARNING.render_reset(),
+                    args.host()
+                );
             }
-            password
+            Session::Ssh(ssh)
         }
     };
+    Ok(ssh)
+}
+
+/// `args` with the local settings from the config file applied, for subcommands.
+fn local_args(args: &Args, matches: &ArgMatches) -> Result<Args> {
+    let mut args = args.clone();
+    args.apply_local_config(&config::Config::load()?, matches);
+    Ok(args)
+}
 
-    let key_name = format!("keyring-rs:{}@AspectWorkflows", args.remote);
-    let keychain = if args.session_keyring { "@s" } else { "@u" };
-    let mut child = ssh
-        .command("keyctl")
-        .args(["padd", "user", &key_name, keychain])
-        .stdin(Stdio::piped())
-        .stdout(Stdio::null())
-        .stderr(Stdio::piped())
-        .spawn()
-        .with_context(|| format!("failed to run keyctl on {}", &args.host))?;
-    let mut stdin = child.stdin.take().context("failed to open stdin")?;
-    stdin.write_all(password.as_bytes()).await?;
-    drop(stdin);
-    let output = child.output().await?;
-    if !output.status.success() {
-        anyhow::bail!(
-            "ssh {} keyctl padd: {}\n\n{}",
-            args.host,
-            output.status,
-            String::from_utf8_lossy(&output.stderr).trim(),
+/// `args` set up as for a sync to `host`, as far as the config file and remembered options go, for
+/// subcommands that talk to the host.
+fn host_args(args: &Args, host: Option<String>, matches: &ArgMatches) -> Result<Args> {
+    let mut args = args.clone();
+    args.host = host;
+    args.apply_config(config::Config::load()?, matches, false)?;
+    args.finish_ssh_args()?;
+    Ok(args)
+}
+
+/// The host for subcommands that take one, when it is not given on the command line or in the
+/// environment.
+fn configured_host() -> Result<String> {
+    Ok(config::Config::load()?
+        .default_host
+        .unwrap_or_else(|| DEFAULT_HOST.into()))
+}
+
+/// Chooses a host when none was given on the command line or in the environment: the configured
+/// `default_host`, else one of the configured `hosts` (picked by the user if there are several),
+/// else `None` for the built-in default.
+fn default_host(config: &config::Config, interactive: bool) -> Result<Option<String>> {
+    if config.default_host.is_some() {
+        return Ok(config.default_host.clone());
+    }
+    let hosts: Vec<&str> = config.hosts.keys().map(String::as_str).collect();
+    match hosts[..] {
+        [] => Ok(None),
+        [host] => Ok(Some(host.into())),
+        _ if !interactive => anyhow::bail!(
+            "several hosts are configured ({}); name one on the command line or set default_host",
+            hosts.join(", ")
+        ),
+        _ => {
+            // Start on whichever of them was synced most recently.
+            let state = state::State::load().unwrap_or_default();
+            let latest = hosts
+                .iter()
+                .enumerate()
+                .filter_map(|(i, host)| Some((i, state.hosts.get(*host)?.checked_at)))
+                .max_by_key(|&(_, checked_at)| checked_at)
+                .map_or(0, |(i, _)| i);
+            interactive::pick("Host to sync to", &hosts, latest).map(|host| Some(host.into()))
+        }
+    }
+}
+
+fn parse_env(s: &str) -> Result<(String, String)> {
+    let (key, value) = s.split_once('=').context("expected KEY=VALUE")?;
+    if key.is_empty() {
+        anyhow::bail!("empty variable name");
+    }
+    Ok((key.into(), value.into()))
+}
+
+fn parse_keychain_fallback(s: &str) -> Result<source::SourceKind> {
+    match s.parse()? {
+        source::SourceKind::Keyring => anyhow::bail!("the keychain cannot stand in for itself"),
+        kind => Ok(kind),
+    }
+}
+
+fn parse_ssh_option(s: &str) -> Result<(String, String)> {
+    let (key, value) = s.split_once('=').context("expected KEY=VALUE")?;
+    if key.is_empty() || !key.chars().all(|c| c.is_ascii_alphanumeric()) {
+        anyhow::bail!("{key:?} is not an ssh option name");
+    }
+    if value.is_empty() {
+        anyhow::bail!("no value for {key}");
+    }
+    Ok((key.into(), value.into()))
+}
+
+/// Either of `flag` and the same setting from the host, which must agree if both are given.
+fn merge<T: PartialEq + std::fmt::Display>(
+    flag: Option<T>,
+    from_host: Option<T>,
+    name: &str,
+) -> Result<Option<T>> {
+    match (flag, from_host) {
+        (Some(flag), Some(from_host)) if flag != from_host => {
+            anyhow::bail!("the host gives {from_host} but {name} gives {flag}")
+        }
+        (flag, from_host) => Ok(flag.or(from_host)),
+    }
+}
+
+/// Records the outcome for `prompt-status`. Failing to do so is not worth failing the run over.
+fn record(args: &Args, status: Status) {
+    if let Err(e) = state::record(args.host(), &args.remote, status) {
+        eprintln!(
+            "{}warning:{} failed to record state: {e:#}",
+            style::WARNING,
+            style::WARNING.render_reset()
         );
     }
+}
 
-    if needs_refresh(&args, Some(&ssh)).await? {
-        anyhow::bail!(
-            concat!(
-                "We tried syncing your credentials to {} but they are still invalid.\n",
-                "Try rerunning with --force."
-            ),
-            args.host
+/// Remembers the options of a successful sync for next time. As with `record`, failing to is only
+/// worth a warning.
+fn remember(args: &Args) {
+    if let Err(e) = state::remember(args.host(), &args.remember) {
+        eprintln!(
+            "{}warning:{} failed to remember options: {e:#}",
+            style::WARNING,
+            style::WARNING.render_reset()
         );
     }
+}
 
-    println!(
-        "Aspect credentials synced to {}. Have a nice day.",
-        args.host
-    );
-    Ok(())
+/// Says where a synced credential went if `sink` fell back to a file for want of keyctl, and how to
+/// point the credential helper at it. This goes to stderr, even in CI mode, which keeps stdout for
+/// its report.
+fn note_fallback(args: &Args, sink: &AnySink) {
+    if let AnySink::Keyctl(keyctl) = sink
+        && let Some(file) = keyctl.fell_back()
+    {
+        eprintln!(
+            "{}warning:{} {} has no keyctl, so the credential is in a file there; point the \
+             credential helper at it with:\n\n    export {}=\"{}\"\n",
+            style::WARNING,
+            style::WARNING.render_reset(),
+            args.host(),
+            sink::FILE_VAR,
+            file.path()
+        );
+    }
 }
 
-async fn needs_refresh<'a>(args: &'a Args, ssh: Option<&'a SshMux<'a, String>>) -> Result<bool> {
-    let helper = &args.credential_helper;
-    let mut cmd = ssh
-        .map(|ssh| ssh.command(helper))
-        .unwrap_or_else(|| Command::new(helper));
-    let mut child = cmd
-        .arg("get")
-        .stdin(Stdio::piped())
-        .stdout(Stdio::null())
-        .stderr(Stdio::piped())
-        .spawn()
-        .with_context(|| {
-            ssh.is_some()
-                .then(|| format!("failed to run {helper} on {}", &args.host))
-                .unwrap_or_else(|| format!("failed to run {helper}"))
-        })?;
-    let mut stdin = child.stdin.take().context("failed to open stdin")?;
-    let test_string = format!(concat!(r#"{{"uri":"https://{}"}}"#, "\n"), &args.remote);
-    stdin.write_all(test_string.as_bytes()).await?;
-    drop(stdin);
-    let output = child
-        .output()
-        .await
-        .with_context(|| format!("failed waiting for {}", &args.credential_helper))?;
-    if !output.status.success() {
-        let re = Regex::new(&format!(
-            r"(?mis)please\s+run.*{}\s+login",
-            regex::escape(&args.credential_helper)
-        ))
-        .context("failed to compile regex")?;
-        if !re.is_match(&output.stderr) {
+impl Args {
+    /// Readies the arguments for a sync: applies `config`, checks them, and sets the options that
+    /// others imply.
+    fn prepare(
+        &mut self,
+        config: config::Config,
+        matches: &ArgMatches,
+        interactive: bool,
+    ) -> Result<()> {
+        self.apply_config(config, matches, interactive)?;
+        // Catch a bad template before doing anything.
+        self.key_name()?;
+        self.finish_ssh_args()?;
+        if self.no_create_socket {
+            self.create_socket = SocketStrategy::Never;
+        }
+        if self.force {
+            self.force_remote = true;
+            self.force_local = true;
+        }
+        Ok(())
+    }
+
+    /// Splits `ssh_args` into what ssh takes, and puts the arguments that the dedicated ssh flags
+    /// stand for in front of them.
+    fn finish_ssh_args(&mut self) -> Result<()> {
+        let dedicated = self.connect_timeout.is_some()
+            || self.ssh_config.is_some()
+            || !self.identity.is_empty()
+            || !self.ssh_option.is_empty()
+            || !self.jump_host.is_empty()
+            || self.port.is_some()
+            || self.login_user.is_some();
+        if dedicated && self.transport_cmd.is_some() {
             anyhow::bail!(
-                "{} get: {}\n\n{}",
-                args.credential_helper,
-                output.status,
-                String::from_utf8_lossy(&output.stderr).trim(),
+                "a connect timeout, ssh config file, identity, option, jump host, port, or \
+                 login user only applies to ssh; give it in the transport command instead"
             );
         }
-        return Ok(true);
+        let mut ssh_args = Vec::new();
+        if let Some(path) = &self.ssh_config {
+            ssh_args.extend(["-F".into(), path.clone()]);
+        }
+        for path in &self.identity {
+            ssh_args.extend(["-i".into(), path.clone()]);
+        }
+        if let Some(timeout) = self.connect_timeout {
+            // ssh takes whole seconds, and 0 would mean no timeout at all.
+            let secs = timeout.as_secs() + u64::from(timeout.subsec_nanos() > 0);
+            ssh_args.push(format!("-oConnectTimeout={}", secs.max(1)));
+        }
+        for (key, value) in &self.ssh_option {
+            ssh_args.extend(["-o".into(), format!("{key}={value}")]);
+        }
+        if !self.jump_host.is_empty() {
+            // ssh takes a single -J, with the hops separated by commas.
+            ssh_args.extend(["-J".into(), self.jump_host.join(",")]);
+        }
+        if let Some(port) = self.port {
+            ssh_args.extend(["-p".into(), port.to_string()]);
+        }
+        if let Some(user) = &self.login_user {
+            ssh_args.extend(["-l".into(), user.clone()]);
+        }
+        ssh_args.extend(ssh_mux::split_ssh_args(&self.ssh_args).context("invalid --ssh-arg")?);
+        self.ssh_args = ssh_args;
+        Ok(())
     }
-    Ok(false)
-}
 
-async fn get_credential(name: &'static str, args: &Arc<Args>) -> Result<String> {
-    let args = args.clone();
-    smol::unblock(move || -> Result<String> {
-        Entry::new(name, &args.remote)
-            .and_then(|e| e.get_password())
-            .context("failed to get aspect credential from keychain")
-    })
-    .await
-}
+    /// Fills in whatever was not given on the command line or in the environment from the options
+    /// remembered for the host, and then from `config` and the host's table in it.
+    fn apply_config(
+        &mut self,
+        config: config::Config,
+        matches: &ArgMatches,
+        interactive: bool,
+    ) -> Result<()> {
+        if let Some(container) = &self.container {
+            if matches.value_source("host") == Some(ValueSource::CommandLine) {
+                anyhow::bail!("--container takes the place of the host; give one or the other");
+            }
+            self.host = Some(container.clone());
+            match self.transport {
+                None => self.transport = Some(Transport::Docker),
+                Some(Transport::Docker | Transport::Podman) => {}
+                Some(_) => anyhow::bail!("--container needs --transport=docker or podman"),
+            }
+        }
+        if self.host.is_none() {
+            self.host = default_host(&config, interactive)?;
+        }
+        if let Some(host) = self.host.as_deref().filter(|_| self.container.is_none()) {
+            let destination: ssh_mux::Destination = host.parse().context("invalid host")?;
+            self.port = merge(self.port, destination.port, "--port")?;
+            self.login_user = merge(self.login_user.take(), destination.user, "--login-user")?;
+            self.host = Some(destination.host);
+        }
+        let config = config.for_host(self.host());
+        if self.session_keyring
+            && !self.keyring.contains(&KeyringTarget::Session)
+            && !self.keyring.contains(&KeyringTarget::Both)
+        {
+            self.keyring.push(KeyringTarget::Session);
+        }
+        if !self.no_remember && !self.batch {
+            let remembered = state::State::load()
+                .ok()
+                .and_then(|mut state| state.options.remove(self.host()))
+                .unwrap_or_default();
+            if self.ssh_args.is_empty() {
+                self.ssh_args = remembered.ssh_args;
+            }
+            if self.keyring.is_empty() {
+                self.keyring = remembered.keyring;
+            }
+            self.key_name = self.key_name.take().or(remembered.key_name);
+            self.remember = state::HostOptions {
+                ssh_args: self.ssh_args.clone(),
+                keyring: self.keyring.clone(),
+                key_name: self.key_name.clone(),
+            };
+        }
+        self.apply_local_config(&config, matches);
+        if self.ssh_args.is_empty() {
+            self.ssh_args = config.ssh_args;
+        }
+        if self.key_name.is_none() {
+            self.key_name = config.key_name;
+        }
+        if self.keyring.is_empty() {
+            self.keyring = config.keyring;
+        }
+        if self.socket_dir.is_none() {
+            self.socket_dir = config.socket_dir;
+        }
+        if self.socket_mode.is_none() {
+            self.socket_mode = config.socket_mode;
+        }
+        if self.sink.is_none() {
+            self.sink = config.sink;
+        }
+        if self.key_perm.is_none() {
+            self.key_perm = config.key_perm;
+        }
+        if self.transport_cmd.is_none() {
+            let transport = self
+                .transport
+                .or(config.transport.filter(|_| config.transport_cmd.is_none()));
+            if (self.namespace.is_some() || self.pod.is_some())
+                && transport != Some(Transport::Kubectl)
+            {
+                anyhow::bail!("--namespace and --pod only apply to --transport=kubectl");
+            }
+            let template = match transport {
+                Some(transport) => transport.template(
+                    self.namespace.as_deref().or(config.namespace.as_deref()),
+                    self.pod.as_deref().or(config.pod.as_deref()),
+                )?,
+                None => config.transport_cmd,
+            };
+            self.transport_cmd = template
+                .as_deref()
+                .map(str::parse)
+                .transpose()
+                .context("invalid transport_cmd")?;
+        }
+        self.check_for_updates = config.check_for_updates;
+        self.single_round_trip |= config.single_round_trip;
+        self.file_fallback |= config.file_fallback;
+        self.join_session_keyring |= config.join_session_keyring;
+        Ok(())
+    }
 
-async fn set_credential(name: &'static str, args: &Arc<Args>, password: String) -> Result<()> {
-    let args = args.clone();
-    smol::unblock(move || -> Result<()> {
-        Entry::new(name, &args.remote)
-            .and_then(|e| e.set_password(&password))
-            .context("failed to set aspect credential in keychain")
-    })
-    .await
-}
+    /// Fills in the settings for the local end, the remote and its credential helper and the ssh
+    /// client, from `config` where not given on the command line.
+    fn apply_local_config(&mut self, config: &config::Config, matches: &ArgMatches) {
+        let defaulted = |id| matches.value_source(id) == Some(ValueSource::DefaultValue);
+        if let Some(remote) = config.remote.clone().filter(|_| defaulted("remote")) {
+            self.remote = remote;
+        }
+        if let Some(helper) = config
+            .credential_helper
+            .clone()
+            .filter(|_| defaulted("credential_helper"))
+        {
+            self.credential_helper = helper;
+        }
+        if self.source.is_none() {
+            self.source = config.source.clone();
+        }
+        if self.keychain_fallback.is_none() {
+            self.keychain_fallback = config.keychain_fallback.clone();
+        }
+        if self.keyring_service.is_none() {
+            self.keyring_service = config.keyring_service.clone();
+        }
+        if self.keyring_account.is_none() {
+            self.keyring_account = config.keyring_account.clone();
+        }
+        if self.helper_args.is_empty() {
+            self.helper_args = config.helper_args.clone();
+        }
+        let mut login_env = config.login_env.clone();
+        login_env.extend(self.login_env.drain(..));
+        self.login_env = login_env.into_iter().collect();
+        if self.ssh_binary.is_none() {
+            self.ssh_binary = config.ssh_binary.clone();
+        }
+    }
+
+    /// Prints a progress message: to stdout normally, but to stderr when running a command or
+    /// reporting JSON so as not to mix with the output.
+    fn say(&self, message: std::fmt::Arguments) {
+        if self.exec.is_empty() && self.output == output::Format::Text {
+            println!("{message}");
+        } else {
+            eprintln!("{message}");
+        }
+    }
+
+    fn ssh(&self) -> &Path {
+        self.ssh_binary
+            .as_deref()
+            .unwrap_or(Path::new(ssh_mux::DEFAULT_SSH))
+    }
+
+    fn host(&self) -> &str {
+        self.host.as_deref().unwrap_or(DEFAULT_HOST)
+    }
+
+    fn key_name(&self) -> Result<String> {
+        let template = self.key_name.as_deref().unwrap_or(keyctl::DEFAULT_KEY_NAME);
+        keyctl::expand_key_name(template, &self.remote, self.host()).context("invalid --key-name")
+    }
+
+    /// The settings a `watch` for the host has to share with this run to sync for it.
+    fn watch_settings(&self) -> control::Settings {
+        fn list<T: ToString>(items: &[T]) -> String {
+            items
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(",")
+        }
+        fn or_default<T: ToString>(item: Option<&T>) -> String {
+            item.map(ToString::to_string).unwrap_or_default()
+        }
+        control::Settings::from([
+            ("--remote", self.remote.clone()),
+            ("--credential-helper", self.credential_helper.clone()),
+            (
+                "--keyring-service",
+                or_default(self.keyring_service.as_ref()),
+            ),
+            (
+                "--keyring-account",
+                or_default(self.keyring_account.as_ref()),
+            ),
+            ("--source", or_default(self.source.as_ref())),
+            (
+                "--keychain-fallback",
+                or_default(self.keychain_fallback.as_ref()),
+            ),
+            ("--ssh-arg", list(&self.ssh_args)),
+            ("--transport-cmd", or_default(self.transport_cmd.as_ref())),
+            ("--keyring", list(&self.keyring)),
+            ("--key-name", or_default(self.key_name.as_ref())),
+            (
+                "--key-perm",
+                self.key_perm
+                    .map(|perm| format!("{perm:#x}"))
+                    .unwrap_or_default(),
+            ),
+            ("--sink", or_default(self.sink.as_ref())),
+        ])
+    }
+
+    /// The local keychain, where the credential helper keeps the credential.
+    fn keyring(&self, options: &SyncOptions) -> source::Keyring {
+        source::Keyring::new(options)
+            .service(self.keyring_service.as_deref().unwrap_or(keyctl::SERVICE))
+            .account(self.keyring_account.as_deref())
+            .fallback(self.keychain_fallback.clone())
+    }
+
+    /// The source `--source` chooses; the keychain logs in with the helper's login options.
+    fn source(&self, options: &SyncOptions) -> source::AnySource {
+        match self.external_source() {
+            Some(kind) => source::AnySource::External(source::External::new(kind.clone())),
+            None => source::AnySource::Keyring(
+                self.keyring(options)
+                    .login_args(&self.helper_args)
+                    .login_env(&self.login_env)
+                    .login_timeout(self.login_timeout)
+                    .login_to_stderr(self.output == output::Format::Json),
+            ),
+        }
+    }
+
+    /// The source `--source` chooses, unless it is the keychain.
+    fn external_source(&self) -> Option<&source::SourceKind> {
+        self.source
+            .as_ref()
+            .filter(|kind| **kind != source::SourceKind::Keyring)
+    }
+
+    fn sink(&self, options: &SyncOptions) -> Result<AnySink> {
+        Ok(self.sink_named(options, self.key_name()?))
+    }
+
+    /// The sink `--sink` chooses, as it stores the credential on the host `transport` reaches:
+    /// keyctl, on a Mac or Windows, means its own credential store. This is for commands that look
+    /// at or remove what a sync stored; a sync itself only probes the host once it has something
+    /// to store, with [`Probed`].
+    async fn host_sink(
+        &self,
+        options: &SyncOptions,
+        transport: &impl RemoteTransport,
+    ) -> Result<AnySink> {
+        let sink = self.sink(options)?;
+        let capabilities = probe::probe(transport).await?;
+        Ok(capabilities.instead(&sink, options).unwrap_or(sink))
+    }
+
+    /// The sink `--sink` chooses, with keyctl storing the credential under `key_name`.
+    fn sink_named(&self, options: &SyncOptions, key_name: String) -> AnySink {
+        match self.sink.unwrap_or_default() {
+            SinkKind::Keyctl => AnySink::Keyctl(
+                sink::Keyctl::new(options)
+                    .key_name(key_name)
+                    .replace(self.replace_key)
+                    .single_round_trip(self.single_round_trip)
+                    .join_session(self.join_session_keyring)
+                    .perm(self.key_perm)
+                    .file_fallback(options, self.file_fallback),
+            ),
+            SinkKind::File => AnySink::File(sink::File::new(options)),
+            SinkKind::SecretTool => AnySink::SecretTool(sink::SecretTool::new(options)),
+            SinkKind::SystemdCreds => AnySink::SystemdCreds(sink::SystemdCreds::new(options)),
+            SinkKind::Security => AnySink::Security(sink::Security::new(options)),
+            SinkKind::CredentialManager => {
+                AnySink::CredentialManager(sink::CredentialManager::new(options))
+            }
+        }
+    }
 
-impl FromStr for CreateSocket {
-    type Err = anyhow::Error;
-
-    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
-        match s {
-            "infer" => Ok(CreateSocket::Infer),
-            // Regrettably there is not any easy way to get at clap's BoolishValueParser from here,
-            // so we inline its current implementation instead.
-            _ => Ok(CreateSocket::Specify(match s {
-                "y" | "yes" | "t" | "true" | "on" | "1" => true,
-                "n" | "no" | "f" | "false" | "off" | "0" => false,
-                _ => anyhow::bail!("unknown value {s}"),
-            })),
+    fn sync_options(&self) -> SyncOptions {
+        SyncOptions {
+            remote: self.remote.clone(),
+            credential_helper: self.credential_helper.clone(),
+            keyrings: if self.keyring.is_empty() {
+                vec![KeyringTarget::default()]
+            } else {
+                self.keyring.clone()
+            },
+            min_validity: self.min_validity,
         }
     }
 }
@@ -0,0 +1,88 @@
+// Copyright 2026 Stairwell, Inc.
+// Author: mrdomino@stairwell.com
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! `--output json`: reports an interactive sync as a single JSON object on stdout rather than
+//! prose, for scripts that wrap it. Progress messages, and anything the credential helper's login
+//! prints, go to stderr instead.
+
+use std::{process::ExitCode, time::Duration};
+
+use anyhow::Result;
+use aspect_reauth::flow::Outcome;
+use clap::ValueEnum;
+use serde::Serialize;
+
+use crate::{Args, EXIT_SYNCED, ci::Status, exit_code};
+
+#[derive(Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    #[default]
+    Text,
+    Json,
+}
+
+#[derive(Serialize)]
+struct Report {
+    host: String,
+    remote: String,
+    key_name: String,
+    status: Status,
+    /// Whether the helper was logged in again.
+    logged_in: bool,
+    /// The serial number of the key the credential was stored as, if it was synced.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    key: Option<i64>,
+    /// When the credential expires, in RFC 3339, if the helper says or it is a JWT that does.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    expires: Option<String>,
+    /// How long the run took, in seconds.
+    duration: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// Prints the report on a sync to the host in `args` that finished with `result` after
+/// `duration`, and returns the exit code the run would have had anyway.
+pub fn print(args: &Args, result: &Result<Outcome>, duration: Duration) -> ExitCode {
+    let outcome = result.as_ref().ok().copied().unwrap_or_default();
+    let report = Report {
+        host: args.host().into(),
+        remote: args.remote.clone(),
+        key_name: args.key_name().unwrap_or_default(),
+        status: match result {
+            Ok(outcome) if outcome.synced => Status::Synced,
+            Ok(_) => Status::Valid,
+            Err(_) => Status::Error,
+        },
+        logged_in: outcome.logged_in,
+        key: outcome.key,
+        expires: outcome
+            .expires
+            .map(|expires| humantime::format_rfc3339_seconds(expires).to_string()),
+        duration: duration.as_secs_f64(),
+        error: result.as_ref().err().map(|e| format!("{e:#}")),
+    };
+    match serde_json::to_string(&report) {
+        Ok(json) => println!("{json}"),
+        Err(e) => eprintln!("failed to serialize report: {e}"),
+    }
+    match result {
+        Ok(outcome) if outcome.synced && args.report_action_in_exit_code => {
+            ExitCode::from(EXIT_SYNCED)
+        }
+        Ok(_) => ExitCode::SUCCESS,
+        Err(e) => exit_code(e),
+    }
+}
@@ -0,0 +1,268 @@
+// Copyright 2026 Stairwell, Inc.
+// Author: mrdomino@stairwell.com
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Finding out, in one round trip before a sync, what the remote has to store the credential in,
+//! so that the sink can be chosen by that, or refused with what is missing, rather than by how its
+//! first command fails there.
+
+use std::{cell::OnceCell, process::Output};
+
+use anyhow::Result;
+
+use crate::{
+    ReauthError, SyncOptions,
+    helper::Check,
+    sink::{AnySink, CredentialManager, NOT_RECOGNIZED, RemoteOs, RemoteSink, Security, SinkKind},
+    transport::RemoteTransport,
+};
+
+/// The script `probe` runs with `sh -c`. It prints the remote's `uname -s`, then `has NAME` for
+/// each of the programs it has and for a runtime directory. It is passed to the remote shell as a
+/// word, so it has no `'` or `\` in it.
+pub const SCRIPT: &str = "uname -s; for program in keyctl secret-tool systemd-creds; do \
+    command -v \"$program\" >/dev/null 2>&1 && echo \"has $program\"; done; \
+    { test -n \"$XDG_RUNTIME_DIR\" || test -d \"/run/user/$(id -u)\"; } && echo has runtime-dir; \
+    exit 0";
+
+/// What a remote has to store the credential in, as `probe` found it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Capabilities {
+    pub os: RemoteOs,
+    /// Whether it has `keyctl`, from keyutils.
+    pub keyctl: bool,
+    /// Whether it has `secret-tool`, from libsecret-tools.
+    pub secret_tool: bool,
+    /// Whether it has `systemd-creds`.
+    pub systemd_creds: bool,
+    /// Whether it has a runtime directory, for a credential file and a session bus to be in:
+    /// `$XDG_RUNTIME_DIR` is set, or else `/run/user/UID` is there.
+    pub runtime_dir: bool,
+}
+
+/// Finds out what the remote on `transport` has. Windows has no `sh`, and its shells say so as
+/// [`remote_os`](crate::sink::remote_os) expects, so it is taken to have none of it.
+pub async fn probe<T: RemoteTransport>(transport: &T) -> Result<Capabilities> {
+    let output = transport.run("sh", &["-c", SCRIPT], b"").await?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let message = format!(
+            "{}sh -c ...: {}\n\n{}",
+            transport.prefix(),
+            output.status,
+            stderr.trim(),
+        );
+        return match output.status.code() {
+            Some(255) => Err(ReauthError::ssh(message).into()),
+            _ if stderr.contains(NOT_RECOGNIZED) => Ok(Capabilities {
+                os: RemoteOs::Windows,
+                keyctl: false,
+                secret_tool: false,
+                systemd_creds: false,
+                runtime_dir: false,
+            }),
+            _ => Err(anyhow::anyhow!("failed to probe the host: {message}")),
+        };
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let mut lines = stdout.lines();
+    let os = RemoteOs::from_uname(lines.next().unwrap_or_default().as_bytes());
+    let has: Vec<&str> = lines.filter_map(|line| line.strip_prefix("has ")).collect();
+    Ok(Capabilities {
+        os,
+        keyctl: has.contains(&"keyctl"),
+        secret_tool: has.contains(&"secret-tool"),
+        systemd_creds: has.contains(&"systemd-creds"),
+        runtime_dir: has.contains(&"runtime-dir"),
+    })
+}
+
+impl Capabilities {
+    /// Checks that the remote, `host`, can take the credential in a `kind` sink (with
+    /// `file_fallback`, as [`Keyctl::file_fallback`](crate::sink::Keyctl::file_fallback) has it).
+    /// If not, the error says what it lacks and which sinks it could take the credential in
+    /// instead.
+    pub fn check(&self, host: &str, kind: SinkKind, file_fallback: bool) -> Result<()> {
+        let Some(lacks) = self.lacks(kind, file_fallback) else {
+            return Ok(());
+        };
+        let mut message = format!("{host} {lacks}");
+        let instead: Vec<String> = self
+            .sinks()
+            .filter(|&other| other != kind)
+            .map(|other| format!("--sink={other}"))
+            .collect();
+        if !instead.is_empty() {
+            message.push_str("; try ");
+            message.push_str(&instead.join(" or "));
+        }
+        Err(ReauthError::Store(message).into())
+    }
+
+    /// The kinds of sink the remote can take the credential in. keyctl, which stands in for the
+    /// keychain of a Mac and the Credential Manager of Windows, is only among them if the remote
+    /// really has it.
+    pub fn sinks(&self) -> impl Iterator<Item = SinkKind> + '_ {
+        SinkKind::ALL.into_iter().filter(|&kind| match kind {
+            SinkKind::Keyctl => self.keyctl,
+            _ => self.lacks(kind, false).is_none(),
+        })
+    }
+
+    /// What stores the credential in place of `sink` on the remote, if anything does: for keyctl,
+    /// the host's own credential store on a Mac or Windows. Elsewhere, a keyctl sink is told what
+    /// was found, so that on a remote without keyctl it goes straight to its fallback file.
+    pub fn instead(&self, sink: &AnySink, options: &SyncOptions) -> Option<AnySink> {
+        let AnySink::Keyctl(keyctl) = sink else {
+            return None;
+        };
+        match self.os {
+            RemoteOs::Mac => Some(AnySink::Security(Security::new(options))),
+            RemoteOs::Windows => Some(AnySink::CredentialManager(CredentialManager::new(options))),
+            RemoteOs::Other => {
+                keyctl.probed(self);
+                None
+            }
+        }
+    }
+
+    /// What the remote lacks to take the credential in a `kind` sink, if anything.
+    fn lacks(&self, kind: SinkKind, file_fallback: bool) -> Option<&'static str> {
+        match kind {
+            SinkKind::Keyctl if self.keyctl || self.os != RemoteOs::Other => None,
+            SinkKind::Keyctl if file_fallback && !self.runtime_dir => Some(
+                "lacks keyutils, and has no runtime directory ($XDG_RUNTIME_DIR) for the fallback \
+                 file",
+            ),
+            SinkKind::Keyctl if file_fallback => None,
+            SinkKind::Keyctl => Some("lacks keyutils"),
+            SinkKind::File if !self.runtime_dir => {
+                Some("has no runtime directory ($XDG_RUNTIME_DIR) for the credential file")
+            }
+            SinkKind::SecretTool if !self.secret_tool => {
+                Some("lacks secret-tool (from libsecret-tools)")
+            }
+            SinkKind::SecretTool if !self.runtime_dir => Some(
+                "has no runtime directory ($XDG_RUNTIME_DIR), so no session bus to reach the \
+                 Secret Service on",
+            ),
+            SinkKind::SystemdCreds if !self.systemd_creds => Some("lacks systemd-creds"),
+            SinkKind::Security if self.os != RemoteOs::Mac => Some("is not a Mac"),
+            SinkKind::CredentialManager if self.os != RemoteOs::Windows => {
+                Some("does not run Windows")
+            }
+            _ => None,
+        }
+    }
+}
+
+/// A sink that probes the remote for what it has the first time there is a credential to store
+/// there, and then stores it as [`Capabilities::instead`] has it, or refuses to, saying what the
+/// remote lacks, as [`Capabilities::check`] does.
+///
+/// A check needs nothing probed, so a sync that finds the remote's credential valid costs no more
+/// round trips than it would without.
+pub struct Probed {
+    sink: AnySink,
+    options: SyncOptions,
+    instead: OnceCell<Option<AnySink>>,
+}
+
+impl Probed {
+    pub fn new(sink: AnySink, options: &SyncOptions) -> Self {
+        Probed {
+            sink,
+            options: options.clone(),
+            instead: OnceCell::new(),
+        }
+    }
+
+    /// The sink the credential goes to: the one given, unless a probe has found otherwise.
+    pub fn sink(&self) -> &AnySink {
+        self.instead
+            .get()
+            .and_then(Option::as_ref)
+            .unwrap_or(&self.sink)
+    }
+
+    /// Probes the remote on `transport`, if it has not been yet, and returns the sink the
+    /// credential goes to there.
+    async fn probed<T: RemoteTransport>(&self, transport: &T) -> Result<&AnySink> {
+        if self.instead.get().is_none() {
+            let capabilities = probe(transport).await?;
+            let file_fallback =
+                matches!(&self.sink, AnySink::Keyctl(keyctl) if keyctl.fallback.is_some());
+            capabilities.check(
+                transport.host().unwrap_or("localhost"),
+                self.sink.kind(),
+                file_fallback,
+            )?;
+            _ = self
+                .instead
+                .set(capabilities.instead(&self.sink, &self.options));
+        }
+        Ok(self.sink())
+    }
+}
+
+impl RemoteSink for Probed {
+    async fn preflight<T: RemoteTransport>(&self, transport: &T) -> Result<()> {
+        self.probed(transport).await?.preflight(transport).await
+    }
+
+    async fn store<T: RemoteTransport>(
+        &self,
+        transport: &T,
+        credential: &str,
+    ) -> Result<Option<i64>> {
+        self.probed(transport)
+            .await?
+            .store(transport, credential)
+            .await
+    }
+
+    async fn store_then_run<T: RemoteTransport>(
+        &self,
+        transport: &T,
+        credential: &str,
+        program: &str,
+        args: &[&str],
+        input: &str,
+    ) -> Result<(Option<i64>, Output)> {
+        self.probed(transport)
+            .await?
+            .store_then_run(transport, credential, program, args, input)
+            .await
+    }
+
+    async fn check<T: RemoteTransport>(
+        &self,
+        transport: &T,
+        options: &SyncOptions,
+    ) -> Result<Check> {
+        self.sink().check(transport, options).await
+    }
+
+    async fn store_and_check<T: RemoteTransport>(
+        &self,
+        transport: &T,
+        credential: &str,
+        options: &SyncOptions,
+    ) -> Result<(Option<i64>, Check)> {
+        self.probed(transport)
+            .await?
+            .store_and_check(transport, credential, options)
+            .await
+    }
+}
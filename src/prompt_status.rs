@@ -0,0 +1,106 @@
+// Copyright 2026 Stairwell, Inc.
+// Author: mrdomino@stairwell.com
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! `aspect-reauth prompt-status`: a credential freshness indicator for shell prompts.
+//!
+//! This answers purely from the state recorded by previous runs, so it never touches ssh or the
+//! keychain and is cheap enough to run on every prompt. Errors are reported as `?` rather than
+//! on stderr, since a prompt is no place for them.
+
+use std::time::Duration;
+
+use aspect_reauth::{
+    human,
+    state::{HostState, State, Status},
+};
+use clap::Args;
+use clap_complete::ArgValueCandidates;
+
+#[derive(Args, Clone)]
+pub struct PromptStatusArgs {
+    /// SSH hostname to report on [default: the most recently synced host]
+    #[arg(add = ArgValueCandidates::new(crate::completions::hosts))]
+    host: Option<String>,
+
+    /// How long after a successful sync to keep reporting the credential as fresh
+    #[arg(long, default_value = "12h", value_parser = humantime::parse_duration)]
+    max_age: Duration,
+
+    /// Print a word (ok, stale, expired, error, unknown) instead of a glyph
+    #[arg(long)]
+    word: bool,
+
+    /// Print the word and how long ago the host was last checked, e.g. "ok, checked 3h 12m ago"
+    #[arg(long, conflicts_with = "word")]
+    long: bool,
+}
+
+enum Freshness {
+    Ok,
+    Stale,
+    Expired,
+    Error,
+    Unknown,
+}
+
+impl Freshness {
+    fn of(state: Option<&HostState>, max_age: Duration) -> Self {
+        match state {
+            None => Self::Unknown,
+            Some(state) => match state.status {
+                Status::Valid if state.age() <= max_age => Self::Ok,
+                Status::Valid => Self::Stale,
+                Status::Invalid => Self::Expired,
+                Status::Error => Self::Error,
+            },
+        }
+    }
+
+    fn glyph(&self) -> &'static str {
+        match self {
+            Self::Ok => "✓",
+            Self::Stale => "~",
+            Self::Expired => "✗",
+            Self::Error => "!",
+            Self::Unknown => "?",
+        }
+    }
+
+    fn word(&self) -> &'static str {
+        match self {
+            Self::Ok => "ok",
+            Self::Stale => "stale",
+            Self::Expired => "expired",
+            Self::Error => "error",
+            Self::Unknown => "unknown",
+        }
+    }
+}
+
+pub fn run(args: &PromptStatusArgs) {
+    let state = State::load().unwrap_or_default();
+    let host_state = match &args.host {
+        Some(host) => state.hosts.get(host),
+        None => state.latest().map(|(_, state)| state),
+    };
+    let freshness = Freshness::of(host_state, args.max_age);
+    match host_state {
+        Some(state) if args.long => {
+            println!("{}, checked {}", freshness.word(), human::ago(state.age()))
+        }
+        _ if args.long || args.word => println!("{}", freshness.word()),
+        _ => println!("{}", freshness.glyph()),
+    }
+}
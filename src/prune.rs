@@ -0,0 +1,210 @@
+// Copyright 2026 Stairwell, Inc.
+// Author: mrdomino@stairwell.com
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! `aspect-reauth prune`: cleans up after runs that did not get to clean up after themselves.
+//!
+//! A run that is killed part way leaves its temporary control socket directory behind, a
+//! persistent control master that has exited leaves its socket, and hosts that are no longer synced
+//! to linger in the state file. There are no lock files to clean up.
+
+use std::{
+    env, fs, io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anstream::println;
+use anyhow::{Context, Result};
+use aspect_reauth::{
+    human,
+    ssh_mux::{FALLBACK_SOCKET_DIR, TEMP_SOCKET_NAME, TEMP_SOCKET_PREFIX, persistent_socket_dirs},
+    state::{self, State},
+};
+use clap::Args;
+
+/// How old a socket directory with no socket in it must be before it counts as abandoned rather
+/// than belonging to a run that is just starting.
+const GRACE: Duration = Duration::from_secs(60);
+
+#[derive(Args, Clone)]
+pub struct PruneArgs {
+    /// Forget hosts that have not been synced to for this long
+    #[arg(long, default_value = "30d", value_parser = humantime::parse_duration)]
+    older_than: Duration,
+
+    /// Report what would be removed without removing it
+    #[arg(long)]
+    dry_run: bool,
+}
+
+/// Prunes socket directories from the system temporary directory, from the one they go in when
+/// that is too deep, and from `socket_dir`, the configured one, if any, then persistent sockets
+/// whose master has exited.
+pub fn run(args: &PruneArgs, socket_dir: Option<&Path>) -> Result<()> {
+    let mut pruned = 0;
+    let mut dirs = socket_dirs(&env::temp_dir())?;
+    dirs.extend(socket_dirs(Path::new(FALLBACK_SOCKET_DIR))?);
+    if let Some(socket_dir) = socket_dir {
+        dirs.extend(socket_dirs(socket_dir)?);
+    }
+    dirs.sort();
+    dirs.dedup();
+    for dir in dirs {
+        let socket = dir.join(TEMP_SOCKET_NAME);
+        if is_live(&socket) {
+            println!(
+                "Skipped {}: a control master is still listening on it",
+                dir.display()
+            );
+            continue;
+        }
+        if !socket.exists() && modified_within(&dir, GRACE) {
+            continue;
+        }
+        if !args.dry_run {
+            fs::remove_dir_all(&dir)
+                .with_context(|| format!("failed to remove {}", dir.display()))?;
+        }
+        println!(
+            "{} leftover control socket directory {}",
+            if args.dry_run {
+                "Would remove"
+            } else {
+                "Removed"
+            },
+            dir.display()
+        );
+        pruned += 1;
+    }
+    for socket in persistent_sockets(socket_dir)? {
+        if is_live(&socket) {
+            continue;
+        }
+        if !args.dry_run {
+            fs::remove_file(&socket)
+                .with_context(|| format!("failed to remove {}", socket.display()))?;
+        }
+        println!(
+            "{} stale persistent control socket {}",
+            if args.dry_run {
+                "Would remove"
+            } else {
+                "Removed"
+            },
+            socket.display()
+        );
+        pruned += 1;
+    }
+
+    let mut state = State::load()?;
+    let stale: Vec<String> = state
+        .hosts
+        .iter()
+        .filter(|(_, host)| host.age() > args.older_than)
+        .map(|(name, _)| name.clone())
+        .collect();
+    for name in &stale {
+        let host = state
+            .hosts
+            .remove(name)
+            .expect("stale host is in the state");
+        state.options.remove(name);
+        println!(
+            "{} {name}, last checked {}",
+            if args.dry_run {
+                "Would forget"
+            } else {
+                "Forgot"
+            },
+            human::ago(state::age(host.checked_at))
+        );
+    }
+    if !stale.is_empty() && !args.dry_run {
+        state.save()?;
+    }
+    pruned += stale.len();
+
+    if pruned == 0 {
+        println!("Nothing to prune.");
+    }
+    Ok(())
+}
+
+/// The temporary control socket directories in `tmp`, ignoring any we cannot look into, e.g.
+/// because they belong to another user.
+fn socket_dirs(tmp: &Path) -> Result<Vec<PathBuf>> {
+    let entries = match fs::read_dir(tmp) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).with_context(|| format!("failed to read {}", tmp.display())),
+    };
+    let mut dirs = Vec::new();
+    for entry in entries {
+        let entry = entry.with_context(|| format!("failed to read {}", tmp.display()))?;
+        let path = entry.path();
+        if entry
+            .file_name()
+            .to_string_lossy()
+            .starts_with(TEMP_SOCKET_PREFIX)
+            && entry.file_type().is_ok_and(|t| t.is_dir())
+            && fs::read_dir(&path).is_ok()
+        {
+            dirs.push(path);
+        }
+    }
+    dirs.sort();
+    Ok(dirs)
+}
+
+/// The persistent control sockets, going by their names: the 16 hex digits of a hash. Any that
+/// ssh is still binding, with a suffix on the name, are left alone.
+fn persistent_sockets(socket_dir: Option<&Path>) -> Result<Vec<PathBuf>> {
+    let mut sockets = Vec::new();
+    for dir in persistent_socket_dirs(socket_dir) {
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e).with_context(|| format!("failed to read {}", dir.display())),
+        };
+        for entry in entries {
+            let entry = entry.with_context(|| format!("failed to read {}", dir.display()))?;
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            if name.len() == 16 && name.bytes().all(|b| b.is_ascii_hexdigit()) {
+                sockets.push(entry.path());
+            }
+        }
+    }
+    sockets.sort();
+    sockets.dedup();
+    Ok(sockets)
+}
+
+/// Whether something is accepting connections on `socket`.
+#[cfg(unix)]
+fn is_live(socket: &Path) -> bool {
+    std::os::unix::net::UnixStream::connect(socket).is_ok()
+}
+
+#[cfg(not(unix))]
+fn is_live(_socket: &Path) -> bool {
+    false
+}
+
+fn modified_within(path: &Path, duration: Duration) -> bool {
+    fs::metadata(path)
+        .and_then(|m| m.modified())
+        .is_ok_and(|t| t.elapsed().unwrap_or_default() < duration)
+}
@@ -0,0 +1,105 @@
+// Copyright 2026 Stairwell, Inc.
+// Author: mrdomino@stairwell.com
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The connection to the host a run talks to, whichever way it is reached.
+
+use std::process::Output;
+
+use anyhow::Result;
+use aspect_reauth::{
+    ssh_mux::SshMux,
+    transport::{RemoteTransport, command::CommandTransport},
+};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+/// How to reach the host, short of spelling out a `--transport-cmd`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Transport {
+    /// OpenSSH, with a control master
+    Ssh,
+    /// Teleport's `tsh ssh`
+    Tsh,
+    /// `kubectl exec` into a pod
+    Kubectl,
+    /// `docker exec` into a local container
+    Docker,
+    /// `podman exec` into a local container
+    Podman,
+}
+
+impl Transport {
+    /// The `--transport-cmd` this amounts to, or `None` for ssh. `namespace` and `pod` pick the
+    /// pod for kubectl, the pod defaulting to the host.
+    pub fn template(self, namespace: Option<&str>, pod: Option<&str>) -> Result<Option<String>> {
+        Ok(match self {
+            Transport::Ssh => None,
+            Transport::Tsh => Some("tsh ssh {host}".into()),
+            Transport::Kubectl => {
+                let mut words = vec!["kubectl exec -i".to_string()];
+                if let Some(namespace) = namespace {
+                    words.push(format!("--namespace={}", shlex::try_quote(namespace)?));
+                }
+                words.push(match pod {
+                    Some(pod) => shlex::try_quote(pod)?.into_owned(),
+                    None => "{host}".into(),
+                });
+                // kubectl runs the command as given, with no shell to parse a command line.
+                words.push("-- sh -c {command}".into());
+                Some(words.join(" "))
+            }
+            Transport::Docker => Some("docker exec -i {host} sh -c {command}".into()),
+            Transport::Podman => Some("podman exec -i {host} sh -c {command}".into()),
+        })
+    }
+}
+
+pub enum Session<'a> {
+    /// Over ssh, normally through a control master.
+    Ssh(SshMux<'a, String>),
+    /// Through `--transport-cmd`.
+    Command(CommandTransport<'a>),
+}
+
+impl RemoteTransport for Session<'_> {
+    fn host(&self) -> Option<&str> {
+        match self {
+            Session::Ssh(ssh) => ssh.host(),
+            Session::Command(command) => command.host(),
+        }
+    }
+
+    async fn run(&self, program: &str, args: &[&str], input: &[u8]) -> Result<Output> {
+        match self {
+            Session::Ssh(ssh) => ssh.run(program, args, input).await,
+            Session::Command(command) => command.run(program, args, input).await,
+        }
+    }
+
+    fn prefix(&self) -> String {
+        match self {
+            Session::Ssh(ssh) => ssh.prefix(),
+            Session::Command(command) => command.prefix(),
+        }
+    }
+
+    async fn close(self) -> Result<()> {
+        match self {
+            Session::Ssh(ssh) => ssh.close().await,
+            Session::Command(command) => command.close().await,
+        }
+    }
+}
@@ -0,0 +1,1529 @@
+// Copyright 2026 Stairwell, Inc.
+// Author: mrdomino@stairwell.com
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    cell::Cell,
+    fmt::{self, Write},
+    process::Output,
+    str::FromStr,
+    time::SystemTime,
+};
+
+use anyhow::Result;
+use base64::prelude::{BASE64_STANDARD, Engine as _};
+use serde::{Deserialize, Serialize};
+use zeroize::Zeroizing;
+
+use crate::{
+    ReauthError, SyncOptions,
+    error::KeyctlErrorKind,
+    helper,
+    helper::Check,
+    keyctl::{self, KeyringTarget},
+    probe::Capabilities,
+    token,
+    transport::RemoteTransport,
+};
+
+/// The line the single-round-trip script prints (with `echo run`) before handing over to the
+/// program it runs.
+const RUN_MARKER: &str = "run\n";
+
+/// The directory `File` stores credentials in, as the remote shell expands it.
+pub const FILE_DIR: &str = "${XDG_RUNTIME_DIR:-/run/user/$(id -u)}/aspect";
+
+/// The variable that points the remote credential helper at a file to read the credential from,
+/// rather than the kernel keyring.
+pub const FILE_VAR: &str = "ASPECT_CREDENTIAL_FILE";
+
+/// Which kind of store on the remote the credential goes to.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SinkKind {
+    /// The kernel keyring, with `keyctl`; see [`Keyctl`].
+    #[default]
+    Keyctl,
+    /// A file only the user can read; see [`File`].
+    File,
+    /// The Secret Service of a desktop session, with `secret-tool`; see [`SecretTool`].
+    SecretTool,
+    /// A credential encrypted with `systemd-creds` for user units; see [`SystemdCreds`].
+    SystemdCreds,
+    /// The login keychain of a Mac, with `security`; see [`Security`].
+    Security,
+    /// The Credential Manager of a Windows host; see [`CredentialManager`].
+    CredentialManager,
+}
+
+/// Where the credential is stored on the remote.
+#[allow(async_fn_in_trait)]
+pub trait RemoteSink {
+    /// Checks that `transport` can take a credential, before there is one to store. `ReauthFlow`
+    /// runs this while the source logs in, so that a remote that could never take the credential
+    /// fails the sync before the user has finished logging in rather than after.
+    async fn preflight<T: RemoteTransport>(&self, _transport: &T) -> Result<()> {
+        Ok(())
+    }
+
+    /// Stores `credential` on `transport`, replacing any previous one. Returns the stored key's
+    /// serial number, for sinks that have such a thing.
+    async fn store<T: RemoteTransport>(
+        &self,
+        transport: &T,
+        credential: &str,
+    ) -> Result<Option<i64>>;
+
+    /// Stores `credential` as `store` does, then runs `program` with `args` and `input` on
+    /// `transport`, returning the stored key's serial number and that run's output. Sinks that can
+    /// do both in one round trip to the remote override this.
+    async fn store_then_run<T: RemoteTransport>(
+        &self,
+        transport: &T,
+        credential: &str,
+        program: &str,
+        args: &[&str],
+        input: &str,
+    ) -> Result<(Option<i64>, Output)> {
+        let key = self.store(transport, credential).await?;
+        let output = transport.run(program, args, input.as_bytes()).await?;
+        Ok((key, output))
+    }
+
+    /// Reports whether the credential on `transport` is stale, by asking the remote credential
+    /// helper, which is where sinks that store it for the helper to find are checked.
+    async fn check<T: RemoteTransport>(
+        &self,
+        transport: &T,
+        options: &SyncOptions,
+    ) -> Result<Check> {
+        helper::check(transport, options).await
+    }
+
+    /// Stores `credential` and then checks it as `check` does, returning the stored key's serial
+    /// number and what the check found. By default the check is the remote helper's `get`, run by
+    /// `store_then_run`.
+    async fn store_and_check<T: RemoteTransport>(
+        &self,
+        transport: &T,
+        credential: &str,
+        options: &SyncOptions,
+    ) -> Result<(Option<i64>, Check)> {
+        let (key, output) = self
+            .store_then_run(
+                transport,
+                credential,
+                &options.credential_helper,
+                &["get"],
+                &helper::request(options),
+            )
+            .await?;
+        Ok((key, helper::check_output(transport, options, output)?))
+    }
+}
+
+/// The remote's kernel keyring, where keyring-rs (and so the credential helper) looks for it.
+///
+/// The credential is stored in each of `keyrings` in turn. If a key with the same description is
+/// already in the first, its payload is updated in place, keeping whatever permissions and links it
+/// has; otherwise (or with `replace`) a new key is added in its place. It is added to the rest
+/// outright, which replaces a key of the same description directly in that keyring. If the credential is a JWT that
+/// says when it expires, the key is set to time out then, so that a stale credential does not
+/// linger on the remote; otherwise any timeout the key had is kept. The serial number returned is
+/// that of the key in the first keyring.
+///
+/// A credential too big for a `user` key is stored as a `big_key` instead, and any `user` key of
+/// the same description in the keyring is invalidated so that it does not shadow the new one; see
+/// [`keyctl::key_type`]. The stock helper only looks for `user` keys, so it needs to know to read
+/// those too.
+///
+/// With `single_round_trip`, all of that and the program run after it by `store_then_run` go to
+/// the remote as one shell script, with the credential on its stdin, rather than a command each.
+///
+/// With `perm`, a key that is added, rather than updated in place, is then given those
+/// permissions with `keyctl setperm`.
+///
+/// With `join_session`, a `padd` that is denied permission, as it is from an ssh session with no
+/// session keyring of its own, is tried again from a new one; see [`keyctl::padd_in_session`].
+///
+/// A remote without keyctl that turns out to be a Mac gets the credential in its login keychain
+/// instead, with [`Security`], and one that turns out to run Windows gets it in its Credential
+/// Manager, with [`CredentialManager`]: where keyring-rs looks for it on each.
+///
+/// With a `fallback`, any other remote without keyctl gets the credential in that [`File`]
+/// instead, and the check looks there too when the remote helper finds nothing; `fell_back` then
+/// says so.
+pub struct Keyctl {
+    pub key_name: String,
+    pub keyrings: Vec<KeyringTarget>,
+    pub replace: bool,
+    pub single_round_trip: bool,
+    pub join_session: bool,
+    pub perm: Option<u32>,
+    pub fallback: Option<File>,
+    pub keychain: Security,
+    pub credential_manager: CredentialManager,
+    fell_back: Cell<bool>,
+    /// What the remote runs, once a missing keyctl has had us ask.
+    os: Cell<Option<RemoteOs>>,
+}
+
+/// What a [`Keyctl`] sink stores the credential in on a remote without keyctl.
+enum Instead<'a> {
+    File(&'a File),
+    Security(&'a Security),
+    CredentialManager(&'a CredentialManager),
+}
+
+impl Keyctl {
+    pub fn new(options: &SyncOptions) -> Self {
+        Keyctl {
+            key_name: keyctl::key_name(&options.remote),
+            keyrings: options.keyrings.clone(),
+            replace: false,
+            single_round_trip: false,
+            join_session: false,
+            perm: None,
+            fallback: None,
+            keychain: Security::new(options),
+            credential_manager: CredentialManager::new(options),
+            fell_back: Cell::new(false),
+            os: Cell::new(None),
+        }
+    }
+
+    /// Stores the credential under `key_name` instead of where keyring-rs would look for it.
+    pub fn key_name(mut self, key_name: String) -> Self {
+        self.key_name = key_name;
+        self
+    }
+
+    /// Always adds a new key, replacing any existing one, rather than updating it.
+    pub fn replace(mut self, replace: bool) -> Self {
+        self.replace = replace;
+        self
+    }
+
+    /// Stores the credential and runs what follows in one remote shell script.
+    pub fn single_round_trip(mut self, single_round_trip: bool) -> Self {
+        self.single_round_trip = single_round_trip;
+        self
+    }
+
+    /// Adds the key from a new session keyring if keyctl is denied permission, rather than
+    /// failing.
+    pub fn join_session(mut self, join_session: bool) -> Self {
+        self.join_session = join_session;
+        self
+    }
+
+    /// Sets the permissions of a key that is added to `perm`, rather than leaving the kernel's.
+    pub fn perm(mut self, perm: Option<u32>) -> Self {
+        self.perm = perm;
+        self
+    }
+
+    /// Stores the credential in a [`File`] on a remote without keyctl, rather than failing.
+    pub fn file_fallback(mut self, options: &SyncOptions, enabled: bool) -> Self {
+        self.fallback = enabled.then(|| File::new(options));
+        self
+    }
+
+    /// Returns the file the credential went to, if the remote had no keyctl and it fell back to
+    /// one.
+    pub fn fell_back(&self) -> Option<&File> {
+        match self.instead()? {
+            Instead::File(file) => Some(file),
+            Instead::Security(_) | Instead::CredentialManager(_) => None,
+        }
+    }
+
+    /// Takes what a [`probe`](crate::probe::probe) found on the remote, so that one without keyctl
+    /// goes straight to what is used instead, rather than once keyctl has failed there.
+    pub fn probed(&self, capabilities: &Capabilities) {
+        self.os.set(Some(capabilities.os));
+        self.fell_back.set(!capabilities.keyctl);
+    }
+
+    /// Returns what the credential goes in instead of the keyring, once it has fallen back.
+    fn instead(&self) -> Option<Instead<'_>> {
+        if !self.fell_back.get() {
+            return None;
+        }
+        match self.os.get() {
+            Some(RemoteOs::Mac) => Some(Instead::Security(&self.keychain)),
+            Some(RemoteOs::Windows) => Some(Instead::CredentialManager(&self.credential_manager)),
+            _ => self.fallback.as_ref().map(Instead::File),
+        }
+    }
+
+    /// Returns what to store the credential in instead, marking it as used, if `result` failed
+    /// for want of keyctl and there is something: the keychain on a Mac, the Credential Manager on
+    /// Windows, or else the fallback file.
+    async fn fall_back<T: RemoteTransport, R>(
+        &self,
+        transport: &T,
+        result: &Result<R>,
+    ) -> Result<Option<Instead<'_>>> {
+        if !result.as_ref().is_err_and(keyctl::not_installed) {
+            return Ok(None);
+        }
+        if self.os.get().is_none() {
+            self.os.set(Some(remote_os(transport).await?));
+        }
+        self.fell_back.set(true);
+        Ok(self.instead())
+    }
+
+    /// Returns the script `store_then_run` sends when `single_round_trip` is set, which takes the
+    /// key name, program, and program input as `$1`, `$2`, and `$3`, then with `join_session`
+    /// [`keyctl::SESSION_SCRIPT`] as `$4`, and the arguments to the program after those. The key
+    /// is of type `key_type`.
+    ///
+    /// It prints `key SERIAL` for each keyring, then [`RUN_MARKER`] and the program's output, and
+    /// exits with the program's status; a keyctl that fails instead has it print `failed COMMAND
+    /// STATUS` and exit with that status. It is passed to the remote shell as a word, so it has no
+    /// `'` or `\` in it.
+    fn script(&self, key_type: &str, timeout: Option<u64>) -> String {
+        let mut script = String::from(
+            "fail() { echo \"failed $1 $2\"; exit \"$2\"; }\n\
+             cred=$(cat; echo .); cred=${cred%.}\n",
+        );
+        if self.join_session {
+            script.push_str("name=$1 program=$2 input=$3 join=$4; shift 4\n");
+        } else {
+            script.push_str("name=$1 program=$2 input=$3; shift 3\n");
+        }
+        _ = writeln!(script, "type={key_type}");
+        for (i, target) in self.keyrings.iter().enumerate() {
+            let search = i == 0 && !self.replace;
+            match target.arg() {
+                Some(arg) => _ = writeln!(script, "ring={arg}"),
+                None => {
+                    script.push_str("ring=$(keyctl get_persistent @s) || fail get_persistent $?\n")
+                }
+            }
+            if self.perm.is_some() {
+                script.push_str("added=\n");
+            }
+            if search {
+                script.push_str(
+                    "if serial=$(keyctl search \"$ring\" \"$type\" \"$name\" 2>/dev/null); then\n\
+                     printf %s \"$cred\" | keyctl pupdate \"$serial\" || \
+                     fail \"pupdate $serial\" $?\n\
+                     else\n",
+                );
+            }
+            script.push_str(
+                "serial=$(printf %s \"$cred\" | keyctl padd \"$type\" \"$name\" \"$ring\") || ",
+            );
+            if self.join_session {
+                script.push_str(
+                    "serial=$(printf %s \"$cred\" | \
+                     keyctl session - sh -c \"$join\" sh \"$name\" \"$ring\" \"$type\") || ",
+                );
+            }
+            script.push_str("fail padd $?\n");
+            if self.perm.is_some() {
+                script.push_str("added=1\n");
+            }
+            if search {
+                script.push_str("fi\n");
+            }
+            if key_type != keyctl::USER {
+                script.push_str(
+                    "if stale=$(keyctl search \"$ring\" user \"$name\" 2>/dev/null); then\n\
+                     keyctl invalidate \"$stale\" || fail \"invalidate $stale\" $?\n\
+                     fi\n",
+                );
+            }
+            if let Some(link) = target.link() {
+                _ = writeln!(
+                    script,
+                    "keyctl link \"$serial\" {link} || fail \"link $serial {link}\" $?"
+                );
+            }
+            if let Some(secs) = timeout {
+                _ = writeln!(
+                    script,
+                    "keyctl timeout \"$serial\" {secs} || fail \"timeout $serial {secs}\" $?"
+                );
+            }
+            if let Some(perm) = self.perm {
+                _ = writeln!(
+                    script,
+                    "if test -n \"$added\"; then\n\
+                     keyctl setperm \"$serial\" {perm:#010x} || \
+                     fail \"setperm $serial {perm:#010x}\" $?\n\
+                     fi"
+                );
+            }
+            script.push_str("echo \"key $serial\"\n");
+        }
+        script.push_str("echo run\n");
+        script.push_str("printf \"%s\" \"$input\" | \"$program\" \"$@\"\n");
+        script
+    }
+
+    /// Checks that keyctl is installed and each of `keyrings` is reachable, looking up the
+    /// persistent keyring (which also checks the session keyring it is linked into).
+    async fn preflight_keyrings<T: RemoteTransport>(&self, transport: &T) -> Result<()> {
+        for target in &self.keyrings {
+            match target.arg() {
+                Some(keyring) => keyctl::rdescribe(transport, &keyring).await?,
+                None => _ = target.resolve(transport).await?,
+            }
+            if let Some(link) = target.link() {
+                keyctl::rdescribe(transport, link).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Stores `credential` in each of `keyrings`, as described on [`Keyctl`].
+    async fn store_in_keyrings<T: RemoteTransport>(
+        &self,
+        transport: &T,
+        credential: &str,
+    ) -> Result<Option<i64>> {
+        let timeout = token::expiry(credential).map(|expires| {
+            expires
+                .duration_since(SystemTime::now())
+                .unwrap_or_default()
+        });
+        let key_type = keyctl::key_type(credential);
+        let mut key = None;
+        for (i, target) in self.keyrings.iter().enumerate() {
+            let keyring = target.resolve(transport).await?;
+            // `keyctl search` is recursive, so past the first keyring it would find the key just
+            // stored in an earlier one through the links between them.
+            let existing = if i > 0 || self.replace {
+                None
+            } else {
+                keyctl::search_type(transport, key_type, &self.key_name, &keyring).await?
+            };
+            let (serial, added) = match existing {
+                Some(serial) => {
+                    keyctl::pupdate(transport, serial, credential).await?;
+                    (serial, false)
+                }
+                None => match keyctl::padd(transport, &self.key_name, &keyring, credential).await {
+                    Err(e) if self.join_session && keyctl::permission_denied(&e) => (
+                        keyctl::padd_in_session(transport, &self.key_name, &keyring, credential)
+                            .await?,
+                        true,
+                    ),
+                    result => (result?, true),
+                },
+            };
+            if key_type != keyctl::USER
+                && let Some(stale) = keyctl::search(transport, &self.key_name, &keyring).await?
+            {
+                keyctl::invalidate(transport, stale).await?;
+            }
+            if let Some(link) = target.link() {
+                keyctl::link(transport, serial, link).await?;
+            }
+            if let Some(timeout) = timeout {
+                keyctl::timeout(transport, serial, timeout).await?;
+            }
+            if let Some(perm) = self.perm.filter(|_| added) {
+                keyctl::setperm(transport, serial, perm).await?;
+            }
+            key = key.or(Some(serial));
+        }
+        Ok(key)
+    }
+
+    /// Stores `credential` in each of `keyrings` and runs `program`, as `store_then_run` does
+    /// without a fallback.
+    async fn store_in_keyrings_then_run<T: RemoteTransport>(
+        &self,
+        transport: &T,
+        credential: &str,
+        program: &str,
+        args: &[&str],
+        input: &str,
+    ) -> Result<(Option<i64>, Output)> {
+        if !self.single_round_trip {
+            let key = self.store_in_keyrings(transport, credential).await?;
+            let output = transport.run(program, args, input.as_bytes()).await?;
+            return Ok((key, output));
+        }
+        let timeout = token::expiry(credential).map(|expires| {
+            keyctl::timeout_secs(
+                expires
+                    .duration_since(SystemTime::now())
+                    .unwrap_or_default(),
+            )
+        });
+        let script = self.script(keyctl::key_type(credential), timeout);
+        let mut words = vec!["-c", &script, "sh", &self.key_name, program, input];
+        if self.join_session {
+            words.push(keyctl::SESSION_SCRIPT);
+        }
+        words.extend(args);
+        let mut output = transport.run("sh", &words, credential.as_bytes()).await?;
+        let stdout = std::mem::take(&mut output.stdout);
+        let (ours, theirs) = match find(&stdout, RUN_MARKER.as_bytes()) {
+            Some(at) => (&stdout[..at], &stdout[at + RUN_MARKER.len()..]),
+            None => (&stdout[..], &[][..]),
+        };
+        let ours = String::from_utf8_lossy(ours);
+        let mut key = None;
+        for line in ours.lines() {
+            if let Some(serial) = line.strip_prefix("key ") {
+                let serial = keyctl::parse_serial(transport, "padd", serial.as_bytes())?;
+                key = key.or(Some(serial));
+            } else if let Some(failed) = line.strip_prefix("failed ") {
+                let command = failed
+                    .rsplit_once(' ')
+                    .map_or(failed, |(command, _)| command);
+                let denied = command == "padd"
+                    && String::from_utf8_lossy(&output.stderr).contains(keyctl::PERMISSION_DENIED);
+                let (kind, hint) = if command.starts_with("pupdate") {
+                    (KeyctlErrorKind::Other, keyctl::PUPDATE_HINT)
+                } else if denied {
+                    let hint = if self.join_session {
+                        ""
+                    } else {
+                        keyctl::SESSION_HINT
+                    };
+                    (KeyctlErrorKind::PermissionDenied, hint)
+                } else {
+                    (KeyctlErrorKind::Other, "")
+                };
+                return Err(keyctl::failure_of_kind(
+                    output.status,
+                    kind,
+                    format!(
+                        "{}keyctl {command}: {}\n\n{}{hint}",
+                        transport.prefix(),
+                        output.status,
+                        String::from_utf8_lossy(&output.stderr).trim(),
+                    ),
+                ));
+            }
+        }
+        if find(&stdout, RUN_MARKER.as_bytes()).is_none() {
+            // Cut off before the script got that far, most likely by a dropped connection.
+            return Err(keyctl::failure(
+                output.status,
+                format!(
+                    "{}sh -c ...: {}\n\n{}",
+                    transport.prefix(),
+                    output.status,
+                    String::from_utf8_lossy(&output.stderr).trim(),
+                ),
+            ));
+        }
+        output.stdout = theirs.to_vec();
+        Ok((key, output))
+    }
+}
+
+impl RemoteSink for Keyctl {
+    /// Checks that keyctl is installed and each of `keyrings` is reachable, or that there is
+    /// something to use instead.
+    async fn preflight<T: RemoteTransport>(&self, transport: &T) -> Result<()> {
+        let result = self.preflight_keyrings(transport).await;
+        match self.fall_back(transport, &result).await? {
+            Some(instead) => instead.preflight(transport).await,
+            None => result,
+        }
+    }
+
+    async fn store<T: RemoteTransport>(
+        &self,
+        transport: &T,
+        credential: &str,
+    ) -> Result<Option<i64>> {
+        if let Some(instead) = self.instead() {
+            return instead.store(transport, credential).await;
+        }
+        let result = self.store_in_keyrings(transport, credential).await;
+        match self.fall_back(transport, &result).await? {
+            Some(instead) => instead.store(transport, credential).await,
+            None => result,
+        }
+    }
+
+    async fn store_then_run<T: RemoteTransport>(
+        &self,
+        transport: &T,
+        credential: &str,
+        program: &str,
+        args: &[&str],
+        input: &str,
+    ) -> Result<(Option<i64>, Output)> {
+        let instead = match self.instead() {
+            Some(instead) => instead,
+            None => {
+                let result = self
+                    .store_in_keyrings_then_run(transport, credential, program, args, input)
+                    .await;
+                match self.fall_back(transport, &result).await? {
+                    Some(instead) => instead,
+                    None => return result,
+                }
+            }
+        };
+        instead
+            .store_then_run(transport, credential, program, args, input)
+            .await
+    }
+
+    /// Looks for the credential in the fallback file too, if the remote helper finds none, so
+    /// that a remote without keyctl is not synced afresh every time.
+    async fn check<T: RemoteTransport>(
+        &self,
+        transport: &T,
+        options: &SyncOptions,
+    ) -> Result<Check> {
+        let check = helper::check(transport, options).await?;
+        let Some(file) = self.fallback.as_ref().filter(|_| check.needs_refresh) else {
+            return Ok(check);
+        };
+        let stored = file.check(transport, options).await?;
+        if stored.needs_refresh {
+            return Ok(check);
+        }
+        self.fell_back.set(true);
+        Ok(stored)
+    }
+
+    async fn store_and_check<T: RemoteTransport>(
+        &self,
+        transport: &T,
+        credential: &str,
+        options: &SyncOptions,
+    ) -> Result<(Option<i64>, Check)> {
+        if let Some(instead) = self.instead() {
+            return instead
+                .store_and_check(transport, credential, options)
+                .await;
+        }
+        let result = self
+            .store_in_keyrings_then_run(
+                transport,
+                credential,
+                &options.credential_helper,
+                &["get"],
+                &helper::request(options),
+            )
+            .await;
+        // Nothing was stored for the helper to check, so its answer is beside the point.
+        if let Some(instead) = self.fall_back(transport, &result).await? {
+            return instead
+                .store_and_check(transport, credential, options)
+                .await;
+        }
+        let (key, output) = result?;
+        Ok((key, helper::check_output(transport, options, output)?))
+    }
+}
+
+impl RemoteSink for Instead<'_> {
+    async fn preflight<T: RemoteTransport>(&self, transport: &T) -> Result<()> {
+        match self {
+            Instead::File(sink) => sink.preflight(transport).await,
+            Instead::Security(sink) => sink.preflight(transport).await,
+            Instead::CredentialManager(sink) => sink.preflight(transport).await,
+        }
+    }
+
+    async fn store<T: RemoteTransport>(
+        &self,
+        transport: &T,
+        credential: &str,
+    ) -> Result<Option<i64>> {
+        match self {
+            Instead::File(sink) => sink.store(transport, credential).await,
+            Instead::Security(sink) => sink.store(transport, credential).await,
+            Instead::CredentialManager(sink) => sink.store(transport, credential).await,
+        }
+    }
+
+    async fn store_and_check<T: RemoteTransport>(
+        &self,
+        transport: &T,
+        credential: &str,
+        options: &SyncOptions,
+    ) -> Result<(Option<i64>, Check)> {
+        match self {
+            Instead::File(sink) => sink.store_and_check(transport, credential, options).await,
+            Instead::Security(sink) => sink.store_and_check(transport, credential, options).await,
+            Instead::CredentialManager(sink) => {
+                sink.store_and_check(transport, credential, options).await
+            }
+        }
+    }
+}
+
+/// The operating systems with a credential store of their own that a sink cares about.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RemoteOs {
+    Mac,
+    Windows,
+    /// Linux, or anything else with a Unix shell.
+    Other,
+}
+
+/// Finds out what the remote on `transport` runs, by its `uname`. Windows has none, and its shells,
+/// cmd and PowerShell alike, say the command "is not recognized as" one; with Git for Windows or
+/// Cygwin, it has one that says so.
+pub async fn remote_os<T: RemoteTransport>(transport: &T) -> Result<RemoteOs> {
+    let output = transport.run("uname", &["-s"], b"").await?;
+    if output.status.code() == Some(255) {
+        return Err(ReauthError::ssh(format!(
+            "{}uname -s: {}\n\n{}",
+            transport.prefix(),
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim(),
+        ))
+        .into());
+    }
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Ok(if stderr.contains(NOT_RECOGNIZED) {
+            RemoteOs::Windows
+        } else {
+            RemoteOs::Other
+        });
+    }
+    Ok(RemoteOs::from_uname(&output.stdout))
+}
+
+impl RemoteOs {
+    /// The operating system whose `uname -s` printed `name`: Windows for Git for Windows (MINGW or
+    /// MSYS) and Cygwin.
+    pub fn from_uname(name: &[u8]) -> Self {
+        let name = name.trim_ascii();
+        if name == b"Darwin" {
+            RemoteOs::Mac
+        } else if [&b"MINGW"[..], b"MSYS", b"CYGWIN"]
+            .iter()
+            .any(|prefix| name.starts_with(prefix))
+        {
+            RemoteOs::Windows
+        } else {
+            RemoteOs::Other
+        }
+    }
+}
+
+/// What cmd and PowerShell say of a command that does not exist.
+pub(crate) const NOT_RECOGNIZED: &str = "is not recognized as";
+
+/// The login keychain of a Mac, with `security`, under the service and account keyring-rs (and so
+/// the credential helper) looks the credential up by there. Over ssh the login keychain is often
+/// locked; `security unlock-keychain` on the host unlocks it.
+///
+/// The credential goes to `security -i` on its stdin rather than on its command line, where
+/// anyone on the host could see it.
+pub struct Security {
+    pub remote: String,
+}
+
+impl Security {
+    pub fn new(options: &SyncOptions) -> Self {
+        Security {
+            remote: options.remote.clone(),
+        }
+    }
+
+    /// The `security -i` command that stores `credential`, updating any existing item. It holds
+    /// the credential, so it is zeroed when dropped.
+    pub fn add_command(&self, credential: &str) -> Zeroizing<String> {
+        Zeroizing::new(format!(
+            "add-generic-password -U -s {} -a {} -w \"{credential}\"\n",
+            keyctl::SERVICE,
+            self.remote
+        ))
+    }
+
+    /// The error for a `security command` that ended in `output`.
+    fn failure<T: RemoteTransport>(transport: &T, command: &str, output: &Output) -> anyhow::Error {
+        let mut message = format!(
+            "{}security {command}: {}\n\n{}",
+            transport.prefix(),
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim(),
+        );
+        if output.status.code() == Some(255) {
+            return ReauthError::ssh(message).into();
+        }
+        if output.stderr.windows(6).any(|w| w == b"locked") {
+            message.push_str("\n\nRun `security unlock-keychain` on the host to unlock it.");
+        }
+        ReauthError::Store(message).into()
+    }
+
+    /// Runs `security command` for the credential's item on `transport`, returning whether it was
+    /// there; `security` exits with 44 when it is not.
+    async fn item<T: RemoteTransport>(&self, transport: &T, command: &str) -> Result<bool> {
+        let output = transport
+            .run(
+                "security",
+                &[command, "-s", keyctl::SERVICE, "-a", &self.remote],
+                b"",
+            )
+            .await?;
+        match output.status.code() {
+            Some(0) => Ok(true),
+            Some(44) => Ok(false),
+            _ => Err(Self::failure(transport, command, &output)),
+        }
+    }
+
+    /// Reports whether the credential is in the keychain on `transport`.
+    pub async fn is_stored<T: RemoteTransport>(&self, transport: &T) -> Result<bool> {
+        self.item(transport, "find-generic-password").await
+    }
+
+    /// Removes the credential from the keychain on `transport`, returning whether it was there.
+    pub async fn delete<T: RemoteTransport>(&self, transport: &T) -> Result<bool> {
+        self.item(transport, "delete-generic-password").await
+    }
+}
+
+impl RemoteSink for Security {
+    async fn store<T: RemoteTransport>(
+        &self,
+        transport: &T,
+        credential: &str,
+    ) -> Result<Option<i64>> {
+        if credential.contains(['"', '\\', '\n']) {
+            return Err(ReauthError::Store(format!(
+                "{}the credential has a quote, backslash, or newline in it, which security -i \
+                 cannot take",
+                transport.prefix()
+            ))
+            .into());
+        }
+        let output = transport
+            .run("security", &["-i"], self.add_command(credential).as_bytes())
+            .await?;
+        // security -i reports a failed command on stderr, but may still exit with 0.
+        if !output.status.success() || !output.stderr.trim_ascii().is_empty() {
+            return Err(Self::failure(transport, "add-generic-password", &output));
+        }
+        Ok(None)
+    }
+}
+
+/// A file on the remote that only the user can read, for hosts without kernel keyrings. It is
+/// named after the remote and kept in [`FILE_DIR`], under the user's runtime directory, which
+/// systemd mounts as a tmpfs, so that the credential is never written to disk.
+///
+/// The remote helper does not look there, so the file itself is what is checked: the credential is
+/// stale if there is no file, or if what is in it is not a JWT good for `min_validity` yet.
+pub struct File {
+    pub remote: String,
+}
+
+impl File {
+    pub fn new(options: &SyncOptions) -> Self {
+        File {
+            remote: options.remote.clone(),
+        }
+    }
+
+    /// Returns the file's path as the remote shell expands it.
+    pub fn path(&self) -> String {
+        format!("{FILE_DIR}/{}", self.remote)
+    }
+
+    /// The script that writes the file named `$1` from stdin. It is written beside the file and
+    /// renamed over it, so that a reader never sees half of it.
+    pub fn write_script() -> String {
+        format!(
+            "dir={FILE_DIR}; umask 077 && mkdir -p \"$dir\" && \
+             cat > \"$dir/$1.tmp\" && mv -f \"$dir/$1.tmp\" \"$dir/$1\""
+        )
+    }
+
+    /// The script that prints the file named `$1`, failing quietly if there is none.
+    pub fn read_script() -> String {
+        format!("cat \"{FILE_DIR}/$1\" 2>/dev/null")
+    }
+
+    /// The error for a failure to `verb` the file, which ended in `output`. ssh exits with 255
+    /// when the connection fails or drops.
+    fn failure<T: RemoteTransport>(
+        &self,
+        transport: &T,
+        verb: &str,
+        output: &Output,
+    ) -> anyhow::Error {
+        let message = format!(
+            "{}failed to {verb} {}: {}\n\n{}",
+            transport.prefix(),
+            self.path(),
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim(),
+        );
+        if output.status.code() == Some(255) {
+            ReauthError::ssh(message).into()
+        } else {
+            ReauthError::Store(message).into()
+        }
+    }
+
+    /// Deletes the file on `transport`, returning whether there was one.
+    pub async fn remove<T: RemoteTransport>(&self, transport: &T) -> Result<bool> {
+        let script =
+            format!("f={FILE_DIR}/$1; if test -e \"$f\"; then rm -f \"$f\" && echo removed; fi");
+        let output = transport
+            .run("sh", &["-c", &script, "sh", &self.remote], b"")
+            .await?;
+        if !output.status.success() {
+            return Err(self.failure(transport, "remove", &output));
+        }
+        Ok(output.stdout.starts_with(b"removed"))
+    }
+
+    /// Returns what is in the file on `transport`, or `None` if there is no such file.
+    async fn read<T: RemoteTransport>(&self, transport: &T) -> Result<Option<Zeroizing<String>>> {
+        let output = transport
+            .run("sh", &["-c", &Self::read_script(), "sh", &self.remote], b"")
+            .await?;
+        if output.status.code() == Some(255) {
+            return Err(self.failure(transport, "read", &output));
+        }
+        Ok(stored(output))
+    }
+}
+
+impl RemoteSink for File {
+    async fn store<T: RemoteTransport>(
+        &self,
+        transport: &T,
+        credential: &str,
+    ) -> Result<Option<i64>> {
+        let output = transport
+            .run(
+                "sh",
+                &["-c", &Self::write_script(), "sh", &self.remote],
+                credential.as_bytes(),
+            )
+            .await?;
+        if !output.status.success() {
+            return Err(self.failure(transport, "write", &output));
+        }
+        Ok(None)
+    }
+
+    async fn check<T: RemoteTransport>(
+        &self,
+        transport: &T,
+        options: &SyncOptions,
+    ) -> Result<Check> {
+        Ok(judge(self.read(transport).await?, options))
+    }
+
+    async fn store_and_check<T: RemoteTransport>(
+        &self,
+        transport: &T,
+        credential: &str,
+        _: &SyncOptions,
+    ) -> Result<(Option<i64>, Check)> {
+        let key = self.store(transport, credential).await?;
+        Ok((key, confirm(self.read(transport).await?, credential)))
+    }
+}
+
+/// The credential a read of one printed in `output`, if it succeeded. Both are zeroed when
+/// dropped.
+fn stored(output: Output) -> Option<Zeroizing<String>> {
+    let stdout = Zeroizing::new(output.stdout);
+    output
+        .status
+        .success()
+        .then(|| Zeroizing::new(String::from_utf8_lossy(&stdout).into_owned()))
+}
+
+/// Checks a credential read back from a sink the remote helper does not look in: it is stale if
+/// there is none, or if it is not a JWT good for `min_validity` yet.
+fn judge(stored: Option<Zeroizing<String>>, options: &SyncOptions) -> Check {
+    let expires = stored.and_then(|credential| token::expiry(&credential));
+    let deadline = SystemTime::now() + options.min_validity;
+    Check {
+        needs_refresh: expires.is_none_or(|expires| expires <= deadline),
+        expires,
+    }
+}
+
+/// Checks a credential read back just after storing `credential`. Whatever the credential says
+/// about itself, it is the one asked for if it is there.
+fn confirm(stored: Option<Zeroizing<String>>, credential: &str) -> Check {
+    Check {
+        needs_refresh: stored.as_deref().map(String::as_str) != Some(credential),
+        expires: token::expiry(credential),
+    }
+}
+
+/// A credential encrypted with `systemd-creds`, for remotes whose build wrappers run as systemd
+/// user units. It is kept in [`CREDSTORE_DIR`], where the user's service manager finds it, so a
+/// unit gets it with `LoadCredentialEncrypted=` and the credential's `name`.
+///
+/// As with [`File`], the remote helper does not look there, so the credential is checked by
+/// decrypting it.
+pub struct SystemdCreds {
+    pub name: String,
+}
+
+/// The directory `SystemdCreds` stores credentials in, as the remote shell expands it.
+pub const CREDSTORE_DIR: &str = "${XDG_CONFIG_HOME:-$HOME/.config}/credstore.encrypted";
+
+impl SystemdCreds {
+    pub fn new(options: &SyncOptions) -> Self {
+        SystemdCreds {
+            name: format!("aspect-{}", options.remote),
+        }
+    }
+
+    /// Returns the encrypted credential's path as the remote shell expands it.
+    pub fn path(&self) -> String {
+        format!("{CREDSTORE_DIR}/{}", self.name)
+    }
+
+    /// The script that encrypts stdin as the credential named `$1`. As with [`File`], it is
+    /// written beside the credential and renamed over it.
+    pub fn encrypt_script() -> String {
+        format!(
+            "dir={CREDSTORE_DIR}; umask 077 && mkdir -p \"$dir\" && \
+             systemd-creds encrypt --user --name=\"$1\" - \"$dir/$1.tmp\" && \
+             mv -f \"$dir/$1.tmp\" \"$dir/$1\""
+        )
+    }
+
+    /// The script that prints the decrypted credential named `$1`, failing quietly if there is
+    /// none or it cannot be decrypted.
+    pub fn decrypt_script() -> String {
+        format!("systemd-creds decrypt --user --name=\"$1\" \"{CREDSTORE_DIR}/$1\" - 2>/dev/null")
+    }
+
+    /// The error for a failure to `verb` the credential, which ended in `output`.
+    fn failure<T: RemoteTransport>(
+        &self,
+        transport: &T,
+        verb: &str,
+        output: &Output,
+    ) -> anyhow::Error {
+        let mut message = format!(
+            "{}failed to {verb} {}: {}\n\n{}",
+            transport.prefix(),
+            self.path(),
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim(),
+        );
+        match output.status.code() {
+            Some(255) => return ReauthError::ssh(message).into(),
+            Some(127) => message.push_str(
+                "\n\nInstall systemd 256 or later on the host to get systemd-creds --user.",
+            ),
+            _ => {}
+        }
+        ReauthError::Store(message).into()
+    }
+
+    /// Deletes the encrypted credential on `transport`, returning whether there was one.
+    pub async fn remove<T: RemoteTransport>(&self, transport: &T) -> Result<bool> {
+        let script = format!(
+            "f={CREDSTORE_DIR}/$1; if test -e \"$f\"; then rm -f \"$f\" && echo removed; fi"
+        );
+        let output = transport
+            .run("sh", &["-c", &script, "sh", &self.name], b"")
+            .await?;
+        if !output.status.success() {
+            return Err(self.failure(transport, "remove", &output));
+        }
+        Ok(output.stdout.starts_with(b"removed"))
+    }
+
+    /// Returns the decrypted credential on `transport`, or `None` if there is none that decrypts.
+    async fn read<T: RemoteTransport>(&self, transport: &T) -> Result<Option<Zeroizing<String>>> {
+        let output = transport
+            .run(
+                "sh",
+                &["-c", &Self::decrypt_script(), "sh", &self.name],
+                b"",
+            )
+            .await?;
+        if output.status.code() == Some(255) {
+            return Err(self.failure(transport, "decrypt", &output));
+        }
+        Ok(stored(output))
+    }
+}
+
+impl RemoteSink for SystemdCreds {
+    async fn store<T: RemoteTransport>(
+        &self,
+        transport: &T,
+        credential: &str,
+    ) -> Result<Option<i64>> {
+        let output = transport
+            .run(
+                "sh",
+                &["-c", &Self::encrypt_script(), "sh", &self.name],
+                credential.as_bytes(),
+            )
+            .await?;
+        if !output.status.success() {
+            return Err(self.failure(transport, "encrypt", &output));
+        }
+        Ok(None)
+    }
+
+    async fn check<T: RemoteTransport>(
+        &self,
+        transport: &T,
+        options: &SyncOptions,
+    ) -> Result<Check> {
+        Ok(judge(self.read(transport).await?, options))
+    }
+
+    async fn store_and_check<T: RemoteTransport>(
+        &self,
+        transport: &T,
+        credential: &str,
+        _: &SyncOptions,
+    ) -> Result<(Option<i64>, Check)> {
+        let key = self.store(transport, credential).await?;
+        Ok((key, confirm(self.read(transport).await?, credential)))
+    }
+}
+
+/// A Secret Service collection on the remote, such as GNOME Keyring's, for devboxes that run a
+/// desktop session. The credential is stored with `secret-tool` under the attributes keyring-rs
+/// (and so the credential helper) looks it up by, on the session bus of the user's desktop session
+/// unless the ssh session has one of its own.
+pub struct SecretTool {
+    pub remote: String,
+}
+
+impl SecretTool {
+    pub fn new(options: &SyncOptions) -> Self {
+        SecretTool {
+            remote: options.remote.clone(),
+        }
+    }
+
+    /// The attributes keyring-rs gives the credential helper's secret for `remote`, as
+    /// `secret-tool` takes them.
+    pub fn attributes(&self) -> [&str; 8] {
+        [
+            "application",
+            "rust-keyring",
+            "service",
+            keyctl::SERVICE,
+            "target",
+            "default",
+            "username",
+            &self.remote,
+        ]
+    }
+
+    /// The label the credential is stored with, which is only for people to read.
+    pub fn label(&self) -> String {
+        format!("{} credential for {}", keyctl::SERVICE, self.remote)
+    }
+
+    /// The script that runs `secret-tool` with its arguments, on the desktop session's bus if the
+    /// ssh session has none.
+    pub fn script() -> &'static str {
+        "export DBUS_SESSION_BUS_ADDRESS=\"${DBUS_SESSION_BUS_ADDRESS:-unix:path=\
+         ${XDG_RUNTIME_DIR:-/run/user/$(id -u)}/bus}\"; exec secret-tool \"$@\""
+    }
+
+    /// Runs `secret-tool command` on `transport` with the credential's attributes and `input`.
+    async fn run<T: RemoteTransport>(
+        &self,
+        transport: &T,
+        command: &[&str],
+        input: &str,
+    ) -> Result<Output> {
+        let mut words = vec!["-c", Self::script(), "sh"];
+        words.extend(command);
+        words.extend(self.attributes());
+        let output = transport.run("sh", &words, input.as_bytes()).await?;
+        Ok(output)
+    }
+
+    /// The error for a `secret-tool command` that ended in `output`. ssh exits with 255 when the
+    /// connection fails or drops, and the shell with 127 when there is no `secret-tool`.
+    fn failure<T: RemoteTransport>(transport: &T, command: &str, output: &Output) -> anyhow::Error {
+        let mut message = format!(
+            "{}secret-tool {command}: {}\n\n{}",
+            transport.prefix(),
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim(),
+        );
+        match output.status.code() {
+            Some(255) => return ReauthError::ssh(message).into(),
+            Some(127) => {
+                message.push_str("\n\nInstall libsecret-tools on the host to get secret-tool.")
+            }
+            _ => {}
+        }
+        ReauthError::Store(message).into()
+    }
+
+    /// Reports whether the credential is in the Secret Service on `transport`.
+    pub async fn is_stored<T: RemoteTransport>(&self, transport: &T) -> Result<bool> {
+        let output = self.run(transport, &["lookup"], "").await?;
+        match output.status.code() {
+            Some(0) => Ok(true),
+            // lookup exits with 1, and prints nothing, when nothing matches.
+            Some(1) if output.stderr.is_empty() => Ok(false),
+            _ => Err(Self::failure(transport, "lookup", &output)),
+        }
+    }
+
+    /// Removes the credential from the Secret Service on `transport`, returning whether it was
+    /// there.
+    pub async fn clear<T: RemoteTransport>(&self, transport: &T) -> Result<bool> {
+        if !self.is_stored(transport).await? {
+            return Ok(false);
+        }
+        let output = self.run(transport, &["clear"], "").await?;
+        if !output.status.success() {
+            return Err(Self::failure(transport, "clear", &output));
+        }
+        Ok(true)
+    }
+}
+
+impl RemoteSink for SecretTool {
+    async fn store<T: RemoteTransport>(
+        &self,
+        transport: &T,
+        credential: &str,
+    ) -> Result<Option<i64>> {
+        let label = format!("--label={}", self.label());
+        let output = self.run(transport, &["store", &label], credential).await?;
+        if !output.status.success() {
+            return Err(Self::failure(transport, "store", &output));
+        }
+        Ok(None)
+    }
+}
+
+/// The Credential Manager of a Windows host, such as a build VM reached with OpenSSH, under the
+/// target name keyring-rs (and so the credential helper) looks the credential up by there.
+///
+/// The credential is stored with `CredWrite`, by a PowerShell script that reads it from stdin, so
+/// that it is on no command line, ssh's or `cmdkey`'s, where anyone on the host could see it.
+/// Windows only lets a session that logged in with a password store credentials, so this fails
+/// over ssh with key authentication.
+pub struct CredentialManager {
+    pub remote: String,
+}
+
+impl CredentialManager {
+    pub fn new(options: &SyncOptions) -> Self {
+        CredentialManager {
+            remote: options.remote.clone(),
+        }
+    }
+
+    /// The target name keyring-rs gives the credential helper's credential for `remote`.
+    pub fn target(&self) -> String {
+        format!("{}.{}", self.remote, keyctl::SERVICE)
+    }
+
+    /// The PowerShell script that stores the credential on its stdin with `CredWrite`, as a
+    /// generic credential encoded as keyring-rs encodes it.
+    pub fn store_script(&self) -> String {
+        format!(
+            "$ErrorActionPreference = 'Stop'; $t = {}; $u = {}; $c = [Console]::In.ReadToEnd()\n\
+             Add-Type -TypeDefinition @'\n{CRED_WRITE}\n'@\n\
+             $e = [AspectReauth.Credential]::Write($t, $u, $c)\n\
+             if ($e) {{ [Console]::Error.WriteLine((New-Object ComponentModel.Win32Exception \
+             $e).Message); exit 1 }}",
+            powershell_quote(&self.target()),
+            powershell_quote(&self.remote),
+        )
+    }
+
+    /// The arguments to `powershell` that run `script`, encoded as PowerShell wants it, so that
+    /// any shell passes it along untouched.
+    pub fn powershell_args(script: &str) -> [String; 4] {
+        let utf16: Vec<u8> = script.encode_utf16().flat_map(u16::to_le_bytes).collect();
+        [
+            "-NoProfile".into(),
+            "-NonInteractive".into(),
+            "-EncodedCommand".into(),
+            BASE64_STANDARD.encode(utf16),
+        ]
+    }
+
+    /// The error for a `command` that ended in `output`. cmdkey reports its errors on stdout, and
+    /// the store script on stderr.
+    fn failure<T: RemoteTransport>(transport: &T, command: &str, output: &Output) -> anyhow::Error {
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let mut message = format!(
+            "{}{command}: {}\n\n{}\n{}",
+            transport.prefix(),
+            output.status,
+            stdout.trim(),
+            stderr.trim(),
+        );
+        if output.status.code() == Some(255) {
+            return ReauthError::ssh(message).into();
+        }
+        if stdout.contains("logon session does not exist")
+            || stderr.contains("logon session does not exist")
+        {
+            message.push_str(
+                "\n\nWindows only stores credentials for a session that logged in with a \
+                 password; ssh in with one rather than with a key.",
+            );
+        }
+        ReauthError::Store(message).into()
+    }
+
+    /// Reports whether the credential is in the Credential Manager on `transport`. `cmdkey /list`
+    /// names the target if it is there, in any language.
+    pub async fn is_stored<T: RemoteTransport>(&self, transport: &T) -> Result<bool> {
+        let list = format!("/list:{}", self.target());
+        let output = transport.run("cmdkey", &[&list], b"").await?;
+        if !output.status.success() {
+            return Err(Self::failure(transport, &format!("cmdkey {list}"), &output));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).contains(&self.target()))
+    }
+
+    /// Removes the credential from the Credential Manager on `transport`, returning whether it was
+    /// there.
+    pub async fn delete<T: RemoteTransport>(&self, transport: &T) -> Result<bool> {
+        if !self.is_stored(transport).await? {
+            return Ok(false);
+        }
+        let delete = format!("/delete:{}", self.target());
+        let output = transport.run("cmdkey", &[&delete], b"").await?;
+        if !output.status.success() {
+            return Err(Self::failure(
+                transport,
+                &format!("cmdkey {delete}"),
+                &output,
+            ));
+        }
+        Ok(true)
+    }
+}
+
+impl RemoteSink for CredentialManager {
+    async fn store<T: RemoteTransport>(
+        &self,
+        transport: &T,
+        credential: &str,
+    ) -> Result<Option<i64>> {
+        let args = Self::powershell_args(&self.store_script());
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        let output = transport
+            .run("powershell", &args, credential.as_bytes())
+            .await?;
+        if !output.status.success() {
+            return Err(Self::failure(
+                transport,
+                &format!("CredWrite {}", self.target()),
+                &output,
+            ));
+        }
+        Ok(None)
+    }
+}
+
+/// The C# that `CredentialManager::store_script` compiles to call `CredWrite` with, storing the
+/// secret as UTF-16, as keyring-rs does, and zeroing its copy of it after.
+const CRED_WRITE: &str = r#"using System;
+using System.Runtime.InteropServices;
+using System.Runtime.InteropServices.ComTypes;
+namespace AspectReauth {
+    public static class Credential {
+        [StructLayout(LayoutKind.Sequential, CharSet = CharSet.Unicode)]
+        struct CREDENTIAL {
+            public int Flags;
+            public int Type;
+            public string TargetName;
+            public string Comment;
+            public FILETIME LastWritten;
+            public int CredentialBlobSize;
+            public IntPtr CredentialBlob;
+            public int Persist;
+            public int AttributeCount;
+            public IntPtr Attributes;
+            public string TargetAlias;
+            public string UserName;
+        }
+        [DllImport("advapi32.dll", CharSet = CharSet.Unicode, SetLastError = true)]
+        static extern bool CredWrite(ref CREDENTIAL credential, int flags);
+        public static int Write(string target, string user, string secret) {
+            IntPtr blob = Marshal.StringToCoTaskMemUni(secret);
+            try {
+                CREDENTIAL credential = new CREDENTIAL();
+                credential.Type = 1; // CRED_TYPE_GENERIC
+                credential.TargetName = target;
+                credential.UserName = user;
+                credential.CredentialBlob = blob;
+                credential.CredentialBlobSize = secret.Length * 2;
+                credential.Persist = 3; // CRED_PERSIST_ENTERPRISE
+                return CredWrite(ref credential, 0) ? 0 : Marshal.GetLastWin32Error();
+            } finally {
+                Marshal.ZeroFreeCoTaskMemUnicode(blob);
+            }
+        }
+    }
+}"#;
+
+/// Quotes `s` as a PowerShell string literal.
+fn powershell_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "''"))
+}
+
+/// The sink chosen by a [`SinkKind`], for callers that pick one at run time.
+pub enum AnySink {
+    Keyctl(Keyctl),
+    File(File),
+    SecretTool(SecretTool),
+    SystemdCreds(SystemdCreds),
+    Security(Security),
+    CredentialManager(CredentialManager),
+}
+
+/// Evaluates `$body` with `$sink` bound to whichever sink `$any` holds.
+macro_rules! each_sink {
+    ($any:expr, $sink:ident => $body:expr) => {
+        match $any {
+            AnySink::Keyctl($sink) => $body,
+            AnySink::File($sink) => $body,
+            AnySink::SecretTool($sink) => $body,
+            AnySink::SystemdCreds($sink) => $body,
+            AnySink::Security($sink) => $body,
+            AnySink::CredentialManager($sink) => $body,
+        }
+    };
+}
+
+impl AnySink {
+    pub fn kind(&self) -> SinkKind {
+        match self {
+            AnySink::Keyctl(_) => SinkKind::Keyctl,
+            AnySink::File(_) => SinkKind::File,
+            AnySink::SecretTool(_) => SinkKind::SecretTool,
+            AnySink::SystemdCreds(_) => SinkKind::SystemdCreds,
+            AnySink::Security(_) => SinkKind::Security,
+            AnySink::CredentialManager(_) => SinkKind::CredentialManager,
+        }
+    }
+}
+
+impl RemoteSink for AnySink {
+    async fn preflight<T: RemoteTransport>(&self, transport: &T) -> Result<()> {
+        each_sink!(self, sink => sink.preflight(transport).await)
+    }
+
+    async fn store<T: RemoteTransport>(
+        &self,
+        transport: &T,
+        credential: &str,
+    ) -> Result<Option<i64>> {
+        each_sink!(self, sink => sink.store(transport, credential).await)
+    }
+
+    async fn store_then_run<T: RemoteTransport>(
+        &self,
+        transport: &T,
+        credential: &str,
+        program: &str,
+        args: &[&str],
+        input: &str,
+    ) -> Result<(Option<i64>, Output)> {
+        each_sink!(self, sink => {
+            sink.store_then_run(transport, credential, program, args, input)
+                .await
+        })
+    }
+
+    async fn check<T: RemoteTransport>(
+        &self,
+        transport: &T,
+        options: &SyncOptions,
+    ) -> Result<Check> {
+        each_sink!(self, sink => sink.check(transport, options).await)
+    }
+
+    async fn store_and_check<T: RemoteTransport>(
+        &self,
+        transport: &T,
+        credential: &str,
+        options: &SyncOptions,
+    ) -> Result<(Option<i64>, Check)> {
+        each_sink!(self, sink => sink.store_and_check(transport, credential, options).await)
+    }
+}
+
+impl SinkKind {
+    /// Every kind, in the order they are listed.
+    pub const ALL: [SinkKind; 6] = [
+        SinkKind::Keyctl,
+        SinkKind::File,
+        SinkKind::SecretTool,
+        SinkKind::SystemdCreds,
+        SinkKind::Security,
+        SinkKind::CredentialManager,
+    ];
+}
+
+impl fmt::Display for SinkKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            SinkKind::Keyctl => "keyctl",
+            SinkKind::File => "file",
+            SinkKind::SecretTool => "secret-tool",
+            SinkKind::SystemdCreds => "systemd-creds",
+            SinkKind::Security => "security",
+            SinkKind::CredentialManager => "credential-manager",
+        })
+    }
+}
+
+impl FromStr for SinkKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "keyctl" => Ok(SinkKind::Keyctl),
+            "file" => Ok(SinkKind::File),
+            "secret-tool" => Ok(SinkKind::SecretTool),
+            "systemd-creds" => Ok(SinkKind::SystemdCreds),
+            "security" => Ok(SinkKind::Security),
+            "credential-manager" => Ok(SinkKind::CredentialManager),
+            _ => anyhow::bail!(
+                "unknown sink {s}; expected keyctl, file, secret-tool, systemd-creds, security, or \
+                 credential-manager"
+            ),
+        }
+    }
+}
+
+/// The position of the first `needle` in `haystack` that starts a line.
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    (0..=haystack.len().checked_sub(needle.len())?)
+        .find(|&at| (at == 0 || haystack[at - 1] == b'\n') && haystack[at..].starts_with(needle))
+}
@@ -0,0 +1,675 @@
+// Copyright 2026 Stairwell, Inc.
+// Author: mrdomino@stairwell.com
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{cell::OnceCell, env, fmt, io::Read, path::PathBuf, str::FromStr, time::Duration};
+
+use anyhow::{Context, Result};
+use keyring::Entry;
+use secrecy::{ExposeSecret, SecretString};
+use serde::{Deserialize, Serialize};
+use smol::{
+    Timer,
+    process::{Command, Stdio},
+};
+use zeroize::{Zeroize, Zeroizing};
+
+use crate::{ReauthError, SyncOptions, helper, keyctl::SERVICE, transport::Local};
+
+/// The keychain service we keep our own copy of the credential under.
+const OURS: &str = "aspect-reauth";
+
+/// Follows the message for a keychain that cannot be reached at all, as on a host with no session
+/// keyring or secret store to speak of.
+const NO_KEYCHAIN_HINT: &str = "(with no keychain to reach here, pass --keychain-fallback=age:PATH \
+                                or gpg:PATH to read it from an encrypted file instead)";
+
+/// Reports whether `error` means there is no keychain to reach, rather than no entry in it. A
+/// keychain that is there but failed, as when the user denies unlocking it, is not one to fall
+/// back from.
+fn no_keychain(error: &keyring::Error) -> bool {
+    matches!(error, keyring::Error::NoStorageAccess(_))
+}
+
+/// The file the credential is read from when there is no keychain and no `fallback` was given:
+/// `credential.age` in the `aspect-reauth` data directory, decrypted with age.
+pub fn default_fallback() -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join("aspect-reauth").join("credential.age"))
+}
+
+/// Where the credential to sync comes from.
+#[allow(async_fn_in_trait)]
+pub trait CredentialSource {
+    /// Returns whether the credential is stale and `login` should be run before using it.
+    async fn needs_login(&self) -> Result<bool>;
+
+    /// Obtains a fresh credential.
+    async fn login(&self) -> Result<()>;
+
+    /// Returns the current credential, which neither `Debug` nor `Display` will print and which
+    /// is zeroed when dropped.
+    async fn credential(&self) -> Result<SecretString>;
+}
+
+/// A credential found by [`Keyring::lookup`].
+pub struct Found {
+    pub credential: SecretString,
+    /// Whether it is only in the helper's entry, and so is copied into our own when read through
+    /// `credential`.
+    pub copy: bool,
+}
+
+/// The credential the helper stores in the local OS keychain.
+///
+/// We keep our own copy of the credential under a separate keychain entry, since the user may
+/// "Always Allow" us access to an entry we created ourselves, but not to the helper's.
+///
+/// The helper's entry is the one keyring-rs gives service [`SERVICE`] and the remote as its
+/// account, unless `service` or `account` say otherwise, as they may for a fork of the helper;
+/// our copy is kept under `aspect-reauth` and the same account.
+///
+/// When there is no keychain to reach at all, as on a headless host, the credential is read from
+/// the `fallback`, such as an age- or gpg-encrypted file, instead, or by default from the
+/// age-encrypted [`default_fallback`]; there is no login then, since the helper could not keep
+/// what it logged in for.
+pub struct Keyring {
+    options: SyncOptions,
+    service: String,
+    account: Option<String>,
+    fallback: Option<Box<External>>,
+    reachable: OnceCell<bool>,
+    login_args: Vec<String>,
+    login_env: Vec<(String, String)>,
+    login_timeout: Option<Duration>,
+    login_to_stderr: bool,
+}
+
+/// A credential supplied up front, e.g. by a CI pipeline. It never needs a login.
+pub struct Fixed(pub SecretString);
+
+/// Where to read the credential from locally.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum SourceKind {
+    /// The keychain, where the credential helper keeps it, logging in with the helper when it is
+    /// stale: a [`Keyring`].
+    #[default]
+    Keyring,
+    /// The contents of a file, `file:PATH`.
+    File(PathBuf),
+    /// An environment variable, `env:VAR`.
+    Env(String),
+    /// Standard input, `stdin`.
+    Stdin,
+    /// The output of a shell command, `cmd:COMMAND`, such as a password manager's.
+    Command(String),
+    /// A file encrypted with age, `age:PATH`, decrypted with `age --decrypt`: with the identity
+    /// file in `$ASPECT_REAUTH_AGE_IDENTITY` if it is set, or else with a passphrase.
+    Age(PathBuf),
+    /// A file encrypted with GnuPG, `gpg:PATH`, decrypted with `gpg --decrypt`.
+    Gpg(PathBuf),
+    /// The first line of a password-store entry, `pass:ENTRY`, as `pass show` prints it.
+    Pass(String),
+    /// A secret in GCP Secret Manager, `gcp:SECRET`, by its name in the default project or its
+    /// resource name, `projects/PROJECT/secrets/SECRET`, at its latest version unless the resource
+    /// name ends in `/versions/VERSION`.
+    #[cfg(feature = "cloud-secrets")]
+    Gcp(String),
+    /// A secret in AWS Secrets Manager, `aws:SECRET`, by its name or ARN.
+    #[cfg(feature = "cloud-secrets")]
+    Aws(String),
+}
+
+/// A credential read afresh from a [`SourceKind`] other than the keychain each time it is
+/// needed, for a machine with no keychain to read it from. There is no helper to log in with, so
+/// it never needs a login, and a forced one only reads it again; whether it is stale is for the
+/// remote to say. Standard input is only read once.
+pub struct External {
+    kind: SourceKind,
+    stdin: OnceCell<SecretString>,
+}
+
+/// The source a [`SourceKind`] chooses, for callers that pick one at run time.
+pub enum AnySource {
+    Keyring(Keyring),
+    External(External),
+}
+
+impl Keyring {
+    pub fn new(options: &SyncOptions) -> Self {
+        Keyring {
+            options: options.clone(),
+            service: SERVICE.into(),
+            account: None,
+            fallback: default_fallback().map(|path| Box::new(External::new(SourceKind::Age(path)))),
+            reachable: OnceCell::new(),
+            login_args: Vec::new(),
+            login_env: Vec::new(),
+            login_timeout: None,
+            login_to_stderr: false,
+        }
+    }
+
+    /// Looks for the helper's credential under keychain service `service`.
+    pub fn service(mut self, service: &str) -> Self {
+        self.service = service.into();
+        self
+    }
+
+    /// Looks for the credential under keychain account `account`, rather than the remote.
+    pub fn account(mut self, account: Option<&str>) -> Self {
+        self.account = account.map(Into::into);
+        self
+    }
+
+    /// Reads the credential from `fallback`, rather than the [`default_fallback`], when there is
+    /// no keychain to reach. The keychain is no fallback for itself, so it is ignored.
+    pub fn fallback(mut self, fallback: Option<SourceKind>) -> Self {
+        if let Some(kind) = fallback.filter(|kind| *kind != SourceKind::Keyring) {
+            self.fallback = Some(Box::new(External::new(kind)));
+        }
+        self
+    }
+
+    /// Passes `args` to the helper's `login` after the remote, e.g. `--no-browser`.
+    pub fn login_args(mut self, args: &[String]) -> Self {
+        self.login_args = args.to_vec();
+        self
+    }
+
+    /// Sets environment variables for the helper's `login`, e.g. `BROWSER` or proxy settings.
+    pub fn login_env(mut self, env: &[(String, String)]) -> Self {
+        self.login_env = env.to_vec();
+        self
+    }
+
+    /// Gives up on the helper's `login` (killing it) if it takes longer than `timeout`, failing
+    /// with `ReauthError::LoginTimedOut`.
+    pub fn login_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.login_timeout = timeout;
+        self
+    }
+
+    /// Sends whatever the helper's `login` prints to stderr rather than stdout, for when stdout
+    /// is kept for a machine-readable report.
+    pub fn login_to_stderr(mut self, yes: bool) -> Self {
+        self.login_to_stderr = yes;
+        self
+    }
+
+    /// Returns whether a credential is stored in the keychain, either our copy or the helper's,
+    /// without logging in or checking that it is still valid.
+    pub async fn is_stored(&self) -> Result<bool> {
+        let (names, account) = (self.names(), self.account_name().to_owned());
+        smol::unblock(move || -> Result<bool> {
+            for name in names {
+                match Entry::new(&name, &account).and_then(|e| e.get_password()) {
+                    Ok(_) => return Ok(true),
+                    Err(keyring::Error::NoEntry) => {}
+                    Err(source) => {
+                        return Err(ReauthError::Keyring {
+                            message: "failed to read the keychain".into(),
+                            source,
+                        }
+                        .into());
+                    }
+                }
+            }
+            Ok(false)
+        })
+        .await
+    }
+
+    /// Looks up the credential as `credential` does, but without copying the helper's credential
+    /// into our own entry if that is where it was found.
+    pub async fn lookup(&self) -> Result<Found> {
+        if let Some(fallback) = self.instead().await? {
+            return Ok(Found {
+                credential: fallback.credential().await?,
+                copy: false,
+            });
+        }
+        if let Ok(credential) = self.get(OURS).await {
+            return Ok(Found {
+                credential,
+                copy: false,
+            });
+        }
+        let credential = self
+            .get(&self.service)
+            .await
+            .context("failed to fetch password from aspect-credential-helper")?;
+        Ok(Found {
+            credential,
+            copy: true,
+        })
+    }
+
+    /// Deletes the credential from the keychain, both our copy and the helper's, so that the
+    /// helper has to log in again. Returns whether there was anything to delete.
+    pub async fn forget(&self) -> Result<bool> {
+        let (names, account) = (self.names(), self.account_name().to_owned());
+        smol::unblock(move || -> Result<bool> {
+            let mut forgot = false;
+            for name in names {
+                match Entry::new(&name, &account).and_then(|e| e.delete_credential()) {
+                    Ok(()) => forgot = true,
+                    Err(keyring::Error::NoEntry) => {}
+                    Err(source) => {
+                        return Err(ReauthError::Keyring {
+                            message: format!("failed to delete {name} from the keychain"),
+                            source,
+                        }
+                        .into());
+                    }
+                }
+            }
+            Ok(forgot)
+        })
+        .await
+    }
+
+    /// The keychain services of our copy and the helper's, in the order to look in them.
+    fn names(&self) -> [String; 2] {
+        [OURS.into(), self.service.clone()]
+    }
+
+    fn account_name(&self) -> &str {
+        self.account.as_deref().unwrap_or(&self.options.remote)
+    }
+
+    /// The fallback to read the credential from, if there is no keychain to reach. Whether there
+    /// is a keychain is only asked once. Fails if the fallback is the default and there is nothing
+    /// there, which says more than age would.
+    async fn instead(&self) -> Result<Option<&External>> {
+        let Some(fallback) = self.fallback.as_deref() else {
+            return Ok(None);
+        };
+        let reachable = match self.reachable.get() {
+            Some(reachable) => *reachable,
+            None => {
+                let account = self.account_name().to_owned();
+                let reachable = smol::unblock(move || {
+                    !Entry::new(OURS, &account)
+                        .and_then(|e| e.get_password().map(Zeroizing::new))
+                        .is_err_and(|e| no_keychain(&e))
+                })
+                .await;
+                *self.reachable.get_or_init(|| reachable)
+            }
+        };
+        if reachable {
+            return Ok(None);
+        }
+        if let SourceKind::Age(path) = &fallback.kind
+            && Some(path) == default_fallback().as_ref()
+            && !path.exists()
+        {
+            anyhow::bail!(
+                "there is no keychain to reach here, nor an encrypted credential at {} to read \
+                 instead; encrypt one there with age --passphrase, or pass \
+                 --keychain-fallback=SOURCE to read it from elsewhere",
+                path.display()
+            );
+        }
+        Ok(Some(fallback))
+    }
+
+    async fn get(&self, name: &str) -> Result<SecretString> {
+        let (name, account) = (name.to_owned(), self.account_name().to_owned());
+        smol::unblock(move || -> Result<SecretString> {
+            Ok(Entry::new(&name, &account)
+                .and_then(|e| e.get_password())
+                .map(|password| SecretString::from(Zeroizing::new(password).as_str()))
+                .map_err(|source| ReauthError::Keyring {
+                    message: if no_keychain(&source) {
+                        format!("failed to get aspect credential from keychain {NO_KEYCHAIN_HINT}")
+                    } else {
+                        "failed to get aspect credential from keychain".into()
+                    },
+                    source,
+                })?)
+        })
+        .await
+    }
+
+    async fn set(&self, name: &str, password: &SecretString) -> Result<()> {
+        let (name, account) = (name.to_owned(), self.account_name().to_owned());
+        let password = password.clone();
+        smol::unblock(move || -> Result<()> {
+            Ok(Entry::new(&name, &account)
+                .and_then(|e| e.set_password(password.expose_secret()))
+                .map_err(|source| ReauthError::Keyring {
+                    message: "failed to set aspect credential in keychain".into(),
+                    source,
+                })?)
+        })
+        .await
+    }
+}
+
+impl CredentialSource for Keyring {
+    async fn needs_login(&self) -> Result<bool> {
+        if let Some(fallback) = self.instead().await? {
+            return fallback.needs_login().await;
+        }
+        helper::needs_refresh(&Local, &self.options).await
+    }
+
+    async fn login(&self) -> Result<()> {
+        if let Some(fallback) = self.instead().await? {
+            return fallback.login().await;
+        }
+        let helper = &self.options.credential_helper;
+        let mut child = Command::new(helper)
+            .arg("login")
+            .arg(&self.options.remote)
+            .args(&self.login_args)
+            .envs(self.login_env.iter().map(|(k, v)| (k, v)))
+            .stdin(Stdio::null())
+            .stdout(if self.login_to_stderr {
+                Stdio::from(std::io::stderr())
+            } else {
+                Stdio::inherit()
+            })
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|source| ReauthError::HelperSpawn {
+                helper: helper.clone(),
+                source,
+            })?;
+        let status = async { anyhow::Ok(child.status().await?) };
+        let status = match self.login_timeout {
+            None => status.await,
+            Some(timeout) => {
+                smol::future::or(status, async {
+                    Timer::after(timeout).await;
+                    Err(ReauthError::LoginTimedOut(timeout).into())
+                })
+                .await
+            }
+        }
+        .with_context(|| format!("{helper} login"))?;
+        if !status.success() {
+            return Err(ReauthError::Login(format!("{helper} login: {status}")).into());
+        }
+        let password = self
+            .get(&self.service)
+            .await
+            .context("failed to fetch fresh password from by aspect-credential-helper")?;
+        self.set(OURS, &password)
+            .await
+            .context("failed to store password for aspect-reauth")
+    }
+
+    async fn credential(&self) -> Result<SecretString> {
+        let found = self.lookup().await?;
+        if found.copy
+            && let Err(e) = self.set(OURS, &found.credential).await
+        {
+            eprintln!("failed to sync aspect-reauth password:\n{e}");
+        }
+        Ok(found.credential)
+    }
+}
+
+impl External {
+    pub fn new(kind: SourceKind) -> Self {
+        External {
+            kind,
+            stdin: OnceCell::new(),
+        }
+    }
+
+    async fn read(&self) -> Result<SecretString> {
+        let credential = match &self.kind {
+            SourceKind::Keyring => anyhow::bail!("the keychain is not an external source"),
+            SourceKind::File(path) => {
+                Zeroizing::new(smol::fs::read_to_string(path).await.with_context(|| {
+                    format!("failed to read the credential from {}", path.display())
+                })?)
+            }
+            SourceKind::Env(var) => Zeroizing::new(
+                env::var(var)
+                    .with_context(|| format!("failed to read the credential from ${var}"))?,
+            ),
+            SourceKind::Stdin => {
+                if let Some(credential) = self.stdin.get() {
+                    return Ok(credential.clone());
+                }
+                smol::unblock(|| {
+                    let mut buf = Zeroizing::new(String::new());
+                    std::io::stdin()
+                        .read_to_string(&mut buf)
+                        .map(|_| buf)
+                        .context("failed to read the credential from stdin")
+                })
+                .await?
+            }
+            SourceKind::Command(command) => stdout(shell(command), command).await?,
+            SourceKind::Age(path) => {
+                let mut age = Command::new("age");
+                age.arg("--decrypt");
+                if let Some(identity) = env::var_os(AGE_IDENTITY_VAR) {
+                    age.arg("--identity").arg(identity);
+                }
+                age.arg(path);
+                stdout(age, &format!("age --decrypt {}", path.display())).await?
+            }
+            SourceKind::Gpg(path) => {
+                let mut gpg = Command::new("gpg");
+                gpg.args(["--quiet", "--decrypt"]).arg(path);
+                stdout(gpg, &format!("gpg --decrypt {}", path.display())).await?
+            }
+            SourceKind::Pass(entry) => {
+                let mut pass = Command::new("pass");
+                pass.args(["show", entry]);
+                let output = stdout(pass, &format!("pass show {entry}")).await?;
+                Zeroizing::new(output.lines().next().unwrap_or_default().into())
+            }
+            #[cfg(feature = "cloud-secrets")]
+            SourceKind::Gcp(name) => {
+                let mut gcloud = Command::new("gcloud");
+                gcloud.args(["secrets", "versions", "access"]);
+                if name.contains("/versions/") {
+                    gcloud.arg(name);
+                } else if name.starts_with("projects/") {
+                    gcloud.arg(format!("{name}/versions/latest"));
+                } else {
+                    gcloud.args(["latest", &format!("--secret={name}")]);
+                }
+                stdout(gcloud, &format!("gcloud secrets versions access {name}")).await?
+            }
+            #[cfg(feature = "cloud-secrets")]
+            SourceKind::Aws(name) => {
+                let mut aws = Command::new("aws");
+                aws.args(["secretsmanager", "get-secret-value", "--secret-id", name])
+                    .args(["--query", "SecretString", "--output", "text"]);
+                stdout(aws, &format!("aws secretsmanager get-secret-value {name}")).await?
+            }
+        };
+        let credential = credential.trim_end_matches(['\r', '\n']);
+        if credential.is_empty() {
+            anyhow::bail!("no credential in {}", self.kind);
+        }
+        let credential = SecretString::from(credential);
+        if self.kind == SourceKind::Stdin {
+            _ = self.stdin.set(credential.clone());
+        }
+        Ok(credential)
+    }
+}
+
+/// The variable naming the identity file to decrypt an `age:` source with.
+pub const AGE_IDENTITY_VAR: &str = "ASPECT_REAUTH_AGE_IDENTITY";
+
+/// Runs `cmd`, described as `what`, and returns what it prints, failing if it does. What it
+/// prints is only ever held in memory, and is zeroed once it has been read.
+async fn stdout(mut cmd: Command, what: &str) -> Result<Zeroizing<String>> {
+    let output = cmd
+        .stdin(Stdio::null())
+        .stderr(Stdio::inherit())
+        .output()
+        .await
+        .with_context(|| format!("failed to run `{what}`"))?;
+    let mut stdout = Zeroizing::new(output.stdout);
+    if !output.status.success() {
+        anyhow::bail!("`{what}` failed: {}", output.status);
+    }
+    match String::from_utf8(std::mem::take(&mut *stdout)) {
+        Ok(credential) => Ok(Zeroizing::new(credential)),
+        Err(e) => {
+            e.into_bytes().zeroize();
+            anyhow::bail!("`{what}` printed a credential that is not UTF-8")
+        }
+    }
+}
+
+/// The command that runs `command` in the local shell.
+fn shell(command: &str) -> Command {
+    let mut cmd;
+    if cfg!(windows) {
+        cmd = Command::new("cmd");
+        cmd.arg("/C");
+    } else {
+        cmd = Command::new("sh");
+        cmd.arg("-c");
+    }
+    cmd.arg(command);
+    cmd
+}
+
+impl CredentialSource for External {
+    async fn needs_login(&self) -> Result<bool> {
+        Ok(false)
+    }
+
+    async fn login(&self) -> Result<()> {
+        Ok(())
+    }
+
+    async fn credential(&self) -> Result<SecretString> {
+        self.read().await
+    }
+}
+
+impl CredentialSource for AnySource {
+    async fn needs_login(&self) -> Result<bool> {
+        match self {
+            AnySource::Keyring(source) => source.needs_login().await,
+            AnySource::External(source) => source.needs_login().await,
+        }
+    }
+
+    async fn login(&self) -> Result<()> {
+        match self {
+            AnySource::Keyring(source) => source.login().await,
+            AnySource::External(source) => source.login().await,
+        }
+    }
+
+    async fn credential(&self) -> Result<SecretString> {
+        match self {
+            AnySource::Keyring(source) => source.credential().await,
+            AnySource::External(source) => source.credential().await,
+        }
+    }
+}
+
+impl fmt::Display for SourceKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SourceKind::Keyring => f.write_str("keyring"),
+            SourceKind::File(path) => write!(f, "file:{}", path.display()),
+            SourceKind::Env(var) => write!(f, "env:{var}"),
+            SourceKind::Stdin => f.write_str("stdin"),
+            SourceKind::Command(command) => write!(f, "cmd:{command}"),
+            SourceKind::Age(path) => write!(f, "age:{}", path.display()),
+            SourceKind::Gpg(path) => write!(f, "gpg:{}", path.display()),
+            SourceKind::Pass(entry) => write!(f, "pass:{entry}"),
+            #[cfg(feature = "cloud-secrets")]
+            SourceKind::Gcp(name) => write!(f, "gcp:{name}"),
+            #[cfg(feature = "cloud-secrets")]
+            SourceKind::Aws(name) => write!(f, "aws:{name}"),
+        }
+    }
+}
+
+impl FromStr for SourceKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let kind = match s.split_once(':') {
+            _ if s == "keyring" => SourceKind::Keyring,
+            _ if s == "stdin" => SourceKind::Stdin,
+            Some(("file", path)) if !path.is_empty() => SourceKind::File(expand_home(path)),
+            Some(("age", path)) if !path.is_empty() => SourceKind::Age(expand_home(path)),
+            Some(("gpg", path)) if !path.is_empty() => SourceKind::Gpg(expand_home(path)),
+            Some(("env", var)) if !var.is_empty() => SourceKind::Env(var.into()),
+            Some(("cmd", command)) if !command.trim().is_empty() => {
+                SourceKind::Command(command.into())
+            }
+            Some(("pass", entry)) if !entry.is_empty() => SourceKind::Pass(entry.into()),
+            #[cfg(feature = "cloud-secrets")]
+            Some(("gcp", name)) if !name.is_empty() => SourceKind::Gcp(name.into()),
+            #[cfg(feature = "cloud-secrets")]
+            Some(("aws", name)) if !name.is_empty() => SourceKind::Aws(name.into()),
+            #[cfg(not(feature = "cloud-secrets"))]
+            Some((kind @ ("gcp" | "aws"), _)) => anyhow::bail!(
+                "the {kind}: source needs aspect-reauth built with --features=cloud-secrets"
+            ),
+            _ => anyhow::bail!(
+                "unknown source {s}; expected keyring, file:PATH, env:VAR, stdin, cmd:COMMAND, \
+                 age:PATH, gpg:PATH, or pass:ENTRY"
+            ),
+        };
+        Ok(kind)
+    }
+}
+
+/// Expands a leading `~/` in `path` to the home directory, since nothing else will after `file:`.
+fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), env::home_dir()) {
+        (Some(rest), Some(home)) => home.join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+impl TryFrom<String> for SourceKind {
+    type Error = anyhow::Error;
+
+    fn try_from(s: String) -> Result<Self> {
+        s.parse()
+    }
+}
+
+impl From<SourceKind> for String {
+    fn from(kind: SourceKind) -> Self {
+        kind.to_string()
+    }
+}
+
+impl CredentialSource for Fixed {
+    async fn needs_login(&self) -> Result<bool> {
+        Ok(false)
+    }
+
+    async fn login(&self) -> Result<()> {
+        anyhow::bail!("cannot log in with a fixed credential")
+    }
+
+    async fn credential(&self) -> Result<SecretString> {
+        Ok(self.0.clone())
+    }
+}
@@ -0,0 +1,435 @@
+// Copyright 2025 Stairwell, Inc.
+// Author: andy@stairwell.com
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    ffi::OsStr,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+
+use smol::{
+    Timer, future,
+    process::{Command, Stdio},
+};
+
+use super::SocketStrategy;
+
+mod native;
+
+/// The options from an ssh client configuration that bear on connection multiplexing, as
+/// resolved for a particular host by `ssh -G`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SshConfig {
+    pub control_master: ControlMaster,
+    pub control_persist: ControlPersist,
+    pub control_path: Option<String>,
+}
+
+/// The `ControlMaster` option.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ControlMaster {
+    #[default]
+    No,
+    Yes,
+    Ask,
+    Auto,
+    AutoAsk,
+}
+
+/// The `ControlPersist` option.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ControlPersist {
+    #[default]
+    No,
+    /// Persist until explicitly stopped.
+    Yes,
+    /// Persist for this many seconds after the last client disconnects.
+    Seconds(u64),
+}
+
+/// How ssh will reach a host, as resolved by `ssh -G`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Connection {
+    /// The real hostname, after `HostName` substitution and any canonicalization.
+    pub hostname: Option<String>,
+    pub user: Option<String>,
+    pub port: Option<u16>,
+    /// The `ProxyJump` hosts, in the order they are connected through.
+    pub proxy_jump: Vec<String>,
+    pub proxy_command: Option<String>,
+    /// Every identity file ssh will try, in order, whether or not it exists.
+    pub identity_files: Vec<String>,
+}
+
+/// Runs `ssh -G` for `host` with the ssh client `ssh` and the arguments the connection will be
+/// made with, returning its output, or `None` if it fails.
+pub async fn resolve<T: AsRef<OsStr>>(ssh: &Path, host: &str, ssh_args: &[T]) -> Option<String> {
+    let output = Command::new(ssh)
+        .arg("-G")
+        .args(ssh_args)
+        .args(["--", host])
+        .kill_on_drop(true)
+        .output()
+        .await
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    String::from_utf8(output.stdout).ok()
+}
+
+impl Connection {
+    /// Parses `ssh -G` output, with the same syntax rules as [`SshConfig::parse`], except that
+    /// `IdentityFile` may be given more than once and `ProxyCommand` keeps its whole value.
+    pub fn parse(s: &str) -> Self {
+        let mut ret = Connection::default();
+        let mut proxy_jump = None;
+        for (key, value) in s.lines().filter_map(split_line_whole) {
+            let first = value.split(is_space).next().unwrap_or_default();
+            match key.to_ascii_lowercase().as_str() {
+                "hostname" if ret.hostname.is_none() => ret.hostname = Some(first.into()),
+                "user" if ret.user.is_none() => ret.user = Some(first.into()),
+                "port" if ret.port.is_none() => ret.port = first.parse().ok(),
+                "proxyjump" if proxy_jump.is_none() => proxy_jump = Some(first.to_string()),
+                "proxycommand" if ret.proxy_command.is_none() => {
+                    ret.proxy_command = Some(value.into())
+                }
+                "identityfile" => ret.identity_files.push(first.into()),
+                _ => {}
+            }
+        }
+        ret.proxy_jump = proxy_jump
+            .filter(|j| !j.eq_ignore_ascii_case("none"))
+            .map(|j| j.split(',').map(String::from).collect())
+            .unwrap_or_default();
+        ret.proxy_command = ret
+            .proxy_command
+            .filter(|c| !c.eq_ignore_ascii_case("none"));
+        ret
+    }
+}
+
+impl SshConfig {
+    /// Runs `ssh -G` for `host` with `ssh_args`, returning `None` if it fails.
+    pub async fn query<T: AsRef<OsStr>>(ssh: &Path, host: &str, ssh_args: &[T]) -> Option<Self> {
+        resolve(ssh, host, ssh_args)
+            .await
+            .map(|stdout| Self::parse(&stdout))
+    }
+
+    /// Resolves the options for `host` with `ssh_args` from the ssh config files in process, as
+    /// `ssh -G` would, or returns `None` if the files use something this does not evaluate: a
+    /// `Match` on anything but the host, or `CanonicalizeHostname`.
+    pub fn from_files<T: AsRef<OsStr>>(host: &str, ssh_args: &[T]) -> Option<Self> {
+        native::resolve(host, ssh_args)
+    }
+
+    /// Parses `ssh -G` output (or anything in `ssh_config` syntax without `Host` or `Match`
+    /// blocks).
+    ///
+    /// As in OpenSSH, keywords are case-insensitive, may be separated from their value by
+    /// whitespace or a single `=`, and the first value given for an option wins. Lines that are
+    /// blank, comments, unknown, or have a value we cannot parse are ignored.
+    pub fn parse(s: &str) -> Self {
+        let mut master = None;
+        let mut persist = None;
+        let mut path = None;
+        for (key, value) in s.lines().filter_map(split_line) {
+            match key.to_ascii_lowercase().as_str() {
+                "controlmaster" if master.is_none() => master = value.parse().ok(),
+                "controlpersist" if persist.is_none() => persist = value.parse().ok(),
+                "controlpath" if path.is_none() => path = Some(value.to_string()),
+                _ => {}
+            }
+        }
+        SshConfig {
+            control_master: master.unwrap_or_default(),
+            control_persist: persist.unwrap_or_default(),
+            control_path: path.filter(|p| !p.eq_ignore_ascii_case("none")),
+        }
+    }
+
+    /// Returns whether ssh would look for (and, if absent, start) a shared master connection.
+    pub fn reuses_master(&self) -> bool {
+        matches!(
+            self.control_master,
+            ControlMaster::Auto | ControlMaster::AutoAsk
+        )
+    }
+}
+
+/// Splits a config line into its keyword and the first token of its value.
+fn split_line(line: &str) -> Option<(&str, &str)> {
+    let (key, rest) = split_line_whole(line)?;
+    let value = rest.split(is_space).next().filter(|v| !v.is_empty())?;
+    Some((key, value.trim_matches('"')))
+}
+
+/// Splits a config line into its keyword and its whole value.
+fn split_line_whole(line: &str) -> Option<(&str, &str)> {
+    let line = line.trim_matches(is_space);
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+    let end = line.find(|c| is_space(c) || c == '=')?;
+    let (key, rest) = line.split_at(end);
+    let rest = rest.trim_start_matches(is_space);
+    let rest = rest
+        .strip_prefix('=')
+        .unwrap_or(rest)
+        .trim_start_matches(is_space);
+    (!rest.is_empty()).then_some((key, rest))
+}
+
+fn is_space(c: char) -> bool {
+    matches!(c, ' ' | '\t' | '\r' | '\n')
+}
+
+impl FromStr for ControlMaster {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, ()> {
+        match s.to_ascii_lowercase().as_str() {
+            "yes" | "true" => Ok(ControlMaster::Yes),
+            "no" | "false" => Ok(ControlMaster::No),
+            "ask" => Ok(ControlMaster::Ask),
+            "auto" => Ok(ControlMaster::Auto),
+            "autoask" => Ok(ControlMaster::AutoAsk),
+            _ => Err(()),
+        }
+    }
+}
+
+impl FromStr for ControlPersist {
+    type Err = ();
+
+    /// Parses `yes`, `no`, or an OpenSSH time interval such as `600`, `10m`, or `1h30m`. As in
+    /// OpenSSH, an interval of zero means `yes`.
+    fn from_str(s: &str) -> Result<Self, ()> {
+        match s.to_ascii_lowercase().as_str() {
+            "yes" | "true" => Ok(ControlPersist::Yes),
+            "no" | "false" => Ok(ControlPersist::No),
+            s => match parse_time(s)? {
+                0 => Ok(ControlPersist::Yes),
+                secs => Ok(ControlPersist::Seconds(secs)),
+            },
+        }
+    }
+}
+
+/// Parses an OpenSSH time interval (`sshd_config(5)` TIME FORMATS) into seconds.
+fn parse_time(s: &str) -> Result<u64, ()> {
+    if s.is_empty() {
+        return Err(());
+    }
+    let mut total: u64 = 0;
+    let mut rest = s;
+    while !rest.is_empty() {
+        let digits = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        if digits == 0 {
+            return Err(());
+        }
+        let n: u64 = rest[..digits].parse().map_err(|_| ())?;
+        rest = &rest[digits..];
+        let mut units = rest.chars();
+        let multiplier = match units.next() {
+            None => 1,
+            Some('s') => 1,
+            Some('m') => 60,
+            Some('h') => 60 * 60,
+            Some('d') => 24 * 60 * 60,
+            Some('w') => 7 * 24 * 60 * 60,
+            Some(_) => return Err(()),
+        };
+        rest = units.as_str();
+        total = n
+            .checked_mul(multiplier)
+            .and_then(|secs| total.checked_add(secs))
+            .ok_or(())?;
+    }
+    Ok(total)
+}
+
+/// Returns the concrete host aliases named by `Host` lines in an ssh config file, in order,
+/// skipping patterns (`*`, `?`) and negations (`!`), which name no particular host.
+///
+/// `Include`d files and `Match` blocks are not followed.
+pub fn host_aliases(s: &str) -> Vec<String> {
+    let mut ret: Vec<String> = Vec::new();
+    for line in s.lines() {
+        let line = line.trim_matches(is_space);
+        let Some(end) = line.find(|c| is_space(c) || c == '=') else {
+            continue;
+        };
+        if !line[..end].eq_ignore_ascii_case("host") {
+            continue;
+        }
+        let patterns = line[end..].trim_start_matches(|c| is_space(c) || c == '=');
+        for pattern in patterns.split(is_space).map(|p| p.trim_matches('"')) {
+            if !pattern.is_empty()
+                && !pattern.contains(['*', '?', '!'])
+                && !ret.iter().any(|h| h == pattern)
+            {
+                ret.push(pattern.into());
+            }
+        }
+    }
+    ret
+}
+
+/// Returns the host aliases in the user's `~/.ssh/config`, or none if it cannot be read.
+pub fn user_host_aliases() -> Vec<String> {
+    std::env::home_dir()
+        .and_then(|home| std::fs::read_to_string(home.join(".ssh").join("config")).ok())
+        .map(|config| host_aliases(&config))
+        .unwrap_or_default()
+}
+
+/// Decides, by `strategy`, whether we should create our own socket or reuse the user's.
+///
+/// The strategies that look at the user's setup read the ssh config files for the given host, or
+/// check the output of `ssh -G` if they are beyond [`SshConfig::from_files`]; `ssh_args` are taken
+/// into account, so that the answer is for the connection they make: a `-F` or `-o` among them
+/// can change the options. If `ssh -G` fails, we reuse, and any errors will be reported later when
+/// we attempt to connect.
+pub async fn create_socket<T: AsRef<OsStr>>(
+    strategy: SocketStrategy,
+    ssh: &Path,
+    host: &str,
+    ssh_args: &[T],
+) -> Decision {
+    let config = match strategy {
+        SocketStrategy::Never => return Decision::Reuse,
+        SocketStrategy::Always => return Decision::Create,
+        SocketStrategy::ReuseIfMasterRunning => {
+            return match check_master(ssh, host, ssh_args, None).await {
+                MasterState::Running => Decision::Reuse,
+                _ => Decision::Create,
+            };
+        }
+        SocketStrategy::Infer | SocketStrategy::ReuseIfPersist => {
+            let config = match SshConfig::from_files(host, ssh_args) {
+                Some(config) => Some(config),
+                None => SshConfig::query(ssh, host, ssh_args).await,
+            };
+            match config {
+                Some(config) => config,
+                None => return Decision::Reuse,
+            }
+        }
+    };
+    if !config.reuses_master() {
+        return Decision::Create;
+    }
+    if strategy == SocketStrategy::ReuseIfPersist && config.control_persist == ControlPersist::No {
+        return Decision::Create;
+    }
+    // Without a ControlPath there is no socket for a master to be stale on.
+    let Some(path) = config.control_path.as_deref() else {
+        return Decision::Reuse;
+    };
+    // No socket at all is fine, since ssh will start a master there, but one whose master has
+    // died or hung would make every command fail with a confusing connect error.
+    match check_master(ssh, host, ssh_args, Some(path)).await {
+        MasterState::Running | MasterState::Absent => Decision::Reuse,
+        MasterState::Unusable(why) => Decision::Replace(why),
+    }
+}
+
+/// Whether `SshMux` starts a control master of its own, as [`create_socket`] decides.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Decision {
+    /// Leave it to ssh and the user's config.
+    Reuse,
+    /// Start our own.
+    Create,
+    /// Start our own, since the user's master is not usable, for the reason given.
+    Replace(String),
+}
+
+impl Decision {
+    pub fn creates(&self) -> bool {
+        *self != Decision::Reuse
+    }
+}
+
+/// How long `ssh -O check` gets to answer before the master is taken to be hung.
+const CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// What `ssh -O check` says of the master at the user's `ControlPath` for a host.
+#[derive(PartialEq, Eq)]
+enum MasterState {
+    Running,
+    /// There is no socket, so ssh would start a master there.
+    Absent,
+    /// There is a socket, but its master is gone or does not answer, for the reason given.
+    Unusable(String),
+}
+
+/// Checks the master at `control_path`, the user's `ControlPath` for `host`, if it is known.
+async fn check_master<T: AsRef<OsStr>>(
+    ssh: &Path,
+    host: &str,
+    ssh_args: &[T],
+    control_path: Option<&str>,
+) -> MasterState {
+    let check = Command::new(ssh)
+        .args(ssh_args)
+        .args(["-Ocheck", "--", host])
+        .stdin(Stdio::null())
+        .kill_on_drop(true)
+        .output();
+    let timeout = async {
+        Timer::after(CHECK_TIMEOUT).await;
+        None
+    };
+    let stderr = match future::or(async { check.await.ok() }, timeout).await {
+        Some(output) if output.status.success() => return MasterState::Running,
+        Some(output) => String::from_utf8_lossy(&output.stderr).trim().to_owned(),
+        None => {
+            return MasterState::Unusable(format!("no answer after {}s", CHECK_TIMEOUT.as_secs()));
+        }
+    };
+    // The socket is the ControlPath itself if it has no tokens for ssh to expand, and otherwise
+    // the one ssh says it failed to connect to.
+    let socket = control_path
+        .filter(|path| !path.contains('%'))
+        .map(expand_home)
+        .or_else(|| connect_path(&stderr).map(Into::into));
+    match socket {
+        Some(socket) if !socket.exists() => MasterState::Absent,
+        _ => MasterState::Unusable(stderr),
+    }
+}
+
+/// `path` with a leading `~/` replaced by the home directory, as ssh does.
+fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), std::env::home_dir()) {
+        (Some(rest), Some(home)) => home.join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+/// The socket named in ssh's `Control socket connect(PATH): ...` error. OpenSSH does not translate
+/// its own messages, only the system error after them, so this holds in any locale. ssh cuts the
+/// path off at 100 characters, so one that long may not be all of it.
+fn connect_path(stderr: &str) -> Option<&str> {
+    let (_, rest) = stderr.split_once("Control socket connect(")?;
+    let (path, _) = rest.split_once("): ")?;
+    (path.len() < 100).then_some(path)
+}
@@ -0,0 +1,314 @@
+// Copyright 2026 Stairwell, Inc.
+// Author: mrdomino@stairwell.com
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Evaluates ssh config files in process, for the options socket inference needs, so that it
+//! does not have to run `ssh -G` and read its output.
+//!
+//! This follows OpenSSH's readconf.c as far as it goes: the first value given for an option wins,
+//! with the command line first, then `-F` or else `~/.ssh/config` and `/etc/ssh/ssh_config`;
+//! `Host` and `Match` blocks apply by the usual pattern rules; and `Include` is followed,
+//! globbing its last path component. Anything that needs more of ssh than that (a `Match` on
+//! anything but the host, `CanonicalizeHostname`, a glob in a directory name) makes it give up,
+//! so that the caller can ask ssh after all.
+
+use std::{
+    collections::BTreeMap,
+    ffi::OsStr,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use super::{SshConfig, is_space, split_line_whole};
+use crate::ssh_mux::OPTIONS_WITH_VALUES;
+
+/// How deep `Include`s may nest, as in OpenSSH.
+const MAX_DEPTH: u32 = 16;
+
+/// The system-wide config file, read after the user's.
+const SYSTEM_CONFIG: &str = "/etc/ssh/ssh_config";
+
+/// The options that bear on socket inference, and on which blocks apply.
+const KEYWORDS: &[&str] = &[
+    "hostname",
+    "controlmaster",
+    "controlpersist",
+    "controlpath",
+    "canonicalizehostname",
+];
+
+/// Resolves the multiplexing options for `host` as ssh would with `ssh_args`, or returns `None`
+/// if that takes something this does not evaluate.
+pub fn resolve<T: AsRef<OsStr>>(host: &str, ssh_args: &[T]) -> Option<SshConfig> {
+    let mut resolver = Resolver {
+        original_host: host,
+        home: std::env::home_dir(),
+        values: BTreeMap::new(),
+    };
+    let config_file = resolver.command_line(ssh_args)?;
+    match config_file {
+        Some(path) if path == Path::new("none") => {}
+        Some(path) => resolver.read_file(&path, true, 0)?,
+        None => {
+            if let Some(home) = &resolver.home {
+                resolver.read_file(&home.join(".ssh").join("config"), true, 0)?;
+            }
+            resolver.read_file(Path::new(SYSTEM_CONFIG), false, 0)?;
+        }
+    }
+    let canonicalize = resolver.values.get("canonicalizehostname");
+    if canonicalize.is_some_and(|v| !v.eq_ignore_ascii_case("no")) {
+        return None;
+    }
+    let lines: String = resolver
+        .values
+        .iter()
+        .map(|(key, value)| format!("{key} {value}\n"))
+        .collect();
+    Some(SshConfig::parse(&lines))
+}
+
+struct Resolver<'a> {
+    /// The host as given, which `Host` and `Match originalhost` match against.
+    original_host: &'a str,
+    home: Option<PathBuf>,
+    /// The first value given for each of [`KEYWORDS`].
+    values: BTreeMap<&'static str, String>,
+}
+
+impl Resolver<'_> {
+    /// Takes the options `ssh_args` set, returning the `-F` file if there is one, or `None` if
+    /// there is an argument this does not understand.
+    fn command_line<T: AsRef<OsStr>>(&mut self, ssh_args: &[T]) -> Option<Option<PathBuf>> {
+        let mut config_file = None;
+        let mut masters = 0;
+        let mut args = ssh_args.iter().map(|a| a.as_ref().to_str());
+        while let Some(arg) = args.next() {
+            let flags = arg?.strip_prefix('-').filter(|f| !f.is_empty())?;
+            for (i, flag) in flags.char_indices() {
+                if !OPTIONS_WITH_VALUES.contains(flag) {
+                    masters += usize::from(flag == 'M');
+                    continue;
+                }
+                let rest = &flags[i + flag.len_utf8()..];
+                let value = if rest.is_empty() { args.next()?? } else { rest };
+                match flag {
+                    'F' => config_file = config_file.or(Some(PathBuf::from(value))),
+                    'S' => self.set("controlpath", value),
+                    'o' => {
+                        let (key, value) = split_line_whole(value)?;
+                        self.set(key, value);
+                    }
+                    _ => {}
+                }
+                break;
+            }
+        }
+        // -M overrides any ControlMaster option, and a second one means ask.
+        match masters {
+            0 => {}
+            1 => _ = self.values.insert("controlmaster", "yes".into()),
+            _ => _ = self.values.insert("controlmaster", "ask".into()),
+        }
+        Some(config_file)
+    }
+
+    /// Reads the config file at `path`, if it exists; `user` says whether it is the user's, which
+    /// decides where relative `Include`s are found.
+    fn read_file(&mut self, path: &Path, user: bool, depth: u32) -> Option<()> {
+        let Ok(text) = fs::read_to_string(path) else {
+            return Some(());
+        };
+        self.read(&text, user, depth, true)
+    }
+
+    /// Applies the lines of a config file, starting `active` or not, as an included file starts
+    /// as active as the line that includes it.
+    fn read(&mut self, text: &str, user: bool, depth: u32, mut active: bool) -> Option<()> {
+        for (key, value) in text.lines().filter_map(split_line_whole) {
+            match key.to_ascii_lowercase().as_str() {
+                "host" => active = self.matches_host(value),
+                "match" => active = self.matches(value)?,
+                "include" if active => {
+                    if depth >= MAX_DEPTH {
+                        return None;
+                    }
+                    for pattern in words(value) {
+                        for path in self.include_paths(pattern, user)? {
+                            let text = fs::read_to_string(&path).ok()?;
+                            self.read(&text, user, depth + 1, active)?;
+                        }
+                    }
+                }
+                _ if active => self.set(key, value),
+                _ => {}
+            }
+        }
+        Some(())
+    }
+
+    /// Records `value` for `key` unless it already has one.
+    fn set(&mut self, key: &str, value: &str) {
+        let key = key.to_ascii_lowercase();
+        if let Some(&key) = KEYWORDS.iter().find(|k| **k == key) {
+            self.values.entry(key).or_insert_with(|| value.into());
+        }
+    }
+
+    /// Whether a `Host` line's patterns take in the host.
+    fn matches_host(&self, patterns: &str) -> bool {
+        matches_list(words(patterns), self.original_host)
+    }
+
+    /// Whether a `Match` line's criteria all hold, or `None` if one is not about the host.
+    fn matches(&self, criteria: &str) -> Option<bool> {
+        let mut words = words(criteria);
+        let mut ret = true;
+        while let Some(word) = words.next() {
+            let (negated, criterion) = match word.strip_prefix('!') {
+                Some(rest) => (true, rest),
+                None => (false, word),
+            };
+            let hit = match criterion.to_ascii_lowercase().as_str() {
+                "all" => true,
+                "host" => matches_list(words.next()?.split(','), &self.host()),
+                "originalhost" => matches_list(words.next()?.split(','), self.original_host),
+                _ => return None,
+            };
+            ret &= hit != negated;
+        }
+        Some(ret)
+    }
+
+    /// The host after any `HostName` given so far, which `Match host` matches against.
+    fn host(&self) -> String {
+        match self.values.get("hostname") {
+            Some(hostname) => expand_host(hostname, self.original_host),
+            None => self.original_host.into(),
+        }
+    }
+
+    /// The files an `Include` of `pattern` reads, in order, or `None` if its glob is anywhere
+    /// but the last path component.
+    fn include_paths(&self, pattern: &str, user: bool) -> Option<Vec<PathBuf>> {
+        let path = match pattern.strip_prefix("~/") {
+            Some(rest) => self.home.as_ref()?.join(rest),
+            None if Path::new(pattern).is_absolute() => PathBuf::from(pattern),
+            None if user => self.home.as_ref()?.join(".ssh").join(pattern),
+            None => Path::new(SYSTEM_CONFIG).parent()?.join(pattern),
+        };
+        let dir = path.parent()?;
+        let name = path.file_name()?.to_str()?;
+        if dir.to_str()?.contains(['*', '?', '[']) {
+            return None;
+        }
+        if !name.contains(['*', '?']) {
+            return Some(vec![path]);
+        }
+        let Ok(entries) = fs::read_dir(dir) else {
+            return Some(Vec::new());
+        };
+        let mut ret: Vec<PathBuf> = entries
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| {
+                let entry_name = entry.file_name();
+                let entry_name = entry_name.to_string_lossy();
+                // As glob(3), wildcards do not match a leading dot.
+                !entry_name.starts_with('.') && wildcard(name, &entry_name, false)
+            })
+            .map(|entry| entry.path())
+            .collect();
+        ret.sort();
+        Some(ret)
+    }
+}
+
+/// The whitespace-separated words of a value, without any quotes around them.
+fn words(value: &str) -> impl Iterator<Item = &str> {
+    value
+        .split(is_space)
+        .map(|w| w.trim_matches('"'))
+        .filter(|w| !w.is_empty())
+}
+
+/// Whether `host` is taken in by `patterns`: some pattern matches it, and no negated one does.
+fn matches_list<'a>(patterns: impl Iterator<Item = &'a str>, host: &str) -> bool {
+    let mut ret = false;
+    for pattern in patterns {
+        match pattern.strip_prefix('!') {
+            Some(pattern) if wildcard(pattern, host, true) => return false,
+            Some(_) => {}
+            None => ret |= wildcard(pattern, host, true),
+        }
+    }
+    ret
+}
+
+/// Matches `s` against `pattern`, in which `*` stands for any run of characters and `?` for any
+/// one, ignoring ASCII case if `fold`.
+fn wildcard(pattern: &str, s: &str, fold: bool) -> bool {
+    let (pattern, s): (Vec<char>, Vec<char>) = if fold {
+        (
+            pattern.to_ascii_lowercase().chars().collect(),
+            s.to_ascii_lowercase().chars().collect(),
+        )
+    } else {
+        (pattern.chars().collect(), s.chars().collect())
+    };
+    let (mut p, mut i) = (0, 0);
+    // Where to resume after the last `*`: the pattern position after it, and the next character
+    // of `s` for it to swallow.
+    let mut star = None;
+    while i < s.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p + 1, i));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == s[i] => {
+                p += 1;
+                i += 1;
+            }
+            _ => match star {
+                Some((after, swallowed)) => {
+                    p = after;
+                    i = swallowed + 1;
+                    star = Some((after, swallowed + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Expands the `%h` and `%%` tokens of a `HostName`.
+fn expand_host(hostname: &str, host: &str) -> String {
+    let mut ret = String::new();
+    let mut chars = hostname.chars();
+    while let Some(c) = chars.next() {
+        match (c, chars.clone().next()) {
+            ('%', Some('h')) => {
+                ret.push_str(host);
+                chars.next();
+            }
+            ('%', Some('%')) => {
+                ret.push('%');
+                chars.next();
+            }
+            _ => ret.push(c),
+        }
+    }
+    ret
+}
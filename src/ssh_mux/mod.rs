@@ -13,20 +13,90 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-mod config;
+pub mod config;
+mod persistent_socket;
 mod temp_socket;
 
-use std::ffi::OsStr;
+use std::{
+    ffi::{OsStr, OsString},
+    path::{Path, PathBuf},
+    process::Output,
+    str::FromStr,
+};
 
 use anyhow::{Context, Result};
-use config::infer_create_socket;
+use serde::{Deserialize, Serialize};
 use smol::process::{Command, Stdio};
 use temp_socket::TempSocket;
 
-#[derive(Clone, Copy)]
-pub enum CreateSocket {
+pub use persistent_socket::sockets_dir as persistent_socket_dir;
+
+use crate::{
+    ReauthError,
+    transport::{RemoteTransport, collect},
+};
+
+/// The ssh client used unless another is given.
+pub const DEFAULT_SSH: &str = "ssh";
+/// The prefix of the temporary directories `SshMux` creates for control sockets.
+pub const TEMP_SOCKET_PREFIX: &str = "aspect-reauth-";
+/// The name of the control socket within such a directory.
+pub const TEMP_SOCKET_NAME: &str = "sock";
+/// Where temporary control socket directories go instead when the system temporary directory is
+/// too deep for a socket path.
+pub const FALLBACK_SOCKET_DIR: &str = "/tmp";
+/// The longest control socket path ssh can listen on: `sun_path` holds 104 bytes on macOS and the
+/// BSDs and 108 on Linux, less the NUL, less the 17 characters ssh appends to the path to bind the
+/// socket before renaming it into place.
+pub const MAX_SOCKET_PATH: usize = if cfg!(target_os = "linux") { 90 } else { 86 };
+/// How long a persistent control master stays up with nothing using it.
+pub const PERSISTENT_IDLE: &str = "30m";
+
+/// The directories persistent control sockets may be in: under `socket_dir`, the configured one,
+/// if any, and under the user's cache directory or in the fallback for it.
+pub fn persistent_socket_dirs(socket_dir: Option<&Path>) -> Vec<PathBuf> {
+    let mut dirs: Vec<PathBuf> = socket_dir.map(persistent_socket_dir).into_iter().collect();
+    if let Some(cache) = dirs::cache_dir() {
+        dirs.push(persistent_socket_dir(&cache));
+        dirs.push(persistent_socket::fallback_dir(&cache));
+    }
+    dirs
+}
+
+/// How `SshMux` decides between starting its own control master and sharing the user's, as
+/// chosen with `--create-socket`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SocketStrategy {
+    /// Never start a master; each command connects as the user's ssh config says.
+    Never,
+    /// Always start our own master.
+    Always,
+    /// Share the user's master if their ssh config sets `ControlMaster auto`, unless its socket
+    /// is stale.
+    #[default]
     Infer,
-    Specify(bool),
+    /// Share the user's master only if one is running already.
+    ReuseIfMasterRunning,
+    /// As [`SocketStrategy::Infer`], but only if `ControlPersist` is set too, so that a master
+    /// ssh starts for the run outlives it.
+    ReuseIfPersist,
+}
+
+/// What becomes of the control master `SshMux` starts, if it starts one.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SocketMode {
+    /// A fresh socket in a temporary directory, stopped and removed when the `SshMux` is done.
+    #[default]
+    Temporary,
+    /// A socket kept for the host under the cache directory, whose master outlives the run, for
+    /// [`PERSISTENT_IDLE`] after its last use, and is picked up again by the next run.
+    Persistent,
+}
+
+enum Socket {
+    Temporary(TempSocket),
+    Persistent(PathBuf),
 }
 
 /// A batched SSH command multiplexer.
@@ -37,69 +107,178 @@ pub enum CreateSocket {
 ///    to be reused across SSH commands so that subsequent commands do not incur connection setup
 ///    overhead.
 pub struct SshMux<'a, T: AsRef<OsStr>> {
+    ssh: &'a Path,
     host: &'a str,
     ssh_args: &'a [T],
-    socket: Option<TempSocket>,
+    socket: Option<Socket>,
+    /// Why the user's control master was passed over for one of our own, if it was.
+    unusable: Option<String>,
 }
 
 impl<'a, T: AsRef<OsStr>> SshMux<'a, T> {
+    /// Connects to `host` with the ssh client `ssh`.
     pub async fn new(
+        ssh: &'a Path,
         host: &'a str,
         ssh_args: &'a [T],
-        create_socket: CreateSocket,
+        strategy: SocketStrategy,
+        socket_dir: Option<&Path>,
+        socket_mode: SocketMode,
     ) -> Result<Self> {
-        let socket = match create_socket.into_option_bool() {
-            Some(val) => val,
-            None => infer_create_socket(host).await,
-        }
-        .then(|| TempSocket::new("aspect-reauth-"))
-        .transpose()?;
-        let mut cmd = Command::new("ssh");
-        cmd.args(ssh_args);
-        if let Some(socket) = &socket {
-            // cf. scp.c in openssh-portable.
-            cmd.arg("-xMTS").arg(socket).args([
-                "-oControlPersist=yes",
-                "-oPermitLocalCommand=no",
-                "-oClearAllForwardings=yes",
-                "-oRemoteCommand=none",
-                "-oForwardAgent=no",
-                "-oBatchMode=yes",
-            ]);
-        }
+        let decision = config::create_socket(strategy, ssh, host, ssh_args).await;
+        let socket = match socket_mode {
+            _ if !decision.creates() => None,
+            SocketMode::Temporary => Some(Socket::Temporary(
+                TempSocket::new(socket_dir, TEMP_SOCKET_PREFIX)
+                    .context("failed to create a directory for the control socket")?,
+            )),
+            SocketMode::Persistent => {
+                let path = persistent_socket::path(socket_dir, ssh, host, ssh_args)?;
+                if is_up(ssh, host, ssh_args, path.as_os_str()).await {
+                    return Ok(SshMux {
+                        ssh,
+                        host,
+                        ssh_args,
+                        socket: Some(Socket::Persistent(path)),
+                        unusable: None,
+                    });
+                }
+                // Left behind by a master that has since exited; ssh will not replace it.
+                let _ = std::fs::remove_file(&path);
+                Some(Socket::Persistent(path))
+            }
+        };
         // If we're reusing an existing socket but the host has ControlMaster=auto and no currently
         // running master, we do not want the created master to have the restrictive set of options
         // we pass to individual commands, so we still run an initial ssh to open a normal session.
-        let output = cmd
-            .args(["--", host, "true"])
+        let output = Command::new(ssh)
+            .args(master_args(
+                host,
+                ssh_args,
+                socket.as_ref().map(Socket::path),
+                socket_mode,
+            ))
             .stdin(Stdio::null())
             .stdout(Stdio::null())
             .stderr(Stdio::piped())
+            .kill_on_drop(true)
             .output()
             .await
-            .context("failed to start SSH control master")?;
+            .map_err(|source| ReauthError::Ssh {
+                message: "failed to start SSH control master".into(),
+                source: Some(source),
+            })?;
         if !output.status.success() {
-            anyhow::bail!(
+            return Err(ReauthError::ssh(format!(
                 "ssh {}: {}\n\n{}",
                 host,
                 output.status,
                 String::from_utf8_lossy(&output.stderr).trim(),
-            );
+            ))
+            .into());
         }
+        let unusable = match decision {
+            config::Decision::Replace(why) => Some(why),
+            _ => None,
+        };
         Ok(SshMux {
+            ssh,
             host,
             ssh_args,
             socket,
+            unusable,
         })
     }
 
+    /// Why the user's control master was not usable, if it was found so and a master of our own
+    /// started instead, for the caller to warn about.
+    pub fn unusable_master(&self) -> Option<&str> {
+        self.unusable.as_deref()
+    }
+
     pub fn command(&self, command: &str) -> Command {
-        let mut ret = Command::new("ssh");
-        ret.args(self.ssh_args);
-        if let Some(socket) = &self.socket {
-            ret.arg("-S").arg(socket);
+        let mut ret = Command::new(self.ssh);
+        ret.args(command_args(
+            self.host,
+            self.ssh_args,
+            self.socket_path(),
+            command,
+        ));
+        ret
+    }
+
+    fn socket_path(&self) -> Option<&OsStr> {
+        self.socket.as_ref().map(Socket::path)
+    }
+}
+
+impl Socket {
+    fn path(&self) -> &OsStr {
+        match self {
+            Socket::Temporary(socket) => socket.path(),
+            Socket::Persistent(path) => path.as_os_str(),
         }
-        ret.args([
+    }
+}
+
+/// Whether a control master is up on `socket`, as `ssh -O check` says.
+async fn is_up<T: AsRef<OsStr>>(ssh: &Path, host: &str, ssh_args: &[T], socket: &OsStr) -> bool {
+    Command::new(ssh)
+        .args(check_args(host, ssh_args, socket))
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .kill_on_drop(true)
+        .status()
+        .await
+        .is_ok_and(|status| status.success())
+}
+
+/// Returns the arguments `SshMux::new` passes to ssh to open the initial session, which is also
+/// the control master if there is a `socket`. A persistent master exits once it has gone unused for
+/// [`PERSISTENT_IDLE`]; a temporary one waits to be stopped.
+pub fn master_args<T: AsRef<OsStr>>(
+    host: &str,
+    ssh_args: &[T],
+    socket: Option<&OsStr>,
+    socket_mode: SocketMode,
+) -> Vec<OsString> {
+    let mut ret: Vec<OsString> = ssh_args.iter().map(|a| a.as_ref().into()).collect();
+    if let Some(socket) = socket {
+        // cf. scp.c in openssh-portable.
+        ret.extend(["-xMTS".into(), socket.into()]);
+        ret.push(match socket_mode {
+            SocketMode::Temporary => "-oControlPersist=yes".into(),
+            SocketMode::Persistent => format!("-oControlPersist={PERSISTENT_IDLE}").into(),
+        });
+        ret.extend(
+            [
+                "-oPermitLocalCommand=no",
+                "-oClearAllForwardings=yes",
+                "-oRemoteCommand=none",
+                "-oForwardAgent=no",
+                "-oBatchMode=yes",
+            ]
+            .map(OsString::from),
+        );
+    }
+    ret.extend(["--", host, "true"].map(OsString::from));
+    ret
+}
+
+/// Returns the arguments `SshMux::command` passes to ssh to run `command`.
+pub fn command_args<T: AsRef<OsStr>>(
+    host: &str,
+    ssh_args: &[T],
+    socket: Option<&OsStr>,
+    command: &str,
+) -> Vec<OsString> {
+    let mut ret: Vec<OsString> = ssh_args.iter().map(|a| a.as_ref().into()).collect();
+    if let Some(socket) = socket {
+        ret.extend(["-S".into(), socket.into()]);
+    }
+    ret.extend(
+        [
             "-xT",
             "-oPermitLocalCommand=no",
             "-oClearAllForwardings=yes",
@@ -107,21 +286,216 @@ impl<'a, T: AsRef<OsStr>> SshMux<'a, T> {
             "-oForwardAgent=no",
             "-oBatchMode=yes",
             "--",
-            self.host,
+            host,
             command,
-        ]);
-        ret
+        ]
+        .map(OsString::from),
+    );
+    ret
+}
+
+/// Returns the arguments to run `command` interactively on `host` once a sync is done.
+///
+/// Unlike `command_args`, these leave the user's ssh configuration in charge, except that agent
+/// forwarding is off unless `forward_agent` is set.
+pub fn exec_args<T: AsRef<OsStr>, U: AsRef<OsStr>>(
+    host: &str,
+    ssh_args: &[T],
+    command: &[U],
+    forward_agent: bool,
+) -> Vec<OsString> {
+    let mut ret: Vec<OsString> = ssh_args.iter().map(|a| a.as_ref().into()).collect();
+    ret.push(if forward_agent {
+        "-oForwardAgent=yes".into()
+    } else {
+        "-oForwardAgent=no".into()
+    });
+    ret.extend(["--", host].map(OsString::from));
+    ret.extend(command.iter().map(|a| a.as_ref().into()));
+    ret
+}
+
+/// Returns the arguments `SshMux::new` passes to ssh to check for a persistent control master on
+/// `socket`.
+pub fn check_args<T: AsRef<OsStr>>(host: &str, ssh_args: &[T], socket: &OsStr) -> Vec<OsString> {
+    let mut ret: Vec<OsString> = ssh_args.iter().map(|a| a.as_ref().into()).collect();
+    ret.extend(["-S".into(), socket.into()]);
+    ret.extend(["-Ocheck", "--", host].map(OsString::from));
+    ret
+}
+
+/// Returns the arguments `SshMux::cleanup` passes to ssh to stop the control master on `socket`.
+pub fn exit_args<T: AsRef<OsStr>>(host: &str, ssh_args: &[T], socket: &OsStr) -> Vec<OsString> {
+    let mut ret: Vec<OsString> = ssh_args.iter().map(|a| a.as_ref().into()).collect();
+    ret.extend(["-S".into(), socket.into()]);
+    ret.extend(["-Oexit", "--", host].map(OsString::from));
+    ret
+}
+
+/// Returns the command line to have ssh run `program` with `args` on the remote, whatever the
+/// user's login shell there is.
+///
+/// ssh joins the command into one string for the remote login shell to parse, which may well not
+/// be a POSIX shell (fish, nushell). Commands made up only of plain words read the same in any
+/// shell, and are passed as they are. Anything else is handed to an explicit `sh -c '...'`, with
+/// each word double-quoted inside, which leaves nothing for the login shell to interpret as long
+/// as there is no single quote or backslash; arguments with those are refused.
+pub fn remote_command(program: &str, args: &[&str]) -> Result<String> {
+    let words = || std::iter::once(program).chain(args.iter().copied());
+    let plain = |word: &str| {
+        !word.is_empty()
+            && word
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "-_=+./:@,".contains(c))
+    };
+    if words().all(plain) {
+        return Ok(words().collect::<Vec<_>>().join(" "));
+    }
+    let mut script = Vec::new();
+    for word in words() {
+        if word.contains(['\'', '\\']) {
+            anyhow::bail!("cannot pass {word:?} to the remote shell: it contains ' or \\");
+        }
+        let mut quoted = String::from('"');
+        for c in word.chars() {
+            if matches!(c, '"' | '$' | '`') {
+                quoted.push('\\');
+            }
+            quoted.push(c);
+        }
+        quoted.push('"');
+        script.push(quoted);
+    }
+    Ok(format!("sh -c '{}'", script.join(" ")))
+}
+
+/// A host as it may be written for ssh, `[user@]host[:port]`, with an IPv6 address in brackets
+/// if there is a port.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Destination {
+    pub user: Option<String>,
+    pub host: String,
+    pub port: Option<u16>,
+}
+
+impl FromStr for Destination {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        // As in ssh, the last @ ends the user, which may itself contain one.
+        let (user, rest) = match s.rsplit_once('@') {
+            Some((user, rest)) => (Some(user), rest),
+            None => (None, s),
+        };
+        if user == Some("") {
+            anyhow::bail!("missing user before @ in {s:?}");
+        }
+        let (host, port) = match rest.strip_prefix('[') {
+            Some(bracketed) => {
+                let (host, after) = bracketed
+                    .split_once(']')
+                    .with_context(|| format!("missing ] in {s:?}"))?;
+                let port = match after {
+                    "" => None,
+                    _ => Some(
+                        after
+                            .strip_prefix(':')
+                            .with_context(|| format!("expected :PORT after ] in {s:?}"))?,
+                    ),
+                };
+                (host, port)
+            }
+            None => match rest.split_once(':') {
+                // Any more colons make it an IPv6 address, which has no port unless bracketed.
+                Some((host, port)) if !port.contains(':') => (host, Some(port)),
+                _ => (rest, None),
+            },
+        };
+        if host.is_empty() {
+            anyhow::bail!("missing host in {s:?}");
+        }
+        let port = port
+            .map(|port| {
+                port.parse()
+                    .ok()
+                    .filter(|&port| port != 0)
+                    .with_context(|| format!("invalid port {port:?} in {s:?}"))
+            })
+            .transpose()?;
+        Ok(Destination {
+            user: user.map(String::from),
+            host: host.into(),
+            port,
+        })
     }
+}
+
+/// The ssh options that take a value, from the getopt string in ssh.c in openssh-portable.
+pub const OPTIONS_WITH_VALUES: &str = "BbcDEeFIiJLlmOoPpQRSWw";
 
-    pub async fn cleanup(&mut self) -> Result<()> {
-        let Some(socket) = self.socket.take() else {
+/// Normalizes user-supplied ssh arguments so that the common ways of writing an option and its
+/// value all do what was meant.
+///
+/// Each argument is passed to ssh as a single argv entry, so `--ssh-arg='-p 23'` would otherwise
+/// reach ssh as the one token `-p 23`. An argument consisting of a value-taking option, whitespace
+/// and a value is split at that first run of whitespace into the option and its value, which
+/// leaves values that themselves contain spaces (`-o ProxyCommand=ssh -W %h:%p bastion`) intact.
+/// A value-taking option given as the last argument with no value after it is an error, rather
+/// than letting it silently swallow the arguments we append.
+pub fn split_ssh_args(ssh_args: &[String]) -> Result<Vec<String>> {
+    let mut ret = Vec::with_capacity(ssh_args.len());
+    for arg in ssh_args {
+        if takes_value(arg) && arg[2..].starts_with(char::is_whitespace) {
+            let (option, value) = arg.split_at(2);
+            ret.push(option.into());
+            let value = value.trim_start();
+            if !value.is_empty() {
+                ret.push(value.into());
+            }
+        } else {
+            ret.push(arg.clone());
+        }
+    }
+    if let Some(last) = ret.last()
+        && last.len() == 2
+        && takes_value(last)
+    {
+        anyhow::bail!(
+            "ssh option {last} needs a value; pass it as --ssh-arg='{last} VALUE' or in a \
+             following --ssh-arg"
+        );
+    }
+    Ok(ret)
+}
+
+/// Whether `arg` starts with a single ssh option that takes a value.
+fn takes_value(arg: &str) -> bool {
+    let mut chars = arg.chars();
+    chars.next() == Some('-')
+        && chars
+            .next()
+            .is_some_and(|c| OPTIONS_WITH_VALUES.contains(c))
+}
+
+impl<T: AsRef<OsStr>> RemoteTransport for SshMux<'_, T> {
+    fn host(&self) -> Option<&str> {
+        Some(self.host)
+    }
+
+    async fn run(&self, program: &str, args: &[&str], input: &[u8]) -> Result<Output> {
+        let cmd = self.command(&remote_command(program, args)?);
+        collect(cmd, input)
+            .await
+            .with_context(|| format!("failed to run {program} on {}", self.host))
+    }
+
+    /// Stops the control master, unless it is a persistent one, which is left for the next run.
+    async fn close(mut self) -> Result<()> {
+        let Some(Socket::Temporary(socket)) = self.socket.take() else {
             return Ok(());
         };
-        Command::new("ssh")
-            .args(self.ssh_args)
-            .arg("-S")
-            .arg(&socket)
-            .args(["-Oexit", "--", self.host])
+        Command::new(self.ssh)
+            .args(exit_args(self.host, self.ssh_args, socket.path()))
             .stdin(Stdio::null())
             .stdout(Stdio::null())
             .stderr(Stdio::null())
@@ -134,19 +508,50 @@ impl<'a, T: AsRef<OsStr>> SshMux<'a, T> {
 
 impl<T: AsRef<OsStr>> Drop for SshMux<'_, T> {
     fn drop(&mut self) {
-        smol::block_on(async {
-            if let Err(e) = self.cleanup().await {
-                eprintln!("cleanup ssh: {}", e);
-            }
-        });
+        // Not closed, most likely because the run was cut short. There is no executor to hand the
+        // work to here, so stop the master the blocking way before its socket is removed.
+        let Some(Socket::Temporary(socket)) = self.socket.take() else {
+            return;
+        };
+        let status = std::process::Command::new(self.ssh)
+            .args(exit_args(self.host, self.ssh_args, socket.path()))
+            .stdin(std::process::Stdio::null())
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::null())
+            .status();
+        if let Err(e) = status {
+            eprintln!("cleanup ssh: failed to cleanup SSH control master: {e}");
+        }
     }
 }
 
-impl CreateSocket {
-    fn into_option_bool(self) -> Option<bool> {
-        match self {
-            CreateSocket::Infer => None,
-            CreateSocket::Specify(b) => Some(b),
+impl FromStr for SocketMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "temporary" => Ok(SocketMode::Temporary),
+            "persistent" => Ok(SocketMode::Persistent),
+            _ => anyhow::bail!("unknown value {s}"),
+        }
+    }
+}
+
+impl FromStr for SocketStrategy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "infer" => Ok(SocketStrategy::Infer),
+            "running" => Ok(SocketStrategy::ReuseIfMasterRunning),
+            "persist" => Ok(SocketStrategy::ReuseIfPersist),
+            "always" => Ok(SocketStrategy::Always),
+            "never" => Ok(SocketStrategy::Never),
+            // Regrettably there is not any easy way to get at clap's BoolishValueParser from here,
+            // so we inline its current implementation instead.
+            "y" | "yes" | "t" | "true" | "on" | "1" => Ok(SocketStrategy::Always),
+            "n" | "no" | "f" | "false" | "off" | "0" => Ok(SocketStrategy::Never),
+            _ => anyhow::bail!("unknown value {s}"),
         }
     }
 }
@@ -0,0 +1,163 @@
+// Copyright 2026 Stairwell, Inc.
+// Author: mrdomino@stairwell.com
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    ffi::OsStr,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+
+use super::{FALLBACK_SOCKET_DIR, MAX_SOCKET_PATH};
+
+/// The length of a socket's name: a 64-bit hash in hex.
+const NAME_LEN: usize = 16;
+
+/// Returns the path of the persistent control socket for reaching `host` with `ssh` and
+/// `ssh_args`, creating the directory it goes in: `aspect-reauth/sockets` under `dir`, or under
+/// the user's cache directory if `None`.
+///
+/// The socket is named for a hash of everything that decides where the connection goes, so a run
+/// with another port, user, or jump host does not reuse a master meant for a different one.
+///
+/// If the cache directory is too deep for the socket path to fit in [`MAX_SOCKET_PATH`], a
+/// directory of the user's own in [`FALLBACK_SOCKET_DIR`] is used instead; see [`fallback_dir`].
+/// A `dir` that is too deep is an error, since it was asked for.
+pub fn path<T: AsRef<OsStr>>(
+    dir: Option<&Path>,
+    ssh: &Path,
+    host: &str,
+    ssh_args: &[T],
+) -> Result<PathBuf> {
+    let name = format!("{:016x}", fnv1a(words(ssh, host, ssh_args)));
+    let dir = match dir {
+        Some(dir) => {
+            let dir = sockets_dir(dir);
+            let len = socket_len(&dir);
+            if len > MAX_SOCKET_PATH {
+                anyhow::bail!(
+                    "the persistent control socket {} would have a {len}-character path, over the \
+                     limit of {MAX_SOCKET_PATH}; use a shorter socket directory",
+                    dir.join(&name).display()
+                );
+            }
+            create(&dir)?;
+            dir
+        }
+        None => {
+            let cache = dirs::cache_dir().context("cannot find the cache directory")?;
+            let dir = sockets_dir(&cache);
+            if socket_len(&dir) > MAX_SOCKET_PATH {
+                let dir = fallback_dir(&cache);
+                create_private(&dir)?;
+                dir
+            } else {
+                create(&dir)?;
+                dir
+            }
+        }
+    };
+    Ok(dir.join(name))
+}
+
+/// Where persistent control sockets go under `base`: the socket directory if one is configured,
+/// or the user's cache directory.
+pub fn sockets_dir(base: &Path) -> PathBuf {
+    base.join("aspect-reauth").join("sockets")
+}
+
+/// Where persistent control sockets go instead when `cache`, the user's cache directory, is too
+/// deep for them: a directory in [`FALLBACK_SOCKET_DIR`] named for a hash of `cache`, so that each
+/// user gets their own and finds it again on the next run.
+pub fn fallback_dir(cache: &Path) -> PathBuf {
+    Path::new(FALLBACK_SOCKET_DIR).join(format!(
+        "aspect-reauth.{:016x}",
+        fnv1a([cache.as_os_str()].into_iter())
+    ))
+}
+
+/// The length of the path of a socket in `dir`.
+fn socket_len(dir: &Path) -> usize {
+    dir.as_os_str().len() + 1 + NAME_LEN
+}
+
+/// Creates `dir` if need be, and makes it ours alone.
+fn create(dir: &Path) -> Result<()> {
+    fs::create_dir_all(dir).with_context(|| format!("failed to create {}", dir.display()))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(dir, fs::Permissions::from_mode(0o700))
+            .with_context(|| format!("failed to restrict {}", dir.display()))?;
+    }
+    Ok(())
+}
+
+/// Creates `dir`, which is in a directory anyone can write to, as ours alone, or checks that it
+/// already is: another user could have made it first, to listen in on our masters.
+fn create_private(dir: &Path) -> Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{DirBuilderExt, MetadataExt};
+        match fs::DirBuilder::new().mode(0o700).create(dir) {
+            Ok(()) => return Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {}
+            Err(e) => {
+                return Err(e).with_context(|| format!("failed to create {}", dir.display()));
+            }
+        }
+        let meta = fs::symlink_metadata(dir)
+            .with_context(|| format!("failed to look at {}", dir.display()))?;
+        // Whoever we are owns a file we have just made.
+        let uid = tempfile::tempfile()
+            .and_then(|file| file.metadata())
+            .context("failed to find out who we are")?
+            .uid();
+        if !meta.is_dir() || meta.uid() != uid || meta.mode() & 0o077 != 0 {
+            anyhow::bail!(
+                "{} is not a directory of ours alone, so it will not hold control sockets",
+                dir.display()
+            );
+        }
+        Ok(())
+    }
+    #[cfg(not(unix))]
+    create(dir)
+}
+
+/// Everything that decides where a connection goes.
+fn words<'a, T: AsRef<OsStr>>(
+    ssh: &'a Path,
+    host: &'a str,
+    ssh_args: &'a [T],
+) -> impl Iterator<Item = &'a OsStr> {
+    [ssh.as_os_str(), OsStr::new(host)]
+        .into_iter()
+        .chain(ssh_args.iter().map(AsRef::as_ref))
+}
+
+/// The 64-bit FNV-1a hash of `words`, each followed by a NUL. Unlike `DefaultHasher`, it stays
+/// the same from one build to the next, and so does the socket's name.
+fn fnv1a<'a>(words: impl Iterator<Item = &'a OsStr>) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for word in words {
+        for &byte in word.as_encoded_bytes().iter().chain(&[0]) {
+            hash ^= u64::from(byte);
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+    hash
+}
@@ -13,16 +13,21 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::{ffi::OsStr, fs::remove_dir_all, path::Path};
+use std::{env, ffi::OsStr, fs::remove_dir_all, path::Path};
 
 use anyhow::Result;
 use tempfile::TempDir;
 
+use super::{FALLBACK_SOCKET_DIR, MAX_SOCKET_PATH, TEMP_SOCKET_NAME};
+
+/// The number of random characters `tempfile` puts after the prefix.
+const RANDOM_LEN: usize = 6;
+
 /// Exposes and controls a path suitable for use as a temporary socket. The path is made available
 /// by `AsRef<OsStr>` on `&TempSocket`, so that a reference to this may be passed directly to
 /// `Command::arg`:
-/// ```
-/// let socket = TempSocket::new()?;
+/// ```ignore
+/// let socket = TempSocket::new(None, "prefix-")?;
 /// let _ = Command::new("ssh").arg("-MS").arg(&socket);
 /// ```
 /// The temporary directory and its contents are removed by `drop`.
@@ -31,25 +36,63 @@ pub struct TempSocket {
 }
 
 impl TempSocket {
-    pub fn new(prefix: &str) -> Result<Self> {
+    /// Creates the socket's directory in `dir`, or the system temporary directory if `None`.
+    ///
+    /// If the system temporary directory is too deep for the socket path to fit in
+    /// [`MAX_SOCKET_PATH`], as `$TMPDIR` can be on macOS, [`FALLBACK_SOCKET_DIR`] is used instead. A
+    /// `dir` that is too deep is an error, since it was asked for.
+    pub fn new(dir: Option<&Path>, prefix: &str) -> Result<Self> {
         let mut builder = tempfile::Builder::new();
         #[cfg(unix)]
         {
             use std::{fs::Permissions, os::unix::fs::PermissionsExt};
             builder.permissions(Permissions::from_mode(0o700));
         }
-        Ok(Self::from_tempdir(builder.prefix(prefix).tempdir()?))
+        builder.prefix(prefix);
+        let tempdir = match dir {
+            Some(dir) => {
+                let len = socket_len(dir, prefix);
+                if len > MAX_SOCKET_PATH {
+                    anyhow::bail!(
+                        "a control socket in {} would have a {len}-character path, over the limit of \
+                         {MAX_SOCKET_PATH}; use a shorter socket directory",
+                        dir.display()
+                    );
+                }
+                builder.tempdir_in(dir)
+            }
+            None => {
+                let tmp = env::temp_dir();
+                if socket_len(&tmp, prefix) > MAX_SOCKET_PATH {
+                    builder.tempdir_in(FALLBACK_SOCKET_DIR)
+                } else {
+                    builder.tempdir_in(tmp)
+                }
+            }
+        };
+        Ok(Self::from_tempdir(tempdir?))
+    }
+
+    pub fn path(&self) -> &OsStr {
+        self.path.as_os_str()
     }
 
     fn from_tempdir(dir: TempDir) -> Self {
         let mut path = dir.keep();
-        path.push("sock");
+        path.push(TEMP_SOCKET_NAME);
         TempSocket {
             path: path.into_boxed_path(),
         }
     }
 }
 
+/// The length of the path of a socket made by `TempSocket::new` in `dir` with `prefix`.
+fn socket_len(dir: &Path, prefix: &str) -> usize {
+    let dir = dir.as_os_str().len();
+    // With a slash before the socket's directory and before its name.
+    dir + 1 + prefix.len() + RANDOM_LEN + 1 + TEMP_SOCKET_NAME.len()
+}
+
 impl AsRef<OsStr> for &TempSocket {
     fn as_ref(&self) -> &OsStr {
         self.path.as_os_str()
@@ -0,0 +1,197 @@
+// Copyright 2026 Stairwell, Inc.
+// Author: mrdomino@stairwell.com
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A small on-disk record of the last known outcome for each host, so that cheap queries such as
+//! a shell prompt can be answered without touching ssh or the keychain.
+
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::PathBuf,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::keyctl::KeyringTarget;
+
+/// The last known state of a host's credential.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    /// The host had (or was given) a valid credential.
+    Valid,
+    /// The host's credential was checked and found invalid, and nothing was synced.
+    Invalid,
+    /// The last run failed before the host's credential could be confirmed.
+    Error,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct HostState {
+    pub remote: String,
+    pub status: Status,
+    /// When this was recorded, in seconds since the Unix epoch.
+    pub checked_at: u64,
+}
+
+impl HostState {
+    /// How long ago this was recorded. Timestamps in the future count as just now.
+    pub fn age(&self) -> Duration {
+        age(self.checked_at)
+    }
+}
+
+/// The options given on the command line for the last successful sync to a host, which are reused
+/// by default on later runs.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HostOptions {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ssh_args: Vec<String>,
+    #[serde(
+        default,
+        skip_serializing_if = "Vec::is_empty",
+        with = "crate::keyctl::keyrings"
+    )]
+    pub keyring: Vec<KeyringTarget>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_name: Option<String>,
+}
+
+impl HostOptions {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// The result of the last check for a newer release.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct UpdateCheck {
+    /// When the check was made, in seconds since the Unix epoch.
+    pub checked_at: u64,
+    /// The latest released version at the time.
+    pub latest: String,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct State {
+    #[serde(default)]
+    pub hosts: BTreeMap<String, HostState>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub options: BTreeMap<String, HostOptions>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub update_check: Option<UpdateCheck>,
+}
+
+impl State {
+    /// Where the state lives: `aspect-reauth/state.json` under the platform's state directory
+    /// (`$XDG_STATE_HOME`), or its cache directory where there is no such thing.
+    pub fn path() -> Option<PathBuf> {
+        dirs::state_dir()
+            .or_else(dirs::cache_dir)
+            .map(|dir| dir.join("aspect-reauth").join("state.json"))
+    }
+
+    /// Where a running `watch` for `host` listens for requests to sync now: a socket under
+    /// `aspect-reauth/watch` next to the state file.
+    pub fn watch_socket(host: &str) -> Option<PathBuf> {
+        let path = Self::path()?;
+        let dir = path
+            .parent()
+            .expect("state path has a parent")
+            .join("watch");
+        Some(dir.join(format!("{}.sock", host.replace('/', "_"))))
+    }
+
+    /// Loads the state, treating a missing file (or nowhere to put one) as empty.
+    pub fn load() -> Result<Self> {
+        let Some(path) = Self::path() else {
+            return Ok(Self::default());
+        };
+        match fs::read(&path) {
+            Ok(bytes) => serde_json::from_slice(&bytes)
+                .with_context(|| format!("failed to parse {}", path.display())),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e).with_context(|| format!("failed to read {}", path.display())),
+        }
+    }
+
+    /// Atomically replaces the state file, so a concurrent reader never sees a partial write.
+    pub fn save(&self) -> Result<()> {
+        let Some(path) = Self::path() else {
+            return Ok(());
+        };
+        let dir = path.parent().expect("state path has a parent");
+        fs::create_dir_all(dir).with_context(|| format!("failed to create {}", dir.display()))?;
+        let mut file = tempfile::NamedTempFile::new_in(dir)
+            .with_context(|| format!("failed to create temporary file in {}", dir.display()))?;
+        serde_json::to_writer_pretty(&mut file, self)?;
+        file.persist(&path)
+            .with_context(|| format!("failed to write {}", path.display()))?;
+        Ok(())
+    }
+
+    /// The most recently recorded host, if any.
+    pub fn latest(&self) -> Option<(&str, &HostState)> {
+        self.hosts
+            .iter()
+            .max_by_key(|(_, state)| state.checked_at)
+            .map(|(host, state)| (host.as_str(), state))
+    }
+}
+
+/// Returns the current time in seconds since the Unix epoch, as used for timestamps here.
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// Returns how long ago `timestamp` (in seconds since the Unix epoch) was, or zero if it is in the
+/// future.
+pub fn age(timestamp: u64) -> Duration {
+    let then = UNIX_EPOCH + Duration::from_secs(timestamp);
+    SystemTime::now().duration_since(then).unwrap_or_default()
+}
+
+/// Records `status` as the current state of `host`.
+pub fn record(host: &str, remote: &str, status: Status) -> Result<()> {
+    let mut state = State::load()?;
+    state.hosts.insert(
+        host.into(),
+        HostState {
+            remote: remote.into(),
+            status,
+            checked_at: now(),
+        },
+    );
+    state.save()
+}
+
+/// Remembers `options` for `host`, or forgets what was remembered if they are empty.
+pub fn remember(host: &str, options: &HostOptions) -> Result<()> {
+    let mut state = State::load()?;
+    if state.options.get(host).unwrap_or(&HostOptions::default()) == options {
+        return Ok(());
+    }
+    if options.is_empty() {
+        state.options.remove(host);
+    } else {
+        state.options.insert(host.into(), options.clone());
+    }
+    state.save()
+}
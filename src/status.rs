@@ -0,0 +1,213 @@
+// Copyright 2026 Stairwell, Inc.
+// Author: mrdomino@stairwell.com
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! `aspect-reauth status`: reports where a host's credential stands, without changing anything.
+//!
+//! Unlike a sync, this never logs in or writes a key; it only asks. The exit code is 0 if every
+//! check passes and 3 if any does not, as with `--ci --check`, or 1 if the host cannot be reached
+//! at all.
+
+use std::{fmt::Display, process::ExitCode, time::SystemTime};
+
+use anstream::println;
+use anyhow::Result;
+use aspect_reauth::{
+    helper::{self, Check},
+    human, keyctl,
+    sink::{AnySink, RemoteSink},
+    transport::Local,
+};
+use clap::Args;
+use clap_complete::ArgValueCandidates;
+
+use crate::{connect, disconnect, style};
+
+#[derive(Args, Clone)]
+pub struct StatusArgs {
+    /// SSH hostname to report on [default: from the config file, or devbox]
+    #[arg(env = "ASPECT_REAUTH_HOST", add = ArgValueCandidates::new(crate::completions::hosts))]
+    pub host: Option<String>,
+}
+
+/// Reports on the host in `args`, which has been set up as for a sync to it.
+pub async fn run(args: &crate::Args) -> Result<ExitCode> {
+    let options = args.sync_options();
+    let key_name = args.key_name()?;
+    let host = args.host();
+    let mut report = Report::default();
+    println!("Credential status for {host} ({}):", options.remote);
+
+    report.row(
+        "local keychain",
+        args.keyring(&options).is_stored().await,
+        |stored| stored.then_some("credential stored"),
+        "no credential stored; run `aspect-reauth` to log in",
+    );
+    report.row(
+        "local helper",
+        helper::check(&Local, &options).await,
+        accepted,
+        "needs a login; run `aspect-reauth` to log in",
+    );
+
+    let ssh = connect(args).await?;
+    match args.host_sink(&options, &ssh).await? {
+        AnySink::File(file) => {
+            report.row(
+                "credential file",
+                file.check(&ssh, &options).await,
+                accepted,
+                format!(
+                    "no current credential in {}; run `aspect-reauth {host}` to sync it",
+                    file.path()
+                ),
+            );
+            return disconnect(ssh, Ok(report.code())).await;
+        }
+        AnySink::SystemdCreds(creds) => {
+            report.row(
+                "encrypted credential",
+                creds.check(&ssh, &options).await,
+                accepted,
+                format!(
+                    "no current credential in {}; run `aspect-reauth {host}` to sync it",
+                    creds.path()
+                ),
+            );
+            return disconnect(ssh, Ok(report.code())).await;
+        }
+        AnySink::Security(keychain) => report.row(
+            "login keychain",
+            keychain.is_stored(&ssh).await,
+            |stored| stored.then_some("credential stored"),
+            "no credential stored",
+        ),
+        AnySink::CredentialManager(credential_manager) => report.row(
+            "credential manager",
+            credential_manager.is_stored(&ssh).await,
+            |stored| stored.then_some("credential stored"),
+            "no credential stored",
+        ),
+        AnySink::SecretTool(secret_tool) => report.row(
+            "secret service",
+            secret_tool.is_stored(&ssh).await,
+            |stored| stored.then_some("credential stored"),
+            "no credential stored",
+        ),
+        AnySink::Keyctl(_) => {
+            for target in &options.keyrings {
+                let found = async {
+                    let keyring = target.resolve(&ssh).await?;
+                    keyctl::search(&ssh, &key_name, &keyring).await
+                };
+                report.row(
+                    &format!("{target} keyring"),
+                    found.await,
+                    |serial| serial.map(|serial| format!("key {serial} present")),
+                    format!("no key described {key_name}"),
+                );
+            }
+        }
+    }
+    report.row(
+        "remote helper",
+        helper::check(&ssh, &options).await,
+        accepted,
+        format!("does not accept the credential; run `aspect-reauth {host}` to sync it"),
+    );
+
+    disconnect(ssh, Ok(report.code())).await
+}
+
+/// Describes a helper's credential that passed its check, with how long it has left if known.
+fn accepted(check: Check) -> Option<String> {
+    if check.needs_refresh {
+        return None;
+    }
+    Some(match check.expires {
+        Some(expires) => {
+            let left = expires
+                .duration_since(SystemTime::now())
+                .unwrap_or_default();
+            format!(
+                "accepts the credential, which expires {}",
+                human::from_now(left)
+            )
+        }
+        None => "accepts the credential".into(),
+    })
+}
+
+/// A list of checks on a host, printed one per row as they are made.
+#[derive(Default)]
+pub struct Report {
+    /// Whether any check has failed.
+    pub failed: bool,
+}
+
+impl Report {
+    /// Prints the outcome of one check: `good` describes a passing `result`, or returns `None`
+    /// if it did not pass, in which case `bad` is shown instead.
+    pub fn row<T, G: Display>(
+        &mut self,
+        name: &str,
+        result: Result<T>,
+        good: impl FnOnce(T) -> Option<G>,
+        bad: impl Display,
+    ) {
+        let good = match result {
+            Ok(value) => good(value),
+            Err(e) => {
+                self.fail(name, format!("{e:#}"));
+                return;
+            }
+        };
+        match good {
+            Some(good) => self.pass(name, good),
+            None => self.fail(name, bad),
+        }
+    }
+
+    /// The exit code for the report: 3 if anything failed.
+    fn code(&self) -> ExitCode {
+        if self.failed {
+            ExitCode::from(3)
+        } else {
+            ExitCode::SUCCESS
+        }
+    }
+
+    pub fn pass(&self, name: &str, what: impl Display) {
+        println!(
+            "  {name:<16}{}{what}{}",
+            style::SUCCESS,
+            style::SUCCESS.render_reset()
+        );
+    }
+
+    /// Prints something worth knowing that is not a failure.
+    pub fn note(&self, name: &str, what: impl Display) {
+        println!("  {name:<16}{what}");
+    }
+
+    pub fn fail(&mut self, name: &str, what: impl Display) {
+        self.failed = true;
+        println!(
+            "  {name:<16}{}{what}{}",
+            style::WARNING,
+            style::WARNING.render_reset()
+        );
+    }
+}
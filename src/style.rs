@@ -0,0 +1,37 @@
+// Copyright 2026 Stairwell, Inc.
+// Author: mrdomino@stairwell.com
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Styles for human-facing output.
+//!
+//! Output goes through anstream's `println!` and `eprintln!`, which strip these styles when the
+//! stream is not a terminal, `NO_COLOR` is set, or `--color=never` was given, so that logs
+//! captured by CI or systemd are free of escape codes.
+
+use anstyle::{AnsiColor, Style};
+
+pub const SUCCESS: Style = AnsiColor::Green.on_default();
+pub const WARNING: Style = AnsiColor::Yellow.on_default().bold();
+pub const ERROR: Style = AnsiColor::Red.on_default().bold();
+/// For the explanatory lines of `--dry-run`.
+pub const COMMENT: Style = Style::new().dimmed();
+
+/// Applies `--color` to everything printed from here on.
+pub fn init(choice: clap::ColorChoice) {
+    anstream::ColorChoice::write_global(match choice {
+        clap::ColorChoice::Auto => anstream::ColorChoice::Auto,
+        clap::ColorChoice::Always => anstream::ColorChoice::Always,
+        clap::ColorChoice::Never => anstream::ColorChoice::Never,
+    });
+}
@@ -0,0 +1,118 @@
+// Copyright 2026 Stairwell, Inc.
+// Author: mrdomino@stairwell.com
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! `aspect-reauth config suggest-ssh`: recommends ssh multiplexing settings for a host.
+//!
+//! With `ControlMaster auto` and `ControlPersist`, every ssh to the host after the first reuses
+//! one connection, which makes this tool (and everything else) start much faster.
+
+use std::{
+    env,
+    fs::{self, OpenOptions},
+    io::Write,
+    path::Path,
+};
+
+use anstream::{print, println};
+use anyhow::{Context, Result};
+use aspect_reauth::ssh_mux::config::{ControlPersist, SshConfig};
+use clap::Args;
+use clap_complete::ArgValueCandidates;
+
+use crate::{interactive::confirm, style};
+
+#[derive(Args, Clone)]
+pub struct SuggestSshArgs {
+    /// SSH hostname to suggest settings for [default: `default_host` from the config file, or
+    /// devbox]
+    #[arg(env = "ASPECT_REAUTH_HOST", add = ArgValueCandidates::new(crate::completions::hosts))]
+    host: Option<String>,
+
+    /// Offer to append the suggestion to ~/.ssh/config
+    #[arg(long)]
+    append: bool,
+}
+
+pub async fn run(args: &SuggestSshArgs, default_host: &str, ssh: &Path) -> Result<()> {
+    let host = args.host.as_deref().unwrap_or(default_host);
+    let config = SshConfig::query::<&str>(ssh, host, &[])
+        .await
+        .with_context(|| format!("failed to run ssh -G for {host}"))?;
+    let Some(snippet) = suggestion(host, &config) else {
+        println!("{host} already reuses a persistent master connection; nothing to suggest.");
+        return Ok(());
+    };
+    println!("Add this to ~/.ssh/config, above any other block that matches {host}:\n");
+    print!("{snippet}");
+    if !args.append {
+        return Ok(());
+    }
+
+    let path = env::home_dir()
+        .context("cannot find home directory")?
+        .join(".ssh")
+        .join("config");
+    println!();
+    if !confirm(&format!("Append this to {}?", path.display()))? {
+        return Ok(());
+    }
+    let dir = path.parent().expect("ssh config path has a parent");
+    fs::create_dir_all(dir).with_context(|| format!("failed to create {}", dir.display()))?;
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .with_context(|| format!("failed to open {}", path.display()))?;
+    write!(file, "\n{snippet}").with_context(|| format!("failed to write {}", path.display()))?;
+    println!("Appended to {}.", path.display());
+
+    // ssh uses the first value it sees for each option, so an earlier block can still win.
+    if SshConfig::query::<&str>(ssh, host, &[])
+        .await
+        .is_some_and(|config| suggestion(host, &config).is_some())
+    {
+        println!(
+            "{}Warning:{} an earlier block in {} still takes precedence for {host}; move the new \
+             block above it.",
+            style::WARNING,
+            style::WARNING.render_reset(),
+            path.display()
+        );
+    }
+    Ok(())
+}
+
+/// Returns a `Host` block setting whichever multiplexing options `config` is missing, or `None`
+/// if it has them all.
+fn suggestion(host: &str, config: &SshConfig) -> Option<String> {
+    let mut lines = Vec::new();
+    if !config.reuses_master() {
+        lines.push("ControlMaster auto");
+    }
+    if config.control_path.is_none() {
+        lines.push("ControlPath ~/.ssh/cm-%C");
+    }
+    if config.control_persist == ControlPersist::No {
+        lines.push("ControlPersist 10m");
+    }
+    if lines.is_empty() {
+        return None;
+    }
+    let mut snippet = format!("Host {host}\n");
+    for line in lines {
+        snippet.push_str(&format!("    {line}\n"));
+    }
+    Some(snippet)
+}
@@ -0,0 +1,42 @@
+// Copyright 2026 Stairwell, Inc.
+// Author: mrdomino@stairwell.com
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! What can be read from a credential itself, without asking a credential helper.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use base64::{Engine, prelude::BASE64_URL_SAFE_NO_PAD};
+use serde::Deserialize;
+
+/// Returns when `credential` expires, if it is a JWT with an `exp` claim. The signature is not
+/// checked: this is only for telling people how long they have.
+pub fn expiry(credential: &str) -> Option<SystemTime> {
+    #[derive(Deserialize)]
+    struct Claims {
+        exp: f64,
+    }
+
+    let credential = credential.trim();
+    let credential = credential.strip_prefix("Bearer ").unwrap_or(credential);
+    let [_, payload, _] = credential.split('.').collect::<Vec<_>>()[..] else {
+        return None;
+    };
+    let payload = BASE64_URL_SAFE_NO_PAD
+        .decode(payload.trim_end_matches('='))
+        .ok()?;
+    let claims: Claims = serde_json::from_slice(&payload).ok()?;
+    let exp = Duration::try_from_secs_f64(claims.exp).ok()?;
+    UNIX_EPOCH.checked_add(exp)
+}
@@ -0,0 +1,161 @@
+// Copyright 2026 Stairwell, Inc.
+// Author: mrdomino@stairwell.com
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Fault injection for exercising failure handling in tests.
+//!
+//! A spec is a comma-separated list of faults of the form `<kind>@<program>[=<arg>][#<count>]`,
+//! each of which applies to the first `<count>` (default: every) commands running `<program>`:
+//!
+//! - `delay@<program>=<duration>`: sleeps before running the command, e.g. `delay@keyctl=2s`.
+//!   Durations take an `ms` or `s` suffix.
+//! - `drop@<program>`: does not run the command, failing as ssh does when the connection drops.
+//! - `partial@<program>`: writes only the first half of the command's stdin.
+
+use std::{cell::Cell, process::Output, str::FromStr, time::Duration};
+
+use anyhow::{Context, Result};
+
+use super::{RemoteTransport, exit_status};
+
+/// A set of faults to inject; see the module documentation for the syntax.
+#[derive(Clone, Debug, Default)]
+pub struct ChaosSpec(Vec<Fault>);
+
+#[derive(Clone, Debug)]
+struct Fault {
+    kind: FaultKind,
+    program: String,
+    count: Option<u32>,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum FaultKind {
+    Delay(Duration),
+    Drop,
+    Partial,
+}
+
+/// Wraps a transport, injecting the faults in a `ChaosSpec` into the commands run through it.
+pub struct Chaos<T> {
+    inner: T,
+    faults: Vec<(Fault, Cell<u32>)>,
+}
+
+impl<T> Chaos<T> {
+    pub fn new(inner: T, spec: ChaosSpec) -> Self {
+        Chaos {
+            inner,
+            faults: spec.0.into_iter().map(|f| (f, Cell::new(0))).collect(),
+        }
+    }
+
+    /// Returns the faults that fire for a command running `program`, counting them as used.
+    fn fire(&self, program: &str) -> Vec<FaultKind> {
+        self.faults
+            .iter()
+            .filter(|(f, used)| {
+                let fires = f.program == program && f.count.is_none_or(|n| used.get() < n);
+                if fires {
+                    used.set(used.get() + 1);
+                }
+                fires
+            })
+            .map(|(f, _)| f.kind)
+            .collect()
+    }
+}
+
+impl<T: RemoteTransport> RemoteTransport for Chaos<T> {
+    fn host(&self) -> Option<&str> {
+        self.inner.host()
+    }
+
+    fn prefix(&self) -> String {
+        self.inner.prefix()
+    }
+
+    async fn close(self) -> Result<()> {
+        self.inner.close().await
+    }
+
+    async fn run(&self, program: &str, args: &[&str], input: &[u8]) -> Result<Output> {
+        let mut input = input;
+        for kind in self.fire(program) {
+            match kind {
+                FaultKind::Delay(d) => {
+                    smol::Timer::after(d).await;
+                }
+                FaultKind::Drop => {
+                    return Ok(Output {
+                        status: exit_status(255),
+                        stdout: Vec::new(),
+                        stderr: b"chaos: connection dropped".to_vec(),
+                    });
+                }
+                FaultKind::Partial => input = &input[..input.len() / 2],
+            }
+        }
+        self.inner.run(program, args, input).await
+    }
+}
+
+impl FromStr for ChaosSpec {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        s.split(',')
+            .filter(|f| !f.is_empty())
+            .map(|f| f.parse().with_context(|| format!("bad fault {f:?}")))
+            .collect::<Result<_>>()
+            .map(ChaosSpec)
+    }
+}
+
+impl FromStr for Fault {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (s, count) = match s.rsplit_once('#') {
+            Some((s, n)) => (s, Some(n.parse().context("bad count")?)),
+            None => (s, None),
+        };
+        let (kind, target) = s.split_once('@').context("missing @<program>")?;
+        let (program, arg) = match target.split_once('=') {
+            Some((p, a)) => (p, Some(a)),
+            None => (target, None),
+        };
+        let kind = match (kind, arg) {
+            ("delay", Some(arg)) => FaultKind::Delay(parse_duration(arg)?),
+            ("drop", None) => FaultKind::Drop,
+            ("partial", None) => FaultKind::Partial,
+            _ => anyhow::bail!("unknown fault {kind}"),
+        };
+        Ok(Fault {
+            kind,
+            program: program.into(),
+            count,
+        })
+    }
+}
+
+fn parse_duration(s: &str) -> Result<Duration> {
+    if let Some(ms) = s.strip_suffix("ms") {
+        Ok(Duration::from_millis(ms.parse()?))
+    } else if let Some(secs) = s.strip_suffix('s') {
+        Ok(Duration::from_secs(secs.parse()?))
+    } else {
+        anyhow::bail!("duration {s:?} needs an ms or s suffix")
+    }
+}
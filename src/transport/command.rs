@@ -0,0 +1,142 @@
+// Copyright 2026 Stairwell, Inc.
+// Author: mrdomino@stairwell.com
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Running remote commands through a program other than plain ssh, such as `gcloud compute ssh`
+//! for hosts only reachable through an IAP tunnel.
+//!
+//! The program is given as a template, e.g. `gcloud compute ssh {host} --`, split into words as a
+//! POSIX shell would. `{host}` is replaced with the host in any word, and `{command}` with the
+//! remote command line as a single word; without `{command}`, the command line is appended, as it
+//! would be for ssh. There is no control master: each command makes its own connection.
+
+use std::{fmt, process::Output, str::FromStr};
+
+use anyhow::{Context, Result};
+use smol::process::Command;
+
+use crate::{
+    ReauthError,
+    ssh_mux::remote_command,
+    transport::{RemoteTransport, collect},
+};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CommandTemplate {
+    /// The template as written, for messages.
+    text: String,
+    words: Vec<String>,
+}
+
+impl CommandTemplate {
+    /// The program the template runs.
+    pub fn program(&self) -> &str {
+        &self.words[0]
+    }
+
+    /// Returns the arguments to pass to `program()` to run `command`, a command line for the
+    /// remote shell, on `host`.
+    pub fn args(&self, host: &str, command: &str) -> Vec<String> {
+        let mut ret: Vec<String> = self.words[1..]
+            .iter()
+            .map(|word| {
+                if word == "{command}" {
+                    command.into()
+                } else {
+                    word.replace("{host}", host)
+                }
+            })
+            .collect();
+        if !self.words.iter().any(|word| word == "{command}") {
+            ret.push(command.into());
+        }
+        ret
+    }
+}
+
+impl FromStr for CommandTemplate {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let words = shlex::split(s).context("unbalanced quotes in transport command")?;
+        if words.is_empty() {
+            anyhow::bail!("empty transport command");
+        }
+        Ok(CommandTemplate {
+            text: s.into(),
+            words,
+        })
+    }
+}
+
+impl fmt::Display for CommandTemplate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+/// Runs commands on a host through a `CommandTemplate`.
+pub struct CommandTransport<'a> {
+    template: &'a CommandTemplate,
+    host: &'a str,
+}
+
+impl<'a> CommandTransport<'a> {
+    /// Checks that `template` reaches `host` by running `true` there.
+    pub async fn new(template: &'a CommandTemplate, host: &'a str) -> Result<Self> {
+        let transport = CommandTransport { template, host };
+        let output = transport
+            .command("true")
+            .kill_on_drop(true)
+            .output()
+            .await
+            .map_err(|source| ReauthError::Ssh {
+                message: format!("failed to start {}", template.program()),
+                source: Some(source),
+            })?;
+        if !output.status.success() {
+            return Err(ReauthError::ssh(format!(
+                "{} {host}: {}\n\n{}",
+                template.program(),
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim(),
+            ))
+            .into());
+        }
+        Ok(transport)
+    }
+
+    pub fn command(&self, command: &str) -> Command {
+        let mut ret = Command::new(self.template.program());
+        ret.args(self.template.args(self.host, command));
+        ret
+    }
+}
+
+impl RemoteTransport for CommandTransport<'_> {
+    fn host(&self) -> Option<&str> {
+        Some(self.host)
+    }
+
+    async fn run(&self, program: &str, args: &[&str], input: &[u8]) -> Result<Output> {
+        let cmd = self.command(&remote_command(program, args)?);
+        collect(cmd, input)
+            .await
+            .with_context(|| format!("failed to run {program} on {}", self.host))
+    }
+
+    fn prefix(&self) -> String {
+        format!("{} {} ", self.template.program(), self.host)
+    }
+}
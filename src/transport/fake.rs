@@ -0,0 +1,159 @@
+// Copyright 2026 Stairwell, Inc.
+// Author: mrdomino@stairwell.com
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{collections::VecDeque, process::Output, sync::Mutex};
+
+use anyhow::Result;
+
+use super::{RemoteTransport, exit_status};
+
+/// An in-process `RemoteTransport` that answers commands from a script instead of running them.
+///
+/// Responses are registered with `expect` against a program and a prefix of its arguments, and are
+/// consumed in order: each command is answered by the first remaining response it matches. A
+/// command matching nothing fails, as does a scripted `Response::Error`. Every command run is
+/// recorded and available from `calls`.
+/// ```
+/// # use aspect_reauth::transport::fake::{FakeTransport, Response};
+/// let fake = FakeTransport::remote("devbox");
+/// fake.expect("keyctl", &["padd"], Response::exit(1, "keyctl: Permission denied"));
+/// ```
+pub struct FakeTransport {
+    host: Option<String>,
+    script: Mutex<VecDeque<(String, Vec<String>, Response)>>,
+    calls: Mutex<Vec<Call>>,
+}
+
+/// A scripted answer to a command.
+#[derive(Clone, Debug)]
+pub enum Response {
+    /// The command ran and exited with `code`.
+    Exit {
+        code: i32,
+        stdout: Vec<u8>,
+        stderr: Vec<u8>,
+    },
+    /// The command could not be run at all.
+    Error(String),
+}
+
+/// A command that was run against a `FakeTransport`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Call {
+    pub program: String,
+    pub args: Vec<String>,
+    pub input: Vec<u8>,
+}
+
+impl FakeTransport {
+    /// A fake standing in for the local machine.
+    pub fn local() -> Self {
+        Self::with_host(None)
+    }
+
+    /// A fake standing in for `host`.
+    pub fn remote(host: &str) -> Self {
+        Self::with_host(Some(host.into()))
+    }
+
+    fn with_host(host: Option<String>) -> Self {
+        FakeTransport {
+            host,
+            script: Mutex::default(),
+            calls: Mutex::default(),
+        }
+    }
+
+    /// Answers the next command running `program` with arguments starting with `args` with
+    /// `response`.
+    pub fn expect(&self, program: &str, args: &[&str], response: Response) -> &Self {
+        self.script.lock().unwrap().push_back((
+            program.into(),
+            args.iter().map(|&a| a.into()).collect(),
+            response,
+        ));
+        self
+    }
+
+    /// Returns every command run so far, in order.
+    pub fn calls(&self) -> Vec<Call> {
+        self.calls.lock().unwrap().clone()
+    }
+
+    /// Returns whether every scripted response has been consumed.
+    pub fn is_done(&self) -> bool {
+        self.script.lock().unwrap().is_empty()
+    }
+}
+
+impl Response {
+    /// A successful exit with `stdout`.
+    pub fn ok(stdout: &str) -> Self {
+        Response::Exit {
+            code: 0,
+            stdout: stdout.into(),
+            stderr: Vec::new(),
+        }
+    }
+
+    /// An exit with `code` and `stderr`.
+    pub fn exit(code: i32, stderr: &str) -> Self {
+        Response::Exit {
+            code,
+            stdout: Vec::new(),
+            stderr: stderr.into(),
+        }
+    }
+}
+
+impl RemoteTransport for FakeTransport {
+    fn host(&self) -> Option<&str> {
+        self.host.as_deref()
+    }
+
+    async fn run(&self, program: &str, args: &[&str], input: &[u8]) -> Result<Output> {
+        self.calls.lock().unwrap().push(Call {
+            program: program.into(),
+            args: args.iter().map(|&a| a.into()).collect(),
+            input: input.into(),
+        });
+        let response = {
+            let mut script = self.script.lock().unwrap();
+            let pos = script.iter().position(|(p, prefix, _)| {
+                p == program
+                    && prefix.len() <= args.len()
+                    && prefix.iter().zip(args).all(|(a, b)| a == b)
+            });
+            pos.and_then(|pos| script.remove(pos))
+        };
+        match response {
+            Some((
+                _,
+                _,
+                Response::Exit {
+                    code,
+                    stdout,
+                    stderr,
+                },
+            )) => Ok(Output {
+                status: exit_status(code),
+                stdout,
+                stderr,
+            }),
+            Some((_, _, Response::Error(msg))) => anyhow::bail!("{msg}"),
+            None => anyhow::bail!("unexpected command: {program} {}", args.join(" ")),
+        }
+    }
+}
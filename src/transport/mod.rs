@@ -0,0 +1,112 @@
+// Copyright 2026 Stairwell, Inc.
+// Author: mrdomino@stairwell.com
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#[cfg(feature = "test-support")]
+pub mod chaos;
+pub mod command;
+#[cfg(feature = "test-support")]
+pub mod fake;
+#[cfg(feature = "test-support")]
+pub mod record;
+pub mod retry;
+
+use std::process::{ExitStatus, Output};
+
+use anyhow::{Context, Result};
+use smol::{
+    io::AsyncWriteExt,
+    process::{Command, Stdio},
+};
+
+/// Somewhere we can run commands: the local machine, or a remote host.
+///
+/// Everything the sync flow does on either end is expressed as a program invocation with some
+/// bytes on stdin, so this is the seam at which the flow may be pointed at something other than a
+/// real process (see `fake::FakeTransport`).
+#[allow(async_fn_in_trait)]
+pub trait RemoteTransport {
+    /// The remote host commands run on, or `None` if they run locally. Used in messages.
+    fn host(&self) -> Option<&str>;
+
+    /// Runs `program` with `args`, writing `input` to its stdin, and collects its output.
+    async fn run(&self, program: &str, args: &[&str], input: &[u8]) -> Result<Output>;
+
+    /// Closes whatever the transport holds open, such as an ssh control master. A transport that
+    /// is dropped instead, say because the run was interrupted, still cleans up after itself, but
+    /// may block the thread to do so.
+    async fn close(self) -> Result<()>
+    where
+        Self: Sized,
+    {
+        Ok(())
+    }
+
+    /// Returns a prefix for messages about a failed command, e.g. `ssh devbox `.
+    fn prefix(&self) -> String {
+        self.host()
+            .map(|host| format!("ssh {host} "))
+            .unwrap_or_default()
+    }
+}
+
+/// Runs commands directly on the local machine.
+pub struct Local;
+
+impl RemoteTransport for Local {
+    fn host(&self) -> Option<&str> {
+        None
+    }
+
+    async fn run(&self, program: &str, args: &[&str], input: &[u8]) -> Result<Output> {
+        let mut cmd = Command::new(program);
+        cmd.args(args);
+        collect(cmd, input)
+            .await
+            .with_context(|| format!("failed to run {program}"))
+    }
+}
+
+/// The status of a process that exited with `code`, for transports that answer commands
+/// themselves rather than running them.
+#[cfg(unix)]
+pub fn exit_status(code: i32) -> ExitStatus {
+    use std::os::unix::process::ExitStatusExt;
+    ExitStatus::from_raw(code << 8)
+}
+
+#[cfg(windows)]
+pub fn exit_status(code: i32) -> ExitStatus {
+    use std::os::windows::process::ExitStatusExt;
+    ExitStatus::from_raw(code as u32)
+}
+
+/// Spawns `cmd` with piped stdio, writes `input` to it, and waits for it to exit.
+///
+/// `input` may be the credential, so it is written from where it is rather than copied first. On
+/// Unix it goes straight into the pipe; on Windows, async-process passes it through a buffer of
+/// its own on a blocking thread, which is freed without being zeroed.
+pub(crate) async fn collect(mut cmd: Command, input: &[u8]) -> std::io::Result<Output> {
+    let mut child = cmd
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        // So that a run given up on does not leave a hung ssh behind.
+        .kill_on_drop(true)
+        .spawn()?;
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    stdin.write_all(input).await?;
+    drop(stdin);
+    child.output().await
+}
@@ -0,0 +1,169 @@
+// Copyright 2026 Stairwell, Inc.
+// Author: mrdomino@stairwell.com
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Recording of remote interactions, and replaying them back as golden tests.
+//!
+//! A fixture is a JSON document listing every command run through a transport along with its
+//! response. Fixtures hold command input verbatim, credentials included, so they should only ever
+//! be recorded against test credentials.
+
+use std::{
+    cell::RefCell,
+    collections::VecDeque,
+    fs,
+    path::{Path, PathBuf},
+    process::Output,
+};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use super::{RemoteTransport, exit_status};
+
+/// The contents of a fixture file.
+#[derive(Default, Deserialize, Serialize)]
+pub struct Fixture {
+    pub host: Option<String>,
+    pub interactions: Vec<Interaction>,
+}
+
+/// One command and its response.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq, Serialize)]
+pub struct Interaction {
+    pub program: String,
+    pub args: Vec<String>,
+    pub input: String,
+    pub code: i32,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+/// Wraps a transport, recording every command run through it. If there is a `path`, the recording
+/// is written to it by `drop`.
+pub struct Recorder<T: RemoteTransport> {
+    inner: T,
+    path: Option<PathBuf>,
+    interactions: RefCell<Vec<Interaction>>,
+}
+
+/// A transport that answers commands from a fixture, failing on any command that does not match
+/// the next one recorded.
+pub struct Replay {
+    host: Option<String>,
+    interactions: RefCell<VecDeque<Interaction>>,
+}
+
+impl Fixture {
+    pub fn load(path: &Path) -> Result<Self> {
+        let bytes = fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+        serde_json::from_slice(&bytes)
+            .with_context(|| format!("failed to parse {}", path.display()))
+    }
+}
+
+impl<T: RemoteTransport> Recorder<T> {
+    pub fn new(inner: T, path: Option<PathBuf>) -> Self {
+        Recorder {
+            inner,
+            path,
+            interactions: RefCell::default(),
+        }
+    }
+
+    fn save(&self) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let fixture = Fixture {
+            host: self.inner.host().map(String::from),
+            interactions: self.interactions.take(),
+        };
+        let json = serde_json::to_string_pretty(&fixture)?;
+        fs::write(path, json + "\n").with_context(|| format!("failed to write {}", path.display()))
+    }
+}
+
+impl<T: RemoteTransport> RemoteTransport for Recorder<T> {
+    fn host(&self) -> Option<&str> {
+        self.inner.host()
+    }
+
+    fn prefix(&self) -> String {
+        self.inner.prefix()
+    }
+
+    /// Writes the fixture, then closes the transport recorded. Nothing is written for a recorder
+    /// that is dropped without being closed.
+    async fn close(self) -> Result<()> {
+        self.save()?;
+        self.inner.close().await
+    }
+
+    async fn run(&self, program: &str, args: &[&str], input: &[u8]) -> Result<Output> {
+        let output = self.inner.run(program, args, input).await?;
+        self.interactions.borrow_mut().push(Interaction {
+            program: program.into(),
+            args: args.iter().map(|&a| a.into()).collect(),
+            input: String::from_utf8_lossy(input).into_owned(),
+            code: output.status.code().unwrap_or(-1),
+            stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        });
+        Ok(output)
+    }
+}
+
+impl Replay {
+    pub fn new(fixture: Fixture) -> Self {
+        Replay {
+            host: fixture.host,
+            interactions: RefCell::new(fixture.interactions.into()),
+        }
+    }
+
+    /// Returns whether every recorded interaction has been replayed.
+    pub fn is_done(&self) -> bool {
+        self.interactions.borrow().is_empty()
+    }
+}
+
+impl RemoteTransport for Replay {
+    fn host(&self) -> Option<&str> {
+        self.host.as_deref()
+    }
+
+    async fn run(&self, program: &str, args: &[&str], input: &[u8]) -> Result<Output> {
+        let input = String::from_utf8_lossy(input);
+        let Some(next) = self.interactions.borrow_mut().pop_front() else {
+            anyhow::bail!("replay: unexpected command {program} {}", args.join(" "));
+        };
+        if next.program != program || next.args != args || next.input != input {
+            anyhow::bail!(
+                "replay: expected {} {} with input {:?}, got {} {} with input {:?}",
+                next.program,
+                next.args.join(" "),
+                next.input,
+                program,
+                args.join(" "),
+                input,
+            );
+        }
+        Ok(Output {
+            status: exit_status(next.code),
+            stdout: next.stdout.into_bytes(),
+            stderr: next.stderr.into_bytes(),
+        })
+    }
+}
@@ -0,0 +1,77 @@
+// Copyright 2026 Stairwell, Inc.
+// Author: mrdomino@stairwell.com
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Retrying remote commands that fail as ssh does when the connection drops.
+
+use std::{process::Output, time::Duration};
+
+use anyhow::Result;
+
+use super::RemoteTransport;
+
+/// How many times to retry, and how long to wait before the first retry; each wait after that is
+/// twice the one before.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Backoff {
+    pub retries: u32,
+    pub delay: Duration,
+}
+
+impl Backoff {
+    /// The wait before retry `n`, counting from zero.
+    pub fn delay(&self, n: u32) -> Duration {
+        self.delay.saturating_mul(2u32.saturating_pow(n))
+    }
+}
+
+/// Wraps a transport, rerunning any command that exits with 255, which is how ssh reports a
+/// connection that could not be made or was lost. The commands the sync runs can all be run again
+/// safely.
+pub struct Retry<T> {
+    inner: T,
+    backoff: Backoff,
+}
+
+impl<T> Retry<T> {
+    pub fn new(inner: T, backoff: Backoff) -> Self {
+        Retry { inner, backoff }
+    }
+}
+
+impl<T: RemoteTransport> RemoteTransport for Retry<T> {
+    fn host(&self) -> Option<&str> {
+        self.inner.host()
+    }
+
+    fn prefix(&self) -> String {
+        self.inner.prefix()
+    }
+
+    async fn close(self) -> Result<()> {
+        self.inner.close().await
+    }
+
+    async fn run(&self, program: &str, args: &[&str], input: &[u8]) -> Result<Output> {
+        let mut n = 0;
+        loop {
+            let output = self.inner.run(program, args, input).await?;
+            if output.status.code() != Some(255) || n == self.backoff.retries {
+                return Ok(output);
+            }
+            smol::Timer::after(self.backoff.delay(n)).await;
+            n += 1;
+        }
+    }
+}
@@ -0,0 +1,109 @@
+// Copyright 2026 Stairwell, Inc.
+// Author: mrdomino@stairwell.com
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Opt-in (`check_for_updates = true` in the config file) notice that a newer release exists.
+//!
+//! Old copies keep whatever remote and helper defaults they were built with, which is a
+//! recurring source of confusion, so it is worth a line on stderr. The check asks crates.io at
+//! most once a day, runs concurrently with the sync, and is abandoned rather than waited for if
+//! it is slow; any failure is silent.
+
+use std::time::Duration;
+
+use anstream::eprintln;
+use aspect_reauth::state::{self, State, UpdateCheck};
+use serde::Deserialize;
+use smol::{Task, Timer, future, process::Command};
+
+use crate::style;
+
+const CURRENT: &str = env!("CARGO_PKG_VERSION");
+const FEED: &str = "https://crates.io/api/v1/crates/aspect-reauth";
+const INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+/// How long to wait for the check after the sync is done.
+const GRACE: Duration = Duration::from_millis(300);
+
+#[derive(Deserialize)]
+struct Feed {
+    #[serde(rename = "crate")]
+    krate: FeedCrate,
+}
+
+#[derive(Deserialize)]
+struct FeedCrate {
+    max_stable_version: String,
+}
+
+/// Starts the check in the background, returning the latest version once it is known.
+pub fn spawn() -> Task<Option<String>> {
+    smol::spawn(async {
+        let mut state = State::load().ok()?;
+        if let Some(check) = &state.update_check
+            && state::age(check.checked_at) < INTERVAL
+        {
+            return Some(check.latest.clone());
+        }
+        let latest = fetch().await?;
+        // Reload so as not to clobber anything recorded in the meantime.
+        state = State::load().ok()?;
+        state.update_check = Some(UpdateCheck {
+            checked_at: state::now(),
+            latest: latest.clone(),
+        });
+        let _ = state.save();
+        Some(latest)
+    })
+}
+
+/// Prints a notice if `check` finds a newer version within a moment.
+pub async fn report(check: Task<Option<String>>) {
+    let latest = future::or(check, async {
+        Timer::after(GRACE).await;
+        None
+    })
+    .await;
+    if let Some(latest) = latest.filter(|latest| is_newer(latest, CURRENT)) {
+        eprintln!(
+            "{}A newer aspect-reauth is available{} ({latest}; this is {CURRENT}). Update with \
+             `cargo install aspect-reauth`.",
+            style::WARNING,
+            style::WARNING.render_reset()
+        );
+    }
+}
+
+async fn fetch() -> Option<String> {
+    let output = Command::new("curl")
+        .args(["-fsSL", "--max-time", "5", "-A"])
+        .arg(format!("aspect-reauth/{CURRENT} (update check)"))
+        .arg(FEED)
+        .output()
+        .await
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let feed: Feed = serde_json::from_slice(&output.stdout).ok()?;
+    Some(feed.krate.max_stable_version)
+}
+
+/// Compares dotted numeric versions, ignoring anything that isn't one.
+fn is_newer(latest: &str, current: &str) -> bool {
+    let parse = |v: &str| -> Option<Vec<u64>> { v.split('.').map(|n| n.parse().ok()).collect() };
+    match (parse(latest), parse(current)) {
+        (Some(latest), Some(current)) => latest > current,
+        _ => false,
+    }
+}
@@ -0,0 +1,241 @@
+// Copyright 2026 Stairwell, Inc.
+// Author: mrdomino@stairwell.com
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! `aspect-reauth watch`: stays resident and keeps a host's credential fresh until interrupted.
+//!
+//! Each pass is an ordinary sync, over one ssh connection that is kept open between passes and
+//! reopened if a pass fails. Between passes it sleeps until shortly before the local credential
+//! expires, if the credential helper says when that is, and then logs in again ahead of time
+//! rather than waiting for a build to fail. It checks at least every `--interval` regardless, so
+//! that a credential revoked or lost on the host is put back.
+//!
+//! It also listens on a control socket, so that a plain sync to the same host asks it to sync now
+//! instead of connecting on its own.
+
+use std::time::{Duration, Instant, SystemTime};
+
+use anstream::{eprintln, println};
+use anyhow::Result;
+use aspect_reauth::{
+    ReauthFlow, flow::Outcome, helper, human, probe::Probed, state::Status, transport::Local,
+};
+use clap::Args;
+use clap_complete::ArgValueCandidates;
+use smol::{Timer, future, stream::StreamExt};
+
+use crate::{connect, control, disconnect, interrupts, record, style};
+
+/// How long to wait before trying again after a failed pass, at most.
+const RETRY: Duration = Duration::from_secs(60);
+
+#[derive(Args, Clone)]
+pub struct WatchArgs {
+    /// SSH hostname to keep in sync [default: from the config file, or devbox]
+    #[arg(env = "ASPECT_REAUTH_HOST", add = ArgValueCandidates::new(crate::completions::hosts))]
+    pub host: Option<String>,
+
+    /// Log in again this long before the credential expires
+    #[arg(long, default_value = "10m", value_parser = humantime::parse_duration)]
+    refresh_before: Duration,
+
+    /// Check the credential at least this often
+    #[arg(long, default_value = "15m", value_parser = humantime::parse_duration)]
+    interval: Duration,
+}
+
+/// Keeps the host in `args`, which has been set up as for a sync to it, in sync until
+/// interrupted, then closes the connection to it.
+pub async fn run(cmd: &WatchArgs, args: &crate::Args) -> Result<()> {
+    let mut signals = interrupts()?;
+    let host = args.host();
+    let listener = control::Listener::bind(host)?;
+    let options = args.sync_options();
+    let source = args.source(&options);
+    let sink = Probed::new(args.sink(&options)?, &options);
+    let settings = args.watch_settings();
+    let mut ssh = None;
+    let mut due = false;
+    let mut at = Instant::now();
+    loop {
+        let next = async {
+            let scheduled = async {
+                Timer::at(at).await;
+                None
+            };
+            let asked = async {
+                match &listener {
+                    Some(listener) => Some(listener.accept().await),
+                    None => future::pending().await,
+                }
+            };
+            let pending = match future::or(scheduled, asked).await {
+                Some(pending) => {
+                    let differ = settings.differences(&pending.request.settings).join(", ");
+                    if !differ.is_empty() {
+                        log(format_args!(
+                            "Refused to sync with other settings ({differ})."
+                        ));
+                        pending
+                            .refuse(format!(
+                                "the watch running for {host} syncs with other settings \
+                                 ({differ}); stop it, or run with the same settings as it"
+                            ))
+                            .await;
+                        return true;
+                    }
+                    log(format_args!("Syncing now, as asked."));
+                    Some(pending)
+                }
+                None => None,
+            };
+            let request = pending
+                .as_ref()
+                .map(|pending| pending.request.clone())
+                .unwrap_or_default();
+            let pass = async {
+                if ssh.is_none() {
+                    ssh = Some(connect(args).await?);
+                }
+                let ssh = ssh.as_ref().expect("connected above");
+                ReauthFlow::new(&source, ssh, &sink, &options)
+                    .force_local(due || request.force_local)
+                    .force_remote(due || request.force_remote)
+                    .run()
+                    .await
+            };
+            let result = pass.await;
+            let wait = match &result {
+                Ok(outcome) => {
+                    record(args, Status::Valid);
+                    let (wait, next_due) = schedule(cmd, outcome, &options).await;
+                    due = next_due;
+                    wait
+                }
+                Err(e) => {
+                    record(args, Status::Error);
+                    log(format_args!(
+                        "{}Sync to {host} failed:{} {e:#}",
+                        style::ERROR,
+                        style::ERROR.render_reset()
+                    ));
+                    // Start over with a fresh connection, in case it was the one that failed.
+                    if let Some(ssh) = ssh.take() {
+                        _ = disconnect(ssh, Ok(())).await;
+                    }
+                    due = false;
+                    RETRY.min(cmd.interval)
+                }
+            };
+            at = Instant::now() + wait;
+            if let Some(pending) = pending {
+                pending.reply(&result).await;
+            }
+            true
+        };
+        let stopped = async {
+            signals.next().await;
+            false
+        };
+        if !future::or(next, stopped).await {
+            break;
+        }
+    }
+    log(format_args!("Stopping."));
+    match ssh {
+        Some(ssh) => disconnect(ssh, Ok(())).await,
+        None => Ok(()),
+    }
+}
+
+/// Reports on a pass that finished with `outcome`, and decides how long to wait for the next
+/// one and whether it should log in again regardless.
+async fn schedule(
+    cmd: &WatchArgs,
+    outcome: &Outcome,
+    options: &aspect_reauth::SyncOptions,
+) -> (Duration, bool) {
+    let done = if outcome.synced {
+        "Aspect credentials synced."
+    } else {
+        "Credentials are valid."
+    };
+    let expires = match helper::expires(&Local, options).await {
+        Ok(expires) => expires,
+        Err(e) => {
+            eprintln!(
+                "{}warning:{} failed to read the credential's expiry: {e:#}",
+                style::WARNING,
+                style::WARNING.render_reset()
+            );
+            None
+        }
+    };
+    let Some(expires) = expires else {
+        log(format_args!(
+            "{done} Next check {}.",
+            human::from_now(cmd.interval)
+        ));
+        return (cmd.interval, false);
+    };
+    let now = SystemTime::now();
+    let left = expires.duration_since(now).unwrap_or_default();
+    let refresh_at = expires.checked_sub(cmd.refresh_before).unwrap_or(now);
+    match refresh_at.duration_since(now) {
+        Ok(wait) if wait > cmd.interval => {
+            log(format_args!(
+                "{done} The credential expires {}; next check {}.",
+                human::from_now(left),
+                human::from_now(cmd.interval)
+            ));
+            (cmd.interval, false)
+        }
+        Ok(wait) => {
+            log(format_args!(
+                "{done} The credential expires {}; logging in again {}.",
+                human::from_now(left),
+                human::from_now(wait)
+            ));
+            (wait, true)
+        }
+        // A fresh login that is already due for another would have us logging in over and over.
+        Err(_) if outcome.logged_in => {
+            log(format_args!(
+                "{done} {}warning:{} the new credential already expires {}; next check {}.",
+                style::WARNING,
+                style::WARNING.render_reset(),
+                human::from_now(left),
+                human::from_now(cmd.interval)
+            ));
+            (cmd.interval, false)
+        }
+        Err(_) => {
+            log(format_args!(
+                "{done} The credential expires {}; logging in again now.",
+                human::from_now(left)
+            ));
+            (Duration::ZERO, true)
+        }
+    }
+}
+
+/// Prints `message` with the time, since this runs for long enough that it matters.
+fn log(message: std::fmt::Arguments) {
+    println!(
+        "{}[{}]{} {message}",
+        style::COMMENT,
+        humantime::format_rfc3339_seconds(SystemTime::now()),
+        style::COMMENT.render_reset()
+    );
+}
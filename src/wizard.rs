@@ -0,0 +1,89 @@
+// Copyright 2026 Stairwell, Inc.
+// Author: mrdomino@stairwell.com
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! First-run setup: when there is no configuration file and we are on a terminal, offer to walk
+//! the user through choosing a host, remote, and helper. The settings are only saved once the sync
+//! that follows succeeds, so a typo doesn't get written down.
+
+use anstream::println;
+use anyhow::{Context, Result};
+use aspect_reauth::ssh_mux::config::user_host_aliases;
+use dialoguer::{Confirm, FuzzySelect, Input};
+
+use crate::{Args, DEFAULT_HELPER, DEFAULT_HOST, DEFAULT_REMOTE, config::Config};
+
+const OTHER: &str = "(another host)";
+
+/// Runs the wizard, updating `args` with the answers. Returns the configuration to save if the
+/// sync succeeds, or `None` if the user declined.
+pub fn run(args: &mut Args) -> Result<Option<Config>> {
+    println!("There is no aspect-reauth configuration file yet.");
+    if !Confirm::new()
+        .with_prompt("Set one up now?")
+        .default(true)
+        .interact()?
+    {
+        // Don't ask again every time.
+        let path = Config::default().save()?;
+        println!(
+            "Created an empty {}; delete it to run this setup again.",
+            path.display()
+        );
+        return Ok(None);
+    }
+
+    let host = pick_host()?;
+    let remote: String = Input::new()
+        .with_prompt("Aspect remote")
+        .default(args.remote.clone())
+        .interact_text()?;
+    let helper: String = Input::new()
+        .with_prompt("Credential helper")
+        .default(args.credential_helper.clone())
+        .interact_text()?;
+    println!("Syncing to {host} to check these settings...");
+
+    args.host = Some(host.clone());
+    args.remote = remote.clone();
+    args.credential_helper = helper.clone();
+    Ok(Some(Config {
+        default_host: Some(host),
+        remote: (remote != DEFAULT_REMOTE).then_some(remote),
+        credential_helper: (helper != DEFAULT_HELPER).then_some(helper),
+        ..Default::default()
+    }))
+}
+
+/// Offers the hosts from `~/.ssh/config`, or asks for one if there are none (or none of them).
+fn pick_host() -> Result<String> {
+    let mut hosts = user_host_aliases();
+    if !hosts.is_empty() {
+        let default = hosts.iter().position(|h| h == DEFAULT_HOST).unwrap_or(0);
+        hosts.push(OTHER.into());
+        let index = FuzzySelect::new()
+            .with_prompt("Host to sync to")
+            .items(&hosts)
+            .default(default)
+            .interact()
+            .context("no host selected")?;
+        if hosts[index] != OTHER {
+            return Ok(hosts.swap_remove(index));
+        }
+    }
+    Ok(Input::new()
+        .with_prompt("Host to sync to")
+        .default(DEFAULT_HOST.to_string())
+        .interact_text()?)
+}
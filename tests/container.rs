@@ -0,0 +1,232 @@
+// Copyright 2026 Stairwell, Inc.
+// Author: mrdomino@stairwell.com
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Runs the real binary against a Linux container with sshd and keyutils, checking the key that
+//! actually lands in the kernel keyring.
+//!
+//! These tests need a container runtime and so are skipped unless
+//! `ASPECT_REAUTH_CONTAINER_TESTS=1`. The runtime defaults to `docker` and may be overridden
+//! with `ASPECT_REAUTH_CONTAINER_RUNTIME` (e.g. `podman`). The container is run with seccomp
+//! disabled, since the default profiles block the keyctl syscalls.
+
+#![cfg(unix)]
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::{Command, Output},
+    thread,
+    time::Duration,
+};
+
+use tempfile::TempDir;
+
+const IMAGE: &str = "aspect-reauth-container-test";
+const REMOTE: &str = "aw-remote.example";
+const USER_UID: &str = "1000";
+
+fn enabled() -> bool {
+    env::var("ASPECT_REAUTH_CONTAINER_TESTS").is_ok_and(|v| v == "1")
+}
+
+fn runtime() -> String {
+    env::var("ASPECT_REAUTH_CONTAINER_RUNTIME").unwrap_or_else(|_| "docker".into())
+}
+
+fn check(output: Output, what: &str) -> String {
+    assert!(
+        output.status.success(),
+        "{what}: {}\n{}",
+        output.status,
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout).unwrap().trim().into()
+}
+
+/// A running devbox container, removed on drop.
+struct Devbox {
+    id: String,
+    port: String,
+    key: PathBuf,
+    _dir: TempDir,
+}
+
+impl Devbox {
+    fn start() -> Self {
+        let context = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/container");
+        check(
+            Command::new(runtime())
+                .args(["build", "-q", "-t", IMAGE])
+                .arg(&context)
+                .output()
+                .unwrap(),
+            "build image",
+        );
+        let dir = TempDir::new().unwrap();
+        let key = dir.path().join("id_ed25519");
+        check(
+            Command::new("ssh-keygen")
+                .args(["-q", "-t", "ed25519", "-N", "", "-f"])
+                .arg(&key)
+                .output()
+                .unwrap(),
+            "ssh-keygen",
+        );
+        let pubkey = fs::read_to_string(key.with_extension("pub")).unwrap();
+        let id = check(
+            Command::new(runtime())
+                .args([
+                    "run",
+                    "-d",
+                    "--security-opt",
+                    "seccomp=unconfined",
+                    "-p",
+                    "127.0.0.1::22",
+                    "-e",
+                ])
+                .arg(format!("AUTHORIZED_KEY={}", pubkey.trim()))
+                .arg(IMAGE)
+                .output()
+                .unwrap(),
+            "run container",
+        );
+        let mut devbox = Devbox {
+            id,
+            port: String::new(),
+            key,
+            _dir: dir,
+        };
+        let mapping = check(
+            Command::new(runtime())
+                .args(["port", &devbox.id, "22"])
+                .output()
+                .unwrap(),
+            "container port",
+        );
+        devbox.port = mapping
+            .lines()
+            .next()
+            .and_then(|l| l.rsplit(':').next())
+            .expect("port mapping")
+            .into();
+        for _ in 0..50 {
+            if devbox.ssh(&["true"]).status.success() {
+                return devbox;
+            }
+            thread::sleep(Duration::from_millis(200));
+        }
+        panic!("sshd in {} never came up", devbox.id);
+    }
+
+    fn ssh_args(&self) -> Vec<String> {
+        vec![
+            "-p".into(),
+            self.port.clone(),
+            "-i".into(),
+            self.key.display().to_string(),
+            "-oStrictHostKeyChecking=no".into(),
+            "-oUserKnownHostsFile=/dev/null".into(),
+            "-oLogLevel=ERROR".into(),
+        ]
+    }
+
+    fn ssh(&self, command: &[&str]) -> Output {
+        Command::new("ssh")
+            .args(self.ssh_args())
+            .args(["-oBatchMode=yes", "--", "tester@127.0.0.1"])
+            .args(command)
+            .output()
+            .unwrap()
+    }
+
+    fn reauth(&self, args: &[&str]) -> Output {
+        let mut cmd = Command::new(env!("CARGO_BIN_EXE_aspect-reauth"));
+        for arg in self.ssh_args() {
+            cmd.arg(format!("--ssh-arg={arg}"));
+        }
+        cmd.args(["--ci", "--remote", REMOTE])
+            .args(["--credential-helper", "aspect-credential-helper"])
+            .args(args)
+            .arg("tester@127.0.0.1")
+            .env("ASPECT_REAUTH_CREDENTIAL", "hunter2")
+            .output()
+            .unwrap()
+    }
+}
+
+impl Drop for Devbox {
+    fn drop(&mut self) {
+        let _ = Command::new(runtime())
+            .args(["rm", "-f", &self.id])
+            .output();
+    }
+}
+
+#[test]
+fn key_lands_in_the_user_keyring() {
+    if !enabled() {
+        eprintln!("skipping: set ASPECT_REAUTH_CONTAINER_TESTS=1 to run");
+        return;
+    }
+    let devbox = Devbox::start();
+    let output = devbox.reauth(&[]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{stdout}");
+    assert!(stdout.contains(r#""status":"synced""#), "{stdout}");
+
+    let name = format!("keyring-rs:{REMOTE}@AspectWorkflows");
+    let id = check(
+        devbox.ssh(&["keyctl", "search", "@u", "user", &name]),
+        "keyctl search",
+    );
+    assert_eq!(
+        check(devbox.ssh(&["keyctl", "pipe", &id]), "keyctl pipe"),
+        "hunter2"
+    );
+
+    // type;uid;gid;perm;description
+    let description = check(
+        devbox.ssh(&["keyctl", "rdescribe", &id]),
+        "keyctl rdescribe",
+    );
+    let fields: Vec<&str> = description.splitn(5, ';').collect();
+    assert_eq!(
+        fields,
+        ["user", USER_UID, USER_UID, "3f010000", name.as_str()]
+    );
+
+    // id flags usage timeout perm uid gid type description
+    let proc_keys = check(devbox.ssh(&["cat", "/proc/keys"]), "cat /proc/keys");
+    let serial = format!("{:08x}", id.parse::<u32>().unwrap());
+    let line = proc_keys
+        .lines()
+        .find(|l| l.starts_with(&serial))
+        .expect("key in /proc/keys");
+    let timeout = line.split_whitespace().nth(3).unwrap();
+    assert_eq!(timeout, "perm", "{line}");
+}
+
+#[test]
+fn second_run_is_a_no_op() {
+    if !enabled() {
+        eprintln!("skipping: set ASPECT_REAUTH_CONTAINER_TESTS=1 to run");
+        return;
+    }
+    let devbox = Devbox::start();
+    assert!(devbox.reauth(&[]).status.success());
+    let output = devbox.reauth(&[]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains(r#""status":"valid""#), "{stdout}");
+}
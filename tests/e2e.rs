@@ -0,0 +1,2684 @@
+// Copyright 2026 Stairwell, Inc.
+// Author: mrdomino@stairwell.com
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Runs the real CLI against `mock-credential-helper` posing as ssh, keyctl, and the helper.
+
+#![cfg(unix)]
+
+use std::{
+    fs,
+    os::unix::fs::{PermissionsExt, symlink},
+    path::{Path, PathBuf},
+    process::{Command, Output, Stdio},
+    time::{Duration, Instant, SystemTime},
+};
+
+use aspect_reauth::{probe, ssh_mux::remote_command};
+use tempfile::TempDir;
+
+const HELPER: &str = "aspect-credential-helper";
+const REMOTE: &str = "aw-remote.example";
+const SSH_OPTS: &str = "-xT -oPermitLocalCommand=no -oClearAllForwardings=yes \
+                        -oRemoteCommand=none -oForwardAgent=no -oBatchMode=yes";
+
+struct Harness {
+    dir: TempDir,
+}
+
+impl Harness {
+    fn new() -> Self {
+        let dir = TempDir::new().unwrap();
+        let mock = Path::new(env!("CARGO_BIN_EXE_mock-credential-helper"));
+        for (sub, names) in [
+            (
+                "bin",
+                &["ssh", "tsh", "kubectl", "docker", "podman", HELPER][..],
+            ),
+            ("remote-bin", &["keyctl", HELPER]),
+        ] {
+            fs::create_dir(dir.path().join(sub)).unwrap();
+            for name in names {
+                symlink(mock, dir.path().join(sub).join(name)).unwrap();
+            }
+        }
+        Harness { dir }
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        self.dir.path().join(name)
+    }
+
+    fn set(&self, name: &str, contents: &str) -> &Self {
+        fs::write(self.path(name), contents).unwrap();
+        self
+    }
+
+    /// Runs aspect-reauth with a clean environment and the mock ssh first on PATH.
+    fn command(&self) -> Command {
+        let path = format!(
+            "{}:{}",
+            self.path("bin").display(),
+            std::env::var("PATH").unwrap_or_default()
+        );
+        let remote_path = format!("{}:/usr/bin:/bin", self.path("remote-bin").display());
+        let mut cmd = Command::new(env!("CARGO_BIN_EXE_aspect-reauth"));
+        cmd.env_clear()
+            .env("PATH", path)
+            .env("HOME", self.dir.path())
+            .env("XDG_STATE_HOME", self.path("state"))
+            .env("MOCK_DIR", self.dir.path())
+            .env("MOCK_REMOTE_PATH", remote_path);
+        cmd
+    }
+
+    fn run(&self, args: &[&str], env: &[(&str, &str)]) -> Output {
+        self.command()
+            .args(["--remote", REMOTE, "--credential-helper", HELPER])
+            .args(args)
+            .envs(env.iter().copied())
+            .output()
+            .unwrap()
+    }
+
+    fn subcommand(&self, args: &[&str]) -> String {
+        let output = self.command().args(args).output().unwrap();
+        assert!(output.status.success(), "{}", stderr(&output));
+        stdout(&output)
+    }
+
+    fn log(&self) -> Vec<String> {
+        fs::read_to_string(self.path("log"))
+            .unwrap_or_default()
+            .lines()
+            .map(String::from)
+            .collect()
+    }
+
+    /// The logged run of the local helper's `login`, which may be interleaved with remote commands.
+    fn login(&self) -> String {
+        let prefix = format!("local {HELPER} login");
+        self.log()
+            .into_iter()
+            .find(|l| l.starts_with(&prefix))
+            .unwrap_or_default()
+    }
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+#[test]
+fn nothing_to_do() {
+    let h = Harness::new();
+    h.set("local-state", "valid").set("remote-state", "valid");
+    let output = h.run(&["-C", "devbox"], &[]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(
+        stdout(&output),
+        "Credential refresh not needed. Have a nice day.\n"
+    );
+    // The local and remote checks run at once, so only each side's own order is fixed.
+    let (local, remote): (Vec<_>, Vec<_>) =
+        h.log().into_iter().partition(|l| l.starts_with("local "));
+    assert_eq!(local, [format!("local {HELPER} get")]);
+    assert_eq!(
+        remote,
+        [
+            "ssh -- devbox true".to_string(),
+            format!("ssh {SSH_OPTS} -- devbox {HELPER} get"),
+            format!("remote {HELPER} get"),
+        ]
+    );
+}
+
+#[test]
+fn remaining_lifetime_is_shown() {
+    let h = Harness::new();
+    let expires = SystemTime::now() + Duration::from_secs(2 * 3600 + 30 * 60 + 30);
+    h.set("local-state", "valid")
+        .set("remote-state", "valid")
+        .set(
+            "remote-expires",
+            &humantime::format_rfc3339_seconds(expires).to_string(),
+        );
+    let output = h.run(&["-C", "devbox"], &[]);
+    assert_eq!(
+        stdout(&output),
+        "Credential refresh not needed. It expires in 2h 30m. Have a nice day.\n"
+    );
+
+    // With more runway asked for than that, the credential is refreshed instead.
+    let output = h.run(
+        &["--ci", "-C", "--min-validity=3h", "devbox"],
+        &[("ASPECT_REAUTH_CREDENTIAL", "hunter2")],
+    );
+    assert!(output.status.success(), "{}", stdout(&output));
+    assert!(stdout(&output).contains(r#""status":"synced""#));
+    let dry_run = stdout(&h.run(&["--dry-run", "--min-validity=3h", "devbox"], &[]));
+    assert!(
+        dry_run.contains(
+            "# Sync the credential to the remote, since the remote credential is stale or expires \
+             within 3h"
+        ),
+        "{dry_run}"
+    );
+}
+
+#[test]
+fn color_only_when_asked_for_off_a_terminal() {
+    let h = Harness::new();
+    h.set("ssh-fail", "");
+    let output = h.run(&["-C", "devbox"], &[]);
+    assert!(
+        stderr(&output).starts_with("Error: "),
+        "{:?}",
+        stderr(&output)
+    );
+    let output = h.run(&["-C", "--color=always", "devbox"], &[]);
+    assert!(
+        stderr(&output).starts_with("\x1b[1m\x1b[31mError:\x1b[0m "),
+        "{:?}",
+        stderr(&output)
+    );
+    let output = h.run(
+        &["-C", "--color=never", "devbox"],
+        &[("CLICOLOR_FORCE", "1")],
+    );
+    assert!(
+        stderr(&output).starts_with("Error: "),
+        "{:?}",
+        stderr(&output)
+    );
+}
+
+#[test]
+fn command_runs_after_the_sync() {
+    let h = Harness::new();
+    h.set("local-state", "valid").set("remote-state", "valid");
+    let output = h.run(&["-C", "devbox", "--", "echo", "hello"], &[]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "hello\n");
+    assert!(stderr(&output).contains("Credential refresh not needed."));
+    assert_eq!(
+        h.log().last().unwrap(),
+        "ssh -oForwardAgent=no -- devbox echo hello"
+    );
+
+    let output = h.run(&["-C", "--forward-agent", "devbox", "--", "exit", "7"], &[]);
+    assert_eq!(output.status.code(), Some(7));
+    assert!(stderr(&output).contains("forwarding your ssh agent to devbox"));
+    assert_eq!(
+        h.log().last().unwrap(),
+        "ssh -oForwardAgent=yes -- devbox exit 7"
+    );
+}
+
+#[test]
+fn dry_run_checks_but_changes_nothing() {
+    let h = Harness::new();
+    h.set("local-state", "valid").set("remote-state", "valid");
+    let dry_run = stdout(&h.run(&["--dry-run", "-C", "devbox"], &[]));
+    assert!(
+        dry_run.contains("# Stop there, since neither credential needs a refresh"),
+        "{dry_run}"
+    );
+
+    // The checks run for real, and only what follows from them is planned.
+    h.set("remote-state", "expired");
+    fs::remove_file(h.path("log")).unwrap();
+    let dry_run = stdout(&h.run(&["--dry-run", "-C", "devbox"], &[]));
+    assert!(!dry_run.contains(" login "), "{dry_run}");
+    assert!(dry_run.contains("devbox keyctl padd user"), "{dry_run}");
+    let log = h.log();
+    assert!(log.contains(&format!("local {HELPER} get")), "{log:?}");
+    assert!(log.contains(&format!("remote {HELPER} get")), "{log:?}");
+    assert!(
+        log.contains(
+            &"remote keyctl search @u user keyring-rs:aw-remote.example@AspectWorkflows"
+                .to_string()
+        ),
+        "{log:?}"
+    );
+    assert!(!log.iter().any(|line| line.contains("padd")), "{log:?}");
+    assert!(!h.path("remote-key").exists());
+
+    // With only the helper's keychain entry, the copy into ours is planned, not made.
+    fs::create_dir(h.path("keychain")).unwrap();
+    h.set(&format!("keychain/AspectWorkflows@{REMOTE}"), "hunter2");
+    let keychain = format!("--keychain-dir={}", h.path("keychain").display());
+    let output = h.run(&["--dry-run", "-C", &keychain, "devbox"], &[]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let dry_run = stdout(&output);
+    assert!(
+        dry_run.contains("would copy the helper's credential into the keychain"),
+        "{dry_run}"
+    );
+    assert!(!dry_run.contains("hunter2"), "{dry_run}");
+    assert!(!h.path(&format!("keychain/aspect-reauth@{REMOTE}")).exists());
+}
+
+#[test]
+fn source_other_than_the_keychain() {
+    let h = Harness::new();
+    h.set("remote-state", "expired").set("token", "hunter2\n");
+    let source = format!("--source=file:{}", h.path("token").display());
+    let output = h.run(&["-C", &source, "devbox"], &[]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(fs::read_to_string(h.path("remote-key")).unwrap(), "hunter2");
+    // Neither the local helper nor its login is run.
+    assert!(
+        !h.log().iter().any(|l| l.starts_with("local ")),
+        "{:?}",
+        h.log()
+    );
+
+    // In CI mode, it takes the place of the environment.
+    h.set("remote-state", "expired");
+    let output = h.run(
+        &["--ci", "-C", "devbox"],
+        &[
+            ("ASPECT_REAUTH_SOURCE", "env:TOKEN"),
+            ("TOKEN", "hunter3"),
+            ("ASPECT_REAUTH_CREDENTIAL", "hunter4"),
+        ],
+    );
+    assert!(output.status.success(), "{}", stdout(&output));
+    assert_eq!(fs::read_to_string(h.path("remote-key")).unwrap(), "hunter3");
+
+    // A password-store entry is the first line of what `pass show` prints.
+    h.set("remote-state", "expired").set(
+        "bin/pass",
+        "#!/bin/sh\necho \"pass $*\" >>\"$MOCK_DIR/log\"\nprintf 'hunter5\\nurl: x\\n'\n",
+    );
+    fs::set_permissions(h.path("bin/pass"), fs::Permissions::from_mode(0o755)).unwrap();
+    let output = h.run(&["-C", "--source=pass:stairwell/aspect", "devbox"], &[]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(fs::read_to_string(h.path("remote-key")).unwrap(), "hunter5");
+    assert!(h.log().contains(&"pass show stairwell/aspect".to_string()));
+
+    h.set("remote-state", "expired");
+    let dry_run = stdout(&h.run(&["--dry-run", "-C", "--source=stdin", "devbox"], &[]));
+    assert!(
+        dry_run.contains("(read the credential from --source=stdin)"),
+        "{dry_run}"
+    );
+    assert!(!dry_run.contains("Check the local credential"), "{dry_run}");
+}
+
+#[test]
+fn encrypted_file_sources() {
+    let h = Harness::new();
+    // Each "decrypts" the file by printing it, after logging how it was run.
+    for cli in ["age", "gpg"] {
+        h.set(
+            &format!("bin/{cli}"),
+            &format!(
+                "#!/bin/sh\necho \"{cli} $*\" >>\"$MOCK_DIR/log\"\n\
+                 for last; do :; done\ncat \"$last\"\n"
+            ),
+        );
+        fs::set_permissions(
+            h.path(&format!("bin/{cli}")),
+            fs::Permissions::from_mode(0o755),
+        )
+        .unwrap();
+    }
+    h.set("cred.age", "hunter2\n").set("cred.gpg", "hunter3\n");
+    let age = h.path("cred.age");
+    let gpg = h.path("cred.gpg");
+
+    let source = format!("--source=age:{}", age.display());
+    let output = h.run(&["--ci", "-C", &source, "devbox"], &[]);
+    assert!(output.status.success(), "{}", stdout(&output));
+    assert_eq!(fs::read_to_string(h.path("remote-key")).unwrap(), "hunter2");
+    assert!(
+        h.log()
+            .contains(&format!("age --decrypt {}", age.display()))
+    );
+
+    // The identity to decrypt with can be given, and a path relative to home is expanded.
+    h.set("remote-state", "expired");
+    let output = h.run(
+        &["--ci", "-C", "--source=age:~/cred.age", "devbox"],
+        &[("ASPECT_REAUTH_AGE_IDENTITY", "/keys.txt")],
+    );
+    assert!(output.status.success(), "{}", stdout(&output));
+    assert!(h.log().contains(&format!(
+        "age --decrypt --identity /keys.txt {}",
+        age.display()
+    )));
+
+    h.set("remote-state", "expired");
+    let source = format!("--source=gpg:{}", gpg.display());
+    let output = h.run(&["--ci", "-C", &source, "devbox"], &[]);
+    assert!(output.status.success(), "{}", stdout(&output));
+    assert_eq!(fs::read_to_string(h.path("remote-key")).unwrap(), "hunter3");
+    assert!(
+        h.log()
+            .contains(&format!("gpg --quiet --decrypt {}", gpg.display()))
+    );
+}
+
+#[test]
+fn cloud_secret_sources() {
+    let h = Harness::new();
+    // Each prints its arguments as the secret, less the part that is the same every time.
+    for (cli, fixed) in [
+        ("gcloud", "secrets versions access "),
+        ("aws", "secretsmanager get-secret-value "),
+    ] {
+        h.set(
+            &format!("bin/{cli}"),
+            &format!("#!/bin/sh\nargs=\"$*\"\nprintf '%s\\n' \"${{args#{fixed}}}\"\n"),
+        );
+        fs::set_permissions(
+            h.path(&format!("bin/{cli}")),
+            fs::Permissions::from_mode(0o755),
+        )
+        .unwrap();
+    }
+    for (source, secret) in [
+        ("gcp:aspect", "latest --secret=aspect"),
+        (
+            "gcp:projects/p/secrets/aspect",
+            "projects/p/secrets/aspect/versions/latest",
+        ),
+        (
+            "gcp:projects/p/secrets/aspect/versions/3",
+            "projects/p/secrets/aspect/versions/3",
+        ),
+        (
+            "aws:prod/aspect",
+            "--secret-id prod/aspect --query SecretString --output text",
+        ),
+    ] {
+        h.set("remote-state", "expired");
+        let output = h.run(
+            &["--ci", "-C", "devbox"],
+            &[("ASPECT_REAUTH_SOURCE", source)],
+        );
+        assert!(output.status.success(), "{source}: {}", stdout(&output));
+        assert_eq!(fs::read_to_string(h.path("remote-key")).unwrap(), secret);
+    }
+}
+
+#[test]
+fn json_output() {
+    let h = Harness::new();
+    let expires = "2099-01-01T00:00:00Z";
+    h.set("local-state", "valid")
+        .set("remote-state", "valid")
+        .set("remote-expires", expires);
+    let output = h.run(&["-C", "--output=json", "devbox"], &[]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["host"], "devbox");
+    assert_eq!(report["remote"], REMOTE);
+    assert_eq!(
+        report["key_name"],
+        format!("keyring-rs:{REMOTE}@AspectWorkflows")
+    );
+    assert_eq!(report["status"], "valid");
+    assert_eq!(report["logged_in"], false);
+    assert_eq!(report["expires"], expires);
+    assert!(report["duration"].as_f64().unwrap() > 0.0, "{report}");
+    assert!(stderr(&output).contains("Have a nice day."));
+
+    h.set("local-state", "expired").set("login-fail", "");
+    let output = h.run(&["-C", "--output=json", "devbox"], &[]);
+    assert_eq!(output.status.code(), Some(7));
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["status"], "error");
+    assert!(
+        report["error"].as_str().unwrap().contains("login"),
+        "{report}"
+    );
+}
+
+#[test]
+fn login_failure_is_reported() {
+    let h = Harness::new();
+    h.set("login-fail", "");
+    let output = h.run(&["-C", "devbox"], &[]);
+    assert_eq!(output.status.code(), Some(7));
+    assert!(
+        stderr(&output).contains(&format!("{HELPER} login: exit status: 1")),
+        "{}",
+        stderr(&output)
+    );
+    // The remote is checked while the login runs, but nothing is stored.
+    let log = h.log();
+    assert_eq!(log[0], "ssh -- devbox true");
+    for line in [
+        format!("local {HELPER} get"),
+        format!("remote {HELPER} get"),
+        format!("local {HELPER} login {REMOTE}"),
+    ] {
+        assert!(log.contains(&line), "{log:?}");
+    }
+    assert!(!log.iter().any(|l| l.contains("padd")), "{log:?}");
+}
+
+#[test]
+fn login_timeout() {
+    let h = Harness::new();
+    h.set("login-hang", "");
+    let start = std::time::Instant::now();
+    let output = h.run(&["-C", "--login-timeout=1s", "devbox"], &[]);
+    assert!(start.elapsed().as_secs() < 30);
+    assert_eq!(output.status.code(), Some(5));
+    assert!(
+        stderr(&output).contains("login did not finish within 1s"),
+        "{}",
+        stderr(&output)
+    );
+    // Nothing is synced after an abandoned login.
+    let log = h.log();
+    assert!(
+        log.contains(&format!("local {HELPER} login {REMOTE}")),
+        "{log:?}"
+    );
+    assert!(!log.iter().any(|l| l.contains("padd")), "{log:?}");
+}
+
+#[test]
+fn keyring_is_checked_during_login() {
+    let h = Harness::new();
+    h.set("keyring-unavailable", "");
+    let output = h.run(&["-C", "devbox"], &[]);
+    assert!(!output.status.success());
+    let log = h.log();
+    assert!(
+        log.contains(&format!("local {HELPER} login {REMOTE}")),
+        "{log:?}"
+    );
+    assert!(
+        log.contains(&"remote keyctl rdescribe @u".into()),
+        "{log:?}"
+    );
+    assert!(!log.iter().any(|l| l.contains("padd")), "{log:?}");
+}
+
+#[test]
+fn helper_args_are_passed_to_login() {
+    let h = Harness::new();
+    h.set("login-fail", "");
+    h.set("config.toml", "helper_args = [\"--tenant=eng\"]\n");
+    let config = h.path("config.toml");
+    let config_env = [("ASPECT_REAUTH_CONFIG", config.to_str().unwrap())];
+
+    h.run(&["-C", "devbox"], &config_env);
+    assert_eq!(
+        h.login(),
+        format!("local {HELPER} login {REMOTE} --tenant=eng")
+    );
+
+    fs::remove_file(h.path("log")).unwrap();
+    h.run(
+        &["-C", "--helper-arg", "--no-browser", "devbox"],
+        &config_env,
+    );
+    assert_eq!(
+        h.login(),
+        format!("local {HELPER} login {REMOTE} --no-browser")
+    );
+}
+
+#[test]
+fn ssh_args_from_config() {
+    let h = Harness::new();
+    h.set("local-state", "valid").set("remote-state", "valid");
+    h.set(
+        "config.toml",
+        "ssh_args = [\"-J bastion\", \"-p\", \"23\"]\n",
+    );
+    let config = h.path("config.toml");
+    let config_env = [("ASPECT_REAUTH_CONFIG", config.to_str().unwrap())];
+
+    h.run(&["-C", "devbox"], &config_env);
+    assert_eq!(h.log()[0], "ssh -J bastion -p 23 -- devbox true");
+
+    // Flags replace the config file's arguments rather than adding to them.
+    fs::remove_file(h.path("log")).unwrap();
+    h.run(&["-C", "--ssh-arg=-p 24", "devbox"], &config_env);
+    assert_eq!(h.log()[0], "ssh -p 24 -- devbox true");
+}
+
+#[test]
+fn keychain_names_from_flag_and_config() {
+    let h = Harness::new();
+    let dry_run = stdout(&h.run(&["--dry-run", "-C", "devbox"], &[]));
+    assert!(
+        dry_run.contains(&format!(
+            "read keychain entry AspectWorkflows/{REMOTE} and copy it to aspect-reauth/{REMOTE}"
+        )),
+        "{dry_run}"
+    );
+
+    h.set("config.toml", "keyring_service = \"AspectFork\"\n");
+    let config = h.path("config.toml");
+    let env = [("ASPECT_REAUTH_CONFIG", config.to_str().unwrap())];
+    let dry_run = stdout(&h.run(&["--dry-run", "-C", "--keyring-account=me", "devbox"], &env));
+    assert!(
+        dry_run.contains("read keychain entry AspectFork/me and copy it to aspect-reauth/me"),
+        "{dry_run}"
+    );
+    assert!(
+        dry_run.contains("(read keychain entry aspect-reauth/me)"),
+        "{dry_run}"
+    );
+}
+
+#[test]
+fn login_env_merges_over_config() {
+    let h = Harness::new();
+    h.set(
+        "config.toml",
+        "[login_env]\nBROWSER = \"lynx\"\nHTTPS_PROXY = \"http://proxy:3128\"\n",
+    );
+    let config = h.path("config.toml");
+    let output = h.run(
+        &[
+            "--dry-run",
+            "-C",
+            "--login-env",
+            "BROWSER=firefox",
+            "devbox",
+        ],
+        &[("ASPECT_REAUTH_CONFIG", config.to_str().unwrap())],
+    );
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(
+        stdout(&output).contains(&format!(
+            "  env BROWSER=firefox HTTPS_PROXY=http://proxy:3128 {HELPER} login {REMOTE}\n"
+        )),
+        "{}",
+        stdout(&output)
+    );
+
+    let output = h.run(&["--dry-run", "--login-env", "=x", "devbox"], &[]);
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn docker_hub_is_looked_up_by_its_index_url() {
+    let h = Harness::new();
+    fs::create_dir(h.path("docker")).unwrap();
+    h.set("local-state", "valid").set("remote-state", "valid");
+    h.set("docker/config.json", r#"{"credsStore":"test"}"#)
+        .set(
+            "bin/docker-credential-test",
+            "#!/bin/sh\necho \"docker-credential-test $* $(cat)\" >>\"$MOCK_DIR/log\"\n\
+             printf '{\"Username\":\"me\",\"Secret\":\"hunter2\"}'\n",
+        )
+        .set(
+            "remote-bin/docker",
+            "#!/bin/sh\necho \"remote docker $* $(cat)\" >>\"$MOCK_DIR/log\"\n",
+        );
+    for bin in ["bin/docker-credential-test", "remote-bin/docker"] {
+        fs::set_permissions(h.path(bin), fs::Permissions::from_mode(0o755)).unwrap();
+    }
+    let docker_config = h.path("docker");
+    let output = h.run(
+        &["-C", "--docker-registry=docker.io", "devbox"],
+        &[("DOCKER_CONFIG", docker_config.to_str().unwrap())],
+    );
+    assert!(output.status.success(), "{}", stderr(&output));
+    let log = h.log();
+    assert!(
+        log.contains(&"docker-credential-test get https://index.docker.io/v1/".to_string()),
+        "{log:?}"
+    );
+    assert!(
+        log.contains(
+            &"remote docker login --username me --password-stdin docker.io hunter2".to_string()
+        ),
+        "{log:?}"
+    );
+}
+
+#[test]
+fn git_credential_needs_a_helper_on_the_host() {
+    let h = Harness::new();
+    h.set("local-state", "valid").set("remote-state", "valid");
+    h.set(
+        "bin/git",
+        "#!/bin/sh\ncat >/dev/null\nprintf 'protocol=https\\nhost=github.com\\nusername=me\\n\
+         password=hunter2\\n'\n",
+    )
+    .set(
+        "remote-bin/git",
+        "#!/bin/sh\nif test \"$1\" = config; then cat \"$MOCK_DIR/git-helper\" 2>/dev/null || \
+         exit 1; else echo \"remote git $*\" >>\"$MOCK_DIR/log\"; fi\n",
+    );
+    for bin in ["bin/git", "remote-bin/git"] {
+        fs::set_permissions(h.path(bin), fs::Permissions::from_mode(0o755)).unwrap();
+    }
+    let output = h.run(&["-C", "--git-host=github.com", "devbox"], &[]);
+    assert!(!output.status.success());
+    assert!(
+        stderr(&output).contains("git has no credential.helper configured"),
+        "{}",
+        stderr(&output)
+    );
+    assert!(
+        !h.log()
+            .contains(&"remote git credential approve".to_string())
+    );
+
+    h.set("git-helper", "store\n");
+    let output = h.run(&["-C", "--git-host=github.com", "devbox"], &[]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("Git credentials synced"));
+    assert!(
+        h.log()
+            .contains(&"remote git credential approve".to_string())
+    );
+}
+
+#[test]
+fn ci_sync_writes_the_key() {
+    let h = Harness::new();
+    let output = h.run(
+        &["--ci", "-C", "devbox"],
+        &[("ASPECT_REAUTH_CREDENTIAL", "hunter2")],
+    );
+    assert!(output.status.success(), "{}", stdout(&output));
+    assert_eq!(
+        stdout(&output),
+        format!(
+            "{{\"host\":\"devbox\",\"remote\":\"{REMOTE}\",\"status\":\"synced\",\"key\":123456}}\n"
+        )
+    );
+    assert_eq!(fs::read_to_string(h.path("remote-key")).unwrap(), "hunter2");
+    assert_eq!(
+        h.log(),
+        [
+            "ssh -- devbox true".to_string(),
+            format!("ssh {SSH_OPTS} -- devbox {HELPER} get"),
+            format!("remote {HELPER} get"),
+            format!(
+                "ssh {SSH_OPTS} -- devbox {}",
+                remote_command("sh", &["-c", probe::SCRIPT]).unwrap()
+            ),
+            format!(
+                "ssh {SSH_OPTS} -- devbox keyctl search @u user keyring-rs:{REMOTE}@AspectWorkflows"
+            ),
+            format!("remote keyctl search @u user keyring-rs:{REMOTE}@AspectWorkflows"),
+            format!(
+                "ssh {SSH_OPTS} -- devbox keyctl padd user keyring-rs:{REMOTE}@AspectWorkflows @u"
+            ),
+            format!("remote keyctl padd user keyring-rs:{REMOTE}@AspectWorkflows @u"),
+            // With no --key-perm, the kernel's permissions are left alone.
+            format!("ssh {SSH_OPTS} -- devbox {HELPER} get"),
+            format!("remote {HELPER} get"),
+        ]
+    );
+
+    // Now that the key exists, it is updated in place.
+    h.set("remote-state", "expired").set("log", "");
+    let output = h.run(
+        &["--ci", "-C", "devbox"],
+        &[("ASPECT_REAUTH_CREDENTIAL", "hunter3")],
+    );
+    assert!(output.status.success(), "{}", stdout(&output));
+    assert_eq!(fs::read_to_string(h.path("remote-key")).unwrap(), "hunter3");
+    assert!(
+        h.log()
+            .contains(&"remote keyctl pupdate 123456".to_string())
+    );
+    // Its permissions are left as they were.
+    assert!(!h.log().iter().any(|l| l.contains("setperm")));
+}
+
+#[test]
+fn key_perm_from_flag_and_config() {
+    let h = Harness::new();
+    h.set("remote-state", "expired");
+    h.set("config.toml", "key_perm = 0x3f3f0000\n");
+    let config = h.path("config.toml");
+    let env = [
+        ("ASPECT_REAUTH_CREDENTIAL", "hunter2"),
+        ("ASPECT_REAUTH_CONFIG", config.to_str().unwrap()),
+    ];
+    let output = h.run(&["--ci", "-C", "devbox"], &env);
+    assert!(output.status.success(), "{}", stdout(&output));
+    assert!(
+        h.log()
+            .contains(&"remote keyctl setperm 123456 0x3f3f0000".to_string())
+    );
+
+    let _ = fs::remove_file(h.path("remote-key"));
+    h.set("remote-state", "expired").set("log", "");
+    let output = h.run(&["--ci", "-C", "--key-perm=3f000000", "devbox"], &env);
+    assert!(output.status.success(), "{}", stdout(&output));
+    assert!(
+        h.log()
+            .contains(&"remote keyctl setperm 123456 0x3f000000".to_string())
+    );
+
+    let output = h.run(&["--ci", "-C", "--key-perm=rwx", "devbox"], &env);
+    assert!(!output.status.success());
+    assert!(
+        stderr(&output).contains("expected a hex mask"),
+        "{}",
+        stderr(&output)
+    );
+
+    // With neither, there is no setperm, in one round trip or several.
+    let _ = fs::remove_file(h.path("remote-key"));
+    h.set("config.toml", "")
+        .set("remote-state", "expired")
+        .set("log", "");
+    let output = h.run(&["--ci", "-C", "devbox"], &env);
+    assert!(output.status.success(), "{}", stdout(&output));
+    assert!(h.path("remote-key").exists());
+    assert!(
+        !h.log().iter().any(|l| l.contains("setperm")),
+        "{:?}",
+        h.log()
+    );
+    let _ = fs::remove_file(h.path("remote-key"));
+    h.set("remote-state", "expired").set("log", "");
+    let output = h.run(&["--ci", "-C", "--single-round-trip", "devbox"], &env);
+    assert!(output.status.success(), "{}", stdout(&output));
+    assert!(h.path("remote-key").exists());
+    assert!(
+        !h.log().iter().any(|l| l.contains("setperm")),
+        "{:?}",
+        h.log()
+    );
+    let help = stdout(&h.run(&["--help"], &[]));
+    assert!(
+        help.contains("keeps keyctl's default permissions"),
+        "{help}"
+    );
+}
+
+#[test]
+fn file_sink_writes_a_private_file() {
+    let h = Harness::new();
+    fs::remove_file(h.path("remote-bin").join("keyctl")).unwrap();
+    let runtime = h.path("run");
+    let env = [
+        ("ASPECT_REAUTH_CREDENTIAL", "hunter2"),
+        ("XDG_RUNTIME_DIR", runtime.to_str().unwrap()),
+    ];
+    let output = h.run(&["--ci", "-C", "--sink=file", "devbox"], &env);
+    assert!(output.status.success(), "{}", stdout(&output));
+    assert!(stdout(&output).contains(r#""status":"synced""#));
+    let file = runtime.join("aspect").join(REMOTE);
+    assert_eq!(fs::read_to_string(&file).unwrap(), "hunter2");
+    let mode = fs::metadata(&file).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o600);
+    assert!(
+        !h.log().iter().any(|l| l.starts_with("remote keyctl")),
+        "{:?}",
+        h.log()
+    );
+
+    let output = h
+        .command()
+        .args(["logout", "-y", "devbox"])
+        .env("ASPECT_REMOTE", REMOTE)
+        .env("ASPECT_REAUTH_SINK", "file")
+        .envs(env)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(
+        stdout(&output).contains("Removed the credential file on devbox.\n"),
+        "{}",
+        stdout(&output)
+    );
+    assert!(!file.exists());
+}
+
+#[test]
+fn missing_keyctl_is_reported() {
+    let h = Harness::new();
+    fs::remove_file(h.path("remote-bin").join("keyctl")).unwrap();
+    let runtime = h.path("run");
+    let env = [
+        ("ASPECT_REAUTH_CREDENTIAL", "hunter2"),
+        ("XDG_RUNTIME_DIR", runtime.to_str().unwrap()),
+    ];
+    for args in [
+        &["--ci", "-C", "devbox"][..],
+        &["--ci", "-C", "--single-round-trip", "devbox"],
+    ] {
+        let output = h.run(args, &env);
+        assert_eq!(output.status.code(), Some(8), "{}", stdout(&output));
+        let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        let error = report["error"].as_str().unwrap();
+        // Found by the probe, before any keyctl is run.
+        assert!(
+            error.starts_with("devbox lacks keyutils; try --sink=file"),
+            "{error}"
+        );
+        assert!(
+            !h.log().iter().any(|l| l.starts_with("remote keyctl")),
+            "{:?}",
+            h.log()
+        );
+    }
+}
+
+#[test]
+fn missing_keyctl_falls_back_to_a_file() {
+    let h = Harness::new();
+    fs::remove_file(h.path("remote-bin").join("keyctl")).unwrap();
+    let runtime = h.path("run");
+    let env = [
+        ("ASPECT_REAUTH_CREDENTIAL", "hunter2"),
+        ("XDG_RUNTIME_DIR", runtime.to_str().unwrap()),
+    ];
+    let file = runtime.join("aspect").join(REMOTE);
+    for args in [
+        &["--ci", "-C", "--file-fallback", "devbox"][..],
+        &[
+            "--ci",
+            "-C",
+            "--file-fallback",
+            "--single-round-trip",
+            "devbox",
+        ],
+    ] {
+        _ = fs::remove_file(&file);
+        let output = h.run(args, &env);
+        assert!(output.status.success(), "{}", stdout(&output));
+        assert!(stdout(&output).contains(r#""status":"synced""#));
+        assert_eq!(fs::read_to_string(&file).unwrap(), "hunter2");
+        assert!(
+            stderr(&output).contains("devbox has no keyctl"),
+            "{}",
+            stderr(&output)
+        );
+        assert!(
+            stderr(&output).contains(r#"export ASPECT_CREDENTIAL_FILE="${XDG_RUNTIME_DIR:-"#),
+            "{}",
+            stderr(&output)
+        );
+    }
+
+    // A check looks in the file too, as a sync would before writing it again.
+    // {"alg":"none"}.{"sub":"me","exp":2000000000}.
+    let jwt = "eyJhbGciOiJub25lIn0.eyJzdWIiOiJtZSIsImV4cCI6MjAwMDAwMDAwMH0.";
+    fs::write(&file, jwt).unwrap();
+    let output = h.run(&["--ci", "--check", "--file-fallback", "devbox"], &env);
+    assert_eq!(output.status.code(), Some(0), "{}", stdout(&output));
+    assert!(stdout(&output).contains(r#""status":"valid""#));
+    fs::remove_file(&file).unwrap();
+    let output = h.run(&["--ci", "--check", "--file-fallback", "devbox"], &env);
+    assert_eq!(output.status.code(), Some(3), "{}", stdout(&output));
+}
+
+#[test]
+fn exit_code_reports_a_sync() {
+    let h = Harness::new();
+    let args = ["--ci", "-C", "--report-action-in-exit-code"];
+    let env = [("ASPECT_REAUTH_CREDENTIAL", "hunter2")];
+    assert_eq!(h.run(&args, &env).status.code(), Some(4));
+    assert_eq!(h.run(&args, &env).status.code(), Some(0));
+    // Without the flag, a sync is still a success.
+    h.set("remote-state", "expired");
+    assert_eq!(h.run(&args[..2], &env).status.code(), Some(0));
+    assert_eq!(fs::read_to_string(h.path("remote-state")).unwrap(), "valid");
+}
+
+#[test]
+fn options_are_remembered_per_host() {
+    let h = Harness::new();
+    h.set("local-state", "valid").set("remote-state", "valid");
+    let output = h.run(
+        &["-C", "--ssh-arg=-p 23", "--keyring=session", "devbox"],
+        &[],
+    );
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    h.set("remote-state", "expired");
+    let dry_run = stdout(&h.run(&["--dry-run", "-C", "devbox"], &[]));
+    assert!(dry_run.contains("ssh -p 23 -- devbox true"), "{dry_run}");
+    assert!(dry_run.contains(" @s <<<"), "{dry_run}");
+    let dry_run = stdout(&h.run(&["--dry-run", "-C", "otherbox"], &[]));
+    assert!(dry_run.contains("ssh -- otherbox true"), "{dry_run}");
+
+    h.set("remote-state", "valid");
+    let output = h.run(&["-C", "--no-remember", "devbox"], &[]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let dry_run = stdout(&h.run(&["--dry-run", "-C", "devbox"], &[]));
+    assert!(dry_run.contains("ssh -- devbox true"), "{dry_run}");
+}
+
+#[test]
+fn host_tables_override_the_global_config() {
+    let h = Harness::new();
+    h.set(
+        "config.toml",
+        &format!(
+            "remote = \"global.example\"\n\
+             credential_helper = \"{HELPER}\"\n\
+             ssh_args = [\"-p 23\"]\n\
+             \n\
+             [hosts.devbox-eu]\n\
+             remote = \"eu.example\"\n\
+             ssh_args = [\"-J bastion\"]\n\
+             keyring = \"session\"\n"
+        ),
+    );
+    let config = h.path("config.toml");
+    let ci = |host: &str, env: &[(&str, &str)]| {
+        let _ = fs::remove_file(h.path("log"));
+        h.set("remote-state", "expired");
+        let output = h
+            .command()
+            .args(["--ci", "-C", host])
+            .env("ASPECT_REAUTH_CONFIG", &config)
+            .env("ASPECT_REAUTH_CREDENTIAL", "hunter2")
+            .envs(env.iter().copied())
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{}", stdout(&output));
+        stdout(&output)
+    };
+
+    let out = ci("devbox-eu", &[]);
+    assert!(out.contains(r#""remote":"eu.example""#), "{out}");
+    let log = h.log();
+    assert_eq!(log[0], "ssh -J bastion -- devbox-eu true");
+    assert!(
+        log.contains(&"remote keyctl search @s user keyring-rs:eu.example@AspectWorkflows".into()),
+        "{log:?}"
+    );
+
+    let out = ci("devbox", &[]);
+    assert!(out.contains(r#""remote":"global.example""#), "{out}");
+    assert_eq!(h.log()[0], "ssh -p 23 -- devbox true");
+
+    // The environment still wins over the host's table.
+    let out = ci("devbox-eu", &[("ASPECT_REMOTE", REMOTE)]);
+    assert!(out.contains(&format!(r#""remote":"{REMOTE}""#)), "{out}");
+}
+
+#[test]
+fn several_keyrings() {
+    let h = Harness::new();
+    h.set("config.toml", "keyring = [\"user\", \"session\"]\n");
+    let config = h.path("config.toml");
+    let output = h.run(
+        &["--ci", "-C", "devbox"],
+        &[
+            ("ASPECT_REAUTH_CREDENTIAL", "hunter2"),
+            ("ASPECT_REAUTH_CONFIG", config.to_str().unwrap()),
+        ],
+    );
+    assert!(output.status.success(), "{}", stdout(&output));
+    let keyctl: Vec<_> = h
+        .log()
+        .into_iter()
+        .filter(|line| line.starts_with("remote keyctl"))
+        .collect();
+    let key_name = format!("keyring-rs:{REMOTE}@AspectWorkflows");
+    assert_eq!(
+        keyctl,
+        [
+            format!("remote keyctl search @u user {key_name}"),
+            format!("remote keyctl padd user {key_name} @u"),
+            format!("remote keyctl padd user {key_name} @s"),
+        ]
+    );
+
+    // The flag can be repeated too, and -s adds the session keyring.
+    h.set("remote-state", "expired");
+    let dry_run = stdout(&h.run(
+        &["--dry-run", "-C", "-s", "--keyring=persistent", "devbox"],
+        &[],
+    ));
+    assert!(
+        dry_run.contains("devbox keyctl get_persistent @s\n"),
+        "{dry_run}"
+    );
+    for keyring in ["PERSISTENT_KEYRING", "@s"] {
+        assert!(
+            dry_run.contains(&format!(
+                "devbox keyctl padd user {key_name} {keyring} <<< '<credential>'\n"
+            )),
+            "{dry_run}"
+        );
+    }
+
+    // With --single-round-trip, it all goes as the one script.
+    let dry_run = stdout(&h.run(&["--dry-run", "-C", "--single-round-trip", "devbox"], &[]));
+    assert!(!dry_run.contains("devbox keyctl padd"), "{dry_run}");
+    assert!(dry_run.contains("echo run"), "{dry_run}");
+}
+
+#[test]
+fn batch_runs_each_job() {
+    let h = Harness::new();
+    h.set("local-state", "valid").set("remote-state", "valid");
+    let mut child = h
+        .command()
+        .args([
+            "--remote",
+            REMOTE,
+            "--credential-helper",
+            HELPER,
+            "--batch",
+            "-C",
+        ])
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .spawn()
+        .unwrap();
+    std::io::Write::write_all(
+        &mut child.stdin.take().unwrap(),
+        br#"{"jobs": [
+            {"host": "devbox", "ssh_args": ["-p 23"]},
+            {"host": "otherbox", "key_name": "{nope}"}
+        ]}"#,
+    )
+    .unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        stdout(&output),
+        format!(
+            "{{\"jobs\":[\
+             {{\"host\":\"devbox\",\"remote\":\"{REMOTE}\",\"status\":\"valid\"}},\
+             {{\"host\":\"otherbox\",\"remote\":\"{REMOTE}\",\"status\":\"error\",\
+             \"error\":\"invalid key_name: unknown placeholder {{nope}} in key name template\"}}]}}\n"
+        )
+    );
+    assert_eq!(h.log()[0], "ssh -p 23 -- devbox true");
+}
+
+#[test]
+fn key_name_template() {
+    let h = Harness::new();
+    let output = h.run(
+        &[
+            "--ci",
+            "-C",
+            "--key-name",
+            "{user}:{service}:{remote}",
+            "devbox",
+        ],
+        &[("ASPECT_REAUTH_CREDENTIAL", "hunter2"), ("USER", "alice")],
+    );
+    assert!(output.status.success(), "{}", stdout(&output));
+    assert!(
+        h.log().contains(&format!(
+            "remote keyctl padd user alice:AspectWorkflows:{REMOTE} @u"
+        )),
+        "{:?}",
+        h.log()
+    );
+
+    // Two host tables that reach the same devbox each get a key of their own.
+    h.set(
+        "config.toml",
+        "key_name = \"{remote}@{profile}\"\n\
+         [hosts.patched]\n\
+         key_name = \"patched:{remote}\"\n",
+    );
+    let config = h.path("config.toml");
+    for host in ["devbox", "patched"] {
+        h.set("remote-state", "expired");
+        let output = h.run(
+            &["--ci", "-C", host],
+            &[
+                ("ASPECT_REAUTH_CREDENTIAL", "hunter2"),
+                ("ASPECT_REAUTH_CONFIG", config.to_str().unwrap()),
+            ],
+        );
+        assert!(output.status.success(), "{}", stdout(&output));
+    }
+    let log = h.log();
+    assert!(
+        log.contains(&format!("remote keyctl search @u user {REMOTE}@devbox")),
+        "{log:?}"
+    );
+    assert!(
+        log.contains(&format!("remote keyctl search @u user patched:{REMOTE}")),
+        "{log:?}"
+    );
+
+    let output = h.run(&["--ci", "-C", "--key-name", "{host}", "devbox"], &[]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(
+        stderr(&output).contains("unknown placeholder {host}"),
+        "{}",
+        stderr(&output)
+    );
+}
+
+#[test]
+fn key_name_with_spaces_is_quoted_for_sh() {
+    let h = Harness::new();
+    let output = h.run(
+        &["--ci", "-C", "--key-name", "my {remote}", "devbox"],
+        &[("ASPECT_REAUTH_CREDENTIAL", "hunter2")],
+    );
+    assert!(output.status.success(), "{}", stdout(&output));
+    assert_eq!(fs::read_to_string(h.path("remote-key")).unwrap(), "hunter2");
+    assert!(
+        h.log().contains(&format!(
+            "ssh {SSH_OPTS} -- devbox sh -c '\"keyctl\" \"padd\" \"user\" \"my {REMOTE}\" \"@u\"'"
+        )),
+        "{:?}",
+        h.log()
+    );
+
+    h.set("remote-state", "expired");
+    let output = h.run(
+        &["--ci", "-C", "--key-name", "it's", "devbox"],
+        &[("ASPECT_REAUTH_CREDENTIAL", "hunter2")],
+    );
+    assert_eq!(output.status.code(), Some(1));
+    assert!(
+        stdout(&output).contains("cannot pass \\\"it's\\\" to the remote shell"),
+        "{}",
+        stdout(&output)
+    );
+}
+
+#[test]
+fn ci_keyctl_failure() {
+    let h = Harness::new();
+    h.set("keyctl-fail", "");
+    let output = h.run(
+        &["--ci", "-C", "devbox"],
+        &[("ASPECT_REAUTH_CREDENTIAL", "hunter2")],
+    );
+    assert_eq!(output.status.code(), Some(8));
+    let out = stdout(&output);
+    assert!(out.contains(r#""status":"error""#), "{out}");
+    assert!(out.contains("Permission denied"), "{out}");
+}
+
+#[test]
+fn ci_check_reports_invalid() {
+    let h = Harness::new();
+    let output = h.run(&["--ci", "--check", "-C", "devbox"], &[]);
+    assert_eq!(output.status.code(), Some(3));
+    assert!(stdout(&output).contains(r#""status":"invalid""#));
+    assert!(!h.path("remote-key").exists());
+}
+
+#[test]
+fn ssh_failure() {
+    let h = Harness::new();
+    h.set("ssh-fail", "");
+    let output = h.run(&["-C", "devbox"], &[]);
+    assert_eq!(output.status.code(), Some(6));
+    assert!(
+        stderr(&output).contains("failed setting up ssh session"),
+        "{}",
+        stderr(&output)
+    );
+    assert_eq!(h.log(), ["ssh -- devbox true"]);
+}
+
+#[test]
+fn hyphenated_ssh_arg_value_is_accepted() {
+    let h = Harness::new();
+    h.set("local-state", "valid").set("remote-state", "valid");
+    let output = h.run(&["-C", "-A", "-p 23", "devbox"], &[]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(h.log()[0], "ssh -p 23 -- devbox true");
+}
+
+#[test]
+fn host_defaults_from_env_then_config() {
+    let h = Harness::new();
+    h.set("local-state", "valid").set("remote-state", "valid");
+    h.set("config.toml", "default_host = \"from-config\"\n");
+    let config = h.path("config.toml");
+    let config = config.to_str().unwrap();
+    let first_line = |args: &[&str], env: &[(&str, &str)]| {
+        let output = h.run(args, env);
+        assert!(output.status.success(), "{}", stderr(&output));
+        let line = h.log()[0].clone();
+        fs::remove_file(h.path("log")).unwrap();
+        line
+    };
+
+    let config_env = ("ASPECT_REAUTH_CONFIG", config);
+    let host_env = ("ASPECT_REAUTH_HOST", "from-env");
+    assert_eq!(first_line(&["-C"], &[]), "ssh -- devbox true");
+    assert_eq!(
+        first_line(&["-C"], &[config_env]),
+        "ssh -- from-config true"
+    );
+    assert_eq!(
+        first_line(&["-C"], &[config_env, host_env]),
+        "ssh -- from-env true"
+    );
+    assert_eq!(
+        first_line(&["-C", "from-arg"], &[config_env, host_env]),
+        "ssh -- from-arg true"
+    );
+}
+
+#[test]
+fn remote_and_helper_from_config() {
+    let h = Harness::new();
+    h.set("login-fail", "");
+    h.set(
+        "config.toml",
+        &format!("remote = \"from-config\"\ncredential_helper = \"{HELPER}\"\n"),
+    );
+    let config = h.path("config.toml");
+    let run = |env: &[(&str, &str)]| {
+        h.command()
+            .args(["-C", "devbox"])
+            .env("ASPECT_REAUTH_CONFIG", &config)
+            .envs(env.iter().copied())
+            .output()
+            .unwrap();
+        let login = h.login();
+        fs::remove_file(h.path("log")).unwrap();
+        login
+    };
+    assert_eq!(run(&[]), format!("local {HELPER} login from-config"));
+    assert_eq!(
+        run(&[("ASPECT_REMOTE", "from-env")]),
+        format!("local {HELPER} login from-env")
+    );
+}
+
+#[test]
+fn configured_hosts_without_a_terminal() {
+    let h = Harness::new();
+    h.set("local-state", "valid").set("remote-state", "valid");
+    let config = h.path("config.toml");
+    let config_env = [("ASPECT_REAUTH_CONFIG", config.to_str().unwrap())];
+
+    h.set("config.toml", "[hosts.only-box]\n");
+    let output = h.run(&["-C"], &config_env);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(h.log()[0], "ssh -- only-box true");
+
+    h.set("config.toml", "[hosts.box-a]\n[hosts.box-b]\n");
+    let output = h.run(&["-C"], &config_env);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(
+        stderr(&output).contains("several hosts are configured (box-a, box-b)"),
+        "{}",
+        stderr(&output)
+    );
+}
+
+#[test]
+fn update_notice_is_opt_in() {
+    let h = Harness::new();
+    h.set("local-state", "valid").set("remote-state", "valid");
+    fs::create_dir_all(h.path("state/aspect-reauth")).unwrap();
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    h.set(
+        "state/aspect-reauth/state.json",
+        &format!(r#"{{"update_check":{{"checked_at":{now},"latest":"999.0.0"}}}}"#),
+    );
+    let config = h.path("config.toml");
+    let config_env = [("ASPECT_REAUTH_CONFIG", config.to_str().unwrap())];
+
+    h.set("config.toml", "");
+    let output = h.run(&["-C", "devbox"], &config_env);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(!stderr(&output).contains("newer"), "{}", stderr(&output));
+
+    h.set("config.toml", "check_for_updates = true\n");
+    let output = h.run(&["-C", "devbox"], &config_env);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(
+        stderr(&output).contains("A newer aspect-reauth is available (999.0.0;"),
+        "{}",
+        stderr(&output)
+    );
+}
+
+#[test]
+fn prompt_status_reflects_the_last_run() {
+    let h = Harness::new();
+    assert_eq!(h.subcommand(&["prompt-status"]), "?\n");
+    h.set("local-state", "valid").set("remote-state", "valid");
+    assert!(h.run(&["-C", "devbox"], &[]).status.success());
+    assert_eq!(h.subcommand(&["prompt-status"]), "✓\n");
+    assert_eq!(h.subcommand(&["prompt-status", "devbox", "--word"]), "ok\n");
+    assert_eq!(
+        h.subcommand(&["prompt-status", "devbox", "--max-age=0s"]),
+        "~\n"
+    );
+    assert_eq!(
+        h.subcommand(&["prompt-status", "other", "--word"]),
+        "unknown\n"
+    );
+
+    h.set("ssh-fail", "");
+    assert!(!h.run(&["-C", "devbox"], &[]).status.success());
+    assert_eq!(h.subcommand(&["prompt-status", "devbox"]), "!\n");
+}
+
+#[test]
+fn suggest_ssh() {
+    let h = Harness::new();
+    assert_eq!(
+        h.subcommand(&["config", "suggest-ssh", "devbox"]),
+        "Add this to ~/.ssh/config, above any other block that matches devbox:\n\n\
+         Host devbox\n    ControlMaster auto\n    ControlPath ~/.ssh/cm-%C\n    \
+         ControlPersist 10m\n"
+    );
+    h.set("ssh-config", "controlmaster auto\ncontrolpath /tmp/cm-%C\n");
+    assert_eq!(
+        h.subcommand(&["config", "suggest-ssh", "devbox"]),
+        "Add this to ~/.ssh/config, above any other block that matches devbox:\n\n\
+         Host devbox\n    ControlPersist 10m\n"
+    );
+    h.set(
+        "ssh-config",
+        "controlmaster auto\ncontrolpath /tmp/cm-%C\ncontrolpersist 600\n",
+    );
+    assert_eq!(
+        h.subcommand(&["config", "suggest-ssh", "devbox"]),
+        "devbox already reuses a persistent master connection; nothing to suggest.\n"
+    );
+}
+
+#[test]
+fn completions_offer_configured_hosts() {
+    let h = Harness::new();
+    assert!(
+        h.subcommand(&["completions", "bash"])
+            .contains("ASPECT_REAUTH_COMPLETE=\"bash\"")
+    );
+
+    fs::create_dir(h.path(".ssh")).unwrap();
+    h.set(
+        ".ssh/config",
+        "Host devbox devbox-eu *.corp\nHost buildbox\n",
+    );
+    h.set(
+        "config.toml",
+        "default_host = \"devbox\"\n[hosts.devbox-us]\nkeyring = \"session\"\n",
+    );
+    let complete = |line: &[&str]| {
+        let output = h
+            .command()
+            .args(["--", "aspect-reauth"])
+            .args(line)
+            .env("ASPECT_REAUTH_COMPLETE", "fish")
+            .env("ASPECT_REAUTH_CONFIG", h.path("config.toml"))
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{}", stderr(&output));
+        stdout(&output)
+    };
+    assert_eq!(
+        complete(&["status", "dev"]),
+        "devbox\ndevbox-eu\ndevbox-us\n"
+    );
+    assert_eq!(complete(&["logout", "b"]), "buildbox\n");
+}
+
+#[test]
+fn yes_confirms_without_a_terminal() {
+    let h = Harness::new();
+    let output = h
+        .command()
+        .args(["config", "suggest-ssh", "--append", "devbox"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(
+        stderr(&output).contains("without a terminal"),
+        "{}",
+        stderr(&output)
+    );
+
+    let output = h
+        .command()
+        .args(["config", "suggest-ssh", "--append", "--yes", "devbox"])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", stderr(&output));
+    let config = fs::read_to_string(h.path(".ssh/config")).unwrap();
+    assert!(
+        config.contains("Host devbox\n    ControlMaster auto\n"),
+        "{config}"
+    );
+}
+
+#[test]
+fn export_token_is_guarded() {
+    let h = Harness::new();
+    let export = |args: &[&str]| {
+        h.command()
+            .arg("export-token")
+            .args(args)
+            .env("ASPECT_REMOTE", REMOTE)
+            .env("ASPECT_CREDENTIAL_HELPER", HELPER)
+            .output()
+            .unwrap()
+    };
+    let output = export(&[]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(
+        stderr(&output).contains("pass --i-understand-the-risk"),
+        "{}",
+        stderr(&output)
+    );
+    assert_eq!(stdout(&output), "");
+    assert!(h.log().is_empty());
+
+    // A stale credential is not logged in to, since the helper would print to the same stdout.
+    h.set("local-state", "expired");
+    let output = export(&["--i-understand-the-risk"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(
+        stderr(&output).contains(&format!("run `{HELPER} login {REMOTE}`")),
+        "{}",
+        stderr(&output)
+    );
+    assert_eq!(stdout(&output), "");
+    assert_eq!(h.log(), [format!("local {HELPER} get")]);
+}
+
+#[test]
+fn prune_removes_leftovers() {
+    let h = Harness::new();
+    let tmp = h.path("tmp");
+    for dir in ["aspect-reauth-dead", "aspect-reauth-starting", "unrelated"] {
+        fs::create_dir_all(tmp.join(dir)).unwrap();
+    }
+    fs::write(tmp.join("aspect-reauth-dead/sock"), "").unwrap();
+    let sockets = h.path("cache/aspect-reauth/sockets");
+    fs::create_dir_all(&sockets).unwrap();
+    fs::write(sockets.join("0123456789abcdef"), "").unwrap();
+    let _live = std::os::unix::net::UnixListener::bind(sockets.join("fedcba9876543210")).unwrap();
+    fs::create_dir_all(h.path("state/aspect-reauth")).unwrap();
+    h.set(
+        "state/aspect-reauth/state.json",
+        &format!(
+            r#"{{"hosts":{{
+                "gone":{{"remote":"{REMOTE}","status":"valid","checked_at":0}},
+                "devbox":{{"remote":"{REMOTE}","status":"valid","checked_at":99999999999}}
+            }},"options":{{"gone":{{"key_name":"x"}}}}}}"#
+        ),
+    );
+    let prune = |args: &[&str]| {
+        let output = h
+            .command()
+            .arg("prune")
+            .args(args)
+            .env("TMPDIR", &tmp)
+            .env("XDG_CACHE_HOME", h.path("cache"))
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{}", stderr(&output));
+        stdout(&output)
+    };
+
+    let dry_run = prune(&["--dry-run"]);
+    assert!(
+        dry_run.contains("Would remove leftover control socket directory"),
+        "{dry_run}"
+    );
+    assert!(
+        dry_run.contains("Would forget gone, last checked"),
+        "{dry_run}"
+    );
+    assert!(
+        dry_run.contains("Would remove stale persistent control socket"),
+        "{dry_run}"
+    );
+    assert!(tmp.join("aspect-reauth-dead").exists());
+
+    let output = prune(&[]);
+    assert!(output.contains("aspect-reauth-dead"), "{output}");
+    assert!(!output.contains("aspect-reauth-starting"), "{output}");
+    assert!(!tmp.join("aspect-reauth-dead").exists());
+    assert!(tmp.join("aspect-reauth-starting").exists());
+    assert!(tmp.join("unrelated").exists());
+    // Only the socket whose master has exited goes.
+    assert!(!sockets.join("0123456789abcdef").exists());
+    assert!(sockets.join("fedcba9876543210").exists());
+    let state = fs::read_to_string(h.path("state/aspect-reauth/state.json")).unwrap();
+    assert!(
+        state.contains("devbox") && !state.contains("gone"),
+        "{state}"
+    );
+
+    assert_eq!(prune(&[]), "Nothing to prune.\n");
+}
+
+#[test]
+fn doctor_shows_the_connection_path() {
+    let h = Harness::new();
+    h.set(
+        "ssh-config",
+        "hostname devbox.internal\nuser alice\nport 22\nproxyjump bastion\n\
+         identityfile /nonexistent/id\ncontrolmaster auto\ncontrolpersist 600\n\
+         controlpath /tmp/cm-%C\n",
+    );
+    let out = h.subcommand(&["doctor", "devbox"]);
+    assert!(
+        out.starts_with(
+            "Connection to devbox:\n\
+             \x20 hostname        devbox.internal\n\
+             \x20 user            alice\n\
+             \x20 port            22\n\
+             \x20 proxy jump      bastion\n\
+             \x20 identity files  /nonexistent/id (missing)\n\
+             \x20 multiplexing    ControlMaster auto, ControlPersist 10m, ControlPath /tmp/cm-%C\n\
+             \x20 aspect-reauth   will share your control master\n\n"
+        ),
+        "{out}"
+    );
+    let checks = out.split_once("Checks:\n").unwrap().1;
+    assert_eq!(
+        checks,
+        format!(
+            "  ssh             {bin}/ssh\n\
+             \x20 local helper    {bin}/{HELPER}\n\
+             \x20 multiplexing    connections are reused\n\
+             \x20 connection      connected to devbox\n\
+             \x20 user keyring    available\n\
+             \x20 remote helper   installed\n",
+            bin = h.path("bin").display()
+        )
+    );
+}
+
+#[test]
+fn doctor_finds_what_is_missing() {
+    let h = Harness::new();
+    h.set("keyring-unavailable", "");
+    fs::remove_file(h.path("remote-bin").join(HELPER)).unwrap();
+    let output = h.command().args(["doctor", "devbox"]).output().unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    let out = stdout(&output);
+    assert!(
+        out.contains("  multiplexing    every run opens a new connection"),
+        "{out}"
+    );
+    assert!(
+        out.contains("  user keyring    not available on devbox"),
+        "{out}"
+    );
+    assert!(
+        out.contains(&format!(
+            "  remote helper   {HELPER} not found on devbox; install it there"
+        )),
+        "{out}"
+    );
+
+    let output = h
+        .command()
+        .args(["doctor", "devbox"])
+        .env("ASPECT_REAUTH_SSH", "/nonexistent/ssh")
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert!(
+        stdout(&output).contains("  ssh             /nonexistent/ssh not found"),
+        "{}",
+        stdout(&output)
+    );
+}
+
+#[test]
+fn status_only_asks() {
+    let h = Harness::new();
+    let status = || {
+        h.command()
+            .args(["status", "devbox"])
+            .env("ASPECT_REMOTE", REMOTE)
+            .output()
+            .unwrap()
+    };
+    let output = status();
+    assert_eq!(output.status.code(), Some(3), "{}", stderr(&output));
+    let out = stdout(&output);
+    assert!(out.starts_with(&format!("Credential status for devbox ({REMOTE}):\n")));
+    assert!(out.contains("  local helper    needs a login"), "{out}");
+    assert!(
+        out.contains(&format!(
+            "  user keyring    no key described keyring-rs:{REMOTE}@AspectWorkflows\n"
+        )),
+        "{out}"
+    );
+    assert!(
+        out.contains("  remote helper   does not accept the credential"),
+        "{out}"
+    );
+    // Nothing was logged in to or written.
+    assert!(
+        !h.log()
+            .iter()
+            .any(|line| line.contains(" login") || line.contains(" padd")),
+        "{:?}",
+        h.log()
+    );
+    assert!(!h.path("remote-key").exists());
+
+    h.set("local-state", "valid")
+        .set("remote-state", "valid")
+        .set("remote-key", "hunter2");
+    let out = stdout(&status());
+    assert!(
+        out.contains("  local helper    accepts the credential"),
+        "{out}"
+    );
+    assert!(
+        out.contains("  user keyring    key 123456 present"),
+        "{out}"
+    );
+    assert!(
+        out.contains("  remote helper   accepts the credential"),
+        "{out}"
+    );
+}
+
+#[test]
+fn logout_revokes_the_key() {
+    let h = Harness::new();
+    h.set("remote-state", "valid").set("remote-key", "hunter2");
+    let logout = |args: &[&str]| {
+        h.command()
+            .arg("logout")
+            .args(args)
+            .env("ASPECT_REMOTE", REMOTE)
+            .output()
+            .unwrap()
+    };
+    // Without a terminal to confirm on, nothing is touched.
+    let output = logout(&["devbox"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(h.log().is_empty());
+
+    let output = logout(&["-y", "devbox"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(
+        stdout(&output).contains("Revoked key 123456 in the user keyring on devbox.\n"),
+        "{}",
+        stdout(&output)
+    );
+    assert!(
+        h.log()
+            .contains(&"remote keyctl invalidate 123456".to_string())
+    );
+    assert!(!h.path("remote-key").exists());
+    assert_eq!(
+        h.subcommand(&["prompt-status", "--word", "devbox"]),
+        "expired\n"
+    );
+
+    let output = logout(&["-y", "devbox"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("No key in the user keyring on devbox.\n"));
+}
+
+/// Runs `aspect-reauth watch` with `args` until its invocations satisfy `done`, then stops it
+/// and returns what it printed.
+fn watch_until(h: &Harness, args: &[&str], done: impl Fn(&[String]) -> bool) -> String {
+    let child = h
+        .command()
+        .arg("watch")
+        .args(args)
+        .env("ASPECT_REMOTE", REMOTE)
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let deadline = Instant::now() + Duration::from_secs(30);
+    while !done(&h.log()) {
+        assert!(Instant::now() < deadline, "{:?}", h.log());
+        std::thread::sleep(Duration::from_millis(50));
+    }
+    let status = Command::new("kill")
+        .args(["-TERM", &child.id().to_string()])
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success(), "{}", stderr(&output));
+    stdout(&output)
+}
+
+#[test]
+fn watch_checks_again_over_the_same_connection() {
+    let h = Harness::new();
+    h.set("local-state", "valid").set("remote-state", "valid");
+    let remote_get = format!("remote {HELPER} get");
+    let out = watch_until(&h, &["--interval=100ms", "devbox"], |log| {
+        log.iter().filter(|line| **line == remote_get).count() >= 3
+    });
+    assert!(
+        out.contains("Credentials are valid. Next check in 0s."),
+        "{out}"
+    );
+    assert!(out.ends_with("Stopping.\n"), "{out}");
+    let log = h.log();
+    assert_eq!(
+        log.iter()
+            .filter(|line| line.starts_with("ssh -xM"))
+            .count(),
+        1,
+        "{log:?}"
+    );
+    assert!(log.last().unwrap().ends_with("-Oexit -- devbox"), "{log:?}");
+}
+
+#[test]
+fn watch_logs_in_before_the_credential_expires() {
+    let h = Harness::new();
+    let expires = SystemTime::now() + Duration::from_secs(300);
+    h.set("local-state", "valid")
+        .set("remote-state", "valid")
+        .set(
+            "local-expires",
+            &humantime::format_rfc3339_seconds(expires).to_string(),
+        );
+    let login = format!("local {HELPER} login {REMOTE}");
+    let out = watch_until(&h, &["--refresh-before=10m", "devbox"], |log| {
+        log.contains(&login)
+    });
+    assert!(
+        out.contains("Credentials are valid. The credential expires in 4m"),
+        "{out}"
+    );
+    assert!(out.contains("; logging in again now."), "{out}");
+}
+
+#[test]
+fn sync_is_delegated_to_a_running_watch() {
+    let h = Harness::new();
+    h.set("local-state", "valid")
+        .set("remote-state", "valid")
+        .set("token", "hunter2\n");
+    let source = format!("file:{}", h.path("token").display());
+    let child = h
+        .command()
+        .args(["watch", "--interval=1h", "devbox"])
+        .env("ASPECT_REMOTE", REMOTE)
+        .env("ASPECT_REAUTH_SOURCE", &source)
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let socket = h.path("state/aspect-reauth/watch/devbox.sock");
+    let remote_get = format!("remote {HELPER} get");
+    let deadline = Instant::now() + Duration::from_secs(30);
+    while !socket.exists() || !h.log().contains(&remote_get) {
+        assert!(Instant::now() < deadline, "{:?}", h.log());
+        std::thread::sleep(Duration::from_millis(50));
+    }
+
+    // A run with other settings is refused rather than synced the watch's way.
+    h.set("remote-state", "expired");
+    let output = h.run(&["-C", "devbox"], &[]);
+    assert!(!output.status.success());
+    assert!(
+        stderr(&output).contains("other settings (--source)"),
+        "{}",
+        stderr(&output)
+    );
+    assert_eq!(
+        fs::read_to_string(h.path("remote-state")).unwrap(),
+        "expired"
+    );
+
+    // The watch syncs over its own connection.
+    let output = h.run(&["-C", "devbox"], &[("ASPECT_REAUTH_SOURCE", &source)]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let out = stdout(&output);
+    assert!(
+        out.contains("Asked the watch running for devbox to sync it."),
+        "{out}"
+    );
+    assert!(
+        out.contains("Aspect credentials synced to devbox."),
+        "{out}"
+    );
+    assert_eq!(fs::read_to_string(h.path("remote-key")).unwrap(), "hunter2");
+    let log = h.log();
+    assert_eq!(
+        log.iter()
+            .filter(|line| line.starts_with("ssh -xM"))
+            .count(),
+        1,
+        "{log:?}"
+    );
+
+    let status = Command::new("kill")
+        .args(["-TERM", &child.id().to_string()])
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("Syncing now, as asked."));
+    assert!(!socket.exists());
+
+    // With no watch listening, a sync runs on its own.
+    h.set("remote-state", "expired");
+    let output = h.run(&["-C", "devbox"], &[("ASPECT_REAUTH_SOURCE", &source)]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(!stdout(&output).contains("Asked the watch"));
+
+    // A state directory too deep for a socket is reported as such.
+    let deep = h.path(&"d".repeat(100));
+    let output = h
+        .command()
+        .args(["watch", "devbox"])
+        .env("XDG_STATE_HOME", &deep)
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(
+        stderr(&output).contains("set $XDG_STATE_HOME to a shorter directory"),
+        "{}",
+        stderr(&output)
+    );
+}
+
+#[test]
+fn temporary_socket_is_used_and_cleaned_up() {
+    let h = Harness::new();
+    h.set("local-state", "valid").set("remote-state", "valid");
+    let output = h.run(&["devbox"], &[]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let log = h.log();
+    assert!(log[0].starts_with("ssh -xMTS "), "{}", log[0]);
+    assert!(log.last().unwrap().ends_with("-Oexit -- devbox"), "{log:?}");
+    assert!(
+        log.iter()
+            .skip(1)
+            .all(|line| !line.starts_with("ssh ") || line.contains(" -S "))
+    );
+}
+
+#[test]
+fn socket_dir_from_config() {
+    let h = Harness::new();
+    h.set("local-state", "valid").set("remote-state", "valid");
+    let sockets = h.path("sockets");
+    fs::create_dir(&sockets).unwrap();
+    h.set(
+        "config.toml",
+        &format!("socket_dir = \"{}\"\n", sockets.display()),
+    );
+    let config = h.path("config.toml");
+    let output = h.run(
+        &["-c", "devbox"],
+        &[("ASPECT_REAUTH_CONFIG", config.to_str().unwrap())],
+    );
+    assert!(output.status.success(), "{}", stderr(&output));
+    let master = &h.log()[0];
+    let expected = format!("ssh -xMTS {}/aspect-reauth-", sockets.display());
+    assert!(master.starts_with(&expected), "{master}");
+    assert_eq!(fs::read_dir(&sockets).unwrap().count(), 0);
+}
+
+#[test]
+fn ssh_binary_from_flag_and_config() {
+    let h = Harness::new();
+    h.set("local-state", "valid").set("remote-state", "valid");
+    let ssh = h.path("openssh").join("ssh");
+    fs::create_dir(h.path("openssh")).unwrap();
+    fs::rename(h.path("bin").join("ssh"), &ssh).unwrap();
+    let output = h.run(&["-C", "devbox"], &[]);
+    assert!(!output.status.success());
+
+    let output = h.run(
+        &["-C", "--ssh-binary", ssh.to_str().unwrap(), "devbox"],
+        &[],
+    );
+    assert!(output.status.success(), "{}", stderr(&output));
+    let output = h.run(&["-C", "--ssh-path", ssh.to_str().unwrap(), "devbox"], &[]);
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    h.set(
+        "config.toml",
+        &format!("ssh_binary = \"{}\"\n", ssh.display()),
+    );
+    let config = h.path("config.toml");
+    let config_env = [("ASPECT_REAUTH_CONFIG", config.to_str().unwrap())];
+    let output = h.run(&["-C", "devbox"], &config_env);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let output = h
+        .command()
+        .args(["doctor", "devbox"])
+        .envs(config_env)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    h.set(
+        "config.toml",
+        &format!("ssh_path = \"{}\"\n", ssh.display()),
+    );
+    let output = h.run(&["-C", "devbox"], &config_env);
+    assert!(output.status.success(), "{}", stderr(&output));
+}
+
+#[test]
+fn transport_cmd_replaces_ssh() {
+    let h = Harness::new();
+    let output = h.run(
+        &[
+            "--ci",
+            "--transport-cmd=ssh -oProxyCommand=iap -- {host}",
+            "devbox",
+        ],
+        &[("ASPECT_REAUTH_CREDENTIAL", "hunter2")],
+    );
+    assert!(output.status.success(), "{}", stdout(&output));
+    assert_eq!(fs::read_to_string(h.path("remote-key")).unwrap(), "hunter2");
+    let log = h.log();
+    assert_eq!(log[0], "ssh -oProxyCommand=iap -- devbox true");
+    // No control master, and nothing but the template on each command.
+    assert!(
+        log.iter()
+            .filter(|line| line.starts_with("ssh "))
+            .all(|line| line.starts_with("ssh -oProxyCommand=iap -- devbox ")),
+        "{log:?}"
+    );
+
+    // A host's table may set it too.
+    fs::remove_file(h.path("log")).unwrap();
+    h.set("local-state", "valid");
+    h.set(
+        "config.toml",
+        "[hosts.iap-box]\ntransport_cmd = \"ssh -J bastion -- {host}.internal\"\n",
+    );
+    let config = h.path("config.toml");
+    let output = h.run(
+        &["-C", "iap-box"],
+        &[("ASPECT_REAUTH_CONFIG", config.to_str().unwrap())],
+    );
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(h.log()[0], "ssh -J bastion -- iap-box.internal true");
+
+    h.set("ssh-fail", "");
+    let output = h.run(
+        &["-C", "iap-box"],
+        &[("ASPECT_REAUTH_CONFIG", config.to_str().unwrap())],
+    );
+    assert_eq!(output.status.code(), Some(6));
+    assert!(
+        stderr(&output).contains("Connection refused"),
+        "{}",
+        stderr(&output)
+    );
+}
+
+#[test]
+fn teleport_transport() {
+    let h = Harness::new();
+    let output = h.run(
+        &["--ci", "--transport=tsh", "devbox"],
+        &[("ASPECT_REAUTH_CREDENTIAL", "hunter2")],
+    );
+    assert!(output.status.success(), "{}", stdout(&output));
+    assert_eq!(fs::read_to_string(h.path("remote-key")).unwrap(), "hunter2");
+    let log = h.log();
+    assert_eq!(log[0], "tsh ssh devbox true");
+    assert!(log.iter().all(|line| !line.starts_with("ssh ")), "{log:?}");
+    assert!(
+        log.contains(&format!("tsh ssh devbox {HELPER} get")),
+        "{log:?}"
+    );
+
+    // As the config file's transport, and with ssh still there for when it is asked for.
+    h.set("local-state", "valid");
+    h.set("config.toml", "[hosts.tele]\ntransport = \"tsh\"\n");
+    let config = h.path("config.toml");
+    let config_env = [("ASPECT_REAUTH_CONFIG", config.to_str().unwrap())];
+    fs::remove_file(h.path("log")).unwrap();
+    let output = h.run(&["-C", "tele"], &config_env);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(h.log()[0], "tsh ssh tele true");
+    fs::remove_file(h.path("log")).unwrap();
+    let output = h.run(&["-C", "--transport=ssh", "tele"], &config_env);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(h.log()[0], "ssh -- tele true");
+
+    h.set("ssh-fail", "");
+    let output = h.run(&["-C", "tele"], &config_env);
+    assert_eq!(output.status.code(), Some(6));
+    assert!(stderr(&output).contains("tsh login"), "{}", stderr(&output));
+}
+
+#[test]
+fn kubectl_transport() {
+    let h = Harness::new();
+    let output = h.run(
+        &[
+            "--ci",
+            "--transport=kubectl",
+            "--namespace=dev",
+            "--pod=devbox-0",
+            "devbox",
+        ],
+        &[("ASPECT_REAUTH_CREDENTIAL", "hunter2")],
+    );
+    assert!(output.status.success(), "{}", stdout(&output));
+    assert_eq!(fs::read_to_string(h.path("remote-key")).unwrap(), "hunter2");
+    let log = h.log();
+    assert_eq!(
+        log[0],
+        "kubectl exec -i --namespace=dev devbox-0 -- sh -c true"
+    );
+    assert!(log.iter().all(|line| !line.starts_with("ssh ")), "{log:?}");
+    assert!(
+        log.iter()
+            .any(|line| line.starts_with("remote keyctl padd")),
+        "{log:?}"
+    );
+
+    // The pod defaults to the host, from the host's table.
+    h.set("local-state", "valid");
+    h.set(
+        "config.toml",
+        "[hosts.pod-1]\ntransport = \"kubectl\"\nnamespace = \"dev\"\n",
+    );
+    let config = h.path("config.toml");
+    fs::remove_file(h.path("log")).unwrap();
+    let output = h.run(
+        &["-C", "pod-1"],
+        &[("ASPECT_REAUTH_CONFIG", config.to_str().unwrap())],
+    );
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(
+        h.log()[0],
+        "kubectl exec -i --namespace=dev pod-1 -- sh -c true"
+    );
+
+    let output = h.run(&["-C", "--pod=devbox-0", "devbox"], &[]);
+    assert!(!output.status.success());
+    assert!(
+        stderr(&output).contains("only apply to --transport=kubectl"),
+        "{}",
+        stderr(&output)
+    );
+}
+
+#[test]
+fn chaos_dropped_connection_still_cleans_up() {
+    let h = Harness::new();
+    let output = h.run(
+        &["--ci", "--chaos=drop@keyctl", "devbox"],
+        &[("ASPECT_REAUTH_CREDENTIAL", "hunter2")],
+    );
+    assert_eq!(output.status.code(), Some(6));
+    let out = stdout(&output);
+    assert!(out.contains("chaos: connection dropped"), "{out}");
+    assert!(!h.path("remote-key").exists());
+    let log = h.log();
+    assert!(log.last().unwrap().ends_with("-Oexit -- devbox"), "{log:?}");
+}
+
+#[test]
+fn chaos_delay_and_transient_drop() {
+    let h = Harness::new();
+    h.set("local-state", "valid").set("remote-state", "valid");
+    let output = h.run(
+        &[
+            "--chaos=delay@aspect-credential-helper=50ms,drop@aspect-credential-helper#1",
+            "-C",
+            "devbox",
+        ],
+        &[],
+    );
+    assert_eq!(output.status.code(), Some(1));
+    assert!(
+        stderr(&output).contains("chaos: connection dropped"),
+        "{}",
+        stderr(&output)
+    );
+    // The dropped remote command never reached the host.
+    assert!(!h.log().iter().any(|l| l.starts_with("remote ")));
+}
+
+#[test]
+fn chaos_partial_write_reaches_the_remote() {
+    let h = Harness::new();
+    let output = h.run(
+        &["--ci", "--chaos=partial@keyctl", "-C", "devbox"],
+        &[("ASPECT_REAUTH_CREDENTIAL", "hunter2")],
+    );
+    assert_eq!(fs::read_to_string(h.path("remote-key")).unwrap(), "hun");
+    // The mock helper accepts any key, so the truncated write goes unnoticed; this pins down that
+    // the fault is actually injected end to end.
+    assert!(output.status.success(), "{}", stdout(&output));
+}
+
+#[test]
+fn container_transport() {
+    let h = Harness::new();
+    let credential = [("ASPECT_REAUTH_CREDENTIAL", "hunter2")];
+    let output = h.run(&["--ci", "--container=toolchain"], &credential);
+    assert!(output.status.success(), "{}", stdout(&output));
+    assert_eq!(fs::read_to_string(h.path("remote-key")).unwrap(), "hunter2");
+    let log = h.log();
+    assert_eq!(log[0], "docker exec -i toolchain sh -c true");
+    assert!(log.iter().all(|line| !line.starts_with("ssh ")), "{log:?}");
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["host"], "toolchain");
+
+    fs::remove_file(h.path("log")).unwrap();
+    let output = h.run(
+        &[
+            "--ci",
+            "--container=toolchain",
+            "--transport=podman",
+            "--force-remote",
+        ],
+        &credential,
+    );
+    assert!(output.status.success(), "{}", stdout(&output));
+    assert_eq!(h.log()[0], "podman exec -i toolchain sh -c true");
+
+    let output = h.run(&["--container=toolchain", "--transport=tsh"], &[]);
+    assert!(!output.status.success());
+    assert!(
+        stderr(&output).contains("--transport=docker or podman"),
+        "{}",
+        stderr(&output)
+    );
+    let output = h.run(&["--container=toolchain", "devbox"], &[]);
+    assert!(!output.status.success());
+    assert!(
+        stderr(&output).contains("one or the other"),
+        "{}",
+        stderr(&output)
+    );
+}
+
+#[test]
+fn jump_hosts_reach_every_ssh() {
+    let h = Harness::new();
+    h.set("local-state", "valid").set("remote-state", "valid");
+    let output = h.run(&["-J", "bastion", "--jump-host=inner", "devbox"], &[]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let log = h.log();
+    let ssh: Vec<_> = log.iter().filter(|line| line.starts_with("ssh ")).collect();
+    assert_eq!(ssh.len(), 3, "{log:?}");
+    assert!(
+        ssh.iter()
+            .all(|line| line.starts_with("ssh -J bastion,inner ")),
+        "{log:?}"
+    );
+}
+
+#[test]
+fn user_and_port_in_the_host() {
+    let h = Harness::new();
+    h.set("local-state", "valid").set("remote-state", "valid");
+    let output = h.run(&["-C", "alice@devbox:2222"], &[]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(h.log()[0], "ssh -p 2222 -l alice -- devbox true");
+    // The outcome is recorded for the host itself.
+    assert_eq!(h.subcommand(&["prompt-status", "devbox", "--word"]), "ok\n");
+
+    fs::remove_file(h.path("log")).unwrap();
+    let output = h.run(&["-C", "--port=2222", "--login-user=alice", "devbox"], &[]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(h.log()[0], "ssh -p 2222 -l alice -- devbox true");
+
+    let output = h.run(&["-C", "--port=22", "devbox:2222"], &[]);
+    assert!(!output.status.success());
+    assert!(
+        stderr(&output).contains("the host gives 2222 but --port gives 22"),
+        "{}",
+        stderr(&output)
+    );
+}
+
+#[test]
+fn ssh_config_and_identity_reach_every_ssh() {
+    let h = Harness::new();
+    h.set("local-state", "valid").set("remote-state", "valid");
+    let output = h.run(
+        &[
+            "-F",
+            "work_config",
+            "-i",
+            "id_work",
+            "--identity=id_backup",
+            "devbox",
+        ],
+        &[],
+    );
+    assert!(output.status.success(), "{}", stderr(&output));
+    let log = h.log();
+    let args = "-F work_config -i id_work -i id_backup";
+    let ssh: Vec<_> = log.iter().filter(|line| line.starts_with("ssh ")).collect();
+    assert_eq!(ssh.len(), 3, "{log:?}");
+    assert!(
+        ssh.iter()
+            .all(|line| line.starts_with(&format!("ssh {args} "))),
+        "{log:?}"
+    );
+
+    let output = h.run(&["-F", "work_config", "--transport=tsh", "devbox"], &[]);
+    assert!(!output.status.success());
+    assert!(
+        stderr(&output).contains("only applies to ssh"),
+        "{}",
+        stderr(&output)
+    );
+}
+
+#[test]
+fn ssh_options_reach_every_ssh() {
+    let h = Harness::new();
+    h.set("local-state", "valid").set("remote-state", "valid");
+    let output = h.run(
+        &[
+            "--ssh-option",
+            "StrictHostKeyChecking=accept-new",
+            "-o",
+            "ProxyCommand=ssh -W %h:%p bastion",
+            "devbox",
+        ],
+        &[],
+    );
+    assert!(output.status.success(), "{}", stderr(&output));
+    let log = h.log();
+    let args = "-o StrictHostKeyChecking=accept-new -o ProxyCommand=ssh -W %h:%p bastion";
+    let ssh: Vec<_> = log.iter().filter(|line| line.starts_with("ssh ")).collect();
+    // The master, the helper check, and the -Oexit cleanup.
+    assert_eq!(ssh.len(), 3, "{log:?}");
+    assert!(
+        ssh.iter()
+            .all(|line| line.starts_with(&format!("ssh {args} "))),
+        "{log:?}"
+    );
+    assert!(ssh[2].ends_with("-Oexit -- devbox"), "{log:?}");
+
+    for bad in ["StrictHostKeyChecking", "Strict Host=no", "Port="] {
+        let output = h.run(&["--ssh-option", bad, "devbox"], &[]);
+        assert_eq!(output.status.code(), Some(2), "{bad}");
+    }
+}
+
+#[test]
+fn dropped_connections_are_retried() {
+    let h = Harness::new();
+    let credential = [("ASPECT_REAUTH_CREDENTIAL", "hunter2")];
+    // The first two keyctl commands fail as a dropped connection does, and are run again.
+    let output = h.run(
+        &[
+            "--ci",
+            "--chaos=drop@keyctl#2",
+            "--retries=2",
+            "--retry-delay=10ms",
+            "devbox",
+        ],
+        &credential,
+    );
+    assert!(output.status.success(), "{}", stdout(&output));
+    assert_eq!(fs::read_to_string(h.path("remote-key")).unwrap(), "hunter2");
+
+    // Out of retries, it fails as before.
+    let output = h.run(
+        &[
+            "--ci",
+            "--chaos=drop@keyctl",
+            "--retries=1",
+            "--retry-delay=10ms",
+            "--force-remote",
+            "devbox",
+        ],
+        &credential,
+    );
+    assert_eq!(output.status.code(), Some(6), "{}", stdout(&output));
+
+    // Connecting is retried too, with the wait doubling each time.
+    h.set("ssh-fail", "");
+    fs::remove_file(h.path("log")).unwrap();
+    let output = h.run(
+        &[
+            "--ci",
+            "--retries=2",
+            "--retry-delay=10ms",
+            "--connect-timeout=5s",
+            "devbox",
+        ],
+        &credential,
+    );
+    assert_eq!(output.status.code(), Some(6));
+    let err = stderr(&output);
+    assert!(err.contains("retrying in 10ms"), "{err}");
+    assert!(err.contains("retrying in 20ms"), "{err}");
+    let masters: Vec<_> = h
+        .log()
+        .into_iter()
+        .filter(|line| line.ends_with("-- devbox true"))
+        .collect();
+    assert_eq!(masters.len(), 3, "{masters:?}");
+    assert!(
+        masters[0].starts_with("ssh -oConnectTimeout=5 "),
+        "{masters:?}"
+    );
+}
+
+#[test]
+fn timeout_stops_the_master_and_removes_the_socket() {
+    let h = Harness::new();
+    let started = Instant::now();
+    let output = h.run(
+        &["--ci", "--chaos=delay@keyctl=30s", "--timeout=1s", "devbox"],
+        &[("ASPECT_REAUTH_CREDENTIAL", "hunter2")],
+    );
+    assert_eq!(output.status.code(), Some(9), "{}", stdout(&output));
+    assert!(started.elapsed() < Duration::from_secs(20));
+    let out = stdout(&output);
+    assert!(out.contains("gave up after 1s"), "{out}");
+    let log = h.log();
+    let socket = log[0]
+        .split(' ')
+        .skip_while(|word| *word != "-xMTS")
+        .nth(1)
+        .unwrap();
+    assert!(log.last().unwrap().ends_with("-Oexit -- devbox"), "{log:?}");
+    assert!(!Path::new(socket).parent().unwrap().exists(), "{socket}");
+    assert_eq!(h.subcommand(&["prompt-status", "devbox"]), "!\n");
+}
+
+#[test]
+fn interrupted_sync_stops_the_master_and_removes_the_socket() {
+    let h = Harness::new();
+    let child = h
+        .command()
+        .args(["--remote", REMOTE, "--credential-helper", HELPER])
+        .args(["--ci", "--chaos=delay@keyctl=30s", "devbox"])
+        .env("ASPECT_REAUTH_CREDENTIAL", "hunter2")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    // Wait for the remote check, after which the sync hangs on keyctl.
+    let started = Instant::now();
+    while !h.log().iter().any(|line| line.starts_with("remote ")) {
+        assert!(started.elapsed() < Duration::from_secs(20), "{:?}", h.log());
+        std::thread::sleep(Duration::from_millis(20));
+    }
+    let kill = Command::new("kill")
+        .args(["-TERM", &child.id().to_string()])
+        .status()
+        .unwrap();
+    assert!(kill.success());
+    let output = child.wait_with_output().unwrap();
+    assert_eq!(output.status.code(), Some(143), "{}", stderr(&output));
+    assert!(
+        stdout(&output).contains("interrupted by SIGTERM"),
+        "{}",
+        stdout(&output)
+    );
+    let log = h.log();
+    let socket = log[0]
+        .split(' ')
+        .skip_while(|word| *word != "-xMTS")
+        .nth(1)
+        .unwrap();
+    assert!(log.last().unwrap().ends_with("-Oexit -- devbox"), "{log:?}");
+    assert!(!Path::new(socket).parent().unwrap().exists(), "{socket}");
+}
+
+#[test]
+fn persistent_socket_is_reused_by_the_next_run() {
+    let h = Harness::new();
+    let cache = h.path("cache");
+    let env = [
+        ("ASPECT_REAUTH_CREDENTIAL", "hunter2"),
+        ("XDG_CACHE_HOME", cache.to_str().unwrap()),
+    ];
+    let output = h.run(&["--ci", "--socket-mode=persistent", "devbox"], &env);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let log = h.log();
+    // Nothing is up yet, so the check fails and a master is started.
+    assert!(log[0].contains("-Ocheck"), "{log:?}");
+    let socket = log[1]
+        .split(' ')
+        .skip_while(|word| *word != "-xMTS")
+        .nth(1)
+        .unwrap()
+        .to_owned();
+    assert!(
+        Path::new(&socket).starts_with(cache.join("aspect-reauth/sockets")),
+        "{socket}"
+    );
+    assert!(log[1].contains("-oControlPersist=30m"), "{log:?}");
+    // The master is left up rather than stopped.
+    assert!(!log.iter().any(|line| line.contains("-Oexit")), "{log:?}");
+    assert!(Path::new(&socket).exists(), "{socket}");
+
+    fs::remove_file(h.path("log")).unwrap();
+    let output = h.run(&["--ci", "--socket-mode=persistent", "devbox"], &env);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let log = h.log();
+    assert!(
+        log[0].ends_with(&format!("-S {socket} -Ocheck -- devbox")),
+        "{log:?}"
+    );
+    assert!(!log.iter().any(|line| line.contains("-xMTS")), "{log:?}");
+    assert!(
+        log[1].starts_with(&format!("ssh -S {socket} -xT")),
+        "{log:?}"
+    );
+
+    // A socket whose master has gone away is replaced.
+    fs::remove_file(&socket).unwrap();
+    fs::remove_file(h.path("log")).unwrap();
+    let output = h.run(&["--ci", "--socket-mode=persistent", "devbox"], &env);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let log = h.log();
+    assert!(log[1].contains(&format!("-xMTS {socket}")), "{log:?}");
+
+    // A dry run only plans the persistent master, and checks over a temporary one.
+    fs::remove_file(&socket).unwrap();
+    fs::remove_file(h.path("log")).unwrap();
+    let output = h.run(&["--dry-run", "--socket-mode=persistent", "devbox"], &env);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).contains("-oControlPersist=30m"));
+    let log = h.log();
+    assert!(!log.iter().any(|line| line.contains(&socket)), "{log:?}");
+    assert!(log.last().unwrap().ends_with("-Oexit -- devbox"), "{log:?}");
+    assert!(!Path::new(&socket).exists(), "{socket}");
+}
+
+#[test]
+fn persistent_socket_falls_back_from_a_deep_cache() {
+    let h = Harness::new();
+    let cache = h.path(&"c".repeat(100));
+    let env = [
+        ("ASPECT_REAUTH_CREDENTIAL", "hunter2"),
+        ("XDG_CACHE_HOME", cache.to_str().unwrap()),
+    ];
+    let output = h.run(&["--ci", "--socket-mode=persistent", "devbox"], &env);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let log = h.log();
+    let socket = log[1]
+        .split(' ')
+        .skip_while(|word| *word != "-xMTS")
+        .nth(1)
+        .unwrap();
+    let dir = Path::new(socket).parent().unwrap();
+    assert!(
+        dir.to_str().unwrap().starts_with("/tmp/aspect-reauth."),
+        "{socket}"
+    );
+    assert_eq!(
+        fs::metadata(dir).unwrap().permissions().mode() & 0o777,
+        0o700
+    );
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn stale_user_master_is_replaced_with_our_own() {
+    let h = Harness::new();
+    fs::create_dir(h.path(".ssh")).unwrap();
+    h.set(
+        ".ssh/config",
+        "ControlMaster auto\nControlPath /tmp/cm-%C\n",
+    );
+    let env = [("ASPECT_REAUTH_CREDENTIAL", "hunter2")];
+    let output = h.run(&["--ci", "devbox"], &env);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let log = h.log();
+    assert_eq!(log[0], "ssh -Ocheck -- devbox");
+    assert!(!log.iter().any(|line| line.contains("-xMTS")), "{log:?}");
+
+    h.set("master-stale", "");
+    fs::remove_file(h.path("log")).unwrap();
+    let output = h.run(&["--ci", "devbox"], &env);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(
+        stderr(&output).contains("the control master for devbox is not usable"),
+        "{}",
+        stderr(&output)
+    );
+    let log = h.log();
+    assert!(log[1].contains("-xMTS"), "{log:?}");
+    assert!(log.last().unwrap().ends_with("-Oexit -- devbox"), "{log:?}");
+
+    // A master with no socket is left to ssh to start, whatever language the error is in.
+    fs::remove_file(h.path("master-stale")).unwrap();
+    h.set("master-absent", "");
+    fs::remove_file(h.path("log")).unwrap();
+    let output = h.run(&["--ci", "devbox"], &env);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(!stderr(&output).contains("warning"), "{}", stderr(&output));
+    assert!(!h.log().iter().any(|line| line.contains("-xMTS")));
+
+    // Without a ControlPath there is no master to check on.
+    h.set(".ssh/config", "ControlMaster auto\n");
+    h.set("master-stale", "");
+    fs::remove_file(h.path("log")).unwrap();
+    let output = h.run(&["--ci", "devbox"], &env);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(!stderr(&output).contains("warning"), "{}", stderr(&output));
+    let log = h.log();
+    assert!(!log.iter().any(|line| line.contains("-Ocheck")), "{log:?}");
+    assert!(!log.iter().any(|line| line.contains("-xMTS")), "{log:?}");
+}
+
+#[test]
+fn deep_temp_dir_falls_back_to_tmp() {
+    let h = Harness::new();
+    h.set("local-state", "valid").set("remote-state", "valid");
+    let deep = h.path(&"d".repeat(100));
+    fs::create_dir(&deep).unwrap();
+    let output = h.run(&["-c", "devbox"], &[("TMPDIR", deep.to_str().unwrap())]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let master = &h.log()[0];
+    assert!(
+        master.starts_with("ssh -xMTS /tmp/aspect-reauth-"),
+        "{master}"
+    );
+
+    // A socket directory that was asked for is not second-guessed.
+    let output = h.run(
+        &["-c", "--socket-dir", deep.to_str().unwrap(), "devbox"],
+        &[],
+    );
+    assert_eq!(output.status.code(), Some(1));
+    assert!(
+        stderr(&output).contains("use a shorter socket directory"),
+        "{}",
+        stderr(&output)
+    );
+}
+
+#[test]
+fn socket_strategies() {
+    let h = Harness::new();
+    h.set("local-state", "valid").set("remote-state", "valid");
+    fs::create_dir(h.path(".ssh")).unwrap();
+    h.set(
+        ".ssh/config",
+        "ControlMaster auto\nControlPath /tmp/cm-%C\n",
+    );
+    let starts_master = |strategy: &str| {
+        let _ = fs::remove_file(h.path("log"));
+        let output = h.run(&[&format!("--create-socket={strategy}"), "devbox"], &[]);
+        assert!(output.status.success(), "{}", stderr(&output));
+        h.log().iter().any(|line| line.contains("-xMTS"))
+    };
+    assert!(starts_master("always"));
+    assert!(!starts_master("never"));
+    assert!(!h.log().iter().any(|line| line.contains("-G")));
+    assert!(!starts_master("infer"));
+    // The ssh config is read in process rather than with ssh -G.
+    assert!(!h.log().iter().any(|line| line.contains("-G")));
+    // Without ControlPersist, the master ssh would start goes away with the run.
+    assert!(starts_master("persist"));
+    assert!(!starts_master("running"));
+
+    h.set("master-stale", "");
+    assert!(starts_master("running"));
+    assert!(!h.log().iter().any(|line| line.contains("-G")));
+    assert!(starts_master("infer"));
+
+    let dry_run = stdout(&h.run(&["--dry-run", "--socket-strategy=running", "devbox"], &[]));
+    assert!(dry_run.contains("ssh -Ocheck -- devbox"), "{dry_run}");
+}
+
+#[test]
+fn single_round_trip_sync_writes_the_key() {
+    let h = Harness::new();
+    let output = h.run(
+        &["--ci", "-C", "--single-round-trip", "devbox"],
+        &[("ASPECT_REAUTH_CREDENTIAL", "hunter2")],
+    );
+    assert!(output.status.success(), "{}", stdout(&output));
+    assert!(stdout(&output).contains(r#""status":"synced","key":123456"#));
+    assert_eq!(fs::read_to_string(h.path("remote-key")).unwrap(), "hunter2");
+    let log = h.log();
+    let ssh: Vec<_> = log.iter().filter(|line| line.starts_with("ssh ")).collect();
+    // The check, the probe, then everything else at once.
+    assert_eq!(ssh.len(), 4, "{log:?}");
+    assert_eq!(
+        log[log.len() - 3..],
+        [
+            format!("remote keyctl search @u user keyring-rs:{REMOTE}@AspectWorkflows"),
+            format!("remote keyctl padd user keyring-rs:{REMOTE}@AspectWorkflows @u"),
+            format!("remote {HELPER} get"),
+        ]
+    );
+
+    h.set("remote-state", "expired").set("log", "");
+    let output = h.run(
+        &["--ci", "-C", "--single-round-trip", "devbox"],
+        &[("ASPECT_REAUTH_CREDENTIAL", "hunter3\n\n")],
+    );
+    assert!(output.status.success(), "{}", stdout(&output));
+    assert_eq!(fs::read_to_string(h.path("remote-key")).unwrap(), "hunter3");
+    assert!(
+        h.log()
+            .contains(&"remote keyctl pupdate 123456".to_string())
+    );
+}
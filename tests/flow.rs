@@ -0,0 +1,250 @@
+// Copyright 2026 Stairwell, Inc.
+// Author: mrdomino@stairwell.com
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Exercises `ReauthFlow`'s decisions about when to log in and when to push.
+
+use std::{
+    cell::{Cell, RefCell},
+    time::Duration,
+};
+
+use anyhow::Result;
+use aspect_reauth::{
+    ReauthFlow, SyncOptions,
+    flow::Outcome,
+    keyctl::KeyringTarget,
+    sink::RemoteSink,
+    source::CredentialSource,
+    transport::{
+        RemoteTransport,
+        fake::{FakeTransport, Response},
+    },
+};
+use secrecy::SecretString;
+
+const HELPER: &str = "aspect-credential-helper";
+const NEEDS_LOGIN: &str = "please run `aspect-credential-helper login`";
+
+/// A source whose staleness and login result are scripted, and which counts what was asked of it.
+#[derive(Default)]
+struct ScriptedSource {
+    stale: bool,
+    login_fails: bool,
+    logins: Cell<u32>,
+    fetches: Cell<u32>,
+}
+
+impl CredentialSource for ScriptedSource {
+    async fn needs_login(&self) -> Result<bool> {
+        Ok(self.stale && self.logins.get() == 0)
+    }
+
+    async fn login(&self) -> Result<()> {
+        self.logins.set(self.logins.get() + 1);
+        if self.login_fails {
+            anyhow::bail!("{HELPER} login: exit status: 1");
+        }
+        Ok(())
+    }
+
+    async fn credential(&self) -> Result<SecretString> {
+        self.fetches.set(self.fetches.get() + 1);
+        Ok(if self.logins.get() > 0 {
+            "fresh"
+        } else {
+            "cached"
+        }
+        .into())
+    }
+}
+
+/// A sink that remembers what it was given.
+#[derive(Default)]
+struct RecordingSink {
+    stored: RefCell<Vec<String>>,
+    fails: bool,
+    preflight_fails: bool,
+    preflights: Cell<u32>,
+}
+
+impl RemoteSink for RecordingSink {
+    async fn preflight<T: RemoteTransport>(&self, _: &T) -> Result<()> {
+        self.preflights.set(self.preflights.get() + 1);
+        if self.preflight_fails {
+            anyhow::bail!("preflight failed");
+        }
+        Ok(())
+    }
+
+    async fn store<T: RemoteTransport>(&self, _: &T, credential: &str) -> Result<Option<i64>> {
+        if self.fails {
+            anyhow::bail!("store failed");
+        }
+        self.stored.borrow_mut().push(credential.into());
+        Ok(synced_key())
+    }
+}
+
+/// The serial number `RecordingSink` reports for every key it stores.
+fn synced_key() -> Option<i64> {
+    Some(42)
+}
+
+fn options() -> SyncOptions {
+    SyncOptions {
+        remote: "aw-remote.example".into(),
+        credential_helper: HELPER.into(),
+        keyrings: vec![KeyringTarget::User],
+        min_validity: Duration::ZERO,
+    }
+}
+
+/// Runs the flow against a remote whose credential is initially `remote_valid`.
+fn run(
+    source: &ScriptedSource,
+    sink: &RecordingSink,
+    remote_valid: bool,
+    force_local: bool,
+    force_remote: bool,
+) -> Result<Outcome> {
+    let fake = FakeTransport::remote("devbox");
+    if !force_remote {
+        fake.expect(
+            HELPER,
+            &["get"],
+            if remote_valid {
+                Response::ok("{}")
+            } else {
+                Response::exit(1, NEEDS_LOGIN)
+            },
+        );
+    }
+    fake.expect(HELPER, &["get"], Response::ok("{}"));
+    let options = options();
+    smol::block_on(
+        ReauthFlow::new(source, &fake, sink, &options)
+            .force_local(force_local)
+            .force_remote(force_remote)
+            .run(),
+    )
+}
+
+#[test]
+fn every_combination_of_staleness_and_force() {
+    for stale in [false, true] {
+        for remote_valid in [false, true] {
+            for force_local in [false, true] {
+                for force_remote in [false, true] {
+                    let source = ScriptedSource {
+                        stale,
+                        ..Default::default()
+                    };
+                    let sink = RecordingSink::default();
+                    let outcome =
+                        run(&source, &sink, remote_valid, force_local, force_remote).unwrap();
+                    let case = format!(
+                        "stale={stale} remote_valid={remote_valid} \
+                         force_local={force_local} force_remote={force_remote}"
+                    );
+                    let logged_in = stale || force_local;
+                    let synced = !remote_valid || force_remote;
+                    let key = if synced { synced_key() } else { None };
+                    assert_eq!(
+                        outcome,
+                        Outcome {
+                            logged_in,
+                            synced,
+                            key,
+                            expires: None,
+                        },
+                        "{case}"
+                    );
+                    assert_eq!(source.logins.get(), logged_in as u32, "{case}");
+                    assert_eq!(source.fetches.get(), synced as u32, "{case}");
+                    // The sink is only readied for a push while a login hides the wait.
+                    assert_eq!(
+                        sink.preflights.get(),
+                        (logged_in && synced) as u32,
+                        "{case}"
+                    );
+                    let expected: &[&str] = match (synced, logged_in) {
+                        (false, _) => &[],
+                        (true, false) => &["cached"],
+                        (true, true) => &["fresh"],
+                    };
+                    assert_eq!(*sink.stored.borrow(), expected, "{case}");
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn login_failure_stops_the_flow() {
+    let source = ScriptedSource {
+        stale: true,
+        login_fails: true,
+        ..Default::default()
+    };
+    let sink = RecordingSink::default();
+    let err = run(&source, &sink, false, false, false).unwrap_err();
+    assert_eq!(err.to_string(), format!("{HELPER} login: exit status: 1"));
+    assert_eq!(source.fetches.get(), 0);
+    assert!(sink.stored.borrow().is_empty());
+}
+
+#[test]
+fn sink_failure_is_propagated() {
+    let source = ScriptedSource::default();
+    let sink = RecordingSink {
+        fails: true,
+        ..Default::default()
+    };
+    let err = run(&source, &sink, false, false, false).unwrap_err();
+    assert_eq!(err.to_string(), "store failed");
+}
+
+#[test]
+fn preflight_failure_stops_the_flow_before_the_push() {
+    let source = ScriptedSource {
+        stale: true,
+        ..Default::default()
+    };
+    let sink = RecordingSink {
+        preflight_fails: true,
+        ..Default::default()
+    };
+    let err = run(&source, &sink, false, false, false).unwrap_err();
+    assert_eq!(err.to_string(), "preflight failed");
+    assert_eq!(source.logins.get(), 1);
+    assert_eq!(source.fetches.get(), 0);
+    assert!(sink.stored.borrow().is_empty());
+}
+
+#[test]
+fn remote_is_checked_while_logging_in() {
+    let source = ScriptedSource {
+        stale: true,
+        login_fails: true,
+        ..Default::default()
+    };
+    let sink = RecordingSink::default();
+    let fake = FakeTransport::remote("devbox");
+    fake.expect(HELPER, &["get"], Response::exit(1, NEEDS_LOGIN));
+    let options = options();
+    smol::block_on(ReauthFlow::new(&source, &fake, &sink, &options).run()).unwrap_err();
+    assert_eq!(fake.calls().len(), 1);
+    assert!(fake.is_done());
+}
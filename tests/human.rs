@@ -0,0 +1,43 @@
+// Copyright 2026 Stairwell, Inc.
+// Author: mrdomino@stairwell.com
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::time::Duration;
+
+use aspect_reauth::human::{ago, duration, from_now};
+
+#[test]
+fn two_most_significant_units() {
+    for (secs, expected) in [
+        (0, "0s"),
+        (45, "45s"),
+        (60, "1m"),
+        (61, "1m 1s"),
+        (3 * 3600 + 12 * 60 + 30, "3h 12m"),
+        (2 * 86400 + 4 * 3600 + 59, "2d 4h"),
+        (2 * 86400 + 3 * 60, "2d"),
+    ] {
+        assert_eq!(duration(Duration::from_secs(secs)), expected, "{secs}");
+    }
+}
+
+#[test]
+fn relative_times() {
+    assert_eq!(ago(Duration::from_millis(300)), "just now");
+    assert_eq!(ago(Duration::from_secs(2 * 86400)), "2d ago");
+    assert_eq!(
+        from_now(Duration::from_secs(3 * 3600 + 12 * 60)),
+        "in 3h 12m"
+    );
+}
@@ -0,0 +1,55 @@
+// Copyright 2026 Stairwell, Inc.
+// Author: mrdomino@stairwell.com
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use aspect_reauth::keyctl::{DEFAULT_KEY_NAME, expand_key_name, key_name};
+
+#[test]
+fn default_template_matches_keyring_rs() {
+    assert_eq!(
+        expand_key_name(DEFAULT_KEY_NAME, "aw.example", "devbox").unwrap(),
+        key_name("aw.example")
+    );
+}
+
+#[test]
+fn braces_can_be_escaped() {
+    assert_eq!(
+        expand_key_name("{{{remote}}}", "aw.example", "devbox").unwrap(),
+        "{aw.example}"
+    );
+}
+
+#[test]
+fn profile_tells_hosts_apart() {
+    assert_eq!(
+        expand_key_name(
+            "keyring-rs:{remote}@{service}:{profile}",
+            "aw.example",
+            "devbox-eu"
+        )
+        .unwrap(),
+        "keyring-rs:aw.example@AspectWorkflows:devbox-eu"
+    );
+}
+
+#[test]
+fn malformed_templates_are_errors() {
+    for template in ["{remote", "remote}", "{nope}", "{}"] {
+        assert!(
+            expand_key_name(template, "aw.example", "devbox").is_err(),
+            "{template}"
+        );
+    }
+}
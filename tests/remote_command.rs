@@ -0,0 +1,65 @@
+// Copyright 2026 Stairwell, Inc.
+// Author: mrdomino@stairwell.com
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::process::Command;
+
+use aspect_reauth::ssh_mux::remote_command;
+use proptest::prelude::*;
+
+#[test]
+fn plain_words_are_passed_as_they_are() {
+    assert_eq!(
+        remote_command(
+            "keyctl",
+            &[
+                "padd",
+                "user",
+                "keyring-rs:aw.example@AspectWorkflows",
+                "@u"
+            ]
+        )
+        .unwrap(),
+        "keyctl padd user keyring-rs:aw.example@AspectWorkflows @u"
+    );
+}
+
+#[test]
+fn anything_else_goes_through_sh() {
+    assert_eq!(
+        remote_command("keyctl", &["padd", "user", "my $key", ""]).unwrap(),
+        r#"sh -c '"keyctl" "padd" "user" "my \$key" ""'"#
+    );
+}
+
+#[test]
+fn quotes_and_backslashes_are_refused() {
+    for arg in ["it's", r"back\slash"] {
+        assert!(remote_command("keyctl", &[arg]).is_err(), "{arg}");
+    }
+}
+
+proptest! {
+    /// Whatever the arguments, a POSIX shell given the command sees exactly them.
+    #[test]
+    fn round_trips_through_sh(args in proptest::collection::vec("[^'\\\\\\x00|]*", 1..4)) {
+        let mut argv = vec!["%s|"];
+        argv.extend(args.iter().map(String::as_str));
+        let command = remote_command("printf", &argv).unwrap();
+        let output = Command::new("sh").args(["-c", &command]).output().unwrap();
+        prop_assert!(output.status.success());
+        let expected: String = args.iter().map(|a| format!("{a}|")).collect();
+        prop_assert_eq!(String::from_utf8_lossy(&output.stdout), expected);
+    }
+}
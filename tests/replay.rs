@@ -0,0 +1,91 @@
+// Copyright 2026 Stairwell, Inc.
+// Author: mrdomino@stairwell.com
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Golden tests: replays recorded remote interactions, failing if the commands we issue drift
+//! from the recording. Re-record a fixture with `aspect-reauth --record=<file>` from a
+//! `test-support` build when a change to the commands is intended.
+
+use std::{path::Path, time::Duration};
+
+use anyhow::Result;
+use aspect_reauth::{
+    ReauthFlow, SyncOptions,
+    keyctl::KeyringTarget,
+    sink::Keyctl,
+    source::Fixed,
+    transport::record::{Fixture, Replay},
+};
+
+fn replay(name: &str, keyring: KeyringTarget) -> (Result<bool>, Replay) {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(name);
+    let replay = Replay::new(Fixture::load(&path).unwrap());
+    let options = SyncOptions {
+        remote: "aw-remote.example".into(),
+        credential_helper: "aspect-credential-helper".into(),
+        keyrings: vec![keyring],
+        min_validity: Duration::ZERO,
+    };
+    let source = Fixed("hunter2".into());
+    let result = smol::block_on(
+        ReauthFlow::new(&source, &replay, &Keyctl::new(&options), &options).sync_remote(),
+    );
+    (result, replay)
+}
+
+#[test]
+fn already_valid() {
+    let (result, replay) = replay("already-valid.json", KeyringTarget::User);
+    assert!(!result.unwrap());
+    assert!(replay.is_done());
+}
+
+#[test]
+fn expired() {
+    let (result, replay) = replay("expired.json", KeyringTarget::User);
+    assert!(result.unwrap());
+    assert!(replay.is_done());
+}
+
+#[test]
+fn expired_session_keyring() {
+    let (result, replay) = replay("expired-session-keyring.json", KeyringTarget::Session);
+    assert!(result.unwrap());
+    assert!(replay.is_done());
+}
+
+#[test]
+fn existing_key() {
+    let (result, replay) = replay("existing-key.json", KeyringTarget::User);
+    assert!(result.unwrap());
+    assert!(replay.is_done());
+}
+
+#[test]
+fn keyctl_failure() {
+    let (result, replay) = replay("keyctl-failure.json", KeyringTarget::User);
+    let err = result.unwrap_err().to_string();
+    assert!(err.contains("Permission denied"), "{err}");
+    assert!(replay.is_done());
+}
+
+#[test]
+fn drift_is_caught() {
+    // Replaying a user-keyring recording with the session keyring must fail.
+    let (result, _) = replay("expired.json", KeyringTarget::Session);
+    let err = result.unwrap_err().to_string();
+    assert!(err.starts_with("replay: expected keyctl"), "{err}");
+}
@@ -0,0 +1,198 @@
+// Copyright 2026 Stairwell, Inc.
+// Author: mrdomino@stairwell.com
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{any::Any, fs, path::PathBuf, time::Duration};
+
+use aspect_reauth::{
+    SyncOptions,
+    keyctl::KeyringTarget,
+    source::{self, CredentialSource, External, Fixed, Keyring, SourceKind},
+};
+use keyring::credential::{Credential, CredentialBuilderApi};
+use secrecy::ExposeSecret;
+
+/// A keychain that is never there, as on a host with no session keyring or secret service.
+struct NoKeychain;
+
+impl CredentialBuilderApi for NoKeychain {
+    fn build(&self, _: Option<&str>, _: &str, _: &str) -> keyring::Result<Box<Credential>> {
+        Err(keyring::Error::NoStorageAccess("no secret service".into()))
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// A keychain that is there but fails, as when the user denies unlocking it.
+struct LockedKeychain;
+
+impl CredentialBuilderApi for LockedKeychain {
+    fn build(&self, _: Option<&str>, _: &str, _: &str) -> keyring::Result<Box<Credential>> {
+        Err(keyring::Error::PlatformFailure("unlock denied".into()))
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+#[test]
+fn kinds_round_trip() {
+    for (s, kind) in [
+        ("keyring", SourceKind::Keyring),
+        ("stdin", SourceKind::Stdin),
+        (
+            "file:/run/token",
+            SourceKind::File(PathBuf::from("/run/token")),
+        ),
+        ("env:ASPECT_TOKEN", SourceKind::Env("ASPECT_TOKEN".into())),
+        (
+            "cmd:pass show aspect",
+            SourceKind::Command("pass show aspect".into()),
+        ),
+        (
+            "pass:stairwell/aspect",
+            SourceKind::Pass("stairwell/aspect".into()),
+        ),
+        ("age:/cred.age", SourceKind::Age("/cred.age".into())),
+        ("gpg:/cred.gpg", SourceKind::Gpg("/cred.gpg".into())),
+        ("gcp:aspect", SourceKind::Gcp("aspect".into())),
+        ("aws:prod/aspect", SourceKind::Aws("prod/aspect".into())),
+    ] {
+        assert_eq!(s.parse::<SourceKind>().unwrap(), kind, "{s}");
+        assert_eq!(kind.to_string(), s);
+    }
+    for s in [
+        "", "keychain", "file:", "env:", "cmd: ", "stdin:x", "pass:", "gcp:", "aws:", "age:",
+        "gpg:",
+    ] {
+        assert!(s.parse::<SourceKind>().is_err(), "{s}");
+    }
+}
+
+#[test]
+fn home_is_expanded() {
+    let home = std::env::home_dir().unwrap();
+    assert_eq!(
+        "age:~/.aspect/cred.age".parse::<SourceKind>().unwrap(),
+        SourceKind::Age(home.join(".aspect/cred.age"))
+    );
+}
+
+#[test]
+fn file_is_read_each_time() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("token");
+    fs::write(&path, "hunter2\n").unwrap();
+    let source = External::new(SourceKind::File(path.clone()));
+    assert!(!smol::block_on(source.needs_login()).unwrap());
+    assert_eq!(
+        smol::block_on(source.credential()).unwrap().expose_secret(),
+        "hunter2"
+    );
+    fs::write(&path, "hunter3").unwrap();
+    assert_eq!(
+        smol::block_on(source.credential()).unwrap().expose_secret(),
+        "hunter3"
+    );
+
+    fs::write(&path, "\n").unwrap();
+    let err = smol::block_on(source.credential()).unwrap_err();
+    assert!(
+        err.to_string().starts_with("no credential in file:"),
+        "{err:#}"
+    );
+}
+
+#[test]
+fn credential_is_not_printed() {
+    let source = Fixed("hunter2".into());
+    let credential = smol::block_on(source.credential()).unwrap();
+    assert!(!format!("{credential:?}").contains("hunter2"));
+    assert_eq!(credential.expose_secret(), "hunter2");
+}
+
+#[test]
+fn unreachable_keychain_falls_back() {
+    keyring::set_default_credential_builder(Box::new(NoKeychain));
+    let options = SyncOptions {
+        remote: "aw-remote.example".into(),
+        credential_helper: "aspect-credential-helper".into(),
+        keyrings: vec![KeyringTarget::User],
+        min_validity: Duration::ZERO,
+    };
+    // Without a fallback given, the default one is read, or said to be missing.
+    let default = source::default_fallback().unwrap();
+    let err = smol::block_on(Keyring::new(&options).credential()).unwrap_err();
+    assert!(
+        format!("{err:#}").contains(&format!(
+            "nor an encrypted credential at {}",
+            default.display()
+        )),
+        "{err:#}"
+    );
+    assert!(
+        format!("{err:#}").contains("--keychain-fallback"),
+        "{err:#}"
+    );
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("token");
+    fs::write(&path, "hunter2\n").unwrap();
+    let source = Keyring::new(&options).fallback(Some(SourceKind::File(path.clone())));
+    // The helper, which has no keychain either, is not asked.
+    assert!(!smol::block_on(source.needs_login()).unwrap());
+    smol::block_on(source.login()).unwrap();
+    assert_eq!(
+        smol::block_on(source.credential()).unwrap().expose_secret(),
+        "hunter2"
+    );
+
+    // A keychain that is there but fails is not passed over. The builder is process-wide, so
+    // this shares the test.
+    keyring::set_default_credential_builder(Box::new(LockedKeychain));
+    let source = Keyring::new(&options).fallback(Some(SourceKind::File(path)));
+    let err = smol::block_on(source.credential()).unwrap_err();
+    assert!(format!("{err:#}").contains("unlock denied"), "{err:#}");
+    assert!(
+        !format!("{err:#}").contains("--keychain-fallback"),
+        "{err:#}"
+    );
+}
+
+#[test]
+fn missing_variable_is_an_error() {
+    let source = External::new(SourceKind::Env("ASPECT_REAUTH_TEST_UNSET".into()));
+    let err = smol::block_on(source.credential()).unwrap_err();
+    assert!(
+        err.to_string().contains("$ASPECT_REAUTH_TEST_UNSET"),
+        "{err:#}"
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn command_output_is_the_credential() {
+    let source = External::new(SourceKind::Command("printf 'hunter2\\r\\n'".into()));
+    assert_eq!(
+        smol::block_on(source.credential()).unwrap().expose_secret(),
+        "hunter2"
+    );
+
+    let source = External::new(SourceKind::Command("exit 3".into()));
+    let err = smol::block_on(source.credential()).unwrap_err();
+    assert!(err.to_string().starts_with("`exit 3` failed"), "{err:#}");
+}
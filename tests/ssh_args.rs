@@ -0,0 +1,105 @@
+// Copyright 2026 Stairwell, Inc.
+// Author: mrdomino@stairwell.com
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use aspect_reauth::ssh_mux::{Destination, split_ssh_args};
+
+fn split(args: &[&str]) -> anyhow::Result<Vec<String>> {
+    split_ssh_args(&args.iter().map(|a| a.to_string()).collect::<Vec<_>>())
+}
+
+#[test]
+fn option_and_value_in_one_argument_are_split() {
+    assert_eq!(split(&["-p 23"]).unwrap(), ["-p", "23"]);
+    assert_eq!(
+        split(&["-i  ~/.ssh/work", "-J bastion"]).unwrap(),
+        ["-i", "~/.ssh/work", "-J", "bastion"]
+    );
+}
+
+#[test]
+fn only_the_first_space_splits() {
+    assert_eq!(
+        split(&["-o ProxyCommand=ssh -W %h:%p bastion"]).unwrap(),
+        ["-o", "ProxyCommand=ssh -W %h:%p bastion"]
+    );
+}
+
+#[test]
+fn other_forms_are_untouched() {
+    for args in [
+        &["-p", "23"][..],
+        &["-p23"],
+        &["-oPort=23"],
+        &["-A", "-4"],
+        &["-v -v"],
+    ] {
+        assert_eq!(split(args).unwrap(), args);
+    }
+}
+
+#[test]
+fn missing_value_is_an_error() {
+    for args in [&["-p"][..], &["-A", "-i"], &["-p "]] {
+        let err = split(args).unwrap_err().to_string();
+        assert!(err.contains("needs a value"), "{args:?}: {err}");
+    }
+}
+
+fn destination(user: Option<&str>, host: &str, port: Option<u16>) -> Destination {
+    Destination {
+        user: user.map(String::from),
+        host: host.into(),
+        port,
+    }
+}
+
+#[test]
+fn destinations_parse() {
+    for (s, expected) in [
+        ("devbox", destination(None, "devbox", None)),
+        ("alice@devbox", destination(Some("alice"), "devbox", None)),
+        ("devbox:2222", destination(None, "devbox", Some(2222))),
+        (
+            "alice@devbox:2222",
+            destination(Some("alice"), "devbox", Some(2222)),
+        ),
+        (
+            "alice@corp@devbox",
+            destination(Some("alice@corp"), "devbox", None),
+        ),
+        ("fe80::1", destination(None, "fe80::1", None)),
+        ("[fe80::1]:2222", destination(None, "fe80::1", Some(2222))),
+        ("bob@[::1]", destination(Some("bob"), "::1", None)),
+    ] {
+        assert_eq!(s.parse::<Destination>().unwrap(), expected, "{s}");
+    }
+}
+
+#[test]
+fn bad_destinations_are_errors() {
+    for s in [
+        "@devbox",
+        "alice@",
+        ":22",
+        "devbox:",
+        "devbox:0",
+        "devbox:ssh",
+        "devbox:70000",
+        "[::1",
+        "[::1]22",
+    ] {
+        assert!(s.parse::<Destination>().is_err(), "{s}");
+    }
+}
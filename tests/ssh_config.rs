@@ -0,0 +1,349 @@
+// Copyright 2026 Stairwell, Inc.
+// Author: mrdomino@stairwell.com
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Tests for the ssh config parsing behind socket inference, from `ssh -G` output and from the
+//! config files themselves.
+
+use std::{fs, path::Path};
+
+use aspect_reauth::ssh_mux::config::{
+    Connection, ControlMaster, ControlPersist, SshConfig, host_aliases,
+};
+use proptest::prelude::*;
+use tempfile::TempDir;
+
+const MASTER_VALUES: &[(&str, ControlMaster)] = &[
+    ("yes", ControlMaster::Yes),
+    ("no", ControlMaster::No),
+    ("ask", ControlMaster::Ask),
+    ("auto", ControlMaster::Auto),
+    ("autoask", ControlMaster::AutoAsk),
+];
+
+/// Randomizes the case of each character of `s`.
+fn any_case(s: &'static str) -> impl Strategy<Value = String> {
+    proptest::collection::vec(any::<bool>(), s.len()).prop_map(move |upper| {
+        s.chars()
+            .zip(upper)
+            .map(|(c, u)| if u { c.to_ascii_uppercase() } else { c })
+            .collect()
+    })
+}
+
+/// Whitespace-and-`=` separators OpenSSH accepts between a keyword and its value.
+fn separator() -> impl Strategy<Value = String> {
+    prop_oneof!["[ \t]{1,4}", "[ \t]{0,3}=[ \t]{0,3}",]
+}
+
+/// A line `SshConfig` should not care about.
+fn noise_line() -> impl Strategy<Value = String> {
+    prop_oneof![
+        Just(String::new()),
+        "[ \t]{0,4}",
+        "#[ -~]{0,30}",
+        "[a-z]{1,12}[ \t=]{1,3}[ -~]{0,20}"
+            .prop_filter("not a control option", |l| !l.starts_with("control")),
+    ]
+}
+
+/// Lines of noise with `line` spliced in at an arbitrary position.
+fn with_noise(line: impl Strategy<Value = String>) -> impl Strategy<Value = String> {
+    (
+        proptest::collection::vec(noise_line(), 0..8),
+        line,
+        any::<prop::sample::Index>(),
+    )
+        .prop_map(|(mut lines, line, at)| {
+            lines.insert(at.index(lines.len() + 1), line);
+            lines.join("\n")
+        })
+}
+
+fn control_master_line() -> impl Strategy<Value = (String, ControlMaster)> {
+    (
+        "[ \t]{0,3}",
+        any_case("controlmaster"),
+        separator(),
+        prop::sample::select(MASTER_VALUES),
+        "[ \t]{0,3}",
+    )
+        .prop_flat_map(|(lead, key, sep, (value, expected), trail)| {
+            any_case(value)
+                .prop_map(move |value| (format!("{lead}{key}{sep}{value}{trail}"), expected))
+        })
+}
+
+proptest! {
+    #[test]
+    fn never_panics(s in "\\PC*") {
+        let _ = SshConfig::parse(&s);
+    }
+
+    #[test]
+    fn never_panics_on_control_lines(
+        key in prop::sample::select(&["controlmaster", "controlpersist", "controlpath"][..]),
+        sep in "[ \t=]{0,3}",
+        value in "\\PC{0,20}",
+    ) {
+        let _ = SshConfig::parse(&format!("{key}{sep}{value}"));
+    }
+
+    #[test]
+    fn control_master_is_found_amid_noise(
+        ((line, expected), config) in control_master_line().prop_flat_map(|(line, expected)| {
+            (Just((line.clone(), expected)), with_noise(Just(line)))
+        }),
+    ) {
+        let parsed = SshConfig::parse(&config);
+        prop_assert_eq!(parsed.control_master, expected, "line {:?}", line);
+        prop_assert_eq!(
+            parsed.reuses_master(),
+            matches!(expected, ControlMaster::Auto | ControlMaster::AutoAsk)
+        );
+    }
+
+    #[test]
+    fn first_control_master_wins(
+        (first, expected) in control_master_line(),
+        (second, _) in control_master_line(),
+    ) {
+        let parsed = SshConfig::parse(&format!("{first}\n{second}"));
+        prop_assert_eq!(parsed.control_master, expected);
+    }
+
+    #[test]
+    fn unparseable_control_master_is_ignored(
+        value in "[a-z]{1,10}".prop_filter("not a valid value", |v| {
+            !["yes", "no", "true", "false", "ask", "auto", "autoask"].contains(&v.as_str())
+        }),
+    ) {
+        let parsed = SshConfig::parse(&format!("controlmaster {value}"));
+        prop_assert_eq!(parsed.control_master, ControlMaster::No);
+        prop_assert!(!parsed.reuses_master());
+    }
+
+    #[test]
+    fn control_persist_seconds(secs in 1u64..10_000_000, sep in separator()) {
+        let parsed = SshConfig::parse(&format!("ControlPersist{sep}{secs}"));
+        prop_assert_eq!(parsed.control_persist, ControlPersist::Seconds(secs));
+    }
+
+    #[test]
+    fn control_persist_units(
+        w in 0u64..3, d in 0u64..7, h in 0u64..24, m in 0u64..60, s in 0u64..60,
+    ) {
+        let total = (((w * 7 + d) * 24 + h) * 60 + m) * 60 + s;
+        let spec = format!("{w}w{d}d{h}h{m}m{s}s");
+        let expected = if total == 0 {
+            ControlPersist::Yes
+        } else {
+            ControlPersist::Seconds(total)
+        };
+        let parsed = SshConfig::parse(&format!("controlpersist {spec}"));
+        prop_assert_eq!(parsed.control_persist, expected);
+    }
+}
+
+#[test]
+fn control_persist_keywords() {
+    for (value, expected) in [
+        ("yes", ControlPersist::Yes),
+        ("no", ControlPersist::No),
+        ("0", ControlPersist::Yes),
+        ("10m", ControlPersist::Seconds(600)),
+        ("bogus", ControlPersist::No),
+        ("99999999999999999999", ControlPersist::No),
+    ] {
+        assert_eq!(
+            SshConfig::parse(&format!("controlpersist {value}")).control_persist,
+            expected,
+            "{value}"
+        );
+    }
+}
+
+#[test]
+fn real_ssh_g_output() {
+    let output = "user alice\nhostname devbox.example\nport 22\ncontrolmaster auto\n\
+                  controlpersist 600\ncontrolpath /home/alice/.ssh/cm-%r@%h:%p\n";
+    assert_eq!(
+        SshConfig::parse(output),
+        SshConfig {
+            control_master: ControlMaster::Auto,
+            control_persist: ControlPersist::Seconds(600),
+            control_path: Some("/home/alice/.ssh/cm-%r@%h:%p".into()),
+        }
+    );
+    assert_eq!(SshConfig::parse("controlpath none").control_path, None);
+}
+
+#[test]
+fn host_aliases_skip_patterns() {
+    let config = "\
+Host devbox devbox-eu
+    HostName devbox.internal
+host=\"quoted\"
+Host *.example !bad db-? devbox
+Match host foo
+";
+    assert_eq!(host_aliases(config), ["devbox", "devbox-eu", "quoted"]);
+}
+
+#[test]
+fn connection_from_ssh_g_output() {
+    let output = "user alice\nhostname devbox.internal\nport 2222\nidentityfile ~/.ssh/id_work\n\
+                  identityfile ~/.ssh/id_ed25519\nproxyjump bastion,inner\n\
+                  proxycommand ssh -W %h:%p jump\n";
+    assert_eq!(
+        Connection::parse(output),
+        Connection {
+            hostname: Some("devbox.internal".into()),
+            user: Some("alice".into()),
+            port: Some(2222),
+            proxy_jump: vec!["bastion".into(), "inner".into()],
+            proxy_command: Some("ssh -W %h:%p jump".into()),
+            identity_files: vec!["~/.ssh/id_work".into(), "~/.ssh/id_ed25519".into()],
+        }
+    );
+    let direct = Connection::parse("proxyjump none\nproxycommand none\n");
+    assert!(direct.proxy_jump.is_empty());
+    assert_eq!(direct.proxy_command, None);
+}
+
+/// Resolves `host` from the config file `config` in `dir`, with `args` before its `-F`.
+fn from_file(dir: &Path, config: &str, host: &str, args: &[&str]) -> Option<SshConfig> {
+    let path = dir.join("config");
+    fs::write(&path, config).unwrap();
+    let path = path.to_str().unwrap();
+    let args: Vec<&str> = args.iter().copied().chain(["-F", path]).collect();
+    SshConfig::from_files(host, &args)
+}
+
+#[test]
+fn config_files_resolve_like_ssh() {
+    let dir = TempDir::new().unwrap();
+    let config = "\
+Host devbox *.corp !bad.corp
+    ControlMaster auto
+    ControlPath ~/.ssh/cm-%C
+Host *.corp
+    ControlMaster no
+    ControlPersist 10m
+Host *
+    ControlPersist yes
+";
+    let resolve = |host| from_file(dir.path(), config, host, &[]).unwrap();
+    assert_eq!(
+        resolve("devbox"),
+        SshConfig {
+            control_master: ControlMaster::Auto,
+            control_persist: ControlPersist::Yes,
+            control_path: Some("~/.ssh/cm-%C".into()),
+        }
+    );
+    let corp = resolve("BUILD.corp");
+    assert_eq!(corp.control_master, ControlMaster::Auto);
+    assert_eq!(corp.control_persist, ControlPersist::Seconds(600));
+    let bad = resolve("bad.corp");
+    assert_eq!(bad.control_master, ControlMaster::No);
+    assert_eq!(
+        resolve("other"),
+        SshConfig {
+            control_persist: ControlPersist::Yes,
+            ..SshConfig::default()
+        }
+    );
+
+    // The command line comes first, and -M beats any ControlMaster.
+    let args = ["-o", "ControlPersist=no", "-oControlPath=/tmp/s"];
+    let config = from_file(dir.path(), "ControlMaster no\n", "devbox", &args).unwrap();
+    assert_eq!(config.control_persist, ControlPersist::No);
+    assert_eq!(config.control_path.as_deref(), Some("/tmp/s"));
+    let config = from_file(dir.path(), "", "devbox", &["-o", "ControlMaster=no", "-M"]).unwrap();
+    assert_eq!(config.control_master, ControlMaster::Yes);
+    let config = from_file(dir.path(), "", "devbox", &["-xMMT"]).unwrap();
+    assert_eq!(config.control_master, ControlMaster::Ask);
+}
+
+#[test]
+fn match_host_sees_the_hostname() {
+    let dir = TempDir::new().unwrap();
+    let config = "\
+Host devbox
+    HostName %h.internal
+Match host *.internal !originalhost other
+    ControlMaster auto
+Match all
+    ControlPersist 5m
+";
+    let config = from_file(dir.path(), config, "devbox", &[]).unwrap();
+    assert_eq!(config.control_master, ControlMaster::Auto);
+    assert_eq!(config.control_persist, ControlPersist::Seconds(300));
+}
+
+#[test]
+fn includes_are_followed() {
+    let dir = TempDir::new().unwrap();
+    let included = dir.path().join("config.d");
+    fs::create_dir(&included).unwrap();
+    fs::write(included.join("10-mux.conf"), "ControlMaster auto\n").unwrap();
+    fs::write(included.join("20-persist.conf"), "ControlPersist 1h\n").unwrap();
+    fs::write(included.join("other"), "ControlPath /ignored\n").unwrap();
+    let config = format!(
+        "Host nope\nInclude {dir}/*.conf\nHost devbox\nInclude {dir}/*.conf\n",
+        dir = included.display()
+    );
+    assert_eq!(
+        from_file(dir.path(), &config, "devbox", &[]),
+        Some(SshConfig {
+            control_master: ControlMaster::Auto,
+            control_persist: ControlPersist::Seconds(3600),
+            control_path: None,
+        })
+    );
+    assert_eq!(
+        from_file(dir.path(), &config, "other", &[]),
+        Some(SshConfig::default())
+    );
+}
+
+#[test]
+fn what_ssh_alone_can_answer_is_left_to_it() {
+    let dir = TempDir::new().unwrap();
+    for config in [
+        "Match exec \"test -e /tmp\"\n",
+        "Match user alice\n",
+        "Match final\n",
+        "CanonicalizeHostname yes\n",
+        "Include /etc/*/config\n",
+    ] {
+        assert_eq!(
+            from_file(dir.path(), config, "devbox", &[]),
+            None,
+            "{config}"
+        );
+    }
+    // Unless it is in a block that does not apply.
+    assert!(
+        from_file(
+            dir.path(),
+            "Host other\nCanonicalizeHostname yes\n",
+            "devbox",
+            &[]
+        )
+        .is_some()
+    );
+    assert_eq!(from_file(dir.path(), "", "devbox", &["devbox"]), None);
+}
@@ -0,0 +1,975 @@
+// Copyright 2026 Stairwell, Inc.
+// Author: mrdomino@stairwell.com
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::Result;
+use aspect_reauth::{
+    ReauthError, ReauthFlow, SyncOptions,
+    error::KeyctlErrorKind,
+    keyctl::KeyringTarget,
+    probe::Probed,
+    sink::{AnySink, File, Keyctl, SecretTool, SystemdCreds},
+    source::Fixed,
+    token,
+    transport::fake::{Call, FakeTransport, Response},
+};
+use base64::prelude::{BASE64_STANDARD, Engine as _};
+
+const HELPER: &str = "aspect-credential-helper";
+const REMOTE: &str = "aw-remote.example";
+const PASSWORD: &str = "hunter2";
+const NEEDS_LOGIN: &str = "Error: please run `aspect-credential-helper login aw-remote.example`";
+
+fn options() -> SyncOptions {
+    SyncOptions {
+        remote: REMOTE.into(),
+        credential_helper: HELPER.into(),
+        keyrings: vec![KeyringTarget::User],
+        min_validity: Duration::ZERO,
+    }
+}
+
+async fn sync(fake: &FakeTransport, force: bool) -> Result<bool> {
+    let options = options();
+    ReauthFlow::new(
+        &Fixed(PASSWORD.into()),
+        fake,
+        &Keyctl::new(&options),
+        &options,
+    )
+    .force_remote(force)
+    .sync_remote()
+    .await
+}
+
+fn get_call() -> Call {
+    Call {
+        program: HELPER.into(),
+        args: vec!["get".into()],
+        input: format!("{{\"uri\":\"https://{REMOTE}\"}}\n").into(),
+    }
+}
+
+fn search_call() -> Call {
+    Call {
+        program: "keyctl".into(),
+        args: vec![
+            "search".into(),
+            "@u".into(),
+            "user".into(),
+            format!("keyring-rs:{REMOTE}@AspectWorkflows"),
+        ],
+        input: Vec::new(),
+    }
+}
+
+fn key_not_found() -> Response {
+    Response::exit(1, "keyctl_search: Required key not available")
+}
+
+fn padd_call() -> Call {
+    Call {
+        program: "keyctl".into(),
+        args: vec![
+            "padd".into(),
+            "user".into(),
+            format!("keyring-rs:{REMOTE}@AspectWorkflows"),
+            "@u".into(),
+        ],
+        input: PASSWORD.into(),
+    }
+}
+
+#[test]
+fn valid_credential_is_left_alone() {
+    let fake = FakeTransport::remote("devbox");
+    fake.expect(HELPER, &["get"], Response::ok("{}"));
+    let synced = smol::block_on(sync(&fake, false)).unwrap();
+    assert!(!synced);
+    assert_eq!(fake.calls(), [get_call()]);
+}
+
+#[test]
+fn expired_credential_is_synced() {
+    let fake = FakeTransport::remote("devbox");
+    fake.expect(HELPER, &["get"], Response::exit(1, NEEDS_LOGIN))
+        .expect("keyctl", &["search"], key_not_found())
+        .expect("keyctl", &["padd"], Response::ok("123456\n"))
+        .expect(HELPER, &["get"], Response::ok("{}"));
+    let synced = smol::block_on(sync(&fake, false)).unwrap();
+    assert!(synced);
+    assert!(fake.is_done());
+    assert_eq!(
+        fake.calls(),
+        [get_call(), search_call(), padd_call(), get_call()]
+    );
+}
+
+#[test]
+fn forced_sync_skips_the_check() {
+    let fake = FakeTransport::remote("devbox");
+    fake.expect("keyctl", &["search"], key_not_found())
+        .expect("keyctl", &["padd"], Response::ok("123456\n"))
+        .expect(HELPER, &["get"], Response::ok("{}"));
+    let synced = smol::block_on(sync(&fake, true)).unwrap();
+    assert!(synced);
+    assert_eq!(fake.calls(), [search_call(), padd_call(), get_call()]);
+}
+
+#[test]
+fn existing_key_is_updated_in_place() {
+    let fake = FakeTransport::remote("devbox");
+    fake.expect(HELPER, &["get"], Response::exit(1, NEEDS_LOGIN))
+        .expect("keyctl", &["search"], Response::ok("555\n"))
+        .expect("keyctl", &["pupdate", "555"], Response::ok(""))
+        .expect(HELPER, &["get"], Response::ok("{}"));
+    let options = options();
+    let (source, sink) = (Fixed(PASSWORD.into()), Keyctl::new(&options));
+    let flow = ReauthFlow::new(&source, &fake, &sink, &options);
+    assert_eq!(
+        smol::block_on(flow.sync_remote_with_key()).unwrap(),
+        (true, Some(555))
+    );
+    assert_eq!(fake.calls()[2].input, PASSWORD.as_bytes());
+    assert!(fake.is_done());
+}
+
+#[test]
+fn replace_skips_the_search() {
+    let fake = FakeTransport::remote("devbox");
+    fake.expect("keyctl", &["padd"], Response::ok("123456\n"))
+        .expect(HELPER, &["get"], Response::ok("{}"));
+    let options = options();
+    let (source, sink) = (Fixed(PASSWORD.into()), Keyctl::new(&options).replace(true));
+    let synced = smol::block_on(
+        ReauthFlow::new(&source, &fake, &sink, &options)
+            .force_remote(true)
+            .sync_remote(),
+    )
+    .unwrap();
+    assert!(synced);
+    assert_eq!(fake.calls(), [padd_call(), get_call()]);
+}
+
+#[test]
+fn still_invalid_after_sync() {
+    let fake = FakeTransport::remote("devbox");
+    fake.expect(HELPER, &["get"], Response::exit(1, NEEDS_LOGIN))
+        .expect("keyctl", &["search"], key_not_found())
+        .expect("keyctl", &["padd"], Response::ok("123456\n"))
+        .expect(HELPER, &["get"], Response::exit(1, NEEDS_LOGIN));
+    let err = smol::block_on(sync(&fake, false)).unwrap_err();
+    assert!(err.to_string().contains("still invalid"), "{err:#}");
+}
+
+#[test]
+fn keyctl_failure_is_reported() {
+    let fake = FakeTransport::remote("devbox");
+    fake.expect(HELPER, &["get"], Response::exit(1, NEEDS_LOGIN))
+        .expect("keyctl", &["search"], key_not_found())
+        .expect(
+            "keyctl",
+            &["padd"],
+            Response::exit(1, "keyctl_add_key: Permission denied"),
+        );
+    let err = smol::block_on(sync(&fake, false)).unwrap_err();
+    assert!(
+        matches!(err.downcast_ref(), Some(ReauthError::Keyctl { .. })),
+        "{err:?}"
+    );
+    let err = err.to_string();
+    assert!(err.starts_with("ssh devbox keyctl padd"), "{err}");
+    assert!(err.contains("Permission denied"), "{err}");
+    assert_eq!(fake.calls(), [get_call(), search_call(), padd_call()]);
+}
+
+#[test]
+fn dropped_connection_is_an_ssh_failure() {
+    let fake = FakeTransport::remote("devbox");
+    fake.expect("keyctl", &["search"], key_not_found()).expect(
+        "keyctl",
+        &["padd"],
+        Response::exit(255, "Connection to devbox closed by remote host."),
+    );
+    let err = smol::block_on(sync(&fake, true)).unwrap_err();
+    assert!(
+        matches!(err.downcast_ref(), Some(ReauthError::Ssh { .. })),
+        "{err:?}"
+    );
+}
+
+#[test]
+fn key_serial_is_captured() {
+    let fake = FakeTransport::remote("devbox");
+    fake.expect(HELPER, &["get"], Response::exit(1, NEEDS_LOGIN))
+        .expect("keyctl", &["search"], key_not_found())
+        .expect("keyctl", &["padd"], Response::ok("123456\n"))
+        .expect(HELPER, &["get"], Response::ok("{}"));
+    let options = options();
+    let (source, sink) = (Fixed(PASSWORD.into()), Keyctl::new(&options));
+    let flow = ReauthFlow::new(&source, &fake, &sink, &options);
+    assert_eq!(
+        smol::block_on(flow.sync_remote_with_key()).unwrap(),
+        (true, Some(123456))
+    );
+
+    fake.expect(HELPER, &["get"], Response::exit(1, NEEDS_LOGIN))
+        .expect("keyctl", &["search"], key_not_found())
+        .expect("keyctl", &["padd"], Response::ok("huh\n"));
+    let err = smol::block_on(flow.sync_remote()).unwrap_err().to_string();
+    assert!(err.contains("rather than a key ID"), "{err}");
+}
+
+#[test]
+fn structured_error_code_means_expired() {
+    let fake = FakeTransport::remote("devbox");
+    fake.expect(
+        HELPER,
+        &["get"],
+        Response::Exit {
+            code: 1,
+            stdout: br#"{"error":{"code":"UNAUTHENTICATED","message":"token revoked"}}"#.into(),
+            stderr: b"no credential".into(),
+        },
+    )
+    .expect("keyctl", &["search"], key_not_found())
+    .expect("keyctl", &["padd"], Response::ok("123456\n"))
+    .expect(HELPER, &["get"], Response::ok("{}"))
+    .expect(
+        HELPER,
+        &["get"],
+        Response::exit(1, r#"{"error":"expired"}"#),
+    )
+    .expect("keyctl", &["search"], key_not_found())
+    .expect("keyctl", &["padd"], Response::ok("123456\n"))
+    .expect(HELPER, &["get"], Response::ok("{}"));
+    assert!(smol::block_on(sync(&fake, false)).unwrap());
+    assert!(smol::block_on(sync(&fake, false)).unwrap());
+    assert!(fake.is_done());
+}
+
+#[test]
+fn other_structured_errors_are_reported() {
+    let fake = FakeTransport::remote("devbox");
+    fake.expect(
+        HELPER,
+        &["get"],
+        Response::exit(1, r#"{"error":"permission_denied"}"#),
+    );
+    let err = smol::block_on(sync(&fake, false)).unwrap_err().to_string();
+    assert!(err.contains("permission_denied"), "{err}");
+}
+
+#[test]
+fn past_expiry_means_expired() {
+    let fake = FakeTransport::remote("devbox");
+    fake.expect(
+        HELPER,
+        &["get"],
+        Response::ok(r#"{"headers":{},"expires":"2020-01-01T00:00:00Z"}"#),
+    )
+    .expect("keyctl", &["search"], key_not_found())
+    .expect("keyctl", &["padd"], Response::ok("123456\n"))
+    .expect(
+        HELPER,
+        &["get"],
+        Response::ok(r#"{"headers":{},"expires":"2999-01-01T00:00:00Z"}"#),
+    );
+    assert!(smol::block_on(sync(&fake, false)).unwrap());
+    assert!(fake.is_done());
+}
+
+/// A helper response for a credential that expires at `expires`.
+fn expiring(expires: SystemTime) -> Response {
+    Response::ok(&format!(
+        r#"{{"headers":{{}},"expires":"{}"}}"#,
+        humantime::format_rfc3339_seconds(expires)
+    ))
+}
+
+#[test]
+fn min_validity_refreshes_a_credential_about_to_expire() {
+    let soon = SystemTime::now() + Duration::from_secs(600);
+    let strict = SyncOptions {
+        min_validity: Duration::from_secs(1800),
+        ..options()
+    };
+    let (source, sink) = (Fixed(PASSWORD.into()), Keyctl::new(&strict));
+
+    let fake = FakeTransport::remote("devbox");
+    fake.expect(HELPER, &["get"], expiring(soon))
+        .expect("keyctl", &["search"], key_not_found())
+        .expect("keyctl", &["padd"], Response::ok("123456\n"))
+        // The fresh credential is no better, but it is the best there is.
+        .expect(HELPER, &["get"], expiring(soon));
+    let outcome = smol::block_on(ReauthFlow::new(&source, &fake, &sink, &strict).run()).unwrap();
+    assert!(outcome.synced);
+    let expires = outcome.expires.unwrap();
+    assert_eq!(
+        humantime::format_rfc3339_seconds(expires).to_string(),
+        humantime::format_rfc3339_seconds(soon).to_string()
+    );
+
+    // Without the threshold, the same credential is left alone.
+    let fake = FakeTransport::remote("devbox");
+    fake.expect(HELPER, &["get"], expiring(soon));
+    let plain = options();
+    let outcome = smol::block_on(ReauthFlow::new(&source, &fake, &sink, &plain).run()).unwrap();
+    assert!(!outcome.synced);
+    assert!(outcome.expires.is_some());
+}
+
+#[test]
+fn jwt_expiry_is_read_from_the_credential() {
+    // {"alg":"none"}.{"sub":"me","exp":2000000000}.
+    let jwt = "eyJhbGciOiJub25lIn0.eyJzdWIiOiJtZSIsImV4cCI6MjAwMDAwMDAwMH0.";
+    let exp = UNIX_EPOCH + Duration::from_secs(2_000_000_000);
+    assert_eq!(token::expiry(jwt), Some(exp));
+    assert_eq!(token::expiry(&format!("Bearer {jwt}\n")), Some(exp));
+    assert_eq!(token::expiry(PASSWORD), None);
+
+    let fake = FakeTransport::remote("devbox");
+    fake.expect("keyctl", &["search"], key_not_found())
+        .expect("keyctl", &["padd"], Response::ok("123456\n"))
+        .expect("keyctl", &["timeout", "123456"], Response::ok(""))
+        .expect(HELPER, &["get"], Response::ok("{}"));
+    let options = options();
+    let (source, sink) = (Fixed(jwt.into()), Keyctl::new(&options));
+    let outcome = smol::block_on(
+        ReauthFlow::new(&source, &fake, &sink, &options)
+            .force_remote(true)
+            .run(),
+    )
+    .unwrap();
+    assert_eq!(outcome.expires, Some(exp));
+    // The key times out when the credential does.
+    let secs: u64 = fake.calls()[2].args[2].parse().unwrap();
+    let left = exp.duration_since(SystemTime::now()).unwrap().as_secs();
+    assert!((left..=left + 2).contains(&secs), "{secs} vs {left}");
+}
+
+#[test]
+fn ssh_failure_is_not_mistaken_for_expiry() {
+    let fake = FakeTransport::remote("devbox");
+    fake.expect(
+        HELPER,
+        &["get"],
+        Response::exit(
+            255,
+            "ssh: connect to host devbox port 22: Connection refused",
+        ),
+    );
+    let err = smol::block_on(sync(&fake, false)).unwrap_err().to_string();
+    assert!(err.contains("Connection refused"), "{err}");
+    assert_eq!(fake.calls(), [get_call()]);
+}
+
+#[test]
+fn unreachable_transport() {
+    let fake = FakeTransport::remote("devbox");
+    fake.expect(HELPER, &["get"], Response::Error("broken pipe".into()));
+    let err = smol::block_on(sync(&fake, false)).unwrap_err();
+    assert_eq!(err.to_string(), "broken pipe");
+}
+
+#[test]
+fn persistent_keyring_is_looked_up_first() {
+    let fake = FakeTransport::remote("devbox");
+    fake.expect("keyctl", &["get_persistent"], Response::ok("987654\n"))
+        .expect("keyctl", &["search"], key_not_found())
+        .expect("keyctl", &["padd"], Response::ok("123456\n"))
+        .expect(HELPER, &["get"], Response::ok("{}"));
+    let options = SyncOptions {
+        keyrings: vec![KeyringTarget::Persistent],
+        ..options()
+    };
+    let synced = smol::block_on(
+        ReauthFlow::new(
+            &Fixed(PASSWORD.into()),
+            &fake,
+            &Keyctl::new(&options),
+            &options,
+        )
+        .force_remote(true)
+        .sync_remote(),
+    )
+    .unwrap();
+    assert!(synced);
+    assert_eq!(fake.calls()[1].args[1], "987654");
+    assert_eq!(fake.calls()[2].args[3], "987654");
+}
+
+#[test]
+fn each_keyring_gets_the_key() {
+    let fake = FakeTransport::remote("devbox");
+    fake.expect("keyctl", &["search"], key_not_found())
+        .expect("keyctl", &["padd"], Response::ok("123456\n"))
+        .expect("keyctl", &["padd"], Response::ok("654321\n"))
+        .expect(HELPER, &["get"], Response::ok("{}"));
+    let options = SyncOptions {
+        keyrings: vec![KeyringTarget::User, KeyringTarget::Session],
+        ..options()
+    };
+    let (source, sink) = (Fixed(PASSWORD.into()), Keyctl::new(&options));
+    let flow = ReauthFlow::new(&source, &fake, &sink, &options).force_remote(true);
+    assert_eq!(
+        smol::block_on(flow.sync_remote_with_key()).unwrap(),
+        (true, Some(123456))
+    );
+    let calls = fake.calls();
+    assert_eq!(calls[..2], [search_call(), padd_call()]);
+    // Searching @s would find the key just added to @u through its link, so it is added outright.
+    assert_eq!(calls[2].args[..3], padd_call().args[..3]);
+    assert_eq!(calls[2].args[3], "@s");
+}
+
+#[test]
+fn both_links_the_user_key_into_the_session_keyring() {
+    let options = SyncOptions {
+        keyrings: vec![KeyringTarget::Both],
+        ..options()
+    };
+    let source = Fixed(PASSWORD.into());
+    let fake = FakeTransport::remote("devbox");
+    fake.expect("keyctl", &["search"], key_not_found())
+        .expect("keyctl", &["padd"], Response::ok("123456\n"))
+        .expect("keyctl", &["link"], Response::ok(""))
+        .expect(HELPER, &["get"], Response::ok("{}"));
+    let sink = Keyctl::new(&options);
+    let flow = ReauthFlow::new(&source, &fake, &sink, &options).force_remote(true);
+    assert_eq!(
+        smol::block_on(flow.sync_remote_with_key()).unwrap(),
+        (true, Some(123456))
+    );
+    let calls = fake.calls();
+    assert_eq!(calls[..2], [search_call(), padd_call()]);
+    assert_eq!(calls[2].args, ["link", "123456", "@s"]);
+
+    let fake = FakeTransport::remote("devbox");
+    fake.expect("sh", &["-c"], Response::ok("key 123456\nrun\n{}"));
+    let sink = Keyctl::new(&options).single_round_trip(true);
+    let flow = ReauthFlow::new(&source, &fake, &sink, &options).force_remote(true);
+    assert!(smol::block_on(flow.sync_remote()).unwrap());
+    assert!(fake.calls()[0].args[1].contains("keyctl link \"$serial\" @s"));
+}
+
+#[test]
+fn keyring_targets_parse() {
+    for (s, arg) in [
+        ("session", Some("@s")),
+        ("@u", Some("@u")),
+        ("user-session", Some("@us")),
+        ("persistent", None),
+        ("both", Some("@u")),
+        ("%:bazel", Some("%:bazel")),
+        ("%keyring:build.shared", Some("%keyring:build.shared")),
+        ("-5", Some("-5")),
+        ("12345", Some("12345")),
+    ] {
+        let target: KeyringTarget = s.parse().unwrap();
+        assert_eq!(target.arg().as_deref(), arg, "{s}");
+    }
+    assert!("sessoin".parse::<KeyringTarget>().is_err());
+    for s in ["%:", "%user:bazel", "%bazel", "%:$(id)"] {
+        assert!(s.parse::<KeyringTarget>().is_err(), "{s}");
+    }
+}
+
+#[test]
+fn single_round_trip_stores_and_checks_in_one_script() {
+    let fake = FakeTransport::remote("devbox");
+    fake.expect(HELPER, &["get"], Response::exit(1, NEEDS_LOGIN))
+        .expect("sh", &["-c"], Response::ok("key 123456\nrun\n{}"));
+    let options = options();
+    let (source, sink) = (
+        Fixed(PASSWORD.into()),
+        Keyctl::new(&options).single_round_trip(true),
+    );
+    let flow = ReauthFlow::new(&source, &fake, &sink, &options);
+    assert_eq!(
+        smol::block_on(flow.sync_remote_with_key()).unwrap(),
+        (true, Some(123456))
+    );
+    let calls = fake.calls();
+    assert_eq!(calls.len(), 2);
+    let script = &calls[1];
+    assert_eq!(script.input, PASSWORD.as_bytes());
+    assert_eq!(
+        script.args[2..],
+        [
+            "sh".to_string(),
+            format!("keyring-rs:{REMOTE}@AspectWorkflows"),
+            HELPER.into(),
+            format!("{{\"uri\":\"https://{REMOTE}\"}}\n"),
+            "get".into(),
+        ]
+    );
+    assert!(script.args[1].contains("keyctl search \"$ring\""));
+    assert!(!script.args[1].contains(['\'', '\\']));
+
+    fake.expect(HELPER, &["get"], Response::exit(1, NEEDS_LOGIN))
+        .expect(
+            "sh",
+            &["-c"],
+            Response::Exit {
+                code: 1,
+                stdout: b"failed padd 1\n".into(),
+                stderr: b"keyctl_add_key: Permission denied".into(),
+            },
+        );
+    let err = smol::block_on(flow.sync_remote()).unwrap_err();
+    assert!(
+        matches!(
+            err.downcast_ref(),
+            Some(ReauthError::Keyctl {
+                kind: KeyctlErrorKind::PermissionDenied,
+                ..
+            })
+        ),
+        "{err:?}"
+    );
+    let err = err.to_string();
+    assert!(err.starts_with("ssh devbox keyctl padd: "), "{err}");
+    assert!(err.contains("Permission denied"), "{err}");
+
+    // The helper's refusal is judged as it would be after a separate store.
+    fake.expect(HELPER, &["get"], Response::exit(1, NEEDS_LOGIN))
+        .expect(
+            "sh",
+            &["-c"],
+            Response::Exit {
+                code: 1,
+                stdout: b"key 123456\nrun\n".into(),
+                stderr: NEEDS_LOGIN.into(),
+            },
+        );
+    let err = smol::block_on(flow.sync_remote()).unwrap_err().to_string();
+    assert!(err.contains("still invalid"), "{err}");
+}
+
+#[test]
+fn denied_padd_is_tried_again_from_a_new_session_keyring() {
+    let options = options();
+    let source = Fixed(PASSWORD.into());
+    let denied = || Response::exit(1, "keyctl_add_key: Permission denied");
+
+    // Without being asked to, it only says how.
+    let fake = FakeTransport::remote("devbox");
+    fake.expect(HELPER, &["get"], Response::exit(1, NEEDS_LOGIN))
+        .expect("keyctl", &["search"], Response::exit(1, ""))
+        .expect("keyctl", &["padd"], denied());
+    let sink = Keyctl::new(&options);
+    let err =
+        smol::block_on(ReauthFlow::new(&source, &fake, &sink, &options).sync_remote()).unwrap_err();
+    assert!(
+        err.to_string()
+            .contains("rerun with --join-session-keyring"),
+        "{err:#}"
+    );
+
+    let fake = FakeTransport::remote("devbox");
+    fake.expect(HELPER, &["get"], Response::exit(1, NEEDS_LOGIN))
+        .expect("keyctl", &["search"], Response::exit(1, ""))
+        .expect("keyctl", &["padd"], denied())
+        .expect("keyctl", &["session", "-"], Response::ok("654321\n"))
+        .expect(HELPER, &["get"], Response::ok("{}"));
+    let sink = Keyctl::new(&options).join_session(true);
+    let flow = ReauthFlow::new(&source, &fake, &sink, &options);
+    assert_eq!(
+        smol::block_on(flow.sync_remote_with_key()).unwrap(),
+        (true, Some(654321))
+    );
+    let session = &fake.calls()[3];
+    assert_eq!(session.input, PASSWORD.as_bytes());
+    assert_eq!(session.args[2..4], ["sh", "-c"]);
+    assert!(session.args[4].contains("keyctl link @u @s"));
+    assert_eq!(
+        session.args[5..],
+        [
+            "sh".to_string(),
+            format!("keyring-rs:{REMOTE}@AspectWorkflows"),
+            "@u".into(),
+            "user".into(),
+        ]
+    );
+
+    // In one round trip, the script tries again itself.
+    let fake = FakeTransport::remote("devbox");
+    fake.expect(HELPER, &["get"], Response::exit(1, NEEDS_LOGIN))
+        .expect("sh", &["-c"], Response::ok("key 654321\nrun\n{}"));
+    let sink = Keyctl::new(&options)
+        .single_round_trip(true)
+        .join_session(true);
+    let flow = ReauthFlow::new(&source, &fake, &sink, &options);
+    assert!(smol::block_on(flow.sync_remote()).unwrap());
+    let script = &fake.calls()[1];
+    assert!(script.args[1].contains("keyctl session - sh -c \"$join\""));
+    assert!(!script.args[1].contains(['\'', '\\']));
+    assert_eq!(script.args[6], session.args[4]);
+    assert_eq!(script.args[7], "get");
+}
+
+#[test]
+fn added_key_is_given_its_permissions() {
+    let options = options();
+    let source = Fixed(PASSWORD.into());
+    let sink = Keyctl::new(&options).perm(Some(0x3f01_0000));
+
+    let fake = FakeTransport::remote("devbox");
+    fake.expect(HELPER, &["get"], Response::exit(1, NEEDS_LOGIN))
+        .expect("keyctl", &["search"], Response::exit(1, ""))
+        .expect("keyctl", &["padd"], Response::ok("654321\n"))
+        .expect(
+            "keyctl",
+            &["setperm", "654321", "0x3f010000"],
+            Response::ok(""),
+        )
+        .expect(HELPER, &["get"], Response::ok("{}"));
+    let flow = ReauthFlow::new(&source, &fake, &sink, &options);
+    assert!(smol::block_on(flow.sync_remote()).unwrap());
+    assert!(fake.is_done());
+
+    // A key updated in place keeps the permissions it had.
+    let fake = FakeTransport::remote("devbox");
+    fake.expect(HELPER, &["get"], Response::exit(1, NEEDS_LOGIN))
+        .expect("keyctl", &["search"], Response::ok("654321\n"))
+        .expect("keyctl", &["pupdate", "654321"], Response::ok(""))
+        .expect(HELPER, &["get"], Response::ok("{}"));
+    let flow = ReauthFlow::new(&source, &fake, &sink, &options);
+    assert!(smol::block_on(flow.sync_remote()).unwrap());
+    assert!(fake.is_done());
+
+    // The script does the same, only for a key it added.
+    let fake = FakeTransport::remote("devbox");
+    fake.expect(HELPER, &["get"], Response::exit(1, NEEDS_LOGIN))
+        .expect("sh", &["-c"], Response::ok("key 654321\nrun\n{}"));
+    let sink = sink.single_round_trip(true);
+    let flow = ReauthFlow::new(&source, &fake, &sink, &options);
+    assert!(smol::block_on(flow.sync_remote()).unwrap());
+    let script = &fake.calls()[1].args[1];
+    assert!(script.contains("added=1\n"));
+    assert!(script.contains("if test -n \"$added\"; then\nkeyctl setperm \"$serial\" 0x3f010000"));
+    assert!(!script.contains(['\'', '\\']));
+}
+
+#[test]
+fn oversized_credential_is_stored_as_a_big_key() {
+    let options = options();
+    let big = "x".repeat(40000);
+    let source = Fixed(big.as_str().into());
+
+    let fake = FakeTransport::remote("devbox");
+    fake.expect(HELPER, &["get"], Response::exit(1, NEEDS_LOGIN))
+        .expect(
+            "keyctl",
+            &["search", "@u", "big_key"],
+            Response::exit(1, ""),
+        )
+        .expect("keyctl", &["padd", "big_key"], Response::ok("654321\n"))
+        .expect(
+            "keyctl",
+            &["search", "@u", "user"],
+            Response::ok("123456\n"),
+        )
+        .expect("keyctl", &["invalidate", "123456"], Response::ok(""))
+        .expect(HELPER, &["get"], Response::ok("{}"));
+    let sink = Keyctl::new(&options);
+    let flow = ReauthFlow::new(&source, &fake, &sink, &options);
+    assert_eq!(
+        smol::block_on(flow.sync_remote_with_key()).unwrap(),
+        (true, Some(654321))
+    );
+    assert!(fake.is_done());
+    assert_eq!(fake.calls()[2].input, big.as_bytes());
+
+    // A credential that fits stays a user key.
+    let fake = FakeTransport::remote("devbox");
+    fake.expect(HELPER, &["get"], Response::exit(1, NEEDS_LOGIN))
+        .expect("keyctl", &["search", "@u", "user"], Response::exit(1, ""))
+        .expect("keyctl", &["padd", "user"], Response::ok("654321\n"))
+        .expect(HELPER, &["get"], Response::ok("{}"));
+    let source = Fixed(big[..32767].into());
+    let flow = ReauthFlow::new(&source, &fake, &sink, &options);
+    assert!(smol::block_on(flow.sync_remote()).unwrap());
+    assert!(fake.is_done());
+
+    // In one round trip, the script does the same.
+    let fake = FakeTransport::remote("devbox");
+    fake.expect(HELPER, &["get"], Response::exit(1, NEEDS_LOGIN))
+        .expect("sh", &["-c"], Response::ok("key 654321\nrun\n{}"));
+    let sink = Keyctl::new(&options).single_round_trip(true);
+    let source = Fixed(big.into());
+    let flow = ReauthFlow::new(&source, &fake, &sink, &options);
+    assert!(smol::block_on(flow.sync_remote()).unwrap());
+    let script = &fake.calls()[1].args[1];
+    assert!(script.contains("type=big_key"));
+    assert!(script.contains("keyctl padd \"$type\""));
+    assert!(script.contains("keyctl invalidate \"$stale\""));
+}
+
+#[test]
+fn file_sink_is_judged_by_the_credential_in_it() {
+    // {"alg":"none"}.{"sub":"me","exp":2000000000}.
+    let jwt = "eyJhbGciOiJub25lIn0.eyJzdWIiOiJtZSIsImV4cCI6MjAwMDAwMDAwMH0.";
+    let options = options();
+    let sink = File::new(&options);
+
+    // A file holding a JWT that is still good needs nothing done.
+    let fake = FakeTransport::remote("devbox");
+    fake.expect("sh", &["-c"], Response::ok(jwt));
+    let outcome =
+        smol::block_on(ReauthFlow::new(&Fixed(jwt.into()), &fake, &sink, &options).run()).unwrap();
+    assert!(!outcome.synced);
+    assert_eq!(fake.calls()[0].args[3], REMOTE);
+
+    // A missing file is written, then read back.
+    let fake = FakeTransport::remote("devbox");
+    fake.expect("sh", &["-c"], Response::exit(1, ""))
+        .expect("sh", &["-c"], Response::ok(""))
+        .expect("sh", &["-c"], Response::ok(PASSWORD));
+    let outcome =
+        smol::block_on(ReauthFlow::new(&Fixed(PASSWORD.into()), &fake, &sink, &options).run())
+            .unwrap();
+    assert!(outcome.synced);
+    assert_eq!(outcome.key, None);
+    let write = &fake.calls()[1];
+    assert!(write.args[1].contains("umask 077"), "{write:?}");
+    assert_eq!(write.input, PASSWORD.as_bytes());
+
+    // A write that fails is reported as such.
+    let fake = FakeTransport::remote("devbox");
+    fake.expect("sh", &["-c"], Response::exit(1, "")).expect(
+        "sh",
+        &["-c"],
+        Response::exit(1, "mkdir: Permission denied"),
+    );
+    let err =
+        smol::block_on(ReauthFlow::new(&Fixed(PASSWORD.into()), &fake, &sink, &options).run())
+            .unwrap_err();
+    assert!(
+        matches!(err.downcast_ref(), Some(ReauthError::Store(_))),
+        "{err:#}"
+    );
+    assert!(err.to_string().contains("Permission denied"), "{err:#}");
+}
+
+#[test]
+fn secret_tool_sink_stores_under_the_keyring_rs_attributes() {
+    let options = options();
+    let sink = SecretTool::new(&options);
+    let fake = FakeTransport::remote("devbox");
+    fake.expect(HELPER, &["get"], Response::exit(1, NEEDS_LOGIN))
+        .expect("sh", &["-c"], Response::ok(""))
+        .expect(HELPER, &["get"], Response::ok("{}"));
+    let outcome =
+        smol::block_on(ReauthFlow::new(&Fixed(PASSWORD.into()), &fake, &sink, &options).run())
+            .unwrap();
+    assert!(outcome.synced);
+    assert_eq!(outcome.key, None);
+    let store = &fake.calls()[1];
+    assert_eq!(store.input, PASSWORD.as_bytes());
+    assert_eq!(
+        store.args[3..],
+        [
+            "store",
+            &format!("--label=AspectWorkflows credential for {REMOTE}"),
+            "application",
+            "rust-keyring",
+            "service",
+            "AspectWorkflows",
+            "target",
+            "default",
+            "username",
+            REMOTE,
+        ]
+    );
+
+    // Without secret-tool on the host, the store fails with a hint.
+    let fake = FakeTransport::remote("devbox");
+    fake.expect(HELPER, &["get"], Response::exit(1, NEEDS_LOGIN))
+        .expect(
+            "sh",
+            &["-c"],
+            Response::exit(127, "sh: secret-tool: not found"),
+        );
+    let err =
+        smol::block_on(ReauthFlow::new(&Fixed(PASSWORD.into()), &fake, &sink, &options).run())
+            .unwrap_err();
+    assert!(
+        matches!(err.downcast_ref(), Some(ReauthError::Store(_))),
+        "{err:#}"
+    );
+    assert!(err.to_string().contains("libsecret-tools"), "{err:#}");
+}
+
+#[test]
+fn systemd_creds_sink_encrypts_for_user_units() {
+    let options = options();
+    let sink = SystemdCreds::new(&options);
+    let fake = FakeTransport::remote("devbox");
+    fake.expect("sh", &["-c"], Response::exit(1, ""))
+        .expect("sh", &["-c"], Response::ok(""))
+        .expect("sh", &["-c"], Response::ok(PASSWORD));
+    let outcome =
+        smol::block_on(ReauthFlow::new(&Fixed(PASSWORD.into()), &fake, &sink, &options).run())
+            .unwrap();
+    assert!(outcome.synced);
+    let calls = fake.calls();
+    assert!(
+        calls[0].args[1].contains("systemd-creds decrypt --user"),
+        "{calls:?}"
+    );
+    assert!(
+        calls[1].args[1].contains("systemd-creds encrypt --user"),
+        "{calls:?}"
+    );
+    assert!(
+        calls[1].args[1].contains("credstore.encrypted"),
+        "{calls:?}"
+    );
+    assert_eq!(calls[1].args[3], format!("aspect-{REMOTE}"));
+    assert_eq!(calls[1].input, PASSWORD.as_bytes());
+
+    // A credential that does not decrypt to the one stored is not taken for it.
+    let fake = FakeTransport::remote("devbox");
+    fake.expect("sh", &["-c"], Response::exit(1, ""))
+        .expect("sh", &["-c"], Response::ok(""))
+        .expect("sh", &["-c"], Response::exit(1, ""));
+    let err =
+        smol::block_on(ReauthFlow::new(&Fixed(PASSWORD.into()), &fake, &sink, &options).run())
+            .unwrap_err();
+    assert!(err.to_string().contains("still invalid"), "{err:#}");
+}
+
+#[test]
+fn mac_without_keyctl_gets_the_login_keychain() {
+    let fake = FakeTransport::remote("devbox");
+    let missing = || Response::exit(127, "zsh: command not found: keyctl");
+    fake.expect(HELPER, &["get"], Response::exit(1, NEEDS_LOGIN))
+        .expect("keyctl", &["search"], missing())
+        .expect("keyctl", &["padd"], missing())
+        .expect("uname", &["-s"], Response::ok("Darwin\n"))
+        .expect("security", &["-i"], Response::ok(""))
+        .expect(HELPER, &["get"], Response::ok("{}"));
+    let synced = smol::block_on(sync(&fake, false)).unwrap();
+    assert!(synced);
+    assert!(fake.is_done());
+    assert_eq!(
+        fake.calls()[4].input,
+        format!("add-generic-password -U -s AspectWorkflows -a {REMOTE} -w \"{PASSWORD}\"\n")
+            .as_bytes()
+    );
+
+    // Anywhere else, a missing keyctl is still an error.
+    let fake = FakeTransport::remote("devbox");
+    fake.expect(HELPER, &["get"], Response::exit(1, NEEDS_LOGIN))
+        .expect("keyctl", &["search"], missing())
+        .expect("keyctl", &["padd"], missing())
+        .expect("uname", &["-s"], Response::ok("Linux\n"));
+    let err = smol::block_on(sync(&fake, false)).unwrap_err();
+    assert!(
+        matches!(
+            err.downcast_ref(),
+            Some(ReauthError::Keyctl {
+                kind: KeyctlErrorKind::NotInstalled,
+                ..
+            })
+        ),
+        "{err:#}"
+    );
+}
+
+#[test]
+fn windows_without_keyctl_gets_the_credential_manager() {
+    let missing = || {
+        Response::exit(
+            1,
+            "'keyctl' is not recognized as an internal or external command,",
+        )
+    };
+    let fake = FakeTransport::remote("devbox");
+    fake.expect(HELPER, &["get"], Response::exit(1, NEEDS_LOGIN))
+        .expect("keyctl", &["search"], missing())
+        .expect("keyctl", &["padd"], missing())
+        .expect(
+            "uname",
+            &["-s"],
+            Response::exit(
+                1,
+                "'uname' is not recognized as an internal or external command,",
+            ),
+        )
+        .expect("powershell", &["-NoProfile"], Response::ok(""))
+        .expect(HELPER, &["get"], Response::ok("{}"));
+    let synced = smol::block_on(sync(&fake, false)).unwrap();
+    assert!(synced);
+    assert!(fake.is_done());
+    let store = &fake.calls()[4];
+    assert_eq!(store.input, PASSWORD.as_bytes());
+    assert_eq!(store.args[2], "-EncodedCommand");
+    let script = BASE64_STANDARD.decode(&store.args[3]).unwrap();
+    let script: Vec<u16> = script
+        .chunks(2)
+        .map(|c| u16::from_le_bytes([c[0], c[1]]))
+        .collect();
+    let script = String::from_utf16(&script).unwrap();
+    assert!(
+        script.contains(&format!("$t = '{REMOTE}.AspectWorkflows'; $u = '{REMOTE}'")),
+        "{script}"
+    );
+    assert!(script.contains("CredWrite"), "{script}");
+    assert!(!script.contains("/pass:"), "{script}");
+}
+
+#[test]
+fn probe_chooses_the_sink_before_storing() {
+    let options = options();
+    let sync = |fake: &FakeTransport| {
+        let sink = Probed::new(AnySink::Keyctl(Keyctl::new(&options)), &options);
+        smol::block_on(ReauthFlow::new(&Fixed(PASSWORD.into()), fake, &sink, &options).run())
+    };
+
+    // A credential that is still valid needs no probe.
+    let fake = FakeTransport::remote("devbox");
+    fake.expect(HELPER, &["get"], Response::ok("{}"));
+    assert!(!sync(&fake).unwrap().synced);
+    assert!(fake.is_done());
+
+    // A Mac gets the login keychain without keyctl being tried.
+    let fake = FakeTransport::remote("devbox");
+    fake.expect(HELPER, &["get"], Response::exit(1, NEEDS_LOGIN))
+        .expect("sh", &["-c"], Response::ok("Darwin\n"))
+        .expect("security", &["-i"], Response::ok(""))
+        .expect(HELPER, &["get"], Response::ok("{}"));
+    assert!(sync(&fake).unwrap().synced);
+    assert!(fake.is_done());
+
+    // A remote without keyctl is refused with what it lacks, not keyctl's own failure.
+    let fake = FakeTransport::remote("devbox");
+    fake.expect(HELPER, &["get"], Response::exit(1, NEEDS_LOGIN))
+        .expect(
+            "sh",
+            &["-c"],
+            Response::ok("Linux\nhas secret-tool\nhas runtime-dir\n"),
+        );
+    let err = sync(&fake).unwrap_err();
+    assert!(matches!(err.downcast_ref(), Some(ReauthError::Store(_))));
+    assert_eq!(
+        err.to_string(),
+        "devbox lacks keyutils; try --sink=file or --sink=secret-tool"
+    );
+    assert!(fake.is_done());
+}
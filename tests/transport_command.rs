@@ -0,0 +1,70 @@
+// Copyright 2026 Stairwell, Inc.
+// Author: mrdomino@stairwell.com
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use aspect_reauth::transport::command::CommandTemplate;
+
+fn args(template: &str, command: &str) -> Vec<String> {
+    let template: CommandTemplate = template.parse().unwrap();
+    template.args("devbox", command)
+}
+
+#[test]
+fn command_is_appended() {
+    let template: CommandTemplate = "gcloud compute ssh {host} --zone us-east1-b --"
+        .parse()
+        .unwrap();
+    assert_eq!(template.program(), "gcloud");
+    assert_eq!(
+        template.args("devbox", "keyctl padd user key @u"),
+        [
+            "compute",
+            "ssh",
+            "devbox",
+            "--zone",
+            "us-east1-b",
+            "--",
+            "keyctl padd user key @u"
+        ]
+    );
+}
+
+#[test]
+fn command_placeholder_is_one_word() {
+    assert_eq!(
+        args("kubectl exec -i {host} -- sh -c {command}", "true"),
+        ["exec", "-i", "devbox", "--", "sh", "-c", "true"]
+    );
+    assert_eq!(
+        args("run {command} --on={host}.corp", "keyctl show"),
+        ["keyctl show", "--on=devbox.corp"]
+    );
+}
+
+#[test]
+fn words_are_split_like_a_shell() {
+    assert_eq!(
+        args("ssh -o 'ProxyCommand=iap %h' {host}", "true"),
+        ["-o", "ProxyCommand=iap %h", "devbox", "true"]
+    );
+    let template: CommandTemplate = "ssh -o 'ProxyCommand=iap %h' {host}".parse().unwrap();
+    assert_eq!(template.to_string(), "ssh -o 'ProxyCommand=iap %h' {host}");
+}
+
+#[test]
+fn bad_templates_are_refused() {
+    for template in ["", "  ", "ssh 'devbox"] {
+        assert!(template.parse::<CommandTemplate>().is_err(), "{template:?}");
+    }
+}